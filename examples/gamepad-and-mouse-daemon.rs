@@ -0,0 +1,108 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use clap::Parser;
+use multiinput::{RawEvent, RawInputManager};
+
+use pad_motion::daemon::*;
+use pad_motion::source::mouse::*;
+
+/// Gamepad and mouse info server running on daemon, serving every connected gamepad.
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Invert mouse X-axis.
+    #[arg(long)]
+    invert_x: bool,
+
+    /// Invert mouse Y-axis.
+    #[arg(long)]
+    invert_y: bool,
+
+    /// Invert gamepad left stick X-axis.
+    #[arg(long)]
+    invert_gamepad_x: bool,
+
+    /// Invert gamepad left stick Y-axis.
+    #[arg(long)]
+    invert_gamepad_y: bool,
+
+    /// Invert gamepad right stick X-axis.
+    #[arg(long)]
+    invert_gamepad_right_x: bool,
+
+    /// Invert gamepad right stick Y-axis.
+    #[arg(long)]
+    invert_gamepad_right_y: bool,
+
+    /// Forward rumble requests to gamepads.
+    #[arg(long)]
+    rumble: bool,
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let running = Arc::new(AtomicBool::new(true));
+
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || {
+            running.store(false, Ordering::SeqCst);
+        })
+        .expect("Error setting Ctrl-C handler");
+    }
+
+    let mouse_to_gyro = Arc::new(Mutex::new(MouseToGyro::new(MouseGyroSettings {
+        invert_x: args.invert_x,
+        invert_y: args.invert_y,
+        ..Default::default()
+    })));
+
+    let mut daemon = {
+        let mouse_to_gyro = mouse_to_gyro.clone();
+        Daemon::builder()
+            .with_stick_inversion(StickInversion {
+                left_x: args.invert_gamepad_x,
+                left_y: args.invert_gamepad_y,
+                right_x: args.invert_gamepad_right_x,
+                right_y: args.invert_gamepad_right_y,
+            })
+            .with_rumble(args.rumble)
+            .with_motion_hook(move |slot_number, controller_data| {
+                if slot_number == 0 {
+                    mouse_to_gyro
+                        .lock()
+                        .unwrap()
+                        .sample(controller_data, Instant::now());
+                }
+            })
+            .start()
+            .expect("Error starting daemon")
+    };
+
+    let mut mouse_manager = RawInputManager::new().unwrap();
+    mouse_manager.register_devices(multiinput::DeviceType::Mice);
+
+    while running.load(Ordering::SeqCst) {
+        while let Some(event) = mouse_manager.get_event() {
+            match event {
+                RawEvent::MouseMoveEvent(_mouse_id, delta_x, delta_y) => {
+                    mouse_to_gyro
+                        .lock()
+                        .unwrap()
+                        .add_movement(delta_x as f32, delta_y as f32);
+                }
+                RawEvent::MouseWheelEvent(_mouse_id, delta) => {
+                    mouse_to_gyro.lock().unwrap().add_wheel(delta);
+                }
+                _ => (),
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    }
+
+    daemon.stop();
+}
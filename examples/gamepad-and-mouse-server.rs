@@ -1,3 +1,5 @@
+#[cfg(any(feature = "scripting", feature = "profiles"))]
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
 use std::time::{Duration, Instant};
@@ -6,8 +8,16 @@ use clap::Parser;
 use gilrs::{Axis, Button, Gilrs};
 use multiinput::{RawEvent, RawInputManager};
 
+#[cfg(feature = "profiles")]
+use pad_motion::game_profiles::*;
 use pad_motion::protocol::*;
+#[cfg(feature = "gilrs-rumble")]
+use pad_motion::rumble::*;
 use pad_motion::server::*;
+use pad_motion::source::mouse::*;
+use pad_motion::transform::Pipeline;
+#[cfg(feature = "scripting")]
+use pad_motion::transform::Script;
 
 /// Gamepad and mouse info server.
 #[derive(Parser, Debug)]
@@ -36,6 +46,26 @@ struct Args {
     /// Invert gamepad right stick Y-axis.
     #[arg(long)]
     invert_gamepad_right_y: bool,
+
+    /// Rhai script remapping controller data.
+    #[cfg(feature = "scripting")]
+    #[arg(long)]
+    script: Option<PathBuf>,
+
+    /// JSON file with per-game transform profiles.
+    #[cfg(feature = "profiles")]
+    #[arg(long)]
+    game_profiles: Option<PathBuf>,
+
+    /// Game profile to use, if not set profile is switched automatically by focused window.
+    #[cfg(feature = "profiles")]
+    #[arg(long, requires = "game_profiles")]
+    game: Option<String>,
+
+    /// Forward rumble requests to gamepad.
+    #[cfg(feature = "gilrs-rumble")]
+    #[arg(long)]
+    rumble: bool,
 }
 
 fn main() {
@@ -52,6 +82,37 @@ fn main() {
     }
 
     let server = Arc::new(Server::new(None, None).unwrap());
+    #[allow(unused_mut)]
+    let mut pipeline = Pipeline::new();
+    #[cfg(feature = "scripting")]
+    if let Some(script) = &args.script {
+        pipeline.push(Script::from_file(script).expect("Error loading script"));
+    }
+    #[cfg(feature = "profiles")]
+    let mut window_watch_join_handle = None;
+    #[cfg(feature = "profiles")]
+    if let Some(game_profiles) = &args.game_profiles {
+        let handle = GameProfiles::load(game_profiles)
+            .expect("Error loading game profiles")
+            .handle();
+        pipeline.push(handle.transform());
+
+        match &args.game {
+            Some(game) => {
+                if !handle.select(game) {
+                    panic!("Unknown game profile: {}", game);
+                }
+            }
+            None => {
+                window_watch_join_handle = Some(watch_active_window(
+                    handle,
+                    Duration::from_secs(1),
+                    running.clone(),
+                ))
+            }
+        }
+    }
+    server.set_transform(0, Some(Box::new(pipeline)));
     let server_thread_join_handle = {
         let server = server.clone();
         server.start(running.clone())
@@ -77,40 +138,55 @@ fn main() {
         }
     }
 
-    let inverter_x = inverter(args.invert_x);
-    let inverter_y = inverter(args.invert_y);
     let gamepad_inverter_x = inverter(args.invert_gamepad_x);
     let gamepad_inverter_y = inverter(args.invert_gamepad_y);
     let gamepad_inverter_right_x = inverter(args.invert_gamepad_right_x);
     let gamepad_inverter_right_y = inverter(args.invert_gamepad_right_y);
 
     let mut gilrs = Gilrs::new().unwrap();
+    #[cfg(feature = "gilrs-rumble")]
+    if args.rumble {
+        let first_gamepad = gilrs.gamepads().next().map(|(id, _gamepad)| id);
+        if let Some(id) = first_gamepad {
+            match GilrsRumble::new(&mut gilrs, id) {
+                Ok(gamepad_rumble) => {
+                    let mut router = RumbleRouter::new();
+                    let output = router.add_output(gamepad_rumble);
+                    router.connect(0, output);
+                    server.set_motor_count(0, 2);
+                    server.set_rumble_callback(Some(router.into_callback()));
+                }
+                Err(error) => eprintln!("Rumble unavailable: {}", error),
+            }
+        }
+    }
     let mut mouse_manager = RawInputManager::new().unwrap();
     mouse_manager.register_devices(multiinput::DeviceType::Mice);
 
-    let now = Instant::now();
+    let mut mouse_to_gyro = MouseToGyro::new(MouseGyroSettings {
+        invert_x: args.invert_x,
+        invert_y: args.invert_y,
+        ..Default::default()
+    });
+
     while running.load(Ordering::SeqCst) {
         // Consume controller events
         while let Some(_event) = gilrs.next_event() {}
 
-        let mut delta_rotation_x = 0.0;
-        let mut delta_rotation_y = 0.0;
-        let mut delta_mouse_wheel = 0.0;
         while let Some(event) = mouse_manager.get_event() {
             match event {
                 RawEvent::MouseMoveEvent(_mouse_id, delta_x, delta_y) => {
-                    delta_rotation_x += delta_x as f32;
-                    delta_rotation_y += delta_y as f32;
+                    mouse_to_gyro.add_movement(delta_x as f32, delta_y as f32);
                 }
                 RawEvent::MouseWheelEvent(_mouse_id, delta) => {
-                    delta_mouse_wheel += delta as f32;
+                    mouse_to_gyro.add_wheel(delta);
                 }
                 _ => (),
             }
         }
 
         let first_gamepad = gilrs.gamepads().next();
-        let controller_data = {
+        let mut controller_data = {
             if let Some((_id, gamepad)) = first_gamepad {
                 let analog_button_value = |button| {
                     gamepad
@@ -162,28 +238,25 @@ fn main() {
                     analog_l1: analog_button_value(Button::LeftTrigger),
                     analog_r2: analog_button_value(Button::RightTrigger2),
                     analog_l2: analog_button_value(Button::LeftTrigger2),
-                    motion_data_timestamp: now.elapsed().as_micros() as u64,
-                    gyroscope_pitch: -delta_rotation_y * 10.0 * inverter_y,
-                    gyroscope_roll: delta_rotation_x * 10.0 * inverter_x,
-                    gyroscope_yaw: delta_mouse_wheel * 300.0,
                     ..Default::default()
                 }
             } else {
                 ControllerData {
                     connected: true,
-                    motion_data_timestamp: now.elapsed().as_micros() as u64,
-                    gyroscope_pitch: -delta_rotation_y * 10.0 * inverter_y,
-                    gyroscope_roll: delta_rotation_x * 10.0 * inverter_x,
-                    gyroscope_yaw: delta_mouse_wheel * 300.0,
                     ..Default::default()
                 }
             }
         };
 
+        mouse_to_gyro.sample(&mut controller_data, Instant::now());
         server.update_controller_data(0, controller_data);
 
         std::thread::sleep(Duration::from_millis(10));
     }
 
     server_thread_join_handle.join().unwrap();
+    #[cfg(feature = "profiles")]
+    if let Some(window_watch_join_handle) = window_watch_join_handle {
+        window_watch_join_handle.join().unwrap();
+    }
 }
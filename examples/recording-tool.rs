@@ -0,0 +1,142 @@
+//! Minimizes recordings: trims, concatenates and remaps slots of recordings made with `pad_motion::recording`.
+
+use clap::{Parser, Subcommand};
+use pad_motion::protocol::MAX_SLOT_COUNT;
+use pad_motion::recording::*;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Error, ErrorKind, Result};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Keep only frames between given times (in seconds).
+    Trim {
+        input: PathBuf,
+        output: PathBuf,
+        #[arg(long, default_value_t = 0.0)]
+        start: f64,
+        #[arg(long)]
+        end: Option<f64>,
+    },
+    /// Join recordings one after another.
+    Concat {
+        output: PathBuf,
+        #[arg(required = true)]
+        inputs: Vec<PathBuf>,
+        /// Time between joined recordings (in seconds).
+        #[arg(long, default_value_t = 0.1)]
+        gap: f64,
+    },
+    /// Move frames between slots.
+    Remap {
+        input: PathBuf,
+        output: PathBuf,
+        /// New slot numbers for slots 0, 1, 2, ..., e.g. `1,0` swaps first two slots.
+        #[arg(long, required = true, value_delimiter = ',')]
+        mapping: Vec<u8>,
+    },
+    /// Print recording summary.
+    Summary {
+        input: PathBuf,
+        /// Report intervals longer than this (in seconds) as gaps.
+        #[arg(long, default_value_t = 0.1)]
+        gap_threshold: f64,
+    },
+}
+
+fn read(path: &PathBuf) -> Result<Recording> {
+    Recording::read_from(&mut BufReader::new(File::open(path)?))
+}
+
+fn write(path: &PathBuf, recording: &Recording) -> Result<()> {
+    recording.write_to(&mut BufWriter::new(File::create(path)?))
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Command::Trim {
+            input,
+            output,
+            start,
+            end,
+        } => {
+            let recording = read(&input)?;
+            let end = end
+                .map(Duration::from_secs_f64)
+                .unwrap_or_else(|| recording.duration());
+            write(
+                &output,
+                &recording.trim(Duration::from_secs_f64(start), end),
+            )
+        }
+        Command::Concat {
+            output,
+            inputs,
+            gap,
+        } => {
+            let recordings = inputs.iter().map(read).collect::<Result<Vec<_>>>()?;
+            write(
+                &output,
+                &Recording::concat(&recordings, Duration::from_secs_f64(gap)),
+            )
+        }
+        Command::Remap {
+            input,
+            output,
+            mapping,
+        } => {
+            if mapping.len() > MAX_SLOT_COUNT as usize
+                || mapping.iter().any(|&slot| slot >= MAX_SLOT_COUNT)
+            {
+                return Err(Error::new(
+                    ErrorKind::InvalidInput,
+                    format!(
+                        "Mapping must contain at most {0} slot numbers lower than {0}",
+                        MAX_SLOT_COUNT
+                    ),
+                ));
+            }
+
+            let mut recording = read(&input)?;
+            recording.remap_slots(&mapping);
+            write(&output, &recording)
+        }
+        Command::Summary {
+            input,
+            gap_threshold,
+        } => {
+            let summary = read(&input)?.summary(Duration::from_secs_f64(gap_threshold));
+            println!("Duration: {:?}", summary.duration);
+            println!("Frames: {}", summary.frame_count);
+            for (slot_number, slot) in summary.slots.iter().enumerate() {
+                if let Some(slot) = slot {
+                    println!(
+                        "Slot {}: {} frames, {:.1} Hz, button presses: {:?}",
+                        slot_number,
+                        slot.frame_count,
+                        slot.sample_rate().unwrap_or_default(),
+                        slot.button_presses
+                    );
+                }
+            }
+            for gap in summary.gaps.iter() {
+                println!(
+                    "Gap in slot {} at {:?} lasting {:?}",
+                    gap.slot, gap.start, gap.duration
+                );
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Server utilities.
+
+use clap::{Parser, Subcommand};
+use pad_motion::bridge::*;
+use pad_motion::client::*;
+use pad_motion::load_test::*;
+use pad_motion::relay::*;
+use pad_motion::server::*;
+use std::io::Result;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Parser, Debug)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    #[command(subcommand)]
+    command: Command,
+    /// Allow test and relayed servers to be reachable beyond localhost.
+    #[arg(long, global = true)]
+    lan_exposure: bool,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Measure packet rates and latencies of server serving many clients.
+    LoadTest {
+        /// Address to run test server on.
+        #[arg(long, default_value = "127.0.0.1:0")]
+        address: SocketAddr,
+        /// Number of synthetic clients.
+        #[arg(long, default_value_t = 10)]
+        clients: usize,
+        /// Test duration (in seconds).
+        #[arg(long, default_value_t = 10.0)]
+        duration: f64,
+        /// Controller data updates per second.
+        #[arg(long, default_value_t = 250.0)]
+        rate: f64,
+    },
+    /// Run public relay forwarding traffic between home servers and remote clients.
+    Relay {
+        /// Relay's public address.
+        #[arg(long, default_value = "0.0.0.0:26770")]
+        address: SocketAddr,
+    },
+    /// Make local server available to remote clients through relay.
+    RelayHome {
+        /// Relay's public address.
+        #[arg(long)]
+        relay: SocketAddr,
+        /// Secret shared with remote end.
+        #[arg(long)]
+        token: String,
+        /// Address of local server.
+        #[arg(long, default_value = "127.0.0.1:26760")]
+        server: SocketAddr,
+    },
+    /// Serve local emulator with data of home server received through relay.
+    RelayRemote {
+        /// Relay's public address.
+        #[arg(long)]
+        relay: SocketAddr,
+        /// Secret shared with home end.
+        #[arg(long)]
+        token: String,
+        /// Address to serve local emulator on.
+        #[arg(long, default_value = "127.0.0.1:26760")]
+        address: SocketAddr,
+    },
+}
+
+fn running_until_interrupted() -> Arc<AtomicBool> {
+    let running = Arc::new(AtomicBool::new(true));
+    {
+        let running = running.clone();
+        ctrlc::set_handler(move || {
+            running.store(false, Ordering::SeqCst);
+        })
+        .expect("Error setting Ctrl-C handler");
+    }
+
+    running
+}
+
+fn run_bridge(client: Client, server: Server) {
+    let running = running_until_interrupted();
+    let mut bridge = Bridge::new(client, server);
+    bridge.start();
+    while running.load(Ordering::SeqCst) {
+        std::thread::sleep(Duration::from_millis(100));
+    }
+    bridge.stop();
+}
+
+fn build_server(address: SocketAddr, lan_exposure: bool) -> Result<Server> {
+    Server::builder()
+        .with_address(address)
+        .with_lan_exposure(lan_exposure)
+        .build()
+}
+
+fn main() -> Result<()> {
+    let args = Args::parse();
+
+    match args.command {
+        Command::LoadTest {
+            address,
+            clients,
+            duration,
+            rate,
+        } => {
+            let config = LoadTestConfig {
+                clients,
+                duration: Duration::from_secs_f64(duration),
+                update_rate: rate,
+                ..Default::default()
+            };
+            let server = build_server(address, args.lan_exposure)?;
+            let report = run_load_test(server, &config)?;
+
+            println!("Clients: {}", report.clients);
+            println!("Duration: {:?}", report.duration);
+            println!(
+                "Updates: {} ({:.1} Hz)",
+                report.updates,
+                report.update_rate()
+            );
+            println!(
+                "Received packets: {} of {} ({:.1}%), {:.1} packets/s",
+                report.received_packets,
+                report.expected_packets,
+                report.delivery_ratio() * 100.0,
+                report.packet_rate()
+            );
+            if let Some(latency) = report.latency {
+                println!(
+                    "Latency: mean {:?}, median {:?}, 99th percentile {:?}, max {:?}",
+                    latency.mean, latency.median, latency.p99, latency.max
+                );
+            }
+
+            Ok(())
+        }
+        Command::Relay { address } => {
+            let running = running_until_interrupted();
+            let relay = Arc::new(Relay::new(address)?);
+            println!("Relay listening on {}", relay.local_addr()?);
+            let thread = relay.clone().start(running.clone());
+            while running.load(Ordering::SeqCst) {
+                std::thread::sleep(Duration::from_secs(1));
+            }
+            let _ = thread.join();
+
+            println!("{:?}", relay.stats());
+            Ok(())
+        }
+        Command::RelayHome {
+            relay,
+            token,
+            server,
+        } => {
+            let transport = RelayTransport::connect(relay, RelayRole::Server, &token)?;
+            run_bridge(
+                Client::new(None, None, Some(server))?,
+                Server::with_transport(None, Box::new(transport)),
+            );
+
+            Ok(())
+        }
+        Command::RelayRemote {
+            relay,
+            token,
+            address,
+        } => {
+            let transport = RelayTransport::connect(relay, RelayRole::Client, &token)?;
+            run_bridge(
+                Client::with_transport(None, Box::new(transport), Some(relay)),
+                build_server(address, args.lan_exposure)?,
+            );
+
+            Ok(())
+        }
+    }
+}
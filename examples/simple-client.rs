@@ -18,6 +18,7 @@ fn main() {
     }
 
     let client = Arc::new(Client::new(None, None, None).unwrap());
+    println!("Listening on {}", client.local_addr().unwrap());
     let client_thread_join_handle = {
         let client = client.clone();
         client.start(running.clone())
@@ -30,7 +31,7 @@ fn main() {
         client
             .request_controller_data(ControllerDataRequest::ReportAll)
             .unwrap();
-        while let Some(event) = client.next_event() {
+        for event in client.events() {
             println!("{:?}", event);
         }
         thread::sleep(Duration::from_secs(1));
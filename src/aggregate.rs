@@ -0,0 +1,197 @@
+//! Client combining multiple upstream servers.
+//!
+//! Controllers are identified by MAC address. When several servers provide the same controller,
+//! data is taken from the highest priority server that is not silent, switching to another one
+//! (and back) transparently.
+
+use crate::client::*;
+use crate::protocol::*;
+use std::collections::HashMap;
+use std::io::Result;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug)]
+pub enum AggregateEvent {
+    /// Event from upstream currently providing controller.
+    Upstream { upstream: usize, event: ClientEvent },
+    /// Controller with given MAC address is now provided by a different upstream
+    /// (`None` when no upstream provides it).
+    Switchover {
+        mac_address: u64,
+        from: Option<usize>,
+        to: Option<usize>,
+    },
+}
+
+struct Upstream {
+    client: Arc<Client>,
+    priority: i32,
+}
+
+/// Multi-server client with priority based failover.
+pub struct AggregateClient {
+    upstreams: Vec<Upstream>,
+    active: HashMap<u64, usize>,
+    silence_timeout: Duration,
+}
+
+const DEFAULT_SILENCE_TIMEOUT: Duration = Duration::from_secs(1);
+
+impl Default for AggregateClient {
+    fn default() -> AggregateClient {
+        AggregateClient::new(DEFAULT_SILENCE_TIMEOUT)
+    }
+}
+
+impl AggregateClient {
+    /// Creates new aggregate client.
+    ///
+    /// # Arguments
+    ///
+    /// * `silence_timeout` - upstream not sending data for a controller for this long is considered silent.
+    pub fn new(silence_timeout: Duration) -> AggregateClient {
+        AggregateClient {
+            upstreams: vec![],
+            active: HashMap::new(),
+            silence_timeout,
+        }
+    }
+
+    /// Adds upstream client (it should be already started), returns its index.
+    ///
+    /// Upstreams with higher `priority` are preferred.
+    pub fn add_upstream(&mut self, client: Arc<Client>, priority: i32) -> usize {
+        self.upstreams.push(Upstream { client, priority });
+
+        self.upstreams.len() - 1
+    }
+
+    /// Returns upstream client with given index.
+    pub fn upstream(&self, upstream: usize) -> &Arc<Client> {
+        &self.upstreams[upstream].client
+    }
+
+    /// Returns index of upstream currently providing controller with given MAC address.
+    pub fn active_upstream(&self, mac_address: u64) -> Option<usize> {
+        self.active.get(&mac_address).copied()
+    }
+
+    /// Returns current info and data of controller with given MAC address.
+    pub fn controller(&self, mac_address: u64) -> Option<(ControllerInfo, ControllerData)> {
+        let upstream = self.active_upstream(mac_address)?;
+        let client = &self.upstreams[upstream].client;
+        let slot_number = self.find_slot(client, mac_address)?;
+
+        Some((
+            client.controller_info(slot_number),
+            client.controller_data(slot_number),
+        ))
+    }
+
+    /// Asks all upstreams for info and data of all their controllers.
+    /// You must call this method periodically, like `Client::request_controller_data`.
+    pub fn request_all(&self) -> Result<()> {
+        for upstream in self.upstreams.iter() {
+            let slot_numbers: Vec<u8> = (0..upstream.client.slot_count()).collect();
+            upstream
+                .client
+                .request_connected_controllers_info(&slot_numbers)?;
+            upstream
+                .client
+                .request_controller_data(ControllerDataRequest::ReportAll)?;
+        }
+
+        Ok(())
+    }
+
+    fn find_slot(&self, client: &Arc<Client>, mac_address: u64) -> Option<u8> {
+        (0..client.slot_count()).find(|&slot_number| {
+            let controller_info = client.controller_info(slot_number);
+            controller_info.mac_address == mac_address
+                && controller_info.slot_state == SlotState::Connected
+        })
+    }
+
+    fn is_live(&self, upstream: usize, mac_address: u64) -> bool {
+        let client = &self.upstreams[upstream].client;
+        match self.find_slot(client, mac_address) {
+            Some(slot_number) => client
+                .data_age(slot_number)
+                .map(|age| age < self.silence_timeout)
+                .unwrap_or(false),
+            None => false,
+        }
+    }
+
+    fn best_upstream(&self, mac_address: u64) -> Option<usize> {
+        (0..self.upstreams.len())
+            .filter(|&upstream| self.is_live(upstream, mac_address))
+            // Prefer higher priority, then lower index.
+            .max_by_key(|&upstream| (self.upstreams[upstream].priority, -(upstream as i64)))
+    }
+
+    /// Drains events of all upstreams and re-evaluates which upstream provides each controller.
+    ///
+    /// Only events from upstreams currently providing a controller are passed through.
+    pub fn poll(&mut self) -> Vec<AggregateEvent> {
+        let mut events = vec![];
+
+        let mut mac_addresses: Vec<u64> = self.active.keys().copied().collect();
+        for upstream in self.upstreams.iter() {
+            for slot_number in 0..upstream.client.slot_count() {
+                let controller_info = upstream.client.controller_info(slot_number);
+                if controller_info.mac_address != 0
+                    && !mac_addresses.contains(&controller_info.mac_address)
+                {
+                    mac_addresses.push(controller_info.mac_address);
+                }
+            }
+        }
+
+        for mac_address in mac_addresses {
+            let from = self.active_upstream(mac_address);
+            let to = self.best_upstream(mac_address);
+            if from != to {
+                match to {
+                    Some(to) => self.active.insert(mac_address, to),
+                    None => self.active.remove(&mac_address),
+                };
+
+                events.push(AggregateEvent::Switchover {
+                    mac_address,
+                    from,
+                    to,
+                });
+            }
+        }
+
+        for (index, upstream) in self.upstreams.iter().enumerate() {
+            while let Some(event) = upstream.client.next_event() {
+                let controller_info = match event {
+                    ClientEvent::ControllerInfoChanged(controller_info)
+                    | ClientEvent::ControllerDisconnected(controller_info) => controller_info,
+                    ClientEvent::ControllerDataChanged {
+                        controller_info, ..
+                    } => controller_info,
+                    // Slot changes it reports are also reported by `ControllerInfoChanged` events.
+                    ClientEvent::AllControllersInfo(_) => continue,
+                    // Upstream's controllers go silent, so it stops providing them.
+                    ClientEvent::TransportFailed(_) => continue,
+                    ClientEvent::SlotHealth(slot_health) => {
+                        upstream.client.controller_info(slot_health.slot)
+                    }
+                };
+
+                if self.active.get(&controller_info.mac_address) == Some(&index) {
+                    events.push(AggregateEvent::Upstream {
+                        upstream: index,
+                        event,
+                    });
+                }
+            }
+        }
+
+        events
+    }
+}
@@ -0,0 +1,167 @@
+use crate::protocol::*;
+use std::time::Duration;
+
+#[derive(Copy, Clone, Debug, Default)]
+struct RunningStatistics {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+
+impl RunningStatistics {
+    fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    fn variance(&self) -> f64 {
+        if self.count > 1 {
+            self.m2 / (self.count - 1) as f64
+        } else {
+            0.0
+        }
+    }
+
+    fn standard_deviation(&self) -> f64 {
+        self.variance().sqrt()
+    }
+}
+
+/// Summary of motion stream quality.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MotionReport {
+    /// Amount of analyzed samples.
+    pub sample_count: u64,
+
+    /// Time between first and last analyzed sample.
+    pub duration: Duration,
+
+    /// Average sample rate in Hz.
+    pub sample_rate: f64,
+
+    /// Mean gyroscope rate (pitch, yaw, roll) multiplied by 60, in degrees per minute.
+    /// For a controller lying still this is how far orientation drifts each minute.
+    pub gyroscope_drift_per_minute: [f64; 3],
+
+    /// Gyroscope noise density (pitch, yaw, roll) in deg/s/√Hz.
+    pub gyroscope_noise_density: [f64; 3],
+
+    /// Accelerometer noise density (x, y, z) in g/√Hz.
+    pub accelerometer_noise_density: [f64; 3],
+
+    /// Mean interval between timestamps.
+    pub mean_interval: Duration,
+
+    /// Standard deviation of intervals between timestamps.
+    pub timestamp_jitter: Duration,
+
+    /// Longest interval between timestamps.
+    pub max_interval: Duration,
+}
+
+/// Computes drift, noise and timestamp jitter statistics of a motion stream.
+///
+/// Feed it samples from a recording or from a live client, ideally while controller lies still.
+/// Samples repeating previous timestamp are ignored, so data received multiple times is counted once.
+#[derive(Clone, Debug, Default)]
+pub struct MotionAnalyzer {
+    first_timestamp: Option<u64>,
+    last_timestamp: Option<u64>,
+    max_interval: u64,
+    intervals: RunningStatistics,
+    gyroscope: [RunningStatistics; 3],
+    accelerometer: [RunningStatistics; 3],
+}
+
+impl MotionAnalyzer {
+    /// Creates new, empty analyzer.
+    pub fn new() -> MotionAnalyzer {
+        Default::default()
+    }
+
+    /// Adds sample to the analysis.
+    pub fn push(&mut self, controller_data: &ControllerData) {
+        let timestamp = controller_data.motion_data_timestamp;
+
+        if let Some(last_timestamp) = self.last_timestamp {
+            if timestamp <= last_timestamp {
+                return;
+            }
+
+            let interval = timestamp - last_timestamp;
+            self.intervals.push(interval as f64);
+            self.max_interval = self.max_interval.max(interval);
+        } else {
+            self.first_timestamp = Some(timestamp);
+        }
+        self.last_timestamp = Some(timestamp);
+
+        self.gyroscope[0].push(controller_data.gyroscope_pitch as f64);
+        self.gyroscope[1].push(controller_data.gyroscope_yaw as f64);
+        self.gyroscope[2].push(controller_data.gyroscope_roll as f64);
+
+        self.accelerometer[0].push(controller_data.accelerometer_x as f64);
+        self.accelerometer[1].push(controller_data.accelerometer_y as f64);
+        self.accelerometer[2].push(controller_data.accelerometer_z as f64);
+    }
+
+    /// Returns report for samples analyzed so far or `None` if less than two distinct samples were pushed.
+    pub fn report(&self) -> Option<MotionReport> {
+        let (first_timestamp, last_timestamp) = match (self.first_timestamp, self.last_timestamp) {
+            (Some(first), Some(last)) if last > first => (first, last),
+            _ => return None,
+        };
+
+        let sample_count = self.gyroscope[0].count;
+        let duration_micros = last_timestamp - first_timestamp;
+        let sample_rate = self.intervals.count as f64 / (duration_micros as f64 / 1_000_000.0);
+
+        // Noise spreads over bandwidth up to Nyquist frequency.
+        let bandwidth_root = (sample_rate / 2.0).sqrt();
+        let noise_density =
+            |statistics: &RunningStatistics| statistics.standard_deviation() / bandwidth_root;
+
+        Some(MotionReport {
+            sample_count,
+            duration: Duration::from_micros(duration_micros),
+            sample_rate,
+            gyroscope_drift_per_minute: [
+                self.gyroscope[0].mean * 60.0,
+                self.gyroscope[1].mean * 60.0,
+                self.gyroscope[2].mean * 60.0,
+            ],
+            gyroscope_noise_density: [
+                noise_density(&self.gyroscope[0]),
+                noise_density(&self.gyroscope[1]),
+                noise_density(&self.gyroscope[2]),
+            ],
+            accelerometer_noise_density: [
+                noise_density(&self.accelerometer[0]),
+                noise_density(&self.accelerometer[1]),
+                noise_density(&self.accelerometer[2]),
+            ],
+            mean_interval: Duration::from_secs_f64(self.intervals.mean / 1_000_000.0),
+            timestamp_jitter: Duration::from_secs_f64(
+                self.intervals.standard_deviation() / 1_000_000.0,
+            ),
+            max_interval: Duration::from_micros(self.max_interval),
+        })
+    }
+}
+
+impl MotionReport {
+    /// Analyzes given samples at once.
+    pub fn from_samples<'a, I>(samples: I) -> Option<MotionReport>
+    where
+        I: IntoIterator<Item = &'a ControllerData>,
+    {
+        let mut analyzer = MotionAnalyzer::new();
+        for sample in samples {
+            analyzer.push(sample);
+        }
+
+        analyzer.report()
+    }
+}
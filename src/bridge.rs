@@ -0,0 +1,157 @@
+//! Serving and consuming the protocol at the same time.
+//!
+//! [`Bridge`] forwards everything its client receives from an upstream server into its own server,
+//! which makes it the base of proxies, multiplexers and recorders.
+
+use crate::client::{Client, ClientEvent, ClientStats, DsClient};
+use crate::protocol::*;
+use crate::server::{DsServer, Server, ServerStats};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Combined metrics of bridge's client and server.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BridgeStats {
+    pub client: ClientStats,
+    pub server: ServerStats,
+}
+
+/// Client and server sharing slot state.
+pub struct Bridge {
+    client: Arc<Client>,
+    server: Arc<Server>,
+    running: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+}
+
+const DEFAULT_REQUEST_INTERVAL: Duration = Duration::from_secs(1);
+
+impl Bridge {
+    /// Creates new bridge from client connected to upstream server and server serving downstream clients.
+    pub fn new(client: Client, server: Server) -> Bridge {
+        Bridge {
+            client: Arc::new(client),
+            server: Arc::new(server),
+            running: Arc::new(AtomicBool::new(false)),
+            threads: vec![],
+        }
+    }
+
+    pub fn client(&self) -> &Arc<Client> {
+        &self.client
+    }
+
+    pub fn server(&self) -> &Arc<Server> {
+        &self.server
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Starts client, server and forwarding threads, requesting upstream data every second.
+    pub fn start(&mut self) {
+        self.start_with_request_interval(DEFAULT_REQUEST_INTERVAL);
+    }
+
+    /// Starts client, server and forwarding threads.
+    ///
+    /// # Arguments
+    ///
+    /// * `request_interval` - how often upstream server is asked for controller info and data.
+    pub fn start_with_request_interval(&mut self, request_interval: Duration) {
+        if self.is_running() {
+            return;
+        }
+        self.running.store(true, Ordering::SeqCst);
+
+        self.threads
+            .push(self.client.clone().start(self.running.clone()));
+        self.threads
+            .push(self.server.clone().start(self.running.clone()));
+
+        let client = self.client.clone();
+        let server = self.server.clone();
+        let running = self.running.clone();
+        self.threads.push(std::thread::spawn(move || {
+            let slot_numbers: Vec<u8> = (0..client.slot_count()).collect();
+            let mut last_request: Option<Instant> = None;
+            while running.load(Ordering::SeqCst) {
+                if last_request.is_none_or(|time| time.elapsed() >= request_interval) {
+                    let _ = client.request_connected_controllers_info(&slot_numbers);
+                    let _ = client.request_controller_data(ControllerDataRequest::ReportAll);
+                    last_request = Some(Instant::now());
+                }
+
+                let mut forwarded = false;
+                while let Some(event) = client.next_event() {
+                    forwarded = true;
+                    if event_slot(&event).is_some_and(|slot| slot >= server.slot_count()) {
+                        // Server doesn't have slot upstream server reported.
+                        continue;
+                    }
+                    match event {
+                        ClientEvent::ControllerInfoChanged(controller_info) => {
+                            server.update_controller_info(controller_info)
+                        }
+                        ClientEvent::ControllerDataChanged {
+                            controller_info,
+                            controller_data,
+                        } => server.update_controller_data(controller_info.slot, controller_data),
+                        ClientEvent::ControllerDisconnected(controller_info) => server
+                            .update_controller_info(ControllerInfo {
+                                slot: controller_info.slot,
+                                ..Default::default()
+                            }),
+                        ClientEvent::AllControllersInfo(_) | ClientEvent::TransportFailed(_) => {}
+                        ClientEvent::SlotHealth(slot_health) => {
+                            server.set_driver_status(slot_health.slot, slot_health.driver_status);
+                            server
+                                .set_battery_percent(slot_health.slot, slot_health.battery_percent);
+                        }
+                    }
+                }
+
+                if !forwarded {
+                    std::thread::sleep(Duration::from_millis(1));
+                }
+            }
+        }));
+    }
+
+    /// Stops all threads and waits for them to finish.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+
+    /// Returns snapshot of client and server metrics.
+    pub fn stats(&self) -> BridgeStats {
+        BridgeStats {
+            client: self.client.stats(),
+            server: self.server.stats(),
+        }
+    }
+}
+
+fn event_slot(event: &ClientEvent) -> Option<u8> {
+    match event {
+        ClientEvent::ControllerInfoChanged(controller_info)
+        | ClientEvent::ControllerDataChanged {
+            controller_info, ..
+        }
+        | ClientEvent::ControllerDisconnected(controller_info) => Some(controller_info.slot),
+        ClientEvent::SlotHealth(slot_health) => Some(slot_health.slot),
+        ClientEvent::AllControllersInfo(_) | ClientEvent::TransportFailed(_) => None,
+    }
+}
+
+impl Drop for Bridge {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
@@ -0,0 +1,260 @@
+use crate::protocol::*;
+
+/// Controller button.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Button {
+    DPadLeft,
+    DPadDown,
+    DPadRight,
+    DPadUp,
+    Start,
+    RightStick,
+    LeftStick,
+    Select,
+    Square,
+    Cross,
+    Circle,
+    Triangle,
+    R1,
+    L1,
+    R2,
+    L2,
+    PS,
+    Touch,
+    /// Analog R2 value crossing tracker's trigger threshold.
+    AnalogR2,
+    /// Analog L2 value crossing tracker's trigger threshold.
+    AnalogL2,
+}
+
+impl Button {
+    /// All digital buttons (excluding analog trigger thresholds).
+    pub const DIGITAL: [Button; 18] = [
+        Button::DPadLeft,
+        Button::DPadDown,
+        Button::DPadRight,
+        Button::DPadUp,
+        Button::Start,
+        Button::RightStick,
+        Button::LeftStick,
+        Button::Select,
+        Button::Square,
+        Button::Cross,
+        Button::Circle,
+        Button::Triangle,
+        Button::R1,
+        Button::L1,
+        Button::R2,
+        Button::L2,
+        Button::PS,
+        Button::Touch,
+    ];
+
+    /// Checks whether button is pressed in given controller data.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller_data` - controller state.
+    /// * `trigger_threshold` - analog value at which `AnalogR2`/`AnalogL2` count as pressed.
+    pub fn is_pressed(self, controller_data: &ControllerData, trigger_threshold: u8) -> bool {
+        match self {
+            Button::DPadLeft => controller_data.d_pad_left,
+            Button::DPadDown => controller_data.d_pad_down,
+            Button::DPadRight => controller_data.d_pad_right,
+            Button::DPadUp => controller_data.d_pad_up,
+            Button::Start => controller_data.start,
+            Button::RightStick => controller_data.right_stick_button,
+            Button::LeftStick => controller_data.left_stick_button,
+            Button::Select => controller_data.select,
+            Button::Square => controller_data.square,
+            Button::Cross => controller_data.cross,
+            Button::Circle => controller_data.circle,
+            Button::Triangle => controller_data.triangle,
+            Button::R1 => controller_data.r1,
+            Button::L1 => controller_data.l1,
+            Button::R2 => controller_data.r2,
+            Button::L2 => controller_data.l2,
+            Button::PS => controller_data.ps != 0,
+            Button::Touch => controller_data.touch != 0,
+            Button::AnalogR2 => controller_data.analog_r2 >= trigger_threshold,
+            Button::AnalogL2 => controller_data.analog_l2 >= trigger_threshold,
+        }
+    }
+
+    /// Sets button state in given controller data.
+    /// Analog triggers are set to full (or zero) value.
+    pub fn set(self, controller_data: &mut ControllerData, pressed: bool) {
+        let analog = if pressed { 255 } else { 0 };
+
+        match self {
+            Button::DPadLeft => controller_data.d_pad_left = pressed,
+            Button::DPadDown => controller_data.d_pad_down = pressed,
+            Button::DPadRight => controller_data.d_pad_right = pressed,
+            Button::DPadUp => controller_data.d_pad_up = pressed,
+            Button::Start => controller_data.start = pressed,
+            Button::RightStick => controller_data.right_stick_button = pressed,
+            Button::LeftStick => controller_data.left_stick_button = pressed,
+            Button::Select => controller_data.select = pressed,
+            Button::Square => controller_data.square = pressed,
+            Button::Cross => controller_data.cross = pressed,
+            Button::Circle => controller_data.circle = pressed,
+            Button::Triangle => controller_data.triangle = pressed,
+            Button::R1 => controller_data.r1 = pressed,
+            Button::L1 => controller_data.l1 = pressed,
+            Button::R2 => controller_data.r2 = pressed,
+            Button::L2 => controller_data.l2 = pressed,
+            Button::PS => controller_data.ps = pressed as u8,
+            Button::Touch => controller_data.touch = pressed as u8,
+            Button::AnalogR2 => controller_data.analog_r2 = analog,
+            Button::AnalogL2 => controller_data.analog_l2 = analog,
+        }
+    }
+}
+
+/// Checks whether all digital buttons and touch activity have the same state in both controller data.
+pub fn same_buttons(a: &ControllerData, b: &ControllerData) -> bool {
+    Button::DIGITAL
+        .iter()
+        .all(|button| button.is_pressed(a, 0) == button.is_pressed(b, 0))
+        && a.first_touch.active == b.first_touch.active
+        && a.second_touch.active == b.second_touch.active
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ButtonEdge {
+    Pressed,
+    Released,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ButtonEvent {
+    pub button: Button,
+    pub edge: ButtonEdge,
+}
+
+const ALL_BUTTONS: [Button; 20] = [
+    Button::DPadLeft,
+    Button::DPadDown,
+    Button::DPadRight,
+    Button::DPadUp,
+    Button::Start,
+    Button::RightStick,
+    Button::LeftStick,
+    Button::Select,
+    Button::Square,
+    Button::Cross,
+    Button::Circle,
+    Button::Triangle,
+    Button::R1,
+    Button::L1,
+    Button::R2,
+    Button::L2,
+    Button::PS,
+    Button::Touch,
+    Button::AnalogR2,
+    Button::AnalogL2,
+];
+
+const DEFAULT_TRIGGER_THRESHOLD: u8 = 128;
+
+/// Turns successive controller data into button press/release events.
+#[derive(Copy, Clone, Debug)]
+pub struct ButtonTracker {
+    previous: ControllerData,
+    trigger_threshold: u8,
+}
+
+impl Default for ButtonTracker {
+    fn default() -> ButtonTracker {
+        ButtonTracker::new()
+    }
+}
+
+impl ButtonTracker {
+    /// Creates new tracker, all buttons start released.
+    pub fn new() -> ButtonTracker {
+        ButtonTracker {
+            previous: Default::default(),
+            trigger_threshold: DEFAULT_TRIGGER_THRESHOLD,
+        }
+    }
+
+    /// Creates new tracker with custom analog trigger threshold (default is 128).
+    pub fn with_trigger_threshold(trigger_threshold: u8) -> ButtonTracker {
+        ButtonTracker {
+            previous: Default::default(),
+            trigger_threshold: trigger_threshold.max(1),
+        }
+    }
+
+    /// Checks whether button was pressed in the latest controller data.
+    pub fn is_pressed(&self, button: Button) -> bool {
+        button.is_pressed(&self.previous, self.trigger_threshold)
+    }
+
+    /// Consumes next controller data, appending edges relative to previous data to `events`.
+    pub fn update_into(&mut self, controller_data: &ControllerData, events: &mut Vec<ButtonEvent>) {
+        for &button in ALL_BUTTONS.iter() {
+            let was_pressed = button.is_pressed(&self.previous, self.trigger_threshold);
+            let is_pressed = button.is_pressed(controller_data, self.trigger_threshold);
+
+            if was_pressed != is_pressed {
+                let edge = if is_pressed {
+                    ButtonEdge::Pressed
+                } else {
+                    ButtonEdge::Released
+                };
+
+                events.push(ButtonEvent { button, edge });
+            }
+        }
+
+        self.previous = *controller_data;
+    }
+
+    /// Consumes next controller data, returning edges relative to previous data.
+    pub fn update(&mut self, controller_data: &ControllerData) -> Vec<ButtonEvent> {
+        let mut events = vec![];
+        self.update_into(controller_data, &mut events);
+
+        events
+    }
+}
+
+/// Converts analog value into digital state with separate press and release thresholds,
+/// so noisy values around a single threshold don't cause rapid toggling.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Hysteresis {
+    /// Value at or above which state becomes pressed.
+    pub press_threshold: u8,
+    /// Value at or below which state becomes released.
+    pub release_threshold: u8,
+    pressed: bool,
+}
+
+impl Hysteresis {
+    pub fn new(press_threshold: u8, release_threshold: u8) -> Hysteresis {
+        assert!(release_threshold < press_threshold);
+
+        Hysteresis {
+            press_threshold,
+            release_threshold,
+            pressed: false,
+        }
+    }
+
+    pub fn is_pressed(&self) -> bool {
+        self.pressed
+    }
+
+    /// Consumes next analog value, returns resulting digital state.
+    pub fn update(&mut self, value: u8) -> bool {
+        if value >= self.press_threshold {
+            self.pressed = true;
+        } else if value <= self.release_threshold {
+            self.pressed = false;
+        }
+
+        self.pressed
+    }
+}
@@ -1,20 +1,31 @@
+use crate::buttons::same_buttons;
+#[cfg(feature = "compression")]
+use crate::compression::*;
 use crate::protocol::*;
+use crate::rumble::{LARGE_MOTOR, SMALL_MOTOR};
+use crate::state::ClientIdentity;
+use crate::transform::Transform;
+use crate::transport::*;
 use crossbeam_queue::ArrayQueue;
 use rand::Rng;
-use std::io::Result;
-use std::net::SocketAddr;
-use std::net::UdpSocket;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 #[derive(Copy, Clone, Debug, Default)]
 struct Slot {
     controller_info: ControllerInfo,
     controller_data: ControllerData,
-    latest_packet_number: u32,
+    latest_packet_number: Option<u32>,
+    latest_data_time: Option<Instant>,
+    health: Option<SlotHealth>,
 }
 
 #[derive(Copy, Clone, Debug)]
@@ -24,46 +35,390 @@ pub enum ClientEvent {
         controller_info: ControllerInfo,
         controller_data: ControllerData,
     },
+    /// Server reported previously connected slot as not connected, cached controller data of the slot was cleared.
+    /// Contains controller info from before disconnection.
+    ControllerDisconnected(ControllerInfo),
+    /// Responses to controller info request, emitted once all requested slots have reported
+    /// or `info_timeout` elapsed (with `None` for slots that didn't report or weren't requested).
+    ///
+    /// Indexed by slot number, covers `MAX_SLOT_COUNT` slots so extended clients get all of theirs.
+    AllControllersInfo([Option<ControllerInfo>; MAX_SLOT_COUNT as usize]),
+    /// Slot health published by server supporting health extension.
+    SlotHealth(SlotHealth),
+    /// Client's transport failed with fatal error and client's thread stopped, see `Client::take_terminal_error`.
+    TransportFailed(ErrorKind),
+}
+
+/// Controller info request waiting for responses.
+#[derive(Copy, Clone, Debug)]
+struct PendingInfo {
+    requested: [bool; MAX_SLOT_COUNT as usize],
+    received: [Option<ControllerInfo>; MAX_SLOT_COUNT as usize],
+    request_time: Instant,
 }
 
+impl PendingInfo {
+    fn is_complete(&self) -> bool {
+        self.requested
+            .iter()
+            .zip(self.received.iter())
+            .all(|(&requested, received)| !requested || received.is_some())
+    }
+}
+
+/// Client metrics snapshot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ClientStats {
+    pub received_packets: u64,
+    pub invalid_packets: u64,
+    /// Packets claiming length larger than maximum message length, counted as invalid as well.
+    pub oversize_packets: u64,
+    pub data_packets: u64,
+    /// Controller data packets received without a controller data request in preceding `PUSH_DETECTION_WINDOW`.
+    pub pushed_packets: u64,
+    /// Whether server was detected sending data on its own, without requests.
+    pub push_mode: bool,
+    /// Controller data packets with implausible motion values (rejected or clamped, see `Client::set_motion_validation`).
+    pub invalid_motion_packets: u64,
+}
+
+/// Servers stop sending data when client doesn't renew its request for this long,
+/// data received later than that is considered pushed.
+pub const PUSH_DETECTION_WINDOW: Duration = Duration::from_secs(5);
+
+/// Packet number dropping by more than this is treated as server restart instead of out-of-order packet.
+const PACKET_NUMBER_RESET_THRESHOLD: u32 = 1024;
+
+/// Default time to wait for all responses to controller info request.
+pub const DEFAULT_INFO_TIMEOUT: Duration = Duration::from_secs(1);
+
 const DEFAULT_PORT: u16 = 3333;
 const DEFAULT_SERVER_PORT: u16 = 26760;
 
+thread_local! {
+    /// Datagrams sent during `Client::craft_requests` call on this thread.
+    static CAPTURED_REQUESTS: RefCell<Option<Vec<Vec<u8>>>> = const { RefCell::new(None) };
+}
+
+/// Default amount of events client queues before dropping new ones.
+pub const DEFAULT_EVENT_QUEUE_CAPACITY: usize = 50;
+
 pub trait DsClient {
     /// Starts background client thread.
     fn start(self, countinue_running: Arc<AtomicBool>) -> JoinHandle<()>;
 
+    /// Returns number of controller slots (`SLOT_COUNT` unless client uses extended mode).
+    fn slot_count(&self) -> u8 {
+        SLOT_COUNT
+    }
+
     /// Gets currently cached controller info for given slot number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `get_controller_info`.
     fn controller_info(&self, slot_number: u8) -> ControllerInfo;
 
     /// Gets currently cached controller data for given slot number.
+    /// Returns neutral (default) data if data is older than TTL set with `set_data_ttl`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `get_controller_data`.
     fn controller_data(&self, slot_number: u8) -> ControllerData;
 
+    /// Same as `controller_info`, but takes validated slot index.
+    fn slot_info(&self, slot_index: SlotIndex) -> ControllerInfo {
+        self.controller_info(slot_index.get())
+    }
+
+    /// Same as `controller_data`, but takes validated slot index.
+    fn slot_data(&self, slot_index: SlotIndex) -> ControllerData {
+        self.controller_data(slot_index.get())
+    }
+
+    /// Same as `controller_info`, but returns `None` on invalid slot number.
+    fn get_controller_info(&self, slot_number: u8) -> Option<ControllerInfo> {
+        check_slot_number_in(slot_number, self.slot_count()).ok()?;
+
+        Some(self.controller_info(slot_number))
+    }
+
+    /// Same as `controller_data`, but returns `None` on invalid slot number.
+    fn get_controller_data(&self, slot_number: u8) -> Option<ControllerData> {
+        check_slot_number_in(slot_number, self.slot_count()).ok()?;
+
+        Some(self.controller_data(slot_number))
+    }
+
     /// Returns next event in event queue or `None` if empty.
     fn next_event(&self) -> Option<ClientEvent>;
 }
 
 pub struct Client {
     server_address: SocketAddr,
-    message_header: MessageHeader,
-    slots: Mutex<[Slot; 4]>,
-    socket: UdpSocket,
+    source_id: AtomicU32,
+    slot_count: u8,
+    slots: Mutex<Vec<Slot>>,
+    socket: Box<dyn Transport>,
+    tap: Mutex<Option<DatagramTap>>,
     events: ArrayQueue<ClientEvent>,
+    coalesce_events: AtomicBool,
+    pending_events: Mutex<Vec<Option<ClientEvent>>>,
+    slot_remap: Mutex<Vec<u8>>,
+    last_data_request_time: Mutex<Option<Instant>>,
+    /// Distinct controller data requests sent so far, repeated by keepalive.
+    data_requests: Mutex<Vec<ControllerDataRequest>>,
+    keepalive_interval: Mutex<Option<Duration>>,
+    pending_info: Mutex<Option<PendingInfo>>,
+    info_timeout: Mutex<Duration>,
+    data_ttl: Mutex<Option<Duration>>,
+    motion_validation: Mutex<MotionValidation>,
+    transforms: Mutex<Vec<Option<Box<dyn Transform>>>>,
+    received_packets: AtomicU64,
+    invalid_packets: AtomicU64,
+    oversize_packets: AtomicU64,
+    receive_buffer_size: AtomicUsize,
+    max_message_length: AtomicUsize,
+    data_packets: AtomicU64,
+    pushed_packets: AtomicU64,
+    verify_checksums: AtomicBool,
+    invalid_motion_packets: AtomicU64,
+    terminal_error: Mutex<Option<Error>>,
+    #[cfg(feature = "compression")]
+    compression_requested: AtomicBool,
+    #[cfg(feature = "compression")]
+    compression_negotiated: AtomicBool,
+}
+
+/// Builder of `Client` with socket and behavior options, see `Client::builder`.
+#[derive(Clone, Debug)]
+pub struct ClientBuilder {
+    id: Option<u32>,
+    address: SocketAddr,
+    server_address: SocketAddr,
+    udp_options: UdpOptions,
+    event_queue_capacity: usize,
+    verify_checksums: bool,
+    slot_count: u8,
+    keepalive_interval: Option<Duration>,
+}
+
+impl Default for ClientBuilder {
+    fn default() -> ClientBuilder {
+        ClientBuilder {
+            id: None,
+            address: SocketAddr::from(([127, 0, 0, 1], 0)),
+            server_address: SocketAddr::from(([127, 0, 0, 1], DEFAULT_SERVER_PORT)),
+            udp_options: Default::default(),
+            event_queue_capacity: DEFAULT_EVENT_QUEUE_CAPACITY,
+            verify_checksums: true,
+            slot_count: SLOT_COUNT,
+            keepalive_interval: None,
+        }
+    }
+}
+
+impl ClientBuilder {
+    /// Client ID (random by default).
+    pub fn with_id(mut self, id: u32) -> ClientBuilder {
+        self.id = Some(id);
+        self
+    }
+
+    /// Client's UDP socket address (`127.0.0.1:0` by default, port 0 lets OS pick free port, see `Client::local_addr`).
+    pub fn with_address(mut self, address: SocketAddr) -> ClientBuilder {
+        self.address = address;
+        self
+    }
+
+    /// Server's UDP socket address (`127.0.0.1:26760` by default).
+    pub fn with_server_address(mut self, server_address: SocketAddr) -> ClientBuilder {
+        self.server_address = server_address;
+        self
+    }
+
+    /// Timeout of blocking receive (default: `READ_TIMEOUT`), client thread stops at most this long
+    /// after it's asked to.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> ClientBuilder {
+        self.udp_options.read_timeout = read_timeout;
+        self
+    }
+
+    /// Timeout of blocking send (default: `READ_TIMEOUT`), pass `None` to block until datagram is sent.
+    pub fn with_write_timeout(mut self, write_timeout: Option<Duration>) -> ClientBuilder {
+        self.udp_options.write_timeout = write_timeout;
+        self
+    }
+
+    /// Amount of events client queues before dropping new ones (default: `DEFAULT_EVENT_QUEUE_CAPACITY`).
+    pub fn with_event_queue_capacity(mut self, event_queue_capacity: usize) -> ClientBuilder {
+        self.event_queue_capacity = event_queue_capacity;
+        self
+    }
+
+    /// See `Client::set_verify_checksums`.
+    pub fn with_verify_checksums(mut self, verify_checksums: bool) -> ClientBuilder {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    /// Number of controller slots (`SLOT_COUNT` by default), up to `MAX_SLOT_COUNT`.
+    ///
+    /// Standard DSU servers only have 4 slots, use more only with servers supporting extended mode.
+    pub fn with_slot_count(mut self, slot_count: u8) -> ClientBuilder {
+        self.slot_count = slot_count;
+        self
+    }
+
+    /// See `Client::set_keepalive_interval`.
+    pub fn with_keepalive_interval(
+        mut self,
+        keepalive_interval: Option<Duration>,
+    ) -> ClientBuilder {
+        self.keepalive_interval = keepalive_interval;
+        self
+    }
+
+    /// Binds client's socket and creates client.
+    ///
+    /// Fails with error wrapping [`PortInUse`] if address is already bound
+    /// and with `ErrorKind::InvalidInput` error if event queue capacity is zero or slot count is invalid.
+    pub fn build(self) -> Result<Client> {
+        self.check()?;
+        let socket = bind_udp_with(self.address, &self.udp_options)?;
+        self.build_with_transport(Box::new(socket))
+    }
+
+    /// Creates client using given transport, address and UDP options are ignored.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` error if event queue capacity is zero or slot count is invalid.
+    pub fn build_with_transport(self, socket: Box<dyn Transport>) -> Result<Client> {
+        self.check()?;
+        let client = Client::with_options(
+            self.id,
+            socket,
+            Some(self.server_address),
+            self.event_queue_capacity,
+            self.slot_count,
+        );
+        client.set_verify_checksums(self.verify_checksums);
+        client.set_keepalive_interval(self.keepalive_interval);
+
+        Ok(client)
+    }
+
+    fn check(&self) -> Result<()> {
+        if self.event_queue_capacity == 0 {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Event queue capacity must not be zero",
+            ));
+        }
+        check_slot_count(self.slot_count)?;
+
+        Ok(())
+    }
 }
 
 impl Client {
+    /// Returns builder of client with socket and behavior options.
+    pub fn builder() -> ClientBuilder {
+        Default::default()
+    }
+
     /// Creates new client.
     ///
     /// # Arguments
     ///
     /// * `id` - client ID, pass `None` to use a random number.
-    /// * `address` - client's UDP socket address, if `None` is passed `127.0.0.1:3333` is used.
+    /// * `address` - client's UDP socket address, if `None` is passed client binds `127.0.0.1` on port chosen by OS
+    ///   (see `local_addr`).
     /// * `server_address` - server's UDP socket address, the default (if `None` is passed) is `127.0.0.1:267601`.
+    ///
+    /// Fails with error wrapping [`PortInUse`] if address is already bound, see `bind_available`.
     pub fn new(
         id: Option<u32>,
         address: Option<SocketAddr>,
         server_address: Option<SocketAddr>,
     ) -> Result<Client> {
+        let client_address = match address {
+            Some(address) => address,
+            None => SocketAddr::from(([127, 0, 0, 1], 0)),
+        };
+        let socket = bind_udp(client_address)?;
+
+        Ok(Client::with_transport(id, Box::new(socket), server_address))
+    }
+
+    /// Creates new client bound to first free port starting from given address' port,
+    /// so multiple instances can run side by side.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - client ID, pass `None` to use a random number.
+    /// * `address` - client's preferred UDP socket address, if `None` is passed `127.0.0.1:3333` is used.
+    /// * `attempts` - number of consecutive ports to try.
+    /// * `server_address` - server's UDP socket address, the default (if `None` is passed) is `127.0.0.1:267601`.
+    ///
+    /// Fails with error wrapping [`PortInUse`] for the last port tried if all of them are taken.
+    pub fn bind_available(
+        id: Option<u32>,
+        address: Option<SocketAddr>,
+        attempts: u16,
+        server_address: Option<SocketAddr>,
+    ) -> Result<Client> {
+        let mut client_address = match address {
+            Some(address) => address,
+            None => SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)),
+        };
+
+        let mut attempt = 1;
+        loop {
+            match bind_udp(client_address) {
+                Ok(socket) => {
+                    return Ok(Client::with_transport(id, Box::new(socket), server_address))
+                }
+                Err(error) if is_port_in_use_error(&error) && attempt < attempts => {
+                    match client_address.port().checked_add(1) {
+                        Some(port) => client_address.set_port(port),
+                        None => return Err(error),
+                    }
+                    attempt += 1;
+                }
+                Err(error) => return Err(error),
+            }
+        }
+    }
+
+    /// Creates new client using given transport instead of UDP socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - client ID, pass `None` to use a random number.
+    /// * `socket` - transport to communicate with server over.
+    /// * `server_address` - server's address, the default (if `None` is passed) is `127.0.0.1:267601`.
+    pub fn with_transport(
+        id: Option<u32>,
+        socket: Box<dyn Transport>,
+        server_address: Option<SocketAddr>,
+    ) -> Client {
+        Client::with_options(
+            id,
+            socket,
+            server_address,
+            DEFAULT_EVENT_QUEUE_CAPACITY,
+            SLOT_COUNT,
+        )
+    }
+
+    fn with_options(
+        id: Option<u32>,
+        socket: Box<dyn Transport>,
+        server_address: Option<SocketAddr>,
+        event_queue_capacity: usize,
+        slot_count: u8,
+    ) -> Client {
         let mut rng = rand::thread_rng();
 
         let client_id = match id {
@@ -71,18 +426,8 @@ impl Client {
             None => rng.gen(),
         };
 
-        let message_header = {
-            MessageHeader {
-                source: MessageSource::Client,
-                protocol_version: PROTOCOL_VERSION,
-                message_length: 0,
-                checksum: 0,
-                source_id: client_id,
-            }
-        };
-
         let slots = {
-            let mut slots: [Slot; 4] = [Default::default(); 4];
+            let mut slots: Vec<Slot> = vec![Default::default(); slot_count as usize];
             for (i, slot) in slots.iter_mut().enumerate() {
                 slot.controller_info.slot = i as u8;
             }
@@ -90,96 +435,806 @@ impl Client {
             Mutex::new(slots)
         };
 
-        let client_address = match address {
-            Some(address) => address,
-            None => SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)),
-        };
-
         let server_address = match server_address {
             Some(address) => address,
             None => SocketAddr::from(([127, 0, 0, 1], DEFAULT_SERVER_PORT)),
         };
-        let socket = UdpSocket::bind(client_address)?;
-        socket.set_read_timeout(Some(Duration::from_secs_f64(0.2)))?;
-        socket.set_write_timeout(Some(Duration::from_secs_f64(0.2)))?;
-
-        let events = ArrayQueue::new(50);
+        let events = ArrayQueue::new(event_queue_capacity);
 
-        Ok(Client {
+        Client {
             server_address,
-            message_header,
+            source_id: AtomicU32::new(client_id),
+            slot_count,
             slots,
             socket,
+            tap: Mutex::new(None),
             events,
+            coalesce_events: AtomicBool::new(false),
+            pending_events: Mutex::new(vec![None; slot_count as usize]),
+            slot_remap: Mutex::new((0..slot_count).collect()),
+            last_data_request_time: Mutex::new(None),
+            data_requests: Mutex::new(vec![]),
+            keepalive_interval: Mutex::new(None),
+            pending_info: Mutex::new(None),
+            info_timeout: Mutex::new(DEFAULT_INFO_TIMEOUT),
+            data_ttl: Mutex::new(None),
+            motion_validation: Mutex::new(MotionValidation::Disabled),
+            transforms: Mutex::new((0..slot_count).map(|_| None).collect()),
+            received_packets: AtomicU64::new(0),
+            invalid_packets: AtomicU64::new(0),
+            oversize_packets: AtomicU64::new(0),
+            receive_buffer_size: AtomicUsize::new(DEFAULT_RECEIVE_BUFFER_SIZE),
+            max_message_length: AtomicUsize::new(DEFAULT_RECEIVE_BUFFER_SIZE),
+            data_packets: AtomicU64::new(0),
+            pushed_packets: AtomicU64::new(0),
+            verify_checksums: AtomicBool::new(true),
+            invalid_motion_packets: AtomicU64::new(0),
+            terminal_error: Mutex::new(None),
+            #[cfg(feature = "compression")]
+            compression_requested: AtomicBool::new(false),
+            #[cfg(feature = "compression")]
+            compression_negotiated: AtomicBool::new(false),
+        }
+    }
+
+    /// Returns client's local socket address.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Sends empty datagram to client's own socket, so blocked receive returns immediately.
+    fn wake(&self) -> Result<()> {
+        let mut address = self.socket.local_addr()?;
+        if address.ip().is_unspecified() {
+            let loopback = match address {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            };
+            address.set_ip(loopback);
+        }
+
+        self.socket.send_to(&[], address)?;
+        Ok(())
+    }
+
+    /// Returns client ID used in message headers.
+    pub fn id(&self) -> u32 {
+        self.source_id.load(Ordering::SeqCst)
+    }
+
+    /// Changes client ID used in message headers, takes effect with the next sent message.
+    pub fn set_id(&self, id: u32) {
+        self.source_id.store(id, Ordering::SeqCst);
+    }
+
+    /// Persists client ID in given file.
+    ///
+    /// If file exists client takes ID from it, otherwise file is created with current ID,
+    /// so servers tracking client identity recognize it after restart.
+    pub fn set_state_file<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+        if path.exists() {
+            self.set_id(ClientIdentity::load(path)?.client_id);
+
+            Ok(())
+        } else {
+            ClientIdentity {
+                client_id: self.id(),
+            }
+            .save(path)
+        }
+    }
+
+    fn message_header(&self) -> MessageHeader {
+        MessageHeader {
+            source: MessageSource::Client,
+            protocol_version: PROTOCOL_VERSION,
+            message_length: 0,
+            checksum: 0,
+            source_id: self.id(),
+        }
+    }
+
+    /// Returns time elapsed since controller data for given slot was last received
+    /// or `None` if no data was received for it yet.
+    pub fn data_age(&self, slot_number: u8) -> Option<Duration> {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize]
+            .latest_data_time
+            .map(|time| time.elapsed())
+    }
+
+    /// Gets currently cached controller data of all slots (see `ClientBuilder::with_slot_count`) at once,
+    /// as they were at the same moment.
+    /// Same as calling `controller_data` for each slot, but without updates arriving in between.
+    pub fn snapshot(&self) -> Vec<ControllerData> {
+        let slots = self.slots.lock().unwrap().clone();
+
+        slots.iter().map(|slot| self.live_data(slot)).collect()
+    }
+
+    /// Returns slot's controller data, or neutral data if it's older than TTL.
+    fn live_data(&self, slot: &Slot) -> ControllerData {
+        let data_ttl = *self.data_ttl.lock().unwrap();
+        let expired = data_ttl.is_some_and(|data_ttl| {
+            slot.latest_data_time
+                .is_none_or(|time| time.elapsed() > data_ttl)
+        });
+        if expired {
+            Default::default()
+        } else {
+            slot.controller_data
+        }
+    }
+
+    /// Sets transform applied to controller data received for given (local) slot
+    /// before it's cached and reported in events, pass `None` to remove it.
+    pub fn set_transform(&self, slot_number: u8, transform: Option<Box<dyn Transform>>) {
+        assert!(slot_number < self.slot_count);
+
+        self.transforms.lock().unwrap()[slot_number as usize] = transform;
+    }
+
+    /// Same as `set_transform`, but takes validated slot index.
+    pub fn set_slot_transform(&self, slot_index: SlotIndex, transform: Option<Box<dyn Transform>>) {
+        self.set_transform(slot_index.get(), transform);
+    }
+
+    /// Sets how long cached controller data stays valid without being refreshed, pass `None` to keep it forever (default).
+    ///
+    /// Expired slot's data reads as neutral (default) data, so consumers don't act on outdated motion.
+    pub fn set_data_ttl(&self, data_ttl: Option<Duration>) {
+        *self.data_ttl.lock().unwrap() = data_ttl;
+    }
+
+    /// Sets how received controller data with NaN, infinite or otherwise implausible motion values is handled
+    /// (disabled by default), see `ControllerData::has_valid_motion`.
+    ///
+    /// Clamped implausible timestamp is replaced with timestamp of slot's cached data.
+    pub fn set_motion_validation(&self, motion_validation: MotionValidation) {
+        *self.motion_validation.lock().unwrap() = motion_validation;
+    }
+
+    /// Enables or disables verification of checksums of received messages (enabled by default).
+    ///
+    /// Messages with incorrect checksum are counted as invalid and dropped.
+    pub fn set_verify_checksums(&self, verify_checksums: bool) {
+        self.verify_checksums
+            .store(verify_checksums, Ordering::SeqCst);
+    }
+
+    /// Returns snapshot of client metrics.
+    pub fn stats(&self) -> ClientStats {
+        let pushed_packets = self.pushed_packets.load(Ordering::Relaxed);
+
+        ClientStats {
+            received_packets: self.received_packets.load(Ordering::Relaxed),
+            invalid_packets: self.invalid_packets.load(Ordering::Relaxed),
+            oversize_packets: self.oversize_packets.load(Ordering::Relaxed),
+            data_packets: self.data_packets.load(Ordering::Relaxed),
+            pushed_packets,
+            push_mode: pushed_packets > 0,
+            invalid_motion_packets: self.invalid_motion_packets.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Presents server's slots under different local slot numbers.
+    ///
+    /// `slot_remap[upstream_slot]` is the local slot number server's slot appears as,
+    /// e.g. `[2, 3, 0, 1]` makes server's slot 2 appear as local slot 0.
+    /// Slot numbers passed to requests and returned in events and getters are local.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `slot_remap` is not a permutation of slot numbers `0..slot_count`.
+    pub fn set_slot_remap<R: AsRef<[u8]>>(&self, slot_remap: R) {
+        let slot_remap = slot_remap.as_ref();
+        assert!(slot_remap.len() == self.slot_count as usize);
+
+        let mut seen = vec![false; slot_remap.len()];
+        for &local_slot in slot_remap.iter() {
+            assert!(local_slot < self.slot_count && !seen[local_slot as usize]);
+            seen[local_slot as usize] = true;
+        }
+
+        *self.slot_remap.lock().unwrap() = slot_remap.to_vec();
+    }
+
+    /// Returns number of controller slots, see `ClientBuilder::with_slot_count`.
+    pub fn slot_count(&self) -> u8 {
+        self.slot_count
+    }
+
+    /// Returns indices of all client's slots in order.
+    pub fn slots(&self) -> impl Iterator<Item = SlotIndex> {
+        SlotIndex::all_in(self.slot_count)
+    }
+
+    fn local_slot(&self, upstream_slot: u8) -> Option<u8> {
+        self.slot_remap
+            .lock()
+            .unwrap()
+            .get(upstream_slot as usize)
+            .copied()
+    }
+
+    fn upstream_slot(&self, local_slot: u8) -> u8 {
+        self.slot_remap
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|&slot| slot == local_slot)
+            .map(|slot| slot as u8)
+            .unwrap_or(local_slot)
+    }
+
+    /// Enables or disables event coalescing (disabled by default).
+    ///
+    /// When enabled, consecutive `ControllerDataChanged` events for the same slot are merged into the latest one
+    /// until they are taken with `next_event`. Events differing in button state are never merged,
+    /// so no press or release is lost, and merged event is delivered before any later event of its slot.
+    pub fn set_event_coalescing(&self, enabled: bool) {
+        self.coalesce_events.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.flush_pending_events();
+        }
+    }
+
+    fn flush_pending_events(&self) {
+        let mut pending_events = self.pending_events.lock().unwrap();
+        for pending_event in pending_events.iter_mut() {
+            if let Some(event) = pending_event.take() {
+                let _ = self.events.push(event);
+            }
+        }
+    }
+
+    /// Sets how long to wait for all responses to controller info request
+    /// before emitting `AllControllersInfo` with slots that reported so far (default: `DEFAULT_INFO_TIMEOUT`).
+    pub fn set_info_timeout(&self, info_timeout: Duration) {
+        *self.info_timeout.lock().unwrap() = info_timeout;
+    }
+
+    /// Returns latest health of given slot published by server, `None` if server doesn't publish health.
+    pub fn slot_health(&self, slot_number: u8) -> Option<SlotHealth> {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize].health
+    }
+
+    /// Sets size of buffer datagrams are received into (default: `DEFAULT_RECEIVE_BUFFER_SIZE`).
+    ///
+    /// Datagrams claiming to be longer than buffer are rejected instead of being parsed truncated.
+    pub fn set_receive_buffer_size(&self, receive_buffer_size: usize) {
+        assert!(receive_buffer_size >= HEADER_LENGTH);
+
+        self.receive_buffer_size
+            .store(receive_buffer_size, Ordering::SeqCst);
+    }
+
+    /// Sets maximum accepted message length, including header (default: `DEFAULT_RECEIVE_BUFFER_SIZE`).
+    pub fn set_max_message_length(&self, max_message_length: usize) {
+        self.max_message_length
+            .store(max_message_length, Ordering::SeqCst);
+    }
+
+    fn receive_pending_info(&self, controller_info: ControllerInfo) {
+        let mut pending_info = self.pending_info.lock().unwrap();
+        if let Some(pending) = pending_info.as_mut() {
+            pending.received[controller_info.slot as usize] = Some(controller_info);
+            if pending.is_complete() {
+                let received = pending.received;
+                *pending_info = None;
+                drop(pending_info);
+
+                self.push_event(ClientEvent::AllControllersInfo(received));
+            }
+        }
+    }
+
+    /// Sets how often client repeats its controller data requests, pass `None` to disable it (default).
+    ///
+    /// Every distinct request (for each slot number, MAC address or all slots) sent with `request_controller_data`
+    /// is repeated until `clear_data_requests` is called.
+    ///
+    /// Servers stop sending data to clients not renewing their requests (see `PUSH_DETECTION_WINDOW`),
+    /// with keepalive enabled `request_controller_data` only needs to be called once.
+    pub fn set_keepalive_interval(&self, keepalive_interval: Option<Duration>) {
+        *self.keepalive_interval.lock().unwrap() = keepalive_interval;
+    }
+
+    /// Runs timer driven work (emitting `AllControllersInfo` once info timeout elapses,
+    /// repeating controller data requests once keepalive interval elapses).
+    ///
+    /// Client's thread calls it after every received datagram and whenever receive times out,
+    /// event loops driving client without starting its thread should call it by `next_timer` at the latest.
+    pub fn poll_timers(&self) {
+        self.check_info_timeout();
+        self.check_keepalive();
+    }
+
+    /// Takes fatal transport error which stopped client's thread, `None` if thread didn't stop because of one.
+    ///
+    /// Transient errors (see `error_severity`) are retried by client's thread and never returned.
+    pub fn take_terminal_error(&self) -> Option<Error> {
+        self.terminal_error.lock().unwrap().take()
+    }
+
+    /// Returns when `poll_timers` should be called next.
+    pub fn next_timer(&self) -> Instant {
+        let now = Instant::now();
+        let info_timeout = *self.info_timeout.lock().unwrap();
+
+        let next_timer = match *self.pending_info.lock().unwrap() {
+            Some(pending) => (pending.request_time + info_timeout).max(now),
+            None => now + READ_TIMEOUT,
+        };
+
+        match self.keepalive_deadline() {
+            Some(deadline) => next_timer.min(deadline.max(now)),
+            None => next_timer,
+        }
+    }
+
+    fn keepalive_deadline(&self) -> Option<Instant> {
+        let keepalive_interval = (*self.keepalive_interval.lock().unwrap())?;
+        if self.data_requests.lock().unwrap().is_empty() {
+            return None;
+        }
+
+        self.last_data_request_time
+            .lock()
+            .unwrap()
+            .map(|time| time + keepalive_interval)
+    }
+
+    fn check_keepalive(&self) {
+        if self
+            .keepalive_deadline()
+            .is_some_and(|deadline| deadline <= Instant::now())
+        {
+            let requests = self.data_requests.lock().unwrap().clone();
+            for request in requests {
+                // Failures are transient or stop client's thread, next request is sent one interval later either way.
+                if self.send_data_request(request).is_err() {
+                    *self.last_data_request_time.lock().unwrap() = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    fn check_info_timeout(&self) {
+        let info_timeout = *self.info_timeout.lock().unwrap();
+
+        let mut pending_info = self.pending_info.lock().unwrap();
+        if let Some(pending) = *pending_info {
+            if pending.request_time.elapsed() >= info_timeout {
+                *pending_info = None;
+                drop(pending_info);
+
+                self.push_event(ClientEvent::AllControllersInfo(pending.received));
+            }
+        }
+    }
+
+    fn push_event(&self, event: ClientEvent) {
+        if !self.coalesce_events.load(Ordering::SeqCst) {
+            let _ = self.events.push(event);
+            return;
+        }
+
+        let mut pending_events = self.pending_events.lock().unwrap();
+        match event {
+            ClientEvent::ControllerDataChanged {
+                controller_info,
+                controller_data,
+            } => {
+                let pending_event = &mut pending_events[controller_info.slot as usize];
+                if let Some(ClientEvent::ControllerDataChanged {
+                    controller_data: pending_data,
+                    ..
+                }) = pending_event
+                {
+                    if !same_buttons(pending_data, &controller_data) {
+                        let _ = self.events.push(pending_event.take().unwrap());
+                    }
+                }
+
+                *pending_event = Some(event);
+            }
+            _ => {
+                // Pending data is older than any later event, so it's queued first.
+                let slot = match &event {
+                    ClientEvent::ControllerInfoChanged(controller_info)
+                    | ClientEvent::ControllerDisconnected(controller_info) => {
+                        Some(controller_info.slot)
+                    }
+                    ClientEvent::SlotHealth(health) => Some(health.slot),
+                    _ => None,
+                };
+                for (pending_slot, pending_event) in pending_events.iter_mut().enumerate() {
+                    if slot.is_none_or(|slot| slot as usize == pending_slot) {
+                        if let Some(pending_event) = pending_event.take() {
+                            let _ = self.events.push(pending_event);
+                        }
+                    }
+                }
+
+                let _ = self.events.push(event);
+            }
+        }
+    }
+
+    fn pop_event(&self) -> Option<ClientEvent> {
+        self.events.pop().or_else(|| {
+            let mut pending_events = self.pending_events.lock().unwrap();
+            pending_events
+                .iter_mut()
+                .find_map(|pending_event| pending_event.take())
         })
     }
 
+    /// Moves all currently queued events to the end of `events` in one call, returns number of events moved.
+    pub fn drain_events(&self, events: &mut Vec<ClientEvent>) -> usize {
+        let length = events.len();
+        while let Some(event) = self.events.pop() {
+            events.push(event);
+        }
+        events.extend(
+            self.pending_events
+                .lock()
+                .unwrap()
+                .iter_mut()
+                .filter_map(|pending_event| pending_event.take()),
+        );
+
+        events.len() - length
+    }
+
+    /// Returns iterator popping queued events, ends once event queue is empty.
+    pub fn events(&self) -> impl Iterator<Item = ClientEvent> + '_ {
+        std::iter::from_fn(move || self.pop_event())
+    }
+
     fn encode_and_send(&self, message: Message) -> Result<()> {
         let mut encoded_message = vec![];
         encode_message(&mut encoded_message, message).unwrap();
 
-        self.socket
-            .send_to(&encoded_message, self.server_address)
-            .map(|_amount| ())
+        let datagram = self.compress(&encoded_message);
+        let captured = CAPTURED_REQUESTS.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(captured) => {
+                captured.push(datagram.to_vec());
+                true
+            }
+            None => false,
+        });
+        let sent = if captured {
+            Ok(datagram.len())
+        } else {
+            self.socket.send_to(&datagram, self.server_address)
+        };
+
+        sent.map(|_amount| {
+            self.tap_datagram(Direction::Sent, self.server_address, &encoded_message);
+        })
+    }
+
+    /// Offers server compression extension (disabled by default), see [`crate::compression`].
+    ///
+    /// Offer is repeated with every data request until server answers it. Servers not supporting compression
+    /// never answer, so client keeps talking standard protocol to them.
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&self, enabled: bool) -> Result<()> {
+        self.compression_requested.store(enabled, Ordering::SeqCst);
+        self.compression_negotiated.store(false, Ordering::SeqCst);
+
+        self.send_compression_offer()
+    }
+
+    /// Checks whether server agreed to compress datagrams.
+    #[cfg(feature = "compression")]
+    pub fn is_compression_negotiated(&self) -> bool {
+        self.compression_negotiated.load(Ordering::SeqCst)
+    }
+
+    #[cfg(feature = "compression")]
+    fn send_compression_offer(&self) -> Result<()> {
+        let algorithms = if self.compression_requested.load(Ordering::SeqCst) {
+            COMPRESSION_LZ4
+        } else {
+            0
+        };
+
+        let message = Message {
+            header: self.message_header(),
+            message_type: MessageType::Compression,
+            payload: MessagePayload::Compression(algorithms),
+        };
+
+        self.encode_and_send(message)
+    }
+
+    #[cfg(feature = "compression")]
+    fn compress<'a>(&self, datagram: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.compression_negotiated.load(Ordering::SeqCst) {
+            compress_datagram(datagram)
+        } else {
+            Cow::Borrowed(datagram)
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn compress<'a>(&self, datagram: &'a [u8]) -> Cow<'a, [u8]> {
+        Cow::Borrowed(datagram)
+    }
+
+    #[cfg(feature = "compression")]
+    fn decompress<'a>(&self, datagram: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        decompress_datagram(datagram, self.receive_buffer_size.load(Ordering::SeqCst))
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn decompress<'a>(&self, datagram: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        Ok(Cow::Borrowed(datagram))
+    }
+
+    /// Sets callback receiving every raw datagram sent or received by client, pass `None` to remove it.
+    ///
+    /// Callback is called from client's threads, keep it short.
+    pub fn set_tap(&self, tap: Option<DatagramTap>) {
+        *self.tap.lock().unwrap() = tap;
+    }
+
+    fn tap_datagram(&self, direction: Direction, peer: SocketAddr, datagram: &[u8]) {
+        if let Some(tap) = self.tap.lock().unwrap().as_ref() {
+            tap(direction, peer, datagram);
+        }
     }
 
     /// Ask server to send controller info for given slot numbers.
     ///
     /// # Arguments
     ///
-    /// * `slot_numbers` - slot numbers to ask info for, must contain at most 4 elements.
+    /// * `slot_numbers` - slot numbers to ask info for, must contain at most `slot_count` elements
+    ///   (request message holds 4 slots, so more slots are requested with multiple messages).
     pub fn request_connected_controllers_info(&self, slot_numbers: &[u8]) -> Result<()> {
-        let slot_numbers = {
-            let mut slots = [0; 4];
+        if slot_numbers.len() > self.slot_count as usize {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Too many slot numbers requested",
+            ));
+        }
+        for &slot_number in slot_numbers.iter() {
+            check_slot_number_in(slot_number, self.slot_count)?;
+        }
+
+        let mut requested = [false; MAX_SLOT_COUNT as usize];
+        for &slot in slot_numbers.iter() {
+            requested[slot as usize] = true;
+        }
 
-            for (i, &slot) in slot_numbers.iter().enumerate() {
-                slots[i] = slot;
+        *self.pending_info.lock().unwrap() = Some(PendingInfo {
+            requested,
+            received: [None; MAX_SLOT_COUNT as usize],
+            request_time: Instant::now(),
+        });
+
+        for chunk in slot_numbers.chunks(SLOT_COUNT as usize) {
+            let mut slots = [0; 4];
+            for (i, &slot) in chunk.iter().enumerate() {
+                slots[i] = self.upstream_slot(slot);
             }
 
-            slots
-        };
+            let payload = MessagePayload::ConnectedControllersRequest {
+                amount: chunk.len() as i32,
+                slot_numbers: slots,
+            };
 
-        let payload = MessagePayload::ConnectedControllersRequest {
-            amount: slot_numbers.len() as i32,
-            slot_numbers,
-        };
+            let message = Message {
+                header: self.message_header(),
+                message_type: MessageType::ConnectedControllers,
+                payload,
+            };
 
-        let message = Message {
-            header: self.message_header,
-            message_type: MessageType::ConnectedControllers,
-            payload,
-        };
+            self.encode_and_send(message)?;
+        }
 
-        self.encode_and_send(message)
+        Ok(())
     }
 
     /// Ask server to send controller data for given slot numbers.
-    /// You must call this method periodically if you want server to send data.
+    /// You must call this method periodically if you want server to send data (unless keepalive is enabled,
+    /// see `set_keepalive_interval`).
     pub fn request_controller_data(&self, request: ControllerDataRequest) -> Result<()> {
+        {
+            let mut data_requests = self.data_requests.lock().unwrap();
+            if !data_requests.contains(&request) {
+                data_requests.push(request);
+            }
+        }
+
+        self.send_data_request(request)
+    }
+
+    /// Asks server to send controller data for given slot, see `request_controller_data`.
+    pub fn request_slot_data(&self, slot_index: SlotIndex) -> Result<()> {
+        self.request_controller_data(slot_index.into())
+    }
+
+    /// Stops keepalive from repeating controller data requests sent so far (see `set_keepalive_interval`).
+    pub fn clear_data_requests(&self) {
+        self.data_requests.lock().unwrap().clear();
+    }
+
+    fn send_data_request(&self, request: ControllerDataRequest) -> Result<()> {
+        let request = match request {
+            ControllerDataRequest::SlotNumber(slot_number) => {
+                ControllerDataRequest::SlotNumber(self.upstream_slot(slot_number))
+            }
+            request => request,
+        };
         let payload = MessagePayload::ControllerDataRequest(request);
 
         let message = Message {
-            header: self.message_header,
+            header: self.message_header(),
             message_type: MessageType::ControllerData,
             payload,
         };
 
+        #[cfg(feature = "compression")]
+        if self.compression_requested.load(Ordering::SeqCst)
+            && !self.compression_negotiated.load(Ordering::SeqCst)
+        {
+            self.send_compression_offer()?;
+        }
+
+        *self.last_data_request_time.lock().unwrap() = Some(Instant::now());
         self.encode_and_send(message)
     }
 
+    /// Ask server to set intensities (0 is off, 255 is full) of motors of controller in given slot.
+    ///
+    /// Uses rumble extension (see `MessageType::Rumble`), servers not supporting it ignore the request.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot_number` - slot of controller to rumble.
+    /// * `small_motor` - intensity of small (high frequency) motor, see `rumble::SMALL_MOTOR`.
+    /// * `big_motor` - intensity of large (low frequency) motor, see `rumble::LARGE_MOTOR`.
+    pub fn send_rumble(&self, slot_number: u8, small_motor: u8, big_motor: u8) -> Result<()> {
+        check_slot_number_in(slot_number, self.slot_count)?;
+
+        let request = ControllerDataRequest::SlotNumber(self.upstream_slot(slot_number));
+        for (motor, intensity) in [(LARGE_MOTOR, big_motor), (SMALL_MOTOR, small_motor)] {
+            let message = Message {
+                header: self.message_header(),
+                message_type: MessageType::Rumble,
+                payload: MessagePayload::RumbleRequest {
+                    request,
+                    motor,
+                    intensity,
+                },
+            };
+
+            self.encode_and_send(message)?;
+        }
+
+        Ok(())
+    }
+
+    /// Builds request datagrams instead of sending them to server.
+    ///
+    /// Calls `requests` (which should call request methods, e.g. `request_controller_data`) and returns
+    /// datagrams they would send, so client can be used over alternative transports (see also `handle_datagram`).
+    /// Only datagrams sent on calling thread are returned, client's state is updated as if they were sent.
+    pub fn craft_requests<F: FnOnce(&Client) -> Result<()>>(
+        &self,
+        requests: F,
+    ) -> Result<Vec<Vec<u8>>> {
+        let previous = CAPTURED_REQUESTS.with(|captured| captured.replace(Some(vec![])));
+        let result = requests(self);
+        let captured = CAPTURED_REQUESTS.with(|captured| captured.replace(previous));
+
+        result.map(|()| captured.unwrap_or_default())
+    }
+
+    /// Handles datagram synchronously on calling thread, as if client's thread received it.
+    ///
+    /// Updates cached controller info and data and queues resulting events (see `DsClient::next_event`),
+    /// so client can be used over alternative transports without starting its thread.
+    /// Fails with `ProtocolError::Io` wrapping `ErrorKind::InvalidInput` error if datagram didn't come
+    /// from client's server and with other `ProtocolError` variants if it's not valid message.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - address datagram came from.
+    /// * `datagram` - received datagram.
+    pub fn handle_datagram(
+        &self,
+        source: SocketAddr,
+        datagram: &[u8],
+    ) -> std::result::Result<(), ProtocolError> {
+        self.receive_datagram(source, datagram)
+            .map_err(ProtocolError::from)
+    }
+
+    fn receive_datagram(&self, source: SocketAddr, datagram: &[u8]) -> Result<()> {
+        let (datagram, decompressed) = match self.decompress(datagram) {
+            Ok(decompressed) => (decompressed, Ok(())),
+            Err(error) => (Cow::Borrowed(datagram), Err(error)),
+        };
+        self.tap_datagram(Direction::Received, source, &datagram);
+
+        if source != self.server_address {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Datagram didn't come from server",
+            ));
+        }
+
+        self.received_packets.fetch_add(1, Ordering::Relaxed);
+        if let Err(error) = decompressed {
+            self.invalid_packets.fetch_add(1, Ordering::Relaxed);
+            return Err(error);
+        }
+
+        let max_length = self
+            .max_message_length
+            .load(Ordering::SeqCst)
+            .min(self.receive_buffer_size.load(Ordering::SeqCst));
+        if let Err(error) = check_message_length(&datagram, max_length) {
+            self.invalid_packets.fetch_add(1, Ordering::Relaxed);
+            self.oversize_packets.fetch_add(1, Ordering::Relaxed);
+            return Err(error);
+        }
+
+        let message = match parse_message(
+            MessageSource::Server,
+            &datagram,
+            self.verify_checksums.load(Ordering::SeqCst),
+        ) {
+            Ok(message) => message,
+            Err(error) => {
+                self.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                return Err(error.into());
+            }
+        };
+
+        if let Some(event) = self.handle_response(message) {
+            self.push_event(event);
+        }
+
+        Ok(())
+    }
+
     fn handle_response(&self, response: Message) -> Option<ClientEvent> {
         match response.message_type {
             MessageType::ProtocolVersion => None,
             _ => {
                 match response.payload {
-                    MessagePayload::ConnectedControllerResponse { controller_info } => {
+                    MessagePayload::ConnectedControllerResponse {
+                        mut controller_info,
+                    } => {
+                        controller_info.slot = self.local_slot(controller_info.slot)?;
                         let slot_number = controller_info.slot;
 
+                        self.receive_pending_info(controller_info);
+
                         let mut slots = self.slots.lock().unwrap();
-                        if slots[slot_number as usize].controller_info != controller_info {
-                            slots[slot_number as usize].controller_info = controller_info;
+                        let slot = &mut slots[slot_number as usize];
+                        if slot.controller_info != controller_info {
+                            let previous_info = slot.controller_info;
+                            slot.controller_info = controller_info;
 
-                            let event = ClientEvent::ControllerInfoChanged(controller_info);
+                            let event = if controller_info.slot_state == SlotState::NotConnected
+                                && previous_info.slot_state != SlotState::NotConnected
+                            {
+                                slot.controller_data = Default::default();
+                                ClientEvent::ControllerDisconnected(previous_info)
+                            } else {
+                                ClientEvent::ControllerInfoChanged(controller_info)
+                            };
 
                             Some(event)
                         } else {
@@ -188,16 +1243,56 @@ impl Client {
                     }
                     MessagePayload::ControllerData {
                         packet_number,
-                        controller_info,
-                        controller_data,
+                        mut controller_info,
+                        mut controller_data,
                     } => {
+                        controller_info.slot = self.local_slot(controller_info.slot)?;
                         let slot_number = controller_info.slot;
 
+                        self.data_packets.fetch_add(1, Ordering::Relaxed);
+                        let requested_recently = self
+                            .last_data_request_time
+                            .lock()
+                            .unwrap()
+                            .map(|time| time.elapsed() < PUSH_DETECTION_WINDOW)
+                            .unwrap_or(false);
+                        if !requested_recently {
+                            self.pushed_packets.fetch_add(1, Ordering::Relaxed);
+                        }
+
                         let mut slots = self.slots.lock().unwrap();
 
                         let slot = slots[slot_number as usize];
-                        if packet_number > slot.latest_packet_number {
-                            slots[slot_number as usize].latest_packet_number = packet_number;
+                        // Servers pushing data may restart and count packets from zero again.
+                        let is_newer = match slot.latest_packet_number {
+                            Some(latest_packet_number) => {
+                                packet_number > latest_packet_number
+                                    || latest_packet_number.wrapping_sub(packet_number)
+                                        > PACKET_NUMBER_RESET_THRESHOLD
+                            }
+                            None => true,
+                        };
+                        if is_newer {
+                            let motion_validation = *self.motion_validation.lock().unwrap();
+                            if motion_validation != MotionValidation::Disabled
+                                && !controller_data.has_valid_motion()
+                            {
+                                self.invalid_motion_packets.fetch_add(1, Ordering::Relaxed);
+                                match motion_validation {
+                                    MotionValidation::Clamp => controller_data
+                                        .clamp_motion(slot.controller_data.motion_data_timestamp),
+                                    _ => return None,
+                                }
+                            }
+
+                            if let Some(transform) =
+                                &mut self.transforms.lock().unwrap()[slot_number as usize]
+                            {
+                                transform.apply(slot_number, &mut controller_data);
+                            }
+
+                            slots[slot_number as usize].latest_packet_number = Some(packet_number);
+                            slots[slot_number as usize].latest_data_time = Some(Instant::now());
 
                             if slot.controller_info != controller_info
                                 || slot.controller_data != controller_data
@@ -218,6 +1313,22 @@ impl Client {
                             None
                         }
                     }
+                    MessagePayload::SlotHealth(mut slot_health) => {
+                        slot_health.slot = self.local_slot(slot_health.slot)?;
+                        self.slots.lock().unwrap()[slot_health.slot as usize].health =
+                            Some(slot_health);
+
+                        Some(ClientEvent::SlotHealth(slot_health))
+                    }
+                    #[cfg(feature = "compression")]
+                    MessagePayload::Compression(algorithm) => {
+                        let negotiated = self.compression_requested.load(Ordering::SeqCst)
+                            && algorithm & COMPRESSION_LZ4 != 0;
+                        self.compression_negotiated
+                            .store(negotiated, Ordering::SeqCst);
+
+                        None
+                    }
                     _ => None, // ignore response
                 }
             }
@@ -228,25 +1339,39 @@ impl Client {
 impl DsClient for Arc<Client> {
     fn start(self, countinue_running: Arc<AtomicBool>) -> JoinHandle<()> {
         std::thread::spawn(move || {
-            let mut buf = [0_u8; 100];
+            let mut buf = vec![];
+            let mut backoff = ReceiveBackoff::default();
             while countinue_running.load(Ordering::SeqCst) {
-                if let Ok((amount, source)) = self.socket.recv_from(&mut buf) {
-                    if source == self.server_address {
-                        let message = parse_message(MessageSource::Server, &buf[..amount], true);
-                        if let Ok(message) = message {
-                            let event = self.handle_response(message);
-                            if let Some(event) = event {
-                                let _ = self.events.push(event);
-                            }
-                        }
+                buf.resize(self.receive_buffer_size.load(Ordering::SeqCst), 0);
+                let received = self.socket.recv_from(&mut buf);
+                // Stopping client wakes it with an empty datagram, don't handle it.
+                if !countinue_running.load(Ordering::SeqCst) {
+                    break;
+                }
+
+                match backoff.handle(received) {
+                    Ok(Some((amount, source))) => {
+                        let _ = self.receive_datagram(source, &buf[..amount]);
+                    }
+                    Ok(None) => {}
+                    Err(error) => {
+                        self.push_event(ClientEvent::TransportFailed(error.kind()));
+                        *self.terminal_error.lock().unwrap() = Some(error);
+                        break;
                     }
                 }
+
+                self.poll_timers();
             }
         })
     }
 
+    fn slot_count(&self) -> u8 {
+        self.slot_count
+    }
+
     fn controller_info(&self, slot_number: u8) -> ControllerInfo {
-        assert!(slot_number < 4);
+        assert!(slot_number < self.slot_count);
 
         let slot = self.slots.lock().unwrap()[slot_number as usize];
 
@@ -254,14 +1379,319 @@ impl DsClient for Arc<Client> {
     }
 
     fn controller_data(&self, slot_number: u8) -> ControllerData {
-        assert!(slot_number < 4);
+        assert!(slot_number < self.slot_count);
 
         let slot = self.slots.lock().unwrap()[slot_number as usize];
 
-        slot.controller_data
+        self.live_data(&slot)
+    }
+
+    fn next_event(&self) -> Option<ClientEvent> {
+        self.pop_event()
+    }
+}
+
+/// Cheap to clone handle of shared `Client`, usable from any thread.
+///
+/// Dereferences to `Client`, so besides `DsClient` methods it exposes all of client's settings and queries.
+#[derive(Clone)]
+pub struct ClientHandle(Arc<Client>);
+
+impl ClientHandle {
+    pub fn new(client: Client) -> ClientHandle {
+        ClientHandle(Arc::new(client))
+    }
+
+    /// Returns shared client, e.g. for APIs taking `Arc<Client>`.
+    pub fn client(&self) -> &Arc<Client> {
+        &self.0
+    }
+
+    /// Starts background client thread, returning handle stopping it.
+    ///
+    /// Unlike `DsClient::start`, stopping doesn't wait for receive timeout.
+    pub fn spawn(&self) -> RunningClient {
+        let countinue_running = Arc::new(AtomicBool::new(true));
+        let thread = self.clone().start(countinue_running.clone());
+
+        RunningClient {
+            client: self.clone(),
+            countinue_running,
+            thread,
+        }
+    }
+}
+
+/// Background thread of client started with `ClientHandle::spawn`.
+///
+/// Dropping it without calling `stop` leaves thread running.
+pub struct RunningClient {
+    client: ClientHandle,
+    countinue_running: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RunningClient {
+    pub fn client(&self) -> &ClientHandle {
+        &self.client
+    }
+
+    /// Checks whether client thread is still running.
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Asks client thread to stop and wakes it, so it stops without waiting for receive timeout.
+    pub fn stop(&self) {
+        self.countinue_running.store(false, Ordering::SeqCst);
+        // If wake fails thread still stops after receive timeout.
+        let _ = self.client.wake();
+    }
+
+    /// Waits for client thread to finish, call `stop` first.
+    pub fn join(self) -> std::thread::Result<()> {
+        self.thread.join()
+    }
+
+    /// Stops client thread and waits for it to finish.
+    pub fn shutdown(self) -> std::thread::Result<()> {
+        self.stop();
+        self.join()
+    }
+}
+
+impl From<Client> for ClientHandle {
+    fn from(client: Client) -> ClientHandle {
+        ClientHandle::new(client)
+    }
+}
+
+impl From<Arc<Client>> for ClientHandle {
+    fn from(client: Arc<Client>) -> ClientHandle {
+        ClientHandle(client)
+    }
+}
+
+impl std::ops::Deref for ClientHandle {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        &self.0
+    }
+}
+
+impl DsClient for ClientHandle {
+    fn start(self, countinue_running: Arc<AtomicBool>) -> JoinHandle<()> {
+        self.0.start(countinue_running)
+    }
+
+    fn slot_count(&self) -> u8 {
+        self.0.slot_count
+    }
+
+    fn controller_info(&self, slot_number: u8) -> ControllerInfo {
+        self.0.controller_info(slot_number)
+    }
+
+    fn controller_data(&self, slot_number: u8) -> ControllerData {
+        self.0.controller_data(slot_number)
     }
 
     fn next_event(&self) -> Option<ClientEvent> {
-        self.events.pop()
+        self.0.next_event()
+    }
+}
+
+// Client is shared between its thread and consumers, fail to compile if it stops being thread-safe.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Client>();
+    assert_send_sync::<ClientHandle>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns controller data requests received by server, in order of arrival.
+    fn received_requests(server: &dyn Transport) -> Vec<ControllerDataRequest> {
+        let mut received = vec![];
+        let mut buf = vec![0; DEFAULT_RECEIVE_BUFFER_SIZE];
+        while let Ok((amount, _)) = server.recv_from(&mut buf) {
+            let message = parse_message(MessageSource::Client, &buf[..amount], true).unwrap();
+            if let MessagePayload::ControllerDataRequest(request) = message.payload {
+                received.push(request);
+            }
+        }
+
+        received
+    }
+
+    fn coalescing_client() -> Client {
+        let network = LoopbackNetwork::new();
+        let client = Client::builder()
+            .build_with_transport(Box::new(
+                network.bind(SocketAddr::from(([10, 0, 0, 2], 1))).unwrap(),
+            ))
+            .unwrap();
+        client.set_event_coalescing(true);
+
+        client
+    }
+
+    fn data_changed(slot: u8, cross: bool, left_stick_x: u8) -> ClientEvent {
+        ClientEvent::ControllerDataChanged {
+            controller_info: ControllerInfo {
+                slot,
+                slot_state: SlotState::Connected,
+                ..Default::default()
+            },
+            controller_data: ControllerData {
+                connected: true,
+                cross,
+                left_stick_x,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Summarizes events as their kind, slot and left stick X (for data changes).
+    fn summary(events: &[ClientEvent]) -> Vec<(&'static str, u8, u8)> {
+        events
+            .iter()
+            .map(|event| match event {
+                ClientEvent::ControllerDataChanged {
+                    controller_info,
+                    controller_data,
+                } => ("data", controller_info.slot, controller_data.left_stick_x),
+                ClientEvent::ControllerInfoChanged(controller_info) => {
+                    ("info", controller_info.slot, 0)
+                }
+                ClientEvent::ControllerDisconnected(controller_info) => {
+                    ("disconnected", controller_info.slot, 0)
+                }
+                ClientEvent::SlotHealth(health) => ("health", health.slot, 0),
+                ClientEvent::AllControllersInfo(_) => ("all info", 0, 0),
+                ClientEvent::TransportFailed(_) => ("transport failed", 0, 0),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn keepalive_repeats_all_data_requests() {
+        let network = LoopbackNetwork::new();
+        let server_address = SocketAddr::from(([10, 0, 0, 1], 26760));
+        let server = network.bind(server_address).unwrap();
+        let client = Client::builder()
+            .with_server_address(server_address)
+            .with_keepalive_interval(Some(Duration::from_millis(10)))
+            .build_with_transport(Box::new(
+                network.bind(SocketAddr::from(([10, 0, 0, 2], 1))).unwrap(),
+            ))
+            .unwrap();
+
+        let requests = [
+            ControllerDataRequest::SlotNumber(0),
+            ControllerDataRequest::SlotNumber(1),
+            ControllerDataRequest::MAC(1),
+        ];
+        for request in requests {
+            client.request_controller_data(request).unwrap();
+        }
+        client
+            .request_controller_data(ControllerDataRequest::SlotNumber(0))
+            .unwrap();
+        assert_eq!(received_requests(&server).len(), 4);
+
+        std::thread::sleep(Duration::from_millis(20));
+        client.poll_timers();
+        assert_eq!(received_requests(&server), requests);
+
+        client.clear_data_requests();
+        std::thread::sleep(Duration::from_millis(20));
+        client.poll_timers();
+        assert!(received_requests(&server).is_empty());
+    }
+
+    #[test]
+    fn slot_index_requests_validated_slot() {
+        let network = LoopbackNetwork::new();
+        let server_address = SocketAddr::from(([10, 0, 0, 1], 26760));
+        let server = network.bind(server_address).unwrap();
+        let client = Client::builder()
+            .with_server_address(server_address)
+            .with_slot_count(MAX_SLOT_COUNT)
+            .build_with_transport(Box::new(
+                network.bind(SocketAddr::from(([10, 0, 0, 2], 1))).unwrap(),
+            ))
+            .unwrap();
+
+        let last = client.slots().last().unwrap();
+        client.request_slot_data(last).unwrap();
+        assert_eq!(
+            received_requests(&server),
+            [ControllerDataRequest::SlotNumber(MAX_SLOT_COUNT - 1)]
+        );
+    }
+
+    #[test]
+    fn coalescing_keeps_latest_data_and_button_edges() {
+        let client = coalescing_client();
+        for event in [
+            data_changed(0, false, 1),
+            data_changed(1, false, 10),
+            data_changed(0, false, 2),
+            data_changed(0, true, 3),
+            data_changed(0, true, 4),
+            data_changed(1, false, 11),
+        ] {
+            client.push_event(event);
+        }
+
+        let mut events = vec![];
+        assert_eq!(client.drain_events(&mut events), 3);
+        assert_eq!(
+            summary(&events),
+            [("data", 0, 2), ("data", 0, 4), ("data", 1, 11)]
+        );
+
+        client.push_event(data_changed(0, true, 5));
+        client.set_event_coalescing(false);
+        client.push_event(data_changed(0, true, 6));
+        let events: Vec<_> = client.events().collect();
+        assert_eq!(summary(&events), [("data", 0, 5), ("data", 0, 6)]);
+    }
+
+    #[test]
+    fn coalesced_data_is_delivered_before_later_events_of_its_slot() {
+        let client = coalescing_client();
+        client.push_event(data_changed(0, false, 1));
+        client.push_event(data_changed(1, false, 10));
+        client.push_event(data_changed(0, false, 2));
+        client.push_event(ClientEvent::SlotHealth(SlotHealth {
+            slot: 0,
+            sample_rate: 250.0,
+            driver_status: Default::default(),
+            battery_percent: None,
+        }));
+        client.push_event(ClientEvent::ControllerDisconnected(ControllerInfo {
+            slot: 1,
+            ..Default::default()
+        }));
+        client.push_event(data_changed(0, false, 3));
+        client.push_event(ClientEvent::TransportFailed(ErrorKind::NotConnected));
+
+        let events: Vec<_> = client.events().collect();
+        assert_eq!(
+            summary(&events),
+            [
+                ("data", 0, 2),
+                ("health", 0, 0),
+                ("data", 1, 10),
+                ("disconnected", 1, 0),
+                ("data", 0, 3),
+                ("transport failed", 0, 0)
+            ]
+        );
     }
 }
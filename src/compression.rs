@@ -0,0 +1,119 @@
+//! Per-datagram LZ4 compression for remote streaming over constrained links (feature `compression`).
+//!
+//! Compression is an extension negotiated between this crate's client and server: client offers it
+//! with `MessageType::Compression` message and server confirms it with the same message type.
+//! Third party peers never negotiate it, so they only ever see standard datagrams.
+//!
+//! Compressed datagram consists of [`COMPRESSED_MAGIC`], `u16` little-endian length of original datagram
+//! and LZ4 block of original datagram. Datagrams compression wouldn't shrink are sent as they are.
+
+use std::borrow::Cow;
+use std::io::{Error, ErrorKind, Result};
+
+/// Magic string starting compressed datagrams, differs from magic string of standard messages.
+pub const COMPRESSED_MAGIC: [u8; 4] = *b"DSUZ";
+
+const PREFIX_LENGTH: usize = COMPRESSED_MAGIC.len() + 2;
+
+/// Checks whether datagram is compressed.
+pub fn is_compressed(datagram: &[u8]) -> bool {
+    datagram.starts_with(&COMPRESSED_MAGIC)
+}
+
+/// Compresses datagram, returns it unchanged if compression wouldn't make it shorter.
+pub fn compress_datagram(datagram: &[u8]) -> Cow<'_, [u8]> {
+    if datagram.len() > u16::MAX as usize {
+        return Cow::Borrowed(datagram);
+    }
+
+    let block = lz4_flex::block::compress(datagram);
+    if PREFIX_LENGTH + block.len() >= datagram.len() {
+        return Cow::Borrowed(datagram);
+    }
+
+    let mut compressed = Vec::with_capacity(PREFIX_LENGTH + block.len());
+    compressed.extend_from_slice(&COMPRESSED_MAGIC);
+    compressed.extend_from_slice(&(datagram.len() as u16).to_le_bytes());
+    compressed.extend_from_slice(&block);
+
+    Cow::Owned(compressed)
+}
+
+/// Decompresses datagram if it is compressed, otherwise returns it unchanged.
+///
+/// # Arguments
+///
+/// * `datagram` - received datagram.
+/// * `max_length` - maximum length of decompressed datagram, longer datagrams are rejected
+///   with `ErrorKind::InvalidData` error before decompressing them.
+pub fn decompress_datagram(datagram: &[u8], max_length: usize) -> Result<Cow<'_, [u8]>> {
+    if !is_compressed(datagram) {
+        return Ok(Cow::Borrowed(datagram));
+    }
+    if datagram.len() < PREFIX_LENGTH {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Compressed datagram is too short",
+        ));
+    }
+
+    let length = u16::from_le_bytes([datagram[4], datagram[5]]) as usize;
+    if length > max_length {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Decompressed datagram would be too long",
+        ));
+    }
+
+    let mut decompressed = vec![0; length];
+    match lz4_flex::block::decompress_into(&datagram[PREFIX_LENGTH..], &mut decompressed) {
+        Ok(amount) if amount == length => Ok(Cow::Owned(decompressed)),
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            "Invalid compressed datagram",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compressed_datagram_roundtrips() {
+        let datagram: Vec<u8> = b"DSUS".iter().chain([0; 96].iter()).copied().collect();
+
+        let compressed = compress_datagram(&datagram);
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < datagram.len());
+        assert_eq!(
+            decompress_datagram(&compressed, datagram.len()).unwrap(),
+            &datagram[..]
+        );
+    }
+
+    #[test]
+    fn incompressible_datagram_is_sent_as_it_is() {
+        let datagram = *b"DSUS";
+
+        let compressed = compress_datagram(&datagram);
+        assert!(!is_compressed(&compressed));
+        assert_eq!(decompress_datagram(&compressed, 4).unwrap(), &datagram[..]);
+    }
+
+    #[test]
+    fn oversize_and_invalid_datagrams_are_rejected() {
+        let datagram = [0; 100];
+        let compressed = compress_datagram(&datagram);
+
+        let error = decompress_datagram(&compressed, datagram.len() - 1).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let error =
+            decompress_datagram(&compressed[..PREFIX_LENGTH + 1], datagram.len()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+
+        let error = decompress_datagram(&COMPRESSED_MAGIC, datagram.len()).unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidData);
+    }
+}
@@ -0,0 +1,196 @@
+//! Cemuhook motion conventions and conversions from common IMU units and frames.
+//!
+//! Motion is expressed in DualShock 4 coordinate frame: X points right, Y up (out of touchpad)
+//! and Z towards player, so when controller lies flat on a table with its triggers away from player:
+//! * accelerometer (in g) reports gravity itself, not reaction force - it reads (0, -1, 0),
+//! * gyroscope (in degrees per second) reports right-handed rotations around X (pitch), Y (yaw) and Z (roll) axes:
+//!   positive pitch tilts far end of controller up, positive yaw turns it left
+//!   and positive roll raises its right side.
+//!
+//! Most IMUs (and e.g. phones lying flat) use right-handed frame with X right, Y forward (away from player)
+//! and Z up, measure specific force in m/s² (reading +9.81 on Z at rest) and angular velocity in rad/s.
+//! Functions of this module convert such readings, so drivers don't have to rediscover signs empirically.
+
+use crate::protocol::ControllerData;
+
+/// Standard gravity (in m/s²).
+pub const STANDARD_GRAVITY: f32 = 9.80665;
+
+/// Accelerometer reading (in g) of controller lying flat at rest.
+pub const ACCELEROMETER_AT_REST: [f32; 3] = [0.0, -1.0, 0.0];
+
+/// Rotates vector from IMU frame (X right, Y forward, Z up) to DualShock 4 frame (X right, Y up, Z towards player).
+pub fn imu_to_ds4_frame(vector: [f32; 3]) -> [f32; 3] {
+    [vector[0], vector[2], -vector[1]]
+}
+
+/// Rotates vector from DualShock 4 frame (X right, Y up, Z towards player) to IMU frame (X right, Y forward, Z up).
+pub fn ds4_to_imu_frame(vector: [f32; 3]) -> [f32; 3] {
+    [vector[0], -vector[2], vector[1]]
+}
+
+/// Converts specific force measured by IMU (in m/s², IMU frame, +Z at rest) to Cemuhook accelerometer reading
+/// (in g, DualShock 4 frame, -Y at rest).
+pub fn accelerometer_from_si(specific_force: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = imu_to_ds4_frame(specific_force);
+    [
+        -x / STANDARD_GRAVITY,
+        -y / STANDARD_GRAVITY,
+        -z / STANDARD_GRAVITY,
+    ]
+}
+
+/// Converts Cemuhook accelerometer reading (in g, DualShock 4 frame) to specific force (in m/s², IMU frame).
+pub fn accelerometer_to_si(accelerometer: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = ds4_to_imu_frame(accelerometer);
+    [
+        -x * STANDARD_GRAVITY,
+        -y * STANDARD_GRAVITY,
+        -z * STANDARD_GRAVITY,
+    ]
+}
+
+/// Converts right-handed angular velocity measured by IMU (in rad/s, IMU frame) to Cemuhook gyroscope
+/// pitch, yaw and roll (in degrees per second).
+///
+/// Rotation around IMU's X axis becomes pitch, around Z (up) yaw and around Y (forward) negated roll,
+/// as DualShock 4's roll axis points towards player. So positive roll raises right side of controller.
+pub fn gyroscope_from_si(angular_velocity: [f32; 3]) -> [f32; 3] {
+    let [pitch, yaw, roll] = imu_to_ds4_frame(angular_velocity);
+    [pitch.to_degrees(), yaw.to_degrees(), roll.to_degrees()]
+}
+
+/// Converts Cemuhook gyroscope pitch, yaw and roll (in degrees per second) to right-handed angular velocity
+/// (in rad/s, IMU frame).
+pub fn gyroscope_to_si(gyroscope: [f32; 3]) -> [f32; 3] {
+    let [x, y, z] = ds4_to_imu_frame(gyroscope);
+    [x.to_radians(), y.to_radians(), z.to_radians()]
+}
+
+/// Returns accelerometer reading (X, Y, Z in g) of controller data.
+pub fn accelerometer(controller_data: &ControllerData) -> [f32; 3] {
+    [
+        controller_data.accelerometer_x,
+        controller_data.accelerometer_y,
+        controller_data.accelerometer_z,
+    ]
+}
+
+/// Returns gyroscope reading (pitch, yaw, roll in degrees per second) of controller data.
+pub fn gyroscope(controller_data: &ControllerData) -> [f32; 3] {
+    [
+        controller_data.gyroscope_pitch,
+        controller_data.gyroscope_yaw,
+        controller_data.gyroscope_roll,
+    ]
+}
+
+/// Writes IMU reading into controller data, converting it to Cemuhook conventions.
+///
+/// # Arguments
+///
+/// * `controller_data` - controller data to update.
+/// * `specific_force` - accelerometer reading in m/s², IMU frame (X right, Y forward, Z up).
+/// * `angular_velocity` - gyroscope reading in rad/s, IMU frame.
+/// * `timestamp` - sample time in microseconds.
+pub fn set_motion_from_si(
+    controller_data: &mut ControllerData,
+    specific_force: [f32; 3],
+    angular_velocity: [f32; 3],
+    timestamp: u64,
+) {
+    let [accelerometer_x, accelerometer_y, accelerometer_z] = accelerometer_from_si(specific_force);
+    let [gyroscope_pitch, gyroscope_yaw, gyroscope_roll] = gyroscope_from_si(angular_velocity);
+
+    controller_data.motion_data_timestamp = timestamp;
+    controller_data.accelerometer_x = accelerometer_x;
+    controller_data.accelerometer_y = accelerometer_y;
+    controller_data.accelerometer_z = accelerometer_z;
+    controller_data.gyroscope_pitch = gyroscope_pitch;
+    controller_data.gyroscope_yaw = gyroscope_yaw;
+    controller_data.gyroscope_roll = gyroscope_roll;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: [f32; 3], expected: [f32; 3]) {
+        for (actual, expected) in actual.iter().zip(expected.iter()) {
+            assert!(
+                (actual - expected).abs() < 1e-4,
+                "{:?} != {:?}",
+                actual,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn frames_round_trip() {
+        let vector = [1.0, 2.0, 3.0];
+        assert_eq!(imu_to_ds4_frame(vector), [1.0, 3.0, -2.0]);
+        assert_eq!(ds4_to_imu_frame(imu_to_ds4_frame(vector)), vector);
+    }
+
+    #[test]
+    fn accelerometer_reads_gravity_at_rest() {
+        assert_close(
+            accelerometer_from_si([0.0, 0.0, STANDARD_GRAVITY]),
+            ACCELEROMETER_AT_REST,
+        );
+    }
+
+    #[test]
+    fn accelerometer_reads_gravity_towards_player_when_pitched_up() {
+        // Far end of controller points up, so Y axis of IMU does too.
+        assert_close(
+            accelerometer_from_si([0.0, STANDARD_GRAVITY, 0.0]),
+            [0.0, 0.0, 1.0],
+        );
+    }
+
+    #[test]
+    fn accelerometer_round_trips() {
+        let specific_force = [1.5, -2.0, 9.0];
+        assert_close(
+            accelerometer_to_si(accelerometer_from_si(specific_force)),
+            specific_force,
+        );
+    }
+
+    #[test]
+    fn gyroscope_signs() {
+        let degrees = 1.0f32.to_degrees();
+        // Far end tilting up.
+        assert_close(gyroscope_from_si([1.0, 0.0, 0.0]), [degrees, 0.0, 0.0]);
+        // Turning left.
+        assert_close(gyroscope_from_si([0.0, 0.0, 1.0]), [0.0, degrees, 0.0]);
+        // Right side rising.
+        assert_close(gyroscope_from_si([0.0, -1.0, 0.0]), [0.0, 0.0, degrees]);
+    }
+
+    #[test]
+    fn gyroscope_round_trips() {
+        let angular_velocity = [0.5, -1.0, 2.0];
+        assert_close(
+            gyroscope_to_si(gyroscope_from_si(angular_velocity)),
+            angular_velocity,
+        );
+    }
+
+    #[test]
+    fn set_motion_from_si_fills_controller_data() {
+        let mut controller_data = ControllerData::default();
+        set_motion_from_si(
+            &mut controller_data,
+            [0.0, 0.0, STANDARD_GRAVITY],
+            [0.0, 0.0, 1.0],
+            1234,
+        );
+
+        assert_eq!(controller_data.motion_data_timestamp, 1234);
+        assert_close(accelerometer(&controller_data), ACCELEROMETER_AT_REST);
+        assert_close(gyroscope(&controller_data), [0.0, 1.0f32.to_degrees(), 0.0]);
+    }
+}
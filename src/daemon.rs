@@ -0,0 +1,464 @@
+//! Reference provider serving gamepads managed by gilrs.
+//!
+//! [`Daemon`] does the orchestration most providers need: gamepads are registered to server slots as they connect
+//! (remembered slots are preferred, see `Server::register_device`), their rumble is forwarded to force feedback
+//! (see `Server::set_rumble_sink`) and their data is passed through slot transforms. Motion can be added
+//! with a motion hook (e.g. mouse movement converted with [`MouseToGyro`](crate::source::mouse::MouseToGyro)),
+//! data can be recorded and, with `http-status` feature, status can be served over HTTP.
+//!
+//! Available with `daemon` feature.
+
+#[cfg(feature = "http-status")]
+use crate::http_status;
+use crate::protocol::*;
+use crate::recording::{Recorder, Recording};
+use crate::rumble::{GilrsRumble, OutputSink};
+use crate::server::{DsServer, Server, ServerBuilder, ServerStats, SlotHandle};
+use crate::transform::Transform;
+use gilrs::{Axis, Button, Gamepad, GamepadId, Gilrs, PowerInfo};
+use std::collections::HashMap;
+use std::io::{Error, Result};
+#[cfg(feature = "http-status")]
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc;
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Default time between gamepad polls.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Stick axes to invert.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct StickInversion {
+    pub left_x: bool,
+    pub left_y: bool,
+    pub right_x: bool,
+    pub right_y: bool,
+}
+
+/// Function adding data (usually motion) to controller data of given slot before it's sent to server.
+pub type MotionHook = Box<dyn FnMut(u8, &mut ControllerData) + Send>;
+
+/// Daemon metrics snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DaemonStats {
+    /// Gamepads currently assigned to slots.
+    pub gamepads: usize,
+    /// Gamepads that connected while all slots were taken.
+    pub rejected_gamepads: u64,
+    pub polls: u64,
+    pub server: ServerStats,
+}
+
+/// Converts state of gamepad to controller data, without motion.
+pub fn gamepad_controller_data(gamepad: &Gamepad, inversion: StickInversion) -> ControllerData {
+    let stick_value = |axis, invert: bool| {
+        let value = if invert {
+            -gamepad.value(axis)
+        } else {
+            gamepad.value(axis)
+        };
+
+        (value * 127.0 + 127.0) as u8
+    };
+    let analog_button_value = |button| {
+        gamepad
+            .button_data(button)
+            .map(|data| (data.value() * 255.0) as u8)
+            .unwrap_or(0)
+    };
+
+    ControllerData {
+        connected: true,
+        d_pad_left: gamepad.is_pressed(Button::DPadLeft),
+        d_pad_down: gamepad.is_pressed(Button::DPadDown),
+        d_pad_right: gamepad.is_pressed(Button::DPadRight),
+        d_pad_up: gamepad.is_pressed(Button::DPadUp),
+        start: gamepad.is_pressed(Button::Start),
+        right_stick_button: gamepad.is_pressed(Button::RightThumb),
+        left_stick_button: gamepad.is_pressed(Button::LeftThumb),
+        select: gamepad.is_pressed(Button::Select),
+        triangle: gamepad.is_pressed(Button::North),
+        circle: gamepad.is_pressed(Button::East),
+        cross: gamepad.is_pressed(Button::South),
+        square: gamepad.is_pressed(Button::West),
+        r1: gamepad.is_pressed(Button::RightTrigger),
+        l1: gamepad.is_pressed(Button::LeftTrigger),
+        r2: gamepad.is_pressed(Button::RightTrigger2),
+        l2: gamepad.is_pressed(Button::LeftTrigger2),
+        ps: analog_button_value(Button::Mode),
+        left_stick_x: stick_value(Axis::LeftStickX, inversion.left_x),
+        left_stick_y: stick_value(Axis::LeftStickY, inversion.left_y),
+        right_stick_x: stick_value(Axis::RightStickX, inversion.right_x),
+        right_stick_y: stick_value(Axis::RightStickY, inversion.right_y),
+        analog_d_pad_left: analog_button_value(Button::DPadLeft),
+        analog_d_pad_down: analog_button_value(Button::DPadDown),
+        analog_d_pad_right: analog_button_value(Button::DPadRight),
+        analog_d_pad_up: analog_button_value(Button::DPadUp),
+        analog_triangle: analog_button_value(Button::North),
+        analog_circle: analog_button_value(Button::East),
+        analog_cross: analog_button_value(Button::South),
+        analog_square: analog_button_value(Button::West),
+        analog_r1: analog_button_value(Button::RightTrigger),
+        analog_l1: analog_button_value(Button::LeftTrigger),
+        analog_r2: analog_button_value(Button::RightTrigger2),
+        analog_l2: analog_button_value(Button::LeftTrigger2),
+        ..Default::default()
+    }
+}
+
+/// Converts gamepad to controller info (slot is assigned on registration).
+///
+/// MAC address is derived from gamepad's UUID, so gamepad keeps its slot across reconnects.
+pub fn gamepad_controller_info(gamepad: &Gamepad, motion: bool) -> ControllerInfo {
+    let uuid = gamepad.uuid();
+    let mac_address = uuid[..6]
+        .iter()
+        .fold(0, |mac_address, &byte| (mac_address << 8) | byte as u64);
+
+    let battery_status = match gamepad.power_info() {
+        PowerInfo::Unknown | PowerInfo::Wired => BatteryStatus::NotApplicable,
+        PowerInfo::Discharging(percent) => match percent {
+            0..=10 => BatteryStatus::Dying,
+            11..=40 => BatteryStatus::Low,
+            41..=70 => BatteryStatus::Medium,
+            71..=95 => BatteryStatus::High,
+            _ => BatteryStatus::Full,
+        },
+        PowerInfo::Charging(_) => BatteryStatus::Charging,
+        PowerInfo::Charged => BatteryStatus::Charged,
+    };
+    let connection_type = match gamepad.power_info() {
+        PowerInfo::Wired | PowerInfo::Charging(_) | PowerInfo::Charged => ConnectionType::USB,
+        _ => ConnectionType::NotApplicable,
+    };
+
+    ControllerInfo {
+        slot_state: SlotState::Connected,
+        device_type: if motion {
+            DeviceType::FullGyro
+        } else {
+            DeviceType::NotApplicable
+        },
+        connection_type,
+        mac_address,
+        battery_status,
+        ..Default::default()
+    }
+}
+
+/// Builder of `Daemon`, see `Daemon::builder`.
+pub struct DaemonBuilder {
+    server: ServerBuilder,
+    poll_interval: Duration,
+    inversion: StickInversion,
+    rumble: bool,
+    transforms: Vec<(u8, Box<dyn Transform>)>,
+    motion_hook: Option<MotionHook>,
+    #[cfg(feature = "http-status")]
+    status_address: Option<SocketAddr>,
+}
+
+impl Default for DaemonBuilder {
+    fn default() -> DaemonBuilder {
+        DaemonBuilder {
+            server: Server::builder(),
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            inversion: Default::default(),
+            rumble: true,
+            transforms: vec![],
+            motion_hook: None,
+            #[cfg(feature = "http-status")]
+            status_address: None,
+        }
+    }
+}
+
+impl DaemonBuilder {
+    /// Options of daemon's server (`Server::builder()` defaults by default).
+    pub fn with_server(mut self, server: ServerBuilder) -> DaemonBuilder {
+        self.server = server;
+        self
+    }
+
+    /// Time between gamepad polls (default: `DEFAULT_POLL_INTERVAL`).
+    pub fn with_poll_interval(mut self, poll_interval: Duration) -> DaemonBuilder {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn with_stick_inversion(mut self, inversion: StickInversion) -> DaemonBuilder {
+        self.inversion = inversion;
+        self
+    }
+
+    /// Forwards rumble requests to gamepads supporting force feedback (enabled by default).
+    pub fn with_rumble(mut self, rumble: bool) -> DaemonBuilder {
+        self.rumble = rumble;
+        self
+    }
+
+    /// Adds transform of given slot, see `Server::set_transform`.
+    ///
+    /// Use `transform::Pipeline` to apply multiple transforms to the same slot.
+    pub fn with_transform<T: Transform + 'static>(
+        mut self,
+        slot_number: u8,
+        transform: T,
+    ) -> DaemonBuilder {
+        self.transforms.push((slot_number, Box::new(transform)));
+        self
+    }
+
+    /// Sets function adding motion to gamepads' data, gamepads are reported with full gyro when it's set.
+    pub fn with_motion_hook<F: FnMut(u8, &mut ControllerData) + Send + 'static>(
+        mut self,
+        motion_hook: F,
+    ) -> DaemonBuilder {
+        self.motion_hook = Some(Box::new(motion_hook));
+        self
+    }
+
+    /// Serves status of daemon's server over HTTP on given address, see `http_status::start`.
+    #[cfg(feature = "http-status")]
+    pub fn with_status_address(mut self, status_address: SocketAddr) -> DaemonBuilder {
+        self.status_address = Some(status_address);
+        self
+    }
+
+    /// Builds server and starts server, status and gamepad threads.
+    ///
+    /// Fails if server can't be built (see `ServerBuilder::build`), status address can't be bound,
+    /// or gilrs fails to initialize.
+    pub fn start(self) -> Result<Daemon> {
+        let server = Arc::new(self.server.build()?);
+        for (slot_number, transform) in self.transforms {
+            check_slot_number_in(slot_number, server.slot_count())?;
+            server.set_transform(slot_number, Some(transform));
+        }
+
+        let mut daemon = Daemon {
+            server,
+            running: Arc::new(AtomicBool::new(true)),
+            threads: vec![],
+            recorder: Arc::new(Mutex::new(None)),
+            gamepads: Arc::new(AtomicUsize::new(0)),
+            rejected_gamepads: Arc::new(AtomicU64::new(0)),
+            polls: Arc::new(AtomicU64::new(0)),
+        };
+
+        #[cfg(feature = "http-status")]
+        if let Some(status_address) = self.status_address {
+            daemon.threads.push(http_status::start(
+                daemon.server.clone(),
+                status_address,
+                daemon.running.clone(),
+            )?);
+        }
+
+        let (started_sender, started_receiver) = mpsc::channel();
+        let mut gamepad_thread = GamepadThread {
+            server: daemon.server.clone(),
+            inversion: self.inversion,
+            rumble: self.rumble,
+            motion_hook: self.motion_hook,
+            recorder: daemon.recorder.clone(),
+            handles: HashMap::new(),
+            gamepads: daemon.gamepads.clone(),
+            rejected_gamepads: daemon.rejected_gamepads.clone(),
+            polls: daemon.polls.clone(),
+        };
+        let poll_interval = self.poll_interval;
+        let running = daemon.running.clone();
+        let thread = std::thread::spawn(move || {
+            // Gilrs isn't `Send` on every platform, so it's created on thread using it.
+            let mut gilrs = match Gilrs::new() {
+                Ok(gilrs) => {
+                    let _ = started_sender.send(Ok(()));
+                    gilrs
+                }
+                Err(error) => {
+                    let _ = started_sender.send(Err(Error::other(error.to_string())));
+                    return;
+                }
+            };
+
+            gamepad_thread.run(&mut gilrs, poll_interval, &running);
+        });
+        let started = started_receiver
+            .recv()
+            .unwrap_or_else(|_| Err(Error::other("Gamepad thread panicked")));
+        daemon.threads.push(thread);
+        started?;
+
+        daemon
+            .threads
+            .push(daemon.server.clone().start(daemon.running.clone()));
+
+        Ok(daemon)
+    }
+}
+
+struct GamepadThread {
+    server: Arc<Server>,
+    inversion: StickInversion,
+    rumble: bool,
+    motion_hook: Option<MotionHook>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    handles: HashMap<GamepadId, SlotHandle>,
+    gamepads: Arc<AtomicUsize>,
+    rejected_gamepads: Arc<AtomicU64>,
+    polls: Arc<AtomicU64>,
+}
+
+impl GamepadThread {
+    fn run(&mut self, gilrs: &mut Gilrs, poll_interval: Duration, running: &AtomicBool) {
+        let connected: Vec<GamepadId> = gilrs.gamepads().map(|(id, _gamepad)| id).collect();
+        for id in connected {
+            self.connect(gilrs, id);
+        }
+
+        while running.load(Ordering::SeqCst) {
+            while let Some(event) = gilrs.next_event() {
+                match event.event {
+                    gilrs::EventType::Connected => self.connect(gilrs, event.id),
+                    gilrs::EventType::Disconnected => self.disconnect(event.id),
+                    _ => {}
+                }
+            }
+
+            self.poll(gilrs);
+            std::thread::sleep(poll_interval);
+        }
+
+        for id in self.handles.keys().copied().collect::<Vec<_>>() {
+            self.disconnect(id);
+        }
+    }
+
+    fn connect(&mut self, gilrs: &mut Gilrs, id: GamepadId) {
+        if self.handles.contains_key(&id) {
+            return;
+        }
+        let controller_info = match gilrs.connected_gamepad(id) {
+            Some(gamepad) => gamepad_controller_info(&gamepad, self.motion_hook.is_some()),
+            None => return,
+        };
+
+        let handle = match self.server.register_device(controller_info) {
+            Some(handle) => handle,
+            None => {
+                self.rejected_gamepads.fetch_add(1, Ordering::SeqCst);
+                return;
+            }
+        };
+        if self.rumble {
+            if let Ok(rumble) = GilrsRumble::new(gilrs, id) {
+                self.server.set_rumble_sink(
+                    handle.slot_number(),
+                    Some(Box::new(OutputSink::new(rumble))),
+                );
+            }
+        }
+
+        self.handles.insert(id, handle);
+        self.gamepads.store(self.handles.len(), Ordering::SeqCst);
+    }
+
+    fn disconnect(&mut self, id: GamepadId) {
+        if let Some(handle) = self.handles.remove(&id) {
+            self.server.set_rumble_sink(handle.slot_number(), None);
+            handle.unregister();
+        }
+        self.gamepads.store(self.handles.len(), Ordering::SeqCst);
+    }
+
+    fn poll(&mut self, gilrs: &Gilrs) {
+        for (&id, handle) in self.handles.iter() {
+            let gamepad = match gilrs.connected_gamepad(id) {
+                Some(gamepad) => gamepad,
+                None => continue,
+            };
+
+            let slot_number = handle.slot_number();
+            let mut controller_data = gamepad_controller_data(&gamepad, self.inversion);
+            if let Some(motion_hook) = &mut self.motion_hook {
+                motion_hook(slot_number, &mut controller_data);
+            }
+
+            if let Some(recorder) = self.recorder.lock().unwrap().as_mut() {
+                recorder.record(slot_number, controller_data);
+            }
+            handle.update_controller_data(controller_data);
+        }
+
+        self.polls.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+/// Server providing gamepads managed by gilrs, with its threads.
+pub struct Daemon {
+    server: Arc<Server>,
+    running: Arc<AtomicBool>,
+    threads: Vec<JoinHandle<()>>,
+    recorder: Arc<Mutex<Option<Recorder>>>,
+    gamepads: Arc<AtomicUsize>,
+    rejected_gamepads: Arc<AtomicU64>,
+    polls: Arc<AtomicU64>,
+}
+
+impl Daemon {
+    /// Returns builder of daemon.
+    pub fn builder() -> DaemonBuilder {
+        Default::default()
+    }
+
+    /// Returns daemon's server, e.g. to set callbacks or inspect slots.
+    pub fn server(&self) -> &Arc<Server> {
+        &self.server
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.running.load(Ordering::SeqCst)
+    }
+
+    /// Starts recording gamepads' data, replacing recording in progress.
+    pub fn start_recording(&self) {
+        *self.recorder.lock().unwrap() = Some(Recorder::new());
+    }
+
+    /// Stops recording, returns `None` if recording wasn't started.
+    pub fn stop_recording(&self) -> Option<Recording> {
+        self.recorder
+            .lock()
+            .unwrap()
+            .take()
+            .map(|recorder| recorder.finish())
+    }
+
+    /// Returns snapshot of daemon metrics.
+    pub fn stats(&self) -> DaemonStats {
+        DaemonStats {
+            gamepads: self.gamepads.load(Ordering::SeqCst),
+            rejected_gamepads: self.rejected_gamepads.load(Ordering::SeqCst),
+            polls: self.polls.load(Ordering::SeqCst),
+            server: self.server.stats(),
+        }
+    }
+
+    /// Stops all threads and waits for them to finish, gamepads' slots are released.
+    pub fn stop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        for thread in self.threads.drain(..) {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Daemon {
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
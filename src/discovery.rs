@@ -0,0 +1,137 @@
+//! Finding servers on local network.
+//!
+//! [`discover`] broadcasts protocol version and controller info requests and collects servers answering them,
+//! so frontends can offer a list of servers instead of asking users for addresses.
+//! Servers bound to localhost (see `server::Visibility`) can't be discovered from other machines.
+
+use crate::protocol::*;
+use crate::transport::*;
+use rand::Rng;
+use std::io::Result;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::time::{Duration, Instant};
+
+/// Default time to wait for answers of servers.
+pub const DEFAULT_DISCOVERY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Server that answered discovery requests.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiscoveredServer {
+    pub address: SocketAddr,
+    pub server_id: u32,
+    /// Protocol version server reported, `None` if it answered controller info request only.
+    pub protocol_version: Option<u16>,
+    /// Info of connected controllers (of standard slots), ordered by slot number.
+    pub connected_slots: Vec<ControllerInfo>,
+}
+
+/// Broadcasts discovery requests to given port of every host of local network
+/// and returns servers answering within timeout.
+///
+/// # Arguments
+///
+/// * `port` - port servers listen on (`26760` by default).
+/// * `timeout` - time to wait for answers.
+pub fn discover(port: u16, timeout: Duration) -> Result<Vec<DiscoveredServer>> {
+    discover_at(SocketAddr::from((Ipv4Addr::BROADCAST, port)), timeout)
+}
+
+/// Same as `discover`, but sends requests to given address (e.g. subnet's broadcast address or single host).
+pub fn discover_at(target: SocketAddr, timeout: Duration) -> Result<Vec<DiscoveredServer>> {
+    let socket = bind_udp_with(
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+        &UdpOptions {
+            read_timeout: Duration::from_millis(50),
+            broadcast: true,
+            ..Default::default()
+        },
+    )?;
+
+    discover_with(&socket, target, timeout)
+}
+
+/// Same as `discover_at`, but uses given transport.
+pub fn discover_with(
+    socket: &dyn Transport,
+    target: SocketAddr,
+    timeout: Duration,
+) -> Result<Vec<DiscoveredServer>> {
+    let header = MessageHeader {
+        source: MessageSource::Client,
+        protocol_version: PROTOCOL_VERSION,
+        message_length: 0,
+        checksum: 0,
+        source_id: rand::thread_rng().gen(),
+    };
+    let requests = [
+        Message {
+            header,
+            message_type: MessageType::ProtocolVersion,
+            payload: MessagePayload::None,
+        },
+        Message {
+            header,
+            message_type: MessageType::ConnectedControllers,
+            payload: MessagePayload::ConnectedControllersRequest {
+                amount: SLOT_COUNT as i32,
+                slot_numbers: [0, 1, 2, 3],
+            },
+        },
+    ];
+    for request in requests {
+        let mut encoded_request = vec![];
+        encode_message(&mut encoded_request, request).unwrap();
+        socket.send_to(&encoded_request, target)?;
+    }
+
+    let mut servers: Vec<DiscoveredServer> = vec![];
+    let mut buf = vec![0; DEFAULT_RECEIVE_BUFFER_SIZE];
+    let deadline = Instant::now() + timeout;
+    while Instant::now() < deadline {
+        let (amount, source) = match socket.recv_from(&mut buf) {
+            Ok(received) => received,
+            Err(error) => match error_severity(&error) {
+                ErrorSeverity::Fatal => return Err(error),
+                _ => continue,
+            },
+        };
+        let message = match parse_message(MessageSource::Server, &buf[..amount], true) {
+            Ok(message) => message,
+            Err(_) => continue,
+        };
+
+        let index = match servers.iter().position(|server| server.address == source) {
+            Some(index) => index,
+            None => {
+                servers.push(DiscoveredServer {
+                    address: source,
+                    server_id: message.header.source_id,
+                    protocol_version: None,
+                    connected_slots: vec![],
+                });
+                servers.len() - 1
+            }
+        };
+        let server = &mut servers[index];
+        match message.payload {
+            MessagePayload::ProtocolVersion(protocol_version) => {
+                server.protocol_version = Some(protocol_version)
+            }
+            MessagePayload::ConnectedControllerResponse { controller_info }
+                if controller_info.slot_state != SlotState::NotConnected
+                    && !server
+                        .connected_slots
+                        .iter()
+                        .any(|connected| connected.slot == controller_info.slot) =>
+            {
+                server.connected_slots.push(controller_info);
+                server
+                    .connected_slots
+                    .sort_by_key(|connected| connected.slot);
+            }
+            _ => {}
+        }
+    }
+
+    Ok(servers)
+}
@@ -0,0 +1,128 @@
+//! Sensor fusion of accelerometer and gyroscope data.
+
+use crate::protocol::*;
+use crate::transform::elapsed_seconds;
+
+/// Result of fusing a single motion sample.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FusedSample {
+    /// Estimated gravity in controller frame as accelerometer reports it at rest, unit length (in g).
+    pub gravity: [f32; 3],
+    /// Accelerometer reading with gravity removed, in g.
+    pub linear_acceleration: [f32; 3],
+}
+
+/// Estimates gravity vector with complementary filter.
+///
+/// Gyroscope rates keep the estimate in sync with fast rotations,
+/// while accelerometer slowly corrects accumulated gyroscope drift.
+/// Gyroscope rates (pitch, yaw, roll) are treated as rotations around accelerometer's X, Y and Z axes.
+#[derive(Copy, Clone, Debug)]
+pub struct GravityEstimator {
+    /// How quickly estimate converges to accelerometer reading, in seconds.
+    /// Larger values reject more linear acceleration, but correct drift slower.
+    pub time_constant: f32,
+    gravity: Option<[f32; 3]>,
+    previous_timestamp: Option<u64>,
+}
+
+const DEFAULT_TIME_CONSTANT: f32 = 0.5;
+
+impl Default for GravityEstimator {
+    fn default() -> GravityEstimator {
+        GravityEstimator::new(DEFAULT_TIME_CONSTANT)
+    }
+}
+
+fn normalized(vector: [f32; 3]) -> Option<[f32; 3]> {
+    let length = (vector[0] * vector[0] + vector[1] * vector[1] + vector[2] * vector[2]).sqrt();
+    if length.is_finite() && length > f32::EPSILON {
+        Some([vector[0] / length, vector[1] / length, vector[2] / length])
+    } else {
+        None
+    }
+}
+
+fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+impl GravityEstimator {
+    pub fn new(time_constant: f32) -> GravityEstimator {
+        GravityEstimator {
+            time_constant,
+            gravity: None,
+            previous_timestamp: None,
+        }
+    }
+
+    /// Current gravity estimate or `None` before first sample.
+    pub fn gravity(&self) -> Option<[f32; 3]> {
+        self.gravity
+    }
+
+    /// Forgets current estimate.
+    pub fn reset(&mut self) {
+        self.gravity = None;
+        self.previous_timestamp = None;
+    }
+
+    /// Fuses next sample.
+    pub fn update(&mut self, controller_data: &ControllerData) -> FusedSample {
+        let acceleration = [
+            controller_data.accelerometer_x,
+            controller_data.accelerometer_y,
+            controller_data.accelerometer_z,
+        ];
+
+        let timestamp = controller_data.motion_data_timestamp;
+        let elapsed = elapsed_seconds(self.previous_timestamp, timestamp);
+        self.previous_timestamp = Some(timestamp);
+
+        let measured = normalized(acceleration);
+        let gravity = match (self.gravity, measured) {
+            (None, Some(measured)) => measured,
+            (None, None) => [0.0, -1.0, 0.0],
+            (Some(gravity), measured) => {
+                // Gravity rotates opposite to controller.
+                let rate = [
+                    controller_data.gyroscope_pitch.to_radians(),
+                    controller_data.gyroscope_yaw.to_radians(),
+                    controller_data.gyroscope_roll.to_radians(),
+                ];
+                let change = cross(rate, gravity);
+                let predicted = [
+                    gravity[0] - change[0] * elapsed,
+                    gravity[1] - change[1] * elapsed,
+                    gravity[2] - change[2] * elapsed,
+                ];
+
+                let alpha = elapsed / (self.time_constant + elapsed);
+                let blended = match measured {
+                    Some(measured) => [
+                        predicted[0] + (measured[0] - predicted[0]) * alpha,
+                        predicted[1] + (measured[1] - predicted[1]) * alpha,
+                        predicted[2] + (measured[2] - predicted[2]) * alpha,
+                    ],
+                    None => predicted,
+                };
+
+                normalized(blended).unwrap_or(gravity)
+            }
+        };
+        self.gravity = Some(gravity);
+
+        FusedSample {
+            gravity,
+            linear_acceleration: [
+                acceleration[0] - gravity[0],
+                acceleration[1] - gravity[1],
+                acceleration[2] - gravity[2],
+            ],
+        }
+    }
+}
@@ -0,0 +1,295 @@
+//! Named per-game transform profiles, switched manually or by active window.
+//!
+//! Available with `profiles` feature.
+//!
+//! Unlike per-controller profiles of [`profiles`](crate::profiles), game profiles apply to every slot
+//! [`GameProfileTransform`] is installed on, and [`GameProfileHandle`] switches them from any thread,
+//! e.g. when [`watch_active_window`] detects another game's window.
+
+use crate::profiles::TransformConfig;
+use crate::protocol::ControllerData;
+use crate::transform::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::process::Command;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Transforms applied while a game is running.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameProfile {
+    /// Case-insensitive substrings of window title activating this profile.
+    pub windows: Vec<String>,
+    pub transforms: Vec<TransformConfig>,
+}
+
+impl GameProfile {
+    /// Checks whether window with given title belongs to this game.
+    pub fn matches_window(&self, window_title: &str) -> bool {
+        let window_title = window_title.to_lowercase();
+        self.windows
+            .iter()
+            .any(|pattern| !pattern.is_empty() && window_title.contains(&pattern.to_lowercase()))
+    }
+
+    /// Creates pipeline applying configured transforms.
+    pub fn pipeline(&self) -> Pipeline {
+        let mut pipeline = Pipeline::new();
+        for transform in self.transforms.iter() {
+            pipeline.push(transform.build());
+        }
+
+        pipeline
+    }
+}
+
+/// Collection of named game profiles.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameProfiles {
+    /// Profile active when no profile matches active window.
+    pub default: Option<String>,
+    pub profiles: BTreeMap<String, GameProfile>,
+}
+
+fn invalid_data_error<E: std::fmt::Display>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+impl GameProfiles {
+    /// Creates new, empty collection.
+    pub fn new() -> GameProfiles {
+        Default::default()
+    }
+
+    /// Loads profiles from JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<GameProfiles> {
+        let json = fs::read_to_string(path)?;
+        GameProfiles::from_json(&json)
+    }
+
+    /// Saves profiles to JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+
+    /// Parses profiles from JSON string.
+    pub fn from_json(json: &str) -> Result<GameProfiles> {
+        let profiles: GameProfiles = serde_json::from_str(json).map_err(invalid_data_error)?;
+        if let Some(default) = &profiles.default {
+            if !profiles.profiles.contains_key(default) {
+                return Err(invalid_data_error("Default game profile doesn't exist"));
+            }
+        }
+
+        Ok(profiles)
+    }
+
+    /// Serializes profiles to JSON string.
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).map_err(invalid_data_error)
+    }
+
+    /// Returns name of the first profile (in name order) matching window with given title.
+    pub fn match_window(&self, window_title: &str) -> Option<&str> {
+        self.profiles
+            .iter()
+            .find(|(_, profile)| profile.matches_window(window_title))
+            .map(|(name, _)| name.as_str())
+    }
+
+    /// Creates handle switching between profiles, starting with default profile.
+    pub fn handle(self) -> GameProfileHandle {
+        let names: Vec<String> = self.profiles.keys().cloned().collect();
+        let active = self
+            .default
+            .as_ref()
+            .and_then(|default| names.iter().position(|name| name == default))
+            .unwrap_or(NO_PROFILE);
+
+        GameProfileHandle {
+            profiles: Arc::new(self),
+            names: Arc::new(names),
+            active: Arc::new(AtomicUsize::new(active)),
+        }
+    }
+}
+
+const NO_PROFILE: usize = usize::MAX;
+
+/// Switches active game profile of all associated `GameProfileTransform`s from any thread.
+#[derive(Clone, Debug)]
+pub struct GameProfileHandle {
+    profiles: Arc<GameProfiles>,
+    names: Arc<Vec<String>>,
+    active: Arc<AtomicUsize>,
+}
+
+impl GameProfileHandle {
+    pub fn profiles(&self) -> &GameProfiles {
+        &self.profiles
+    }
+
+    /// Name of active profile, `None` if no profile is active.
+    pub fn active_name(&self) -> Option<&str> {
+        self.names
+            .get(self.active.load(Ordering::SeqCst))
+            .map(|name| name.as_str())
+    }
+
+    /// Activates profile with given name, returns `false` if there's no such profile.
+    pub fn select(&self, name: &str) -> bool {
+        match self
+            .names
+            .iter()
+            .position(|profile_name| profile_name == name)
+        {
+            Some(index) => {
+                self.active.store(index, Ordering::SeqCst);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deactivates profiles, controller data passes unchanged.
+    pub fn deselect(&self) {
+        self.active.store(NO_PROFILE, Ordering::SeqCst);
+    }
+
+    /// Activates profile matching window with given title, or default profile if none matches.
+    /// Returns name of activated profile.
+    pub fn select_for_window(&self, window_title: &str) -> Option<&str> {
+        let name = self
+            .profiles
+            .match_window(window_title)
+            .or(self.profiles.default.as_deref());
+
+        match name {
+            Some(name) => {
+                self.select(name);
+            }
+            None => self.deselect(),
+        }
+
+        self.active_name()
+    }
+
+    /// Creates transform applying active profile, install one on every slot profiles should affect.
+    pub fn transform(&self) -> GameProfileTransform {
+        GameProfileTransform {
+            pipelines: self
+                .names
+                .iter()
+                .map(|name| self.profiles.profiles[name].pipeline())
+                .collect(),
+            active: self.active.clone(),
+        }
+    }
+}
+
+/// Applies transforms of active game profile, see `GameProfileHandle`.
+pub struct GameProfileTransform {
+    pipelines: Vec<Pipeline>,
+    active: Arc<AtomicUsize>,
+}
+
+impl Transform for GameProfileTransform {
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData) {
+        if let Some(pipeline) = self.pipelines.get_mut(self.active.load(Ordering::SeqCst)) {
+            pipeline.apply(slot_number, controller_data);
+        }
+    }
+}
+
+fn command_output(command: &mut Command) -> Result<String> {
+    let output = command.output()?;
+    if !output.status.success() {
+        return Err(Error::other(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Returns title of focused window.
+///
+/// Supported on Linux with X11 (requires `xprop`) and macOS (returns name of frontmost application),
+/// fails with `ErrorKind::Unsupported` error elsewhere.
+#[cfg(target_os = "linux")]
+pub fn active_window_title() -> Result<String> {
+    let active_window =
+        command_output(Command::new("xprop").args(["-root", "_NET_ACTIVE_WINDOW"]))?;
+    let window_id = active_window
+        .rsplit(' ')
+        .next()
+        .filter(|window_id| window_id.starts_with("0x"))
+        .ok_or_else(|| invalid_data_error("No active window"))?;
+
+    let window_name =
+        command_output(Command::new("xprop").args(["-id", window_id, "_NET_WM_NAME"]))?;
+    match window_name.split_once(" = ") {
+        Some((_, title)) => Ok(title.trim_matches('"').to_string()),
+        None => Err(invalid_data_error("Active window has no title")),
+    }
+}
+
+/// Returns title of focused window.
+///
+/// Supported on Linux with X11 (requires `xprop`) and macOS (returns name of frontmost application),
+/// fails with `ErrorKind::Unsupported` error elsewhere.
+#[cfg(target_os = "macos")]
+pub fn active_window_title() -> Result<String> {
+    command_output(Command::new("osascript").args([
+        "-e",
+        "tell application \"System Events\" to get name of first application process whose frontmost is true",
+    ]))
+}
+
+/// Returns title of focused window.
+///
+/// Supported on Linux with X11 (requires `xprop`) and macOS (returns name of frontmost application),
+/// fails with `ErrorKind::Unsupported` error elsewhere.
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn active_window_title() -> Result<String> {
+    Err(Error::new(
+        ErrorKind::Unsupported,
+        "Active window detection is not supported on this platform",
+    ))
+}
+
+/// Starts background thread switching game profile whenever focused window changes.
+///
+/// Thread stops when `countinue_running` is set to `false`.
+///
+/// # Arguments
+///
+/// * `handle` - handle of switched profiles.
+/// * `interval` - how often focused window is checked.
+/// * `countinue_running` - flag stopping thread.
+pub fn watch_active_window(
+    handle: GameProfileHandle,
+    interval: Duration,
+    countinue_running: Arc<AtomicBool>,
+) -> JoinHandle<()> {
+    std::thread::spawn(move || {
+        let mut previous_title = None;
+        while countinue_running.load(Ordering::SeqCst) {
+            if let Ok(title) = active_window_title() {
+                if previous_title.as_ref() != Some(&title) {
+                    handle.select_for_window(&title);
+                    previous_title = Some(title);
+                }
+            }
+
+            std::thread::sleep(interval);
+        }
+    })
+}
@@ -0,0 +1,114 @@
+//! In-memory ring buffer of recent slot states, answering "what did the controller do right before..." questions.
+
+use crate::protocol::*;
+use crate::recording::*;
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+/// Maximum amount of entries history keeps regardless of its duration, bounds memory used by fast producers.
+pub const MAX_HISTORY_ENTRIES: usize = 1 << 18;
+
+/// State of a slot after one of its updates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    pub time: Instant,
+    pub controller_info: ControllerInfo,
+    pub controller_data: ControllerData,
+}
+
+/// Ring buffer of slot states not older than given duration (relative to the latest entry).
+#[derive(Clone, Debug)]
+pub struct StateHistory {
+    duration: Duration,
+    entries: VecDeque<HistoryEntry>,
+}
+
+impl StateHistory {
+    /// Creates new, empty history keeping entries for given duration.
+    pub fn new(duration: Duration) -> StateHistory {
+        StateHistory {
+            duration,
+            entries: VecDeque::new(),
+        }
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// Changes how long entries are kept, dropping entries that are too old already.
+    pub fn set_duration(&mut self, duration: Duration) {
+        self.duration = duration;
+        self.drop_old_entries();
+    }
+
+    /// Adds entry, dropping entries older than history's duration.
+    pub fn push(&mut self, entry: HistoryEntry) {
+        self.entries.push_back(entry);
+        if self.entries.len() > MAX_HISTORY_ENTRIES {
+            self.entries.pop_front();
+        }
+
+        self.drop_old_entries();
+    }
+
+    fn drop_old_entries(&mut self) {
+        let latest_time = match self.entries.back() {
+            Some(latest) => latest.time,
+            None => return,
+        };
+
+        while let Some(oldest) = self.entries.front() {
+            if latest_time.duration_since(oldest.time) <= self.duration {
+                break;
+            }
+
+            self.entries.pop_front();
+        }
+    }
+
+    /// Entries from the oldest to the latest.
+    pub fn entries(&self) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries.iter()
+    }
+
+    /// Entries of given slot from the oldest to the latest.
+    pub fn slot_entries(&self, slot_number: u8) -> impl Iterator<Item = &HistoryEntry> {
+        self.entries
+            .iter()
+            .filter(move |entry| entry.controller_info.slot == slot_number)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Converts history to recording, frame times are relative to the oldest entry.
+    pub fn to_recording(&self) -> Recording {
+        let start = match self.entries.front() {
+            Some(oldest) => oldest.time,
+            None => return Recording::new(),
+        };
+
+        let frames = self
+            .entries
+            .iter()
+            .map(|entry| RecordedFrame {
+                // Recordings store time with microsecond precision.
+                time: Duration::from_micros(entry.time.duration_since(start).as_micros() as u64),
+                slot: entry.controller_info.slot,
+                controller_data: entry.controller_data,
+            })
+            .collect();
+
+        Recording { frames }
+    }
+}
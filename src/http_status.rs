@@ -0,0 +1,205 @@
+//! Minimal HTTP endpoint serving JSON snapshot of a running [`Server`](crate::server::Server).
+//!
+//! Available with `http-status` feature.
+//!
+//! `GET /` (or `GET /status`) returns slots, connected clients and server metrics.
+
+use crate::protocol::*;
+use crate::server::*;
+use std::fmt::Write as _;
+use std::io::prelude::*;
+use std::io::Result;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+fn json_f32(output: &mut String, value: f32) {
+    if value.is_finite() {
+        let _ = write!(output, "{}", value);
+    } else {
+        output.push_str("null");
+    }
+}
+
+fn json_controller_info(output: &mut String, controller_info: &ControllerInfo) {
+    let _ = write!(
+        output,
+        "{{\"slot\":{},\"slot_state\":\"{:?}\",\"device_type\":\"{:?}\",\"connection_type\":\"{:?}\",\"mac_address\":\"{:012x}\",\"battery_status\":\"{:?}\"}}",
+        controller_info.slot,
+        controller_info.slot_state,
+        controller_info.device_type,
+        controller_info.connection_type,
+        controller_info.mac_address,
+        controller_info.battery_status,
+    );
+}
+
+fn json_controller_data(output: &mut String, controller_data: &ControllerData) {
+    let _ = write!(
+        output,
+        "{{\"connected\":{},\"motion_data_timestamp\":{},\"accelerometer\":[",
+        controller_data.connected, controller_data.motion_data_timestamp,
+    );
+    json_f32(output, controller_data.accelerometer_x);
+    output.push(',');
+    json_f32(output, controller_data.accelerometer_y);
+    output.push(',');
+    json_f32(output, controller_data.accelerometer_z);
+    output.push_str("],\"gyroscope\":[");
+    json_f32(output, controller_data.gyroscope_pitch);
+    output.push(',');
+    json_f32(output, controller_data.gyroscope_yaw);
+    output.push(',');
+    json_f32(output, controller_data.gyroscope_roll);
+    let _ = write!(
+        output,
+        "],\"left_stick\":[{},{}],\"right_stick\":[{},{}]}}",
+        controller_data.left_stick_x,
+        controller_data.left_stick_y,
+        controller_data.right_stick_x,
+        controller_data.right_stick_y,
+    );
+}
+
+fn json_client(output: &mut String, client: &ClientStats) {
+    let _ = write!(
+        output,
+        "{{\"address\":\"{}\",\"packet_number\":{},\"since_last_request_ms\":{},\"slot_numbers\":{:?},\"mac_addresses\":[",
+        client.address,
+        client.packet_number,
+        client.since_last_request.as_secs_f64() * 1000.0,
+        client.slot_numbers,
+    );
+    for (i, mac_address) in client.mac_addresses.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        let _ = write!(output, "\"{:012x}\"", mac_address);
+    }
+
+    let request_intervals = &client.request_intervals;
+    let milliseconds = |duration: Option<Duration>| match duration {
+        Some(duration) => format!("{}", duration.as_secs_f64() * 1000.0),
+        None => "null".to_string(),
+    };
+    let _ = write!(
+        output,
+        "],\"request_intervals\":{{\"bucket_bounds_ms\":{:?},\"counts\":{:?},\"min_ms\":{},\"mean_ms\":{},\"max_ms\":{}}}}}",
+        REQUEST_INTERVAL_BUCKETS
+            .iter()
+            .map(|bound| bound.as_millis())
+            .collect::<Vec<_>>(),
+        request_intervals.counts,
+        milliseconds(request_intervals.min),
+        milliseconds(request_intervals.mean()),
+        milliseconds(request_intervals.max),
+    );
+}
+
+/// Renders JSON snapshot of given server.
+pub fn status_json(server: &Server) -> String {
+    let mut output = String::new();
+
+    output.push_str("{\"slots\":[");
+    for slot_number in 0..server.slot_count() {
+        if slot_number > 0 {
+            output.push(',');
+        }
+        output.push_str("{\"info\":");
+        json_controller_info(&mut output, &server.controller_info(slot_number));
+        output.push_str(",\"data\":");
+        json_controller_data(&mut output, &server.controller_data(slot_number));
+        output.push('}');
+    }
+
+    let stats = server.stats();
+    output.push_str("],\"clients\":[");
+    for (i, client) in stats.clients.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        json_client(&mut output, client);
+    }
+
+    let metrics = [
+        ("received_packets", stats.received_packets),
+        ("invalid_packets", stats.invalid_packets),
+        ("oversize_packets", stats.oversize_packets),
+        ("sent_packets", stats.sent_packets),
+        ("identity_mismatches", stats.identity_mismatches),
+        ("rejected_replays", stats.rejected_replays),
+        ("throttled_info_requests", stats.throttled_info_requests),
+        ("stale_data_updates", stats.stale_data_updates),
+        ("invalid_motion_updates", stats.invalid_motion_updates),
+        ("thread_panics", stats.thread_panics),
+    ];
+    output.push_str("],\"metrics\":{");
+    for (i, (name, value)) in metrics.iter().enumerate() {
+        if i > 0 {
+            output.push(',');
+        }
+        let _ = write!(output, "\"{}\":{}", name, value);
+    }
+    output.push_str("}}");
+
+    output
+}
+
+fn handle_connection(server: &Server, mut stream: TcpStream) -> Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(1)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(1)))?;
+
+    let mut buf = [0_u8; 1024];
+    let amount = stream.read(&mut buf)?;
+    let request = String::from_utf8_lossy(&buf[..amount]);
+    let mut request_line = request.lines().next().unwrap_or("").split_whitespace();
+
+    let response = match (request_line.next(), request_line.next()) {
+        (Some("GET"), Some("/")) | (Some("GET"), Some("/status")) => {
+            let body = status_json(server);
+            format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            )
+        }
+        (Some("GET"), _) => {
+            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+        }
+        _ => "HTTP/1.1 405 Method Not Allowed\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+            .to_string(),
+    };
+
+    stream.write_all(response.as_bytes())
+}
+
+/// Starts background thread serving status of given server over HTTP.
+///
+/// # Arguments
+///
+/// * `server` - server to report on.
+/// * `address` - TCP socket address to listen on.
+/// * `countinue_running` - thread stops when this is set to `false`.
+pub fn start(
+    server: Arc<Server>,
+    address: SocketAddr,
+    countinue_running: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>> {
+    let listener = TcpListener::bind(address)?;
+    listener.set_nonblocking(true)?;
+
+    Ok(std::thread::spawn(move || {
+        while countinue_running.load(Ordering::SeqCst) {
+            match listener.accept() {
+                Ok((stream, _address)) => {
+                    let _ = stream
+                        .set_nonblocking(false)
+                        .and_then(|_| handle_connection(&server, stream));
+                }
+                Err(_) => std::thread::sleep(Duration::from_millis(50)),
+            }
+        }
+    }))
+}
@@ -4,6 +4,38 @@
 //!
 //! Navigate to github repository for [examples](https://github.com/zduny/pad_motion/tree/master/examples) of usage.
 
+pub mod aggregate;
+pub mod analysis;
+pub mod bridge;
+pub mod buttons;
 pub mod client;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod conventions;
+#[cfg(feature = "daemon")]
+pub mod daemon;
+pub mod discovery;
+pub mod fusion;
+#[cfg(feature = "profiles")]
+pub mod game_profiles;
+pub mod history;
+#[cfg(feature = "http-status")]
+pub mod http_status;
+pub mod load_test;
+#[cfg(feature = "profiles")]
+pub mod profiles;
 pub mod protocol;
+#[cfg(feature = "quic")]
+pub mod quic;
+pub mod recording;
+pub mod relay;
+pub mod rumble;
+pub mod sampler;
 pub mod server;
+pub mod source;
+pub mod spectator;
+pub mod state;
+#[cfg(feature = "testing")]
+pub mod testing;
+pub mod transform;
+pub mod transport;
@@ -0,0 +1,262 @@
+//! Load testing of server fan-out, for sizing hardware running multiple emulators.
+//!
+//! [`run_load_test`] feeds server's slot with synthetic data at fixed rate while synthetic clients
+//! request it, then reports achieved packet rates and latencies (from feeding data to its reception).
+
+use crate::protocol::*;
+use crate::server::{DsServer, Server};
+use crate::transport::*;
+use std::io::Result;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Parameters of load test.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LoadTestConfig {
+    /// Number of synthetic clients.
+    pub clients: usize,
+    /// Time data is fed for.
+    pub duration: Duration,
+    /// Controller data updates per second.
+    pub update_rate: f64,
+    /// Time between data requests of each client.
+    pub request_interval: Duration,
+    /// Slot data is fed into.
+    pub slot: u8,
+}
+
+impl Default for LoadTestConfig {
+    fn default() -> LoadTestConfig {
+        LoadTestConfig {
+            clients: 10,
+            duration: Duration::from_secs(10),
+            update_rate: 250.0,
+            request_interval: Duration::from_secs(1),
+            slot: 0,
+        }
+    }
+}
+
+/// Results of load test.
+#[derive(Clone, Debug, PartialEq)]
+pub struct LoadTestReport {
+    pub clients: usize,
+    /// Time data was fed for.
+    pub duration: Duration,
+    /// Updates fed to server.
+    pub updates: u64,
+    /// Data packets received by all clients.
+    pub received_packets: u64,
+    /// Data packets clients should have received if none were lost (updates times clients).
+    pub expected_packets: u64,
+    /// Latency from feeding data to its reception, `None` if no packets were received.
+    pub latency: Option<LatencyReport>,
+}
+
+impl LoadTestReport {
+    /// Updates fed per second.
+    pub fn update_rate(&self) -> f64 {
+        self.updates as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Data packets received per second by all clients together.
+    pub fn packet_rate(&self) -> f64 {
+        self.received_packets as f64 / self.duration.as_secs_f64()
+    }
+
+    /// Fraction (from 0 to 1) of expected packets that were received.
+    pub fn delivery_ratio(&self) -> f64 {
+        if self.expected_packets == 0 {
+            return 1.0;
+        }
+
+        self.received_packets as f64 / self.expected_packets as f64
+    }
+}
+
+/// Distribution of packet latencies.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct LatencyReport {
+    pub mean: Duration,
+    pub median: Duration,
+    /// 99th percentile.
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl LatencyReport {
+    fn from_latencies(latencies: &mut [u64]) -> Option<LatencyReport> {
+        if latencies.is_empty() {
+            return None;
+        }
+
+        latencies.sort_unstable();
+        let percentile = |percentile: usize| {
+            let index = (latencies.len() * percentile / 100).min(latencies.len() - 1);
+            Duration::from_micros(latencies[index])
+        };
+        let sum: u64 = latencies.iter().sum();
+
+        Some(LatencyReport {
+            mean: Duration::from_micros(sum / latencies.len() as u64),
+            median: percentile(50),
+            p99: percentile(99),
+            max: Duration::from_micros(latencies[latencies.len() - 1]),
+        })
+    }
+}
+
+/// Time clients keep receiving after feeding stops, so packets in flight are counted.
+const DRAIN_TIME: Duration = Duration::from_millis(500);
+
+fn data_request() -> Vec<u8> {
+    let message = Message {
+        header: MessageHeader {
+            source: MessageSource::Client,
+            protocol_version: PROTOCOL_VERSION,
+            message_length: 0,
+            checksum: 0,
+            source_id: rand::random(),
+        },
+        message_type: MessageType::ControllerData,
+        payload: MessagePayload::ControllerDataRequest(ControllerDataRequest::ReportAll),
+    };
+
+    let mut encoded_message = vec![];
+    encode_message(&mut encoded_message, message).unwrap();
+
+    encoded_message
+}
+
+/// Runs load test against server, blocking until it finishes.
+///
+/// Server is started for the duration of test, its slot given in `config` is overwritten with synthetic data.
+/// Synthetic clients use UDP sockets on server's IP address, so server must use UDP transport.
+///
+/// # Arguments
+///
+/// * `server` - server to test, not yet started.
+/// * `config` - test parameters.
+pub fn run_load_test(server: Server, config: &LoadTestConfig) -> Result<LoadTestReport> {
+    assert!(config.slot < server.slot_count());
+    assert!(config.update_rate > 0.0);
+
+    let server_address = server.local_addr()?;
+    let client_ip = match server_address.ip() {
+        ip if ip.is_unspecified() => IpAddr::V4(Ipv4Addr::LOCALHOST),
+        ip => ip,
+    };
+    let server_address = SocketAddr::new(client_ip, server_address.port());
+
+    let server = Arc::new(server);
+    let server_running = Arc::new(AtomicBool::new(true));
+    let server_thread = server.clone().start(server_running.clone());
+
+    let start = Instant::now();
+    let feeding = Arc::new(AtomicBool::new(true));
+    let mut client_threads = vec![];
+    for _ in 0..config.clients {
+        let socket = bind_udp(SocketAddr::new(client_ip, 0))?;
+        let feeding = feeding.clone();
+        let request = data_request();
+        let request_interval = config.request_interval;
+        let slot = config.slot;
+        client_threads.push(std::thread::spawn(move || {
+            let mut latencies = vec![];
+            let mut last_timestamp = 0;
+            let mut buf = [0; DEFAULT_RECEIVE_BUFFER_SIZE];
+            let mut last_request: Option<Instant> = None;
+            let mut drain_start: Option<Instant> = None;
+            loop {
+                if !feeding.load(Ordering::SeqCst) {
+                    let drain_start = *drain_start.get_or_insert_with(Instant::now);
+                    if drain_start.elapsed() >= DRAIN_TIME {
+                        break;
+                    }
+                } else if last_request.is_none_or(|last| last.elapsed() >= request_interval) {
+                    let _ = socket.send_to(&request, server_address);
+                    last_request = Some(Instant::now());
+                }
+
+                let amount = match socket.recv_from(&mut buf) {
+                    Ok((amount, _)) => amount,
+                    Err(_) => continue,
+                };
+                let received = start.elapsed().as_micros() as u64;
+                if let Ok(Message {
+                    payload:
+                        MessagePayload::ControllerData {
+                            controller_info,
+                            controller_data,
+                            ..
+                        },
+                    ..
+                }) = parse_message(MessageSource::Server, &buf[..amount], true)
+                {
+                    // Responses to data requests repeat the latest update, they are not counted.
+                    if controller_info.slot == slot
+                        && controller_data.connected
+                        && controller_data.motion_data_timestamp > last_timestamp
+                    {
+                        last_timestamp = controller_data.motion_data_timestamp;
+                        latencies
+                            .push(received.saturating_sub(controller_data.motion_data_timestamp));
+                    }
+                }
+            }
+
+            latencies
+        }));
+    }
+
+    server.update_controller_info(ControllerInfo {
+        slot: config.slot,
+        slot_state: SlotState::Connected,
+        device_type: DeviceType::FullGyro,
+        connection_type: ConnectionType::USB,
+        ..Default::default()
+    });
+
+    // Give clients time to send their first requests.
+    std::thread::sleep(Duration::from_millis(100));
+
+    let update_interval = Duration::from_secs_f64(1.0 / config.update_rate);
+    let feeding_start = Instant::now();
+    let mut updates = 0;
+    while feeding_start.elapsed() < config.duration {
+        let controller_data = ControllerData {
+            connected: true,
+            motion_data_timestamp: start.elapsed().as_micros() as u64,
+            ..Default::default()
+        };
+        server.update_controller_data(config.slot, controller_data);
+        updates += 1;
+
+        let next_update = feeding_start + update_interval.mul_f64(updates as f64);
+        let now = Instant::now();
+        if next_update > now {
+            std::thread::sleep(next_update - now);
+        }
+    }
+    let duration = feeding_start.elapsed();
+    feeding.store(false, Ordering::SeqCst);
+
+    let mut latencies = vec![];
+    for client_thread in client_threads {
+        latencies.extend(client_thread.join().unwrap());
+    }
+
+    server_running.store(false, Ordering::SeqCst);
+    let _ = server_thread.join();
+
+    Ok(LoadTestReport {
+        clients: config.clients,
+        duration,
+        updates,
+        received_packets: latencies.len() as u64,
+        expected_packets: updates * config.clients as u64,
+        latency: LatencyReport::from_latencies(&mut latencies),
+    })
+}
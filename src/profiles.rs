@@ -0,0 +1,175 @@
+//! Per-controller settings persisted in a JSON file.
+//!
+//! Available with `profiles` feature.
+//!
+//! Profiles are keyed by controller MAC address. When installed on a [`Server`](crate::server::Server)
+//! with `set_profiles`, profile's calibration and transforms are applied automatically
+//! whenever controller with matching MAC address appears in a slot.
+
+use crate::transform::*;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+/// Configuration of a transform that can be stored in a profile.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum TransformConfig {
+    StickTouch {
+        speed: f32,
+        dead_zone: f32,
+        #[serde(default)]
+        consume_stick: bool,
+    },
+    AxisRemap {
+        matrix: [[f32; 3]; 3],
+    },
+    Southpaw {
+        swap_shoulders: bool,
+        mirror_motion: bool,
+    },
+}
+
+impl TransformConfig {
+    /// Creates transform described by this configuration.
+    pub fn build(&self) -> Box<dyn Transform> {
+        match *self {
+            TransformConfig::StickTouch {
+                speed,
+                dead_zone,
+                consume_stick,
+            } => {
+                let mut stick_touch = StickTouch::default();
+                stick_touch.speed = speed;
+                stick_touch.dead_zone = dead_zone;
+                stick_touch.consume_stick = consume_stick;
+
+                Box::new(stick_touch)
+            }
+            TransformConfig::AxisRemap { matrix } => Box::new(AxisRemap::new(matrix)),
+            TransformConfig::Southpaw {
+                swap_shoulders,
+                mirror_motion,
+            } => Box::new(Southpaw {
+                swap_shoulders,
+                mirror_motion,
+            }),
+        }
+    }
+}
+
+/// Settings of a single controller.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ControllerProfile {
+    pub display_name: Option<String>,
+    pub preferred_slot: Option<u8>,
+    pub calibration: Calibration,
+    pub transforms: Vec<TransformConfig>,
+}
+
+impl ControllerProfile {
+    /// Creates pipeline applying calibration followed by configured transforms.
+    pub fn pipeline(&self) -> Pipeline {
+        let mut pipeline = Pipeline::new().with(self.calibration);
+        for transform in self.transforms.iter() {
+            pipeline.push(transform.build());
+        }
+
+        pipeline
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ProfileFile {
+    profiles: BTreeMap<String, ControllerProfile>,
+}
+
+/// Collection of controller profiles keyed by MAC address.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ProfileStore {
+    profiles: BTreeMap<u64, ControllerProfile>,
+}
+
+fn invalid_data_error<E: std::fmt::Display>(error: E) -> Error {
+    Error::new(ErrorKind::InvalidData, error.to_string())
+}
+
+impl ProfileStore {
+    /// Creates new, empty store.
+    pub fn new() -> ProfileStore {
+        Default::default()
+    }
+
+    /// Loads profiles from JSON file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ProfileStore> {
+        let json = fs::read_to_string(path)?;
+        ProfileStore::from_json(&json)
+    }
+
+    /// Loads profiles from JSON file or returns empty store if file doesn't exist.
+    pub fn load_or_default<P: AsRef<Path>>(path: P) -> Result<ProfileStore> {
+        match fs::read_to_string(path) {
+            Ok(json) => ProfileStore::from_json(&json),
+            Err(error) if error.kind() == ErrorKind::NotFound => Ok(ProfileStore::new()),
+            Err(error) => Err(error),
+        }
+    }
+
+    /// Saves profiles to JSON file.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        fs::write(path, self.to_json()?)
+    }
+
+    /// Parses profiles from JSON string.
+    pub fn from_json(json: &str) -> Result<ProfileStore> {
+        let file: ProfileFile = serde_json::from_str(json).map_err(invalid_data_error)?;
+
+        let mut profiles = BTreeMap::new();
+        for (mac_address, profile) in file.profiles {
+            let mac_address = u64::from_str_radix(&mac_address.replace(':', ""), 16)
+                .map_err(|_| invalid_data_error("Invalid MAC address"))?;
+            profiles.insert(mac_address, profile);
+        }
+
+        Ok(ProfileStore { profiles })
+    }
+
+    /// Serializes profiles to JSON string.
+    pub fn to_json(&self) -> Result<String> {
+        let file = ProfileFile {
+            profiles: self
+                .profiles
+                .iter()
+                .map(|(mac_address, profile)| (format!("{:012x}", mac_address), profile.clone()))
+                .collect(),
+        };
+
+        serde_json::to_string_pretty(&file).map_err(invalid_data_error)
+    }
+
+    pub fn get(&self, mac_address: u64) -> Option<&ControllerProfile> {
+        self.profiles.get(&mac_address)
+    }
+
+    pub fn get_mut(&mut self, mac_address: u64) -> Option<&mut ControllerProfile> {
+        self.profiles.get_mut(&mac_address)
+    }
+
+    pub fn insert(&mut self, mac_address: u64, profile: ControllerProfile) {
+        self.profiles.insert(mac_address, profile);
+    }
+
+    pub fn remove(&mut self, mac_address: u64) -> Option<ControllerProfile> {
+        self.profiles.remove(&mac_address)
+    }
+
+    /// Iterates over MAC addresses and their profiles.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &ControllerProfile)> {
+        self.profiles
+            .iter()
+            .map(|(&mac_address, profile)| (mac_address, profile))
+    }
+}
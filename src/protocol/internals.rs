@@ -1,10 +1,10 @@
 use super::*;
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use std::io::prelude::*;
-use std::io::{Cursor, Error, ErrorKind, Result};
+use std::io::{Cursor, Error, Result};
 
-fn invalid_data_error(message: &str) -> Error {
-    Error::new(ErrorKind::InvalidData, message)
+fn invalid_data_error(message: &'static str) -> Error {
+    ProtocolError::InvalidValue(message).into()
 }
 
 pub fn encode_message_header(writer: &mut Vec<u8>, message_header: MessageHeader) -> Result<()> {
@@ -23,21 +23,11 @@ pub fn parse_message_header(reader: &mut Cursor<&[u8]>) -> Result<MessageHeader>
     let source = {
         let mut buffer = [0_u8; 4];
         reader.read_exact(&mut buffer)?;
-        let magic_string = {
-            let string = std::str::from_utf8(&buffer);
-
-            match string {
-                Ok(str) => Ok(str),
-                _ => Err(invalid_data_error(
-                    "Magic string is not a valid UTF-8 string",
-                )),
-            }
-        }?;
 
-        match magic_string {
-            "DSUS" => Ok(MessageSource::Server),
-            "DSUC" => Ok(MessageSource::Client),
-            _ => Err(invalid_data_error("Unrecognized magic string")),
+        match &buffer {
+            b"DSUS" => Ok(MessageSource::Server),
+            b"DSUC" => Ok(MessageSource::Client),
+            _ => Err(Error::from(ProtocolError::BadMagic)),
         }?
     };
 
@@ -60,6 +50,10 @@ pub fn encode_message_type(writer: &mut Vec<u8>, message_type: MessageType) -> R
         MessageType::ProtocolVersion => 0x100000,
         MessageType::ConnectedControllers => 0x100001,
         MessageType::ControllerData => 0x100002,
+        MessageType::SlotHealth => 0x1F0000,
+        MessageType::Compression => 0x1F0001,
+        MessageType::MotorInfo => 0x110001,
+        MessageType::Rumble => 0x110002,
     };
 
     writer.write_u32::<LittleEndian>(encoded)
@@ -73,6 +67,10 @@ pub fn parse_message_type(reader: &mut Cursor<&[u8]>) -> Result<MessageType> {
             0x100000 => Ok(MessageType::ProtocolVersion),
             0x100001 => Ok(MessageType::ConnectedControllers),
             0x100002 => Ok(MessageType::ControllerData),
+            0x1F0000 => Ok(MessageType::SlotHealth),
+            0x1F0001 => Ok(MessageType::Compression),
+            0x110001 => Ok(MessageType::MotorInfo),
+            0x110002 => Ok(MessageType::Rumble),
             _ => Err(invalid_data_error("Invalid message type")),
         }
     }?;
@@ -121,7 +119,7 @@ pub fn encode_controller_info(writer: &mut Vec<u8>, controller_info: ControllerI
 
 pub fn parse_controller_info(reader: &mut Cursor<&[u8]>) -> Result<ControllerInfo> {
     let slot = reader.read_u8()?;
-    if slot > 4 {
+    if slot >= MAX_SLOT_COUNT {
         return Err(invalid_data_error("Invalid slot number"));
     }
 
@@ -246,23 +244,21 @@ pub fn encode_controller_data_request(
 }
 
 pub fn parse_controller_data_request(reader: &mut Cursor<&[u8]>) -> Result<ControllerDataRequest> {
+    // Request always consists of request type, slot number and MAC address, regardless of its type.
     let request_type = reader.read_u8()?;
+    let slot_number = reader.read_u8()?;
+    let mac_address = reader.read_u48::<LittleEndian>()?;
 
     match request_type {
         0 => Ok(ControllerDataRequest::ReportAll),
         1 => {
-            let slot_number = reader.read_u8()?;
-            if slot_number >= 4 {
+            if slot_number >= MAX_SLOT_COUNT {
                 return Err(invalid_data_error("Invalid slot number requested"));
             }
 
             Ok(ControllerDataRequest::SlotNumber(slot_number))
         }
-        2 => {
-            let mac_address = reader.read_u48::<LittleEndian>()?;
-
-            Ok(ControllerDataRequest::MAC(mac_address))
-        }
+        2 => Ok(ControllerDataRequest::MAC(mac_address)),
         _ => Err(invalid_data_error("Invalid controller data request type")),
     }
 }
@@ -480,6 +476,55 @@ pub fn parse_controller_data(reader: &mut Cursor<&[u8]>) -> Result<(u32, Control
     ))
 }
 
+pub fn encode_slot_health(writer: &mut Vec<u8>, slot_health: SlotHealth) -> Result<()> {
+    writer.write_u8(slot_health.slot)?;
+    writer.write_f32::<LittleEndian>(slot_health.sample_rate)?;
+
+    let driver_status = match slot_health.driver_status {
+        DriverStatus::Unknown => 0,
+        DriverStatus::Running => 1,
+        DriverStatus::Degraded => 2,
+        DriverStatus::Stopped => 3,
+    };
+    writer.write_u8(driver_status)?;
+
+    writer.write_u8(
+        slot_health
+            .battery_percent
+            .map_or(0xFF, |percent| percent.min(100)),
+    )
+}
+
+pub fn parse_slot_health(reader: &mut Cursor<&[u8]>) -> Result<SlotHealth> {
+    let slot = reader.read_u8()?;
+    if slot >= MAX_SLOT_COUNT {
+        return Err(invalid_data_error("Invalid slot number"));
+    }
+
+    let sample_rate = reader.read_f32::<LittleEndian>()?;
+
+    let driver_status = match reader.read_u8()? {
+        0 => DriverStatus::Unknown,
+        1 => DriverStatus::Running,
+        2 => DriverStatus::Degraded,
+        3 => DriverStatus::Stopped,
+        _ => return Err(invalid_data_error("Invalid driver status")),
+    };
+
+    let battery_percent = match reader.read_u8()? {
+        0xFF => None,
+        percent if percent <= 100 => Some(percent),
+        _ => return Err(invalid_data_error("Invalid battery percent")),
+    };
+
+    Ok(SlotHealth {
+        slot,
+        sample_rate,
+        driver_status,
+        battery_percent,
+    })
+}
+
 pub fn encode_message_payload(writer: &mut Vec<u8>, message_payload: MessagePayload) -> Result<()> {
     match message_payload {
         MessagePayload::None => Ok(()),
@@ -515,6 +560,27 @@ pub fn encode_message_payload(writer: &mut Vec<u8>, message_payload: MessagePayl
             encode_controller_info(writer, controller_info)?;
             encode_controller_data(writer, packet_number, controller_data)
         }
+        MessagePayload::SlotHealth(slot_health) => encode_slot_health(writer, slot_health),
+        MessagePayload::Compression(algorithms) => writer.write_u32::<LittleEndian>(algorithms),
+        MessagePayload::MotorInfoRequest(request) => {
+            encode_controller_data_request(writer, request)
+        }
+        MessagePayload::MotorInfoResponse {
+            controller_info,
+            motor_count,
+        } => {
+            encode_controller_info(writer, controller_info)?;
+            writer.write_u8(motor_count)
+        }
+        MessagePayload::RumbleRequest {
+            request,
+            motor,
+            intensity,
+        } => {
+            encode_controller_data_request(writer, request)?;
+            writer.write_u8(motor)?;
+            writer.write_u8(intensity)
+        }
     }
 }
 
@@ -548,6 +614,20 @@ pub fn parse_message_payload(
                     controller_data,
                 })
             }
+            MessageType::SlotHealth => Ok(MessagePayload::SlotHealth(parse_slot_health(reader)?)),
+            MessageType::Compression => Ok(MessagePayload::Compression(
+                reader.read_u32::<LittleEndian>()?,
+            )),
+            MessageType::MotorInfo => {
+                let controller_info = parse_controller_info(reader)?;
+                let motor_count = reader.read_u8()?;
+
+                Ok(MessagePayload::MotorInfoResponse {
+                    controller_info,
+                    motor_count,
+                })
+            }
+            MessageType::Rumble => Err(invalid_data_error("Rumble is requested by clients only")),
         },
         MessageSource::Client => match message_type {
             MessageType::ProtocolVersion => Ok(MessagePayload::None),
@@ -560,7 +640,7 @@ pub fn parse_message_payload(
                 let mut slot_numbers = [0; 4];
                 for i in 0..amount {
                     let slot_number = reader.read_u8()?;
-                    if slot_number >= 4 {
+                    if slot_number >= MAX_SLOT_COUNT {
                         return Err(invalid_data_error("Invalid slot number"));
                     }
 
@@ -579,6 +659,26 @@ pub fn parse_message_payload(
                     controller_data_request,
                 ))
             }
+            MessageType::SlotHealth => {
+                Err(invalid_data_error("Slot health is sent by servers only"))
+            }
+            MessageType::Compression => Ok(MessagePayload::Compression(
+                reader.read_u32::<LittleEndian>()?,
+            )),
+            MessageType::MotorInfo => Ok(MessagePayload::MotorInfoRequest(
+                parse_controller_data_request(reader)?,
+            )),
+            MessageType::Rumble => {
+                let request = parse_controller_data_request(reader)?;
+                let motor = reader.read_u8()?;
+                let intensity = reader.read_u8()?;
+
+                Ok(MessagePayload::RumbleRequest {
+                    request,
+                    motor,
+                    intensity,
+                })
+            }
         },
     }
 }
@@ -1,12 +1,291 @@
+//! Cemuhook (DSU) messages and their encoding.
+//!
+//! Protocol logic doesn't depend on any I/O, so it can be embedded in any event loop or framework:
+//! * [`parse_message`] and [`encode_message`] convert between bytes and typed messages,
+//! * `Server` and `Client` are state machines taking received bytes and returning bytes to send:
+//!   - input: `Server::handle_datagram` (returns responses) and `Client::handle_datagram` (queues events,
+//!     see `DsClient::next_event`),
+//!   - output: `Server::capture_datagrams` and `Client::craft_requests` return datagrams of any operation
+//!     (e.g. controller data update or request) instead of sending them,
+//!   - timers: `poll_timers` runs time driven work and `next_timer` tells when it's due,
+//!     server with fixed send rate additionally needs `Server::tick` every `Server::tick_interval`.
+//!
+//! Threads started with `DsServer::start` and `DsClient::start` are thin drivers of the same state machines
+//! over a [`Transport`](crate::transport::Transport).
+
 pub mod internals;
 
 use byteorder::{LittleEndian, WriteBytesExt};
 use crc32fast::Hasher;
 use internals::*;
+use std::convert::TryFrom;
 use std::io::{Cursor, Error, ErrorKind, Result};
 
 pub const PROTOCOL_VERSION: u16 = 1001;
 
+/// DS4 touchpad horizontal resolution.
+pub const DS4_TOUCHPAD_WIDTH: u16 = 1920;
+
+/// DS4 touchpad vertical resolution.
+pub const DS4_TOUCHPAD_HEIGHT: u16 = 943;
+
+/// Number of controller slots.
+pub const SLOT_COUNT: u8 = 4;
+
+/// Maximum number of controller slots in extended mode (supported by some DSU forks),
+/// see `ServerBuilder::with_slot_count` and `ClientBuilder::with_slot_count`.
+pub const MAX_SLOT_COUNT: u8 = 8;
+
+/// Returns slot number if it's valid, `ErrorKind::InvalidInput` error otherwise.
+pub fn check_slot_number(slot_number: u8) -> Result<u8> {
+    check_slot_number_in(slot_number, SLOT_COUNT)
+}
+
+/// Returns slot number if it's lower than `slot_count`, `ErrorKind::InvalidInput` error otherwise.
+pub fn check_slot_number_in(slot_number: u8, slot_count: u8) -> Result<u8> {
+    if slot_number < slot_count {
+        Ok(slot_number)
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid slot number: {}", slot_number),
+        ))
+    }
+}
+
+/// Returns slot count if it's between 1 and `MAX_SLOT_COUNT`, `ErrorKind::InvalidInput` error otherwise.
+pub(crate) fn check_slot_count(slot_count: u8) -> Result<u8> {
+    if (1..=MAX_SLOT_COUNT).contains(&slot_count) {
+        Ok(slot_count)
+    } else {
+        Err(Error::new(
+            ErrorKind::InvalidInput,
+            format!("Invalid slot count: {}", slot_count),
+        ))
+    }
+}
+
+/// Slot number validated at construction, so APIs taking it never fail on invalid slots
+/// (as long as index was created for slot count of server or client it's passed to).
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SlotIndex(u8);
+
+impl SlotIndex {
+    /// Creates slot index or returns `None` if slot number is not lower than `SLOT_COUNT`,
+    /// see `new_in` for servers and clients in extended mode.
+    pub fn new(slot_number: u8) -> Option<SlotIndex> {
+        SlotIndex::new_in(slot_number, SLOT_COUNT)
+    }
+
+    /// Creates slot index or returns `None` if slot number is not lower than `slot_count`.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot_number` - slot number.
+    /// * `slot_count` - number of slots of server or client index is created for, see `Server::slot_count`.
+    pub fn new_in(slot_number: u8, slot_count: u8) -> Option<SlotIndex> {
+        (slot_number < slot_count).then_some(SlotIndex(slot_number))
+    }
+
+    /// Returns indices of all `SLOT_COUNT` slots in order, see `Server::slots` and `Client::slots` for extended mode.
+    pub fn all() -> impl Iterator<Item = SlotIndex> {
+        SlotIndex::all_in(SLOT_COUNT)
+    }
+
+    /// Returns indices of first `slot_count` slots in order.
+    pub fn all_in(slot_count: u8) -> impl Iterator<Item = SlotIndex> {
+        (0..slot_count).map(SlotIndex)
+    }
+
+    pub fn get(self) -> u8 {
+        self.0
+    }
+}
+
+/// Accepts slot numbers lower than `SLOT_COUNT`, see `SlotIndex::new_in` for extended mode.
+impl TryFrom<u8> for SlotIndex {
+    type Error = Error;
+
+    fn try_from(slot_number: u8) -> Result<SlotIndex> {
+        check_slot_number(slot_number).map(SlotIndex)
+    }
+}
+
+impl From<SlotIndex> for u8 {
+    fn from(slot_index: SlotIndex) -> u8 {
+        slot_index.0
+    }
+}
+
+impl std::fmt::Display for SlotIndex {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<SlotIndex> for ControllerDataRequest {
+    fn from(slot_index: SlotIndex) -> ControllerDataRequest {
+        ControllerDataRequest::SlotNumber(slot_index.0)
+    }
+}
+
+/// Default receive buffer size, fits the largest protocol message (controller data).
+pub const DEFAULT_RECEIVE_BUFFER_SIZE: usize = 100;
+
+/// Length of message header preceding message type and payload.
+pub const HEADER_LENGTH: usize = 16;
+
+/// Datagram claiming to be longer than accepted.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct OversizeMessage {
+    /// Total length (including header) claimed by message header.
+    pub claimed_length: usize,
+    pub max_length: usize,
+}
+
+impl std::fmt::Display for OversizeMessage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Message length {} exceeds maximum of {}",
+            self.claimed_length, self.max_length
+        )
+    }
+}
+
+impl std::error::Error for OversizeMessage {}
+
+/// Checks length claimed by message header against maximum accepted length.
+///
+/// Fails with `ErrorKind::InvalidData` error wrapping [`OversizeMessage`] if message is too long.
+/// Packets too short to contain length are left for `parse_message` to reject.
+pub fn check_message_length(packet: &[u8], max_length: usize) -> Result<()> {
+    if packet.len() < 8 {
+        return Ok(());
+    }
+
+    let claimed_length = HEADER_LENGTH + u16::from_le_bytes([packet[6], packet[7]]) as usize;
+    if claimed_length > max_length {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            OversizeMessage {
+                claimed_length,
+                max_length,
+            },
+        ));
+    }
+
+    Ok(())
+}
+
+/// Checks whether error was caused by oversize message.
+pub fn is_oversize_error(error: &Error) -> bool {
+    error.get_ref().is_some_and(|inner| {
+        inner.is::<OversizeMessage>()
+            || matches!(
+                inner.downcast_ref::<ProtocolError>(),
+                Some(ProtocolError::Oversize(_))
+            )
+    })
+}
+
+/// Error of parsing or encoding protocol message.
+///
+/// Returned by `parse_message`, `encode_message` and `handle_datagram` of server and client.
+/// APIs returning `std::io::Error` wrap it as `ErrorKind::InvalidData` error, see `ProtocolError::of`.
+#[derive(Debug)]
+pub enum ProtocolError {
+    /// Packet doesn't start with magic string of client or server.
+    BadMagic,
+    UnsupportedVersion(u16),
+    BadChecksum {
+        /// Checksum claimed by message header.
+        expected: u32,
+        /// Checksum computed from packet.
+        actual: u32,
+    },
+    /// Packet ends before message does.
+    Truncated,
+    /// Message is longer than accepted, see `check_message_length`.
+    Oversize(OversizeMessage),
+    /// Field holds value outside of its range (e.g. unknown message type or slot number not below `MAX_SLOT_COUNT`).
+    InvalidValue(&'static str),
+    Io(Error),
+}
+
+impl ProtocolError {
+    /// Returns protocol error wrapped in given I/O error, `None` if it wasn't caused by invalid message.
+    pub fn of(error: &Error) -> Option<&ProtocolError> {
+        error.get_ref()?.downcast_ref::<ProtocolError>()
+    }
+}
+
+impl std::fmt::Display for ProtocolError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProtocolError::BadMagic => write!(f, "Unrecognized magic string"),
+            ProtocolError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported protocol version {}", version)
+            }
+            ProtocolError::BadChecksum { expected, actual } => write!(
+                f,
+                "Packet has incorrect checksum {:#010x} (computed {:#010x})",
+                expected, actual
+            ),
+            ProtocolError::Truncated => write!(f, "Received packet is too short"),
+            ProtocolError::Oversize(oversize) => oversize.fmt(f),
+            ProtocolError::InvalidValue(message) => write!(f, "{}", message),
+            ProtocolError::Io(error) => error.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ProtocolError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ProtocolError::Oversize(oversize) => Some(oversize),
+            ProtocolError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<Error> for ProtocolError {
+    fn from(error: Error) -> ProtocolError {
+        if error
+            .get_ref()
+            .is_some_and(|inner| inner.is::<ProtocolError>())
+        {
+            return *error
+                .into_inner()
+                .unwrap()
+                .downcast::<ProtocolError>()
+                .unwrap();
+        }
+
+        if let Some(oversize) = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<OversizeMessage>())
+        {
+            return ProtocolError::Oversize(*oversize);
+        }
+
+        match error.kind() {
+            ErrorKind::UnexpectedEof => ProtocolError::Truncated,
+            _ => ProtocolError::Io(error),
+        }
+    }
+}
+
+impl From<ProtocolError> for Error {
+    fn from(error: ProtocolError) -> Error {
+        match error {
+            ProtocolError::Io(error) => error,
+            error => Error::new(ErrorKind::InvalidData, error),
+        }
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum MessageSource {
     Server,
@@ -18,6 +297,14 @@ pub enum MessageType {
     ProtocolVersion,
     ConnectedControllers,
     ControllerData,
+    /// Extension (not part of Cemuhook protocol): per-slot health published by server.
+    SlotHealth,
+    /// Extension (not part of Cemuhook protocol): compression handshake, see `MessagePayload::Compression`.
+    Compression,
+    /// Community extension (DS4Windows and BetterJoy forks): motor information, see `MessagePayload::MotorInfoResponse`.
+    MotorInfo,
+    /// Community extension (DS4Windows and BetterJoy forks): rumble request, see `MessagePayload::RumbleRequest`.
+    Rumble,
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -77,6 +364,31 @@ impl Default for BatteryStatus {
     }
 }
 
+/// State of driver providing slot's controller data.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum DriverStatus {
+    #[default]
+    Unknown,
+    Running,
+    /// Driver works, but with problems (e.g. dropped samples or reconnecting).
+    Degraded,
+    Stopped,
+}
+
+/// LZ4 compression algorithm bit of `MessagePayload::Compression`.
+pub const COMPRESSION_LZ4: u32 = 1;
+
+/// Health of slot, published by servers with health extension enabled.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SlotHealth {
+    pub slot: u8,
+    /// Controller data updates per second.
+    pub sample_rate: f32,
+    pub driver_status: DriverStatus,
+    /// Battery charge from 0 to 100, `None` if unknown.
+    pub battery_percent: Option<u8>,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum ControllerDataRequest {
     ReportAll,
@@ -101,6 +413,22 @@ pub enum MessagePayload {
         controller_info: ControllerInfo,
         controller_data: ControllerData,
     },
+    SlotHealth(SlotHealth),
+    /// Bitmask of compression algorithms (see [`COMPRESSION_LZ4`]) client supports,
+    /// or algorithm server picked for it (0 if none).
+    Compression(u32),
+    /// Request for amount of motors of requested slots.
+    MotorInfoRequest(ControllerDataRequest),
+    MotorInfoResponse {
+        controller_info: ControllerInfo,
+        motor_count: u8,
+    },
+    /// Request to set intensity (0 is off, 255 is full) of motor of requested slots.
+    RumbleRequest {
+        request: ControllerDataRequest,
+        motor: u8,
+        intensity: u8,
+    },
 }
 
 #[derive(Copy, Clone, Debug, PartialEq)]
@@ -130,6 +458,58 @@ pub struct TouchData {
     pub position_y: u16,
 }
 
+/// Maps normalized coordinate to touchpad position of given resolution.
+fn denormalize(value: f32, resolution: u16) -> u16 {
+    let max = resolution.saturating_sub(1) as f32;
+    if value.is_nan() {
+        return 0;
+    }
+
+    (value * max).round().clamp(0.0, max) as u16
+}
+
+impl TouchData {
+    /// Creates active touch at given DS4 touchpad position.
+    pub fn new(id: u8, position_x: u16, position_y: u16) -> TouchData {
+        TouchData {
+            active: true,
+            id,
+            position_x,
+            position_y,
+        }
+    }
+
+    /// Creates active touch at given normalized position, see `set_normalized`.
+    pub fn from_normalized(id: u8, x: f32, y: f32) -> TouchData {
+        let mut touch = TouchData::new(id, 0, 0);
+        touch.set_normalized(x, y);
+
+        touch
+    }
+
+    /// Sets position from normalized coordinates, mapping 0.0–1.0 to full DS4 touchpad resolution
+    /// (`DS4_TOUCHPAD_WIDTH` by `DS4_TOUCHPAD_HEIGHT`). Values outside of range are clamped, NaN maps to 0.
+    ///
+    /// # Arguments
+    ///
+    /// * `x` - horizontal coordinate, 0.0 is left edge.
+    /// * `y` - vertical coordinate, 0.0 is top edge.
+    pub fn set_normalized(&mut self, x: f32, y: f32) {
+        self.position_x = denormalize(x, DS4_TOUCHPAD_WIDTH);
+        self.position_y = denormalize(y, DS4_TOUCHPAD_HEIGHT);
+    }
+
+    /// Returns position as normalized coordinates, inverse of `set_normalized`.
+    ///
+    /// Positions beyond DS4 touchpad resolution map to values above 1.0.
+    pub fn normalized(&self) -> (f32, f32) {
+        (
+            self.position_x as f32 / (DS4_TOUCHPAD_WIDTH - 1) as f32,
+            self.position_y as f32 / (DS4_TOUCHPAD_HEIGHT - 1) as f32,
+        )
+    }
+}
+
 #[derive(Copy, Clone, Debug, Default, PartialEq)]
 pub struct ControllerData {
     pub connected: bool,
@@ -178,6 +558,159 @@ pub struct ControllerData {
     pub gyroscope_roll: f32,
 }
 
+/// Length of encoded motion block: timestamp followed by accelerometer and gyroscope readings.
+pub const MOTION_BLOCK_LENGTH: usize = 32;
+
+/// Largest plausible accelerometer reading (in g), consumer IMUs saturate at 16 g.
+pub const MAX_ACCELERATION: f32 = 64.0;
+
+/// Largest plausible gyroscope reading (in degrees per second), consumer IMUs saturate at 4000 °/s.
+pub const MAX_ANGULAR_VELOCITY: f32 = 10_000.0;
+
+/// Largest plausible motion timestamp (100 years in microseconds).
+pub const MAX_MOTION_TIMESTAMP: u64 = 100 * 365 * 24 * 60 * 60 * 1_000_000;
+
+/// How implausible motion values (see `ControllerData::has_valid_motion`) are handled.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MotionValidation {
+    /// Motion values are passed as they are.
+    #[default]
+    Disabled,
+    /// Controller data with implausible motion values is dropped.
+    Reject,
+    /// Implausible motion values are replaced with plausible ones, see `ControllerData::clamp_motion`.
+    Clamp,
+}
+
+impl ControllerData {
+    /// Encodes motion block the same way it's encoded in controller data message (little-endian).
+    ///
+    /// Lets producers (e.g. microcontroller firmware) pre-encode motion,
+    /// so host only has to patch timestamp with `patch_motion_timestamp`.
+    pub fn write_motion(&self, block: &mut [u8; MOTION_BLOCK_LENGTH]) {
+        block[0..8].copy_from_slice(&self.motion_data_timestamp.to_le_bytes());
+
+        let values = [
+            self.accelerometer_x,
+            self.accelerometer_y,
+            self.accelerometer_z,
+            self.gyroscope_pitch,
+            self.gyroscope_yaw,
+            self.gyroscope_roll,
+        ];
+        for (i, value) in values.iter().enumerate() {
+            let offset = 8 + i * 4;
+            block[offset..offset + 4].copy_from_slice(&value.to_le_bytes());
+        }
+    }
+
+    /// Sets motion fields from block encoded with `write_motion`.
+    pub fn read_motion(&mut self, block: &[u8; MOTION_BLOCK_LENGTH]) {
+        let mut timestamp = [0_u8; 8];
+        timestamp.copy_from_slice(&block[0..8]);
+        self.motion_data_timestamp = u64::from_le_bytes(timestamp);
+
+        let value = |i: usize| {
+            let offset = 8 + i * 4;
+            f32::from_le_bytes([
+                block[offset],
+                block[offset + 1],
+                block[offset + 2],
+                block[offset + 3],
+            ])
+        };
+        self.accelerometer_x = value(0);
+        self.accelerometer_y = value(1);
+        self.accelerometer_z = value(2);
+        self.gyroscope_pitch = value(3);
+        self.gyroscope_yaw = value(4);
+        self.gyroscope_roll = value(5);
+    }
+
+    /// Checks whether motion values are finite, within `MAX_ACCELERATION` and `MAX_ANGULAR_VELOCITY`,
+    /// and timestamp is not above `MAX_MOTION_TIMESTAMP`.
+    pub fn has_valid_motion(&self) -> bool {
+        let is_valid = |value: f32, max: f32| value.is_finite() && value.abs() <= max;
+
+        is_valid(self.accelerometer_x, MAX_ACCELERATION)
+            && is_valid(self.accelerometer_y, MAX_ACCELERATION)
+            && is_valid(self.accelerometer_z, MAX_ACCELERATION)
+            && is_valid(self.gyroscope_pitch, MAX_ANGULAR_VELOCITY)
+            && is_valid(self.gyroscope_yaw, MAX_ANGULAR_VELOCITY)
+            && is_valid(self.gyroscope_roll, MAX_ANGULAR_VELOCITY)
+            && self.motion_data_timestamp <= MAX_MOTION_TIMESTAMP
+    }
+
+    /// Makes motion values valid: NaNs become zero, other values are clamped to plausible range
+    /// and implausible timestamp is replaced with given fallback (e.g. timestamp of previous data).
+    pub fn clamp_motion(&mut self, fallback_timestamp: u64) {
+        let clamp = |value: &mut f32, max: f32| {
+            *value = if value.is_nan() {
+                0.0
+            } else {
+                value.clamp(-max, max)
+            };
+        };
+
+        clamp(&mut self.accelerometer_x, MAX_ACCELERATION);
+        clamp(&mut self.accelerometer_y, MAX_ACCELERATION);
+        clamp(&mut self.accelerometer_z, MAX_ACCELERATION);
+        clamp(&mut self.gyroscope_pitch, MAX_ANGULAR_VELOCITY);
+        clamp(&mut self.gyroscope_yaw, MAX_ANGULAR_VELOCITY);
+        clamp(&mut self.gyroscope_roll, MAX_ANGULAR_VELOCITY);
+
+        if self.motion_data_timestamp > MAX_MOTION_TIMESTAMP {
+            self.motion_data_timestamp = fallback_timestamp.min(MAX_MOTION_TIMESTAMP);
+        }
+    }
+
+    /// Replaces timestamp in motion block encoded with `write_motion`.
+    pub fn patch_motion_timestamp(block: &mut [u8; MOTION_BLOCK_LENGTH], timestamp: u64) {
+        block[0..8].copy_from_slice(&timestamp.to_le_bytes());
+    }
+}
+
+/// Single accelerometer and gyroscope reading.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct MotionSample {
+    /// Sample time in microseconds.
+    pub timestamp: u64,
+    /// Acceleration (X, Y, Z) in g.
+    pub accelerometer: [f32; 3],
+    /// Angular velocity (pitch, yaw, roll) in degrees per second.
+    pub gyroscope: [f32; 3],
+}
+
+impl MotionSample {
+    /// Takes motion of controller data.
+    pub fn of(controller_data: &ControllerData) -> MotionSample {
+        MotionSample {
+            timestamp: controller_data.motion_data_timestamp,
+            accelerometer: [
+                controller_data.accelerometer_x,
+                controller_data.accelerometer_y,
+                controller_data.accelerometer_z,
+            ],
+            gyroscope: [
+                controller_data.gyroscope_pitch,
+                controller_data.gyroscope_yaw,
+                controller_data.gyroscope_roll,
+            ],
+        }
+    }
+
+    /// Replaces motion fields (including timestamp) of controller data with this sample.
+    pub fn apply_to(&self, controller_data: &mut ControllerData) {
+        controller_data.motion_data_timestamp = self.timestamp;
+        controller_data.accelerometer_x = self.accelerometer[0];
+        controller_data.accelerometer_y = self.accelerometer[1];
+        controller_data.accelerometer_z = self.accelerometer[2];
+        controller_data.gyroscope_pitch = self.gyroscope[0];
+        controller_data.gyroscope_yaw = self.gyroscope[1];
+        controller_data.gyroscope_roll = self.gyroscope[2];
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Message {
     pub header: MessageHeader,
@@ -196,7 +729,10 @@ fn compute_checksum(packet: &[u8]) -> u32 {
     hasher.finalize()
 }
 
-pub fn encode_message(writer: &mut Vec<u8>, message: Message) -> Result<()> {
+pub fn encode_message(
+    writer: &mut Vec<u8>,
+    message: Message,
+) -> std::result::Result<(), ProtocolError> {
     encode_message_header(writer, message.header)?;
     encode_message_type(writer, message.message_type)?;
     encode_message_payload(writer, message.payload)?;
@@ -218,31 +754,25 @@ pub fn parse_message(
     message_source: MessageSource,
     packet: &[u8],
     verify_checksum: bool,
-) -> Result<Message> {
+) -> std::result::Result<Message, ProtocolError> {
     let mut reader = Cursor::new(packet);
     let header = parse_message_header(&mut reader)?;
 
     if header.protocol_version != PROTOCOL_VERSION {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            "Unsupported protocol version",
-        ));
+        return Err(ProtocolError::UnsupportedVersion(header.protocol_version));
     }
 
-    if packet.len() - 16 < header.message_length as usize {
-        return Err(Error::new(
-            ErrorKind::InvalidData,
-            "Received packet is too short",
-        ));
+    if packet.len() - HEADER_LENGTH < header.message_length as usize {
+        return Err(ProtocolError::Truncated);
     }
 
     if verify_checksum {
         let checksum = compute_checksum(packet);
         if checksum != header.checksum {
-            return Err(Error::new(
-                ErrorKind::InvalidData,
-                "Packet has incorrect checksum",
-            ));
+            return Err(ProtocolError::BadChecksum {
+                expected: header.checksum,
+                actual: checksum,
+            });
         }
     }
 
@@ -256,3 +786,82 @@ pub fn parse_message(
         payload,
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encoded(
+        source: MessageSource,
+        message_type: MessageType,
+        payload: MessagePayload,
+    ) -> Vec<u8> {
+        let message = Message {
+            header: MessageHeader {
+                source,
+                protocol_version: PROTOCOL_VERSION,
+                message_length: 0,
+                checksum: 0,
+                source_id: 1,
+            },
+            message_type,
+            payload,
+        };
+        let mut datagram = vec![];
+        encode_message(&mut datagram, message).unwrap();
+
+        datagram
+    }
+
+    fn roundtrip(source: MessageSource, message_type: MessageType, payload: MessagePayload) {
+        let parsed = parse_message(source, &encoded(source, message_type, payload), true).unwrap();
+        assert_eq!(parsed.message_type, message_type);
+        assert_eq!(parsed.payload, payload);
+    }
+
+    #[test]
+    fn rumble_extension_messages_roundtrip() {
+        roundtrip(
+            MessageSource::Client,
+            MessageType::MotorInfo,
+            MessagePayload::MotorInfoRequest(ControllerDataRequest::SlotNumber(1)),
+        );
+        roundtrip(
+            MessageSource::Server,
+            MessageType::MotorInfo,
+            MessagePayload::MotorInfoResponse {
+                controller_info: ControllerInfo {
+                    slot: 1,
+                    slot_state: SlotState::Connected,
+                    mac_address: 0x1234_5678_9abc,
+                    ..Default::default()
+                },
+                motor_count: 2,
+            },
+        );
+        roundtrip(
+            MessageSource::Client,
+            MessageType::Rumble,
+            MessagePayload::RumbleRequest {
+                request: ControllerDataRequest::MAC(0x1234_5678_9abc),
+                motor: 1,
+                intensity: 200,
+            },
+        );
+    }
+
+    #[test]
+    fn rumble_from_server_is_rejected() {
+        let datagram = encoded(
+            MessageSource::Server,
+            MessageType::Rumble,
+            MessagePayload::RumbleRequest {
+                request: ControllerDataRequest::SlotNumber(0),
+                motor: 0,
+                intensity: 255,
+            },
+        );
+
+        assert!(parse_message(MessageSource::Server, &datagram, true).is_err());
+    }
+}
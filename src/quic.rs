@@ -0,0 +1,285 @@
+//! Experimental QUIC transports (feature `quic`).
+//!
+//! [`QuicServerTransport`] and [`QuicClientTransport`] carry protocol messages in unreliable QUIC datagrams,
+//! giving this crate's client and server encrypted, congestion-aware remote streaming.
+//! They only talk to each other, serve standard DSU peers over a separate UDP server.
+//!
+//! Server proves its identity with certificate of [`QuicIdentity`], clients trust given server certificate only.
+
+use crate::transport::*;
+use quinn::rustls::pki_types::{CertificateDer, PrivateKeyDer, PrivatePkcs8KeyDer};
+use quinn::rustls::RootCertStore;
+use quinn::{ClientConfig, Connection, Endpoint, ServerConfig};
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use tokio::runtime::Runtime;
+
+type Datagram = (Vec<u8>, SocketAddr);
+
+/// Certificate and private key server identifies itself with.
+#[derive(Clone, Debug)]
+pub struct QuicIdentity {
+    /// DER encoded certificate, clients need it to connect.
+    pub certificate: Vec<u8>,
+    /// DER encoded PKCS #8 private key.
+    pub private_key: Vec<u8>,
+}
+
+impl QuicIdentity {
+    /// Generates self-signed identity.
+    ///
+    /// # Arguments
+    ///
+    /// * `server_names` - names clients connect to server by (e.g. `localhost` or host name).
+    pub fn self_signed(server_names: &[&str]) -> Result<QuicIdentity> {
+        let names: Vec<String> = server_names.iter().map(|name| name.to_string()).collect();
+        let certified_key = rcgen::generate_simple_self_signed(names).map_err(Error::other)?;
+
+        Ok(QuicIdentity {
+            certificate: certified_key.cert.der().to_vec(),
+            private_key: certified_key.key_pair.serialize_der(),
+        })
+    }
+}
+
+fn new_runtime() -> Result<Runtime> {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+}
+
+fn receive(receiver: &Mutex<Receiver<Datagram>>, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+    match receiver.lock().unwrap().recv_timeout(READ_TIMEOUT) {
+        Ok((datagram, source)) => {
+            let amount = datagram.len().min(buf.len());
+            buf[..amount].copy_from_slice(&datagram[..amount]);
+
+            Ok((amount, source))
+        }
+        Err(RecvTimeoutError::Timeout) => Err(Error::new(
+            ErrorKind::WouldBlock,
+            "No datagram received before timeout",
+        )),
+        Err(RecvTimeoutError::Disconnected) => Err(Error::new(
+            ErrorKind::NotConnected,
+            "QUIC endpoint is closed",
+        )),
+    }
+}
+
+fn send(connection: &Connection, buf: &[u8]) -> Result<usize> {
+    connection
+        .send_datagram(buf.to_vec().into())
+        .map(|_| buf.len())
+        .map_err(Error::other)
+}
+
+/// Forwards datagrams of connection to receiver until connection is closed.
+async fn forward_datagrams(connection: Connection, sender: Sender<Datagram>) {
+    let remote_address = connection.remote_address();
+    while let Ok(datagram) = connection.read_datagram().await {
+        if sender.send((datagram.to_vec(), remote_address)).is_err() {
+            break;
+        }
+    }
+}
+
+/// Server side of QUIC transport, accepts connections of any number of clients.
+///
+/// Clients are addressed by their remote address, datagrams sent to addresses without connection are dropped.
+/// Connections are served on background thread, stopped when transport is dropped.
+pub struct QuicServerTransport {
+    endpoint: Endpoint,
+    local_addr: SocketAddr,
+    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    receiver: Mutex<Receiver<Datagram>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl QuicServerTransport {
+    /// Binds server transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - server's UDP socket address.
+    /// * `identity` - server's certificate and private key.
+    pub fn bind(address: SocketAddr, identity: &QuicIdentity) -> Result<QuicServerTransport> {
+        let certificate = CertificateDer::from(identity.certificate.clone());
+        let private_key =
+            PrivateKeyDer::Pkcs8(PrivatePkcs8KeyDer::from(identity.private_key.clone()));
+        let config = ServerConfig::with_single_cert(vec![certificate], private_key)
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+
+        let runtime = new_runtime()?;
+        let endpoint = runtime.block_on(async { Endpoint::server(config, address) })?;
+        let local_addr = endpoint.local_addr()?;
+
+        let connections = Arc::new(Mutex::new(HashMap::new()));
+        let (sender, receiver) = channel();
+        let thread = {
+            let endpoint = endpoint.clone();
+            let connections = connections.clone();
+            std::thread::spawn(move || {
+                runtime.block_on(accept_connections(endpoint, connections, sender))
+            })
+        };
+
+        Ok(QuicServerTransport {
+            endpoint,
+            local_addr,
+            connections,
+            receiver: Mutex::new(receiver),
+            thread: Some(thread),
+        })
+    }
+
+    /// Returns number of connected clients.
+    pub fn connection_count(&self) -> usize {
+        self.connections.lock().unwrap().len()
+    }
+}
+
+async fn accept_connections(
+    endpoint: Endpoint,
+    connections: Arc<Mutex<HashMap<SocketAddr, Connection>>>,
+    sender: Sender<Datagram>,
+) {
+    while let Some(incoming) = endpoint.accept().await {
+        let connections = connections.clone();
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let connection = match incoming.await {
+                Ok(connection) => connection,
+                Err(_) => return,
+            };
+            let remote_address = connection.remote_address();
+            connections
+                .lock()
+                .unwrap()
+                .insert(remote_address, connection.clone());
+
+            forward_datagrams(connection, sender).await;
+
+            connections.lock().unwrap().remove(&remote_address);
+        });
+    }
+}
+
+impl Transport for QuicServerTransport {
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize> {
+        let connection = self
+            .connections
+            .lock()
+            .unwrap()
+            .get(&target)
+            .cloned()
+            .ok_or_else(|| Error::new(ErrorKind::NotConnected, "No QUIC connection to target"))?;
+
+        send(&connection, buf)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        receive(&self.receiver, buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.local_addr)
+    }
+}
+
+impl Drop for QuicServerTransport {
+    fn drop(&mut self) {
+        self.endpoint.close(0u32.into(), b"server stopped");
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// Client side of QUIC transport, connected to single server.
+///
+/// Datagrams are always sent to connected server, regardless of target address.
+/// Connection is served on background thread, closed when transport is dropped.
+pub struct QuicClientTransport {
+    endpoint: Endpoint,
+    connection: Connection,
+    receiver: Mutex<Receiver<Datagram>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl QuicClientTransport {
+    /// Connects to QUIC server, blocking until connection is established.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - client's UDP socket address (use port 0 to let OS pick one).
+    /// * `server_address` - server's UDP socket address.
+    /// * `server_name` - name of server, must be one of the names in server's certificate.
+    /// * `server_certificate` - DER encoded certificate server is expected to present.
+    pub fn connect(
+        address: SocketAddr,
+        server_address: SocketAddr,
+        server_name: &str,
+        server_certificate: &[u8],
+    ) -> Result<QuicClientTransport> {
+        let mut roots = RootCertStore::empty();
+        roots
+            .add(CertificateDer::from(server_certificate.to_vec()))
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+        let config = ClientConfig::with_root_certificates(Arc::new(roots))
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?;
+
+        let runtime = new_runtime()?;
+        let (endpoint, connection) = runtime.block_on(async {
+            let mut endpoint = Endpoint::client(address)?;
+            endpoint.set_default_client_config(config);
+            let connection = endpoint
+                .connect(server_address, server_name)
+                .map_err(|error| Error::new(ErrorKind::InvalidInput, error))?
+                .await
+                .map_err(|error| Error::new(ErrorKind::ConnectionRefused, error))?;
+
+            Ok::<(Endpoint, Connection), Error>((endpoint, connection))
+        })?;
+
+        let (sender, receiver) = channel();
+        let thread = {
+            let connection = connection.clone();
+            std::thread::spawn(move || runtime.block_on(forward_datagrams(connection, sender)))
+        };
+
+        Ok(QuicClientTransport {
+            endpoint,
+            connection,
+            receiver: Mutex::new(receiver),
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Transport for QuicClientTransport {
+    fn send_to(&self, buf: &[u8], _target: SocketAddr) -> Result<usize> {
+        send(&self.connection, buf)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        receive(&self.receiver, buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.endpoint.local_addr()
+    }
+}
+
+impl Drop for QuicClientTransport {
+    fn drop(&mut self) {
+        self.connection.close(0u32.into(), b"client stopped");
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
@@ -0,0 +1,593 @@
+use crate::buttons::*;
+use crate::protocol::internals::*;
+use crate::protocol::*;
+use crate::server::*;
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+use std::collections::HashMap;
+use std::io::prelude::*;
+use std::io::{Cursor, Error, ErrorKind, Result};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Magic of unversioned recordings (version 1).
+const LEGACY_MAGIC: &[u8; 4] = b"PMRC";
+const MAGIC: &[u8; 4] = b"PMRV";
+
+/// Version of recording format written by `Recording::write_to`.
+///
+/// Version 1 - no version header.
+/// Version 2 - version header after magic.
+///
+/// Controller data of every frame is length-prefixed, so frames written by older versions
+/// can be parsed into current `ControllerData`, with fields they lack (new fields are appended
+/// to the encoding) left at default values.
+pub const RECORDING_VERSION: u16 = 2;
+
+/// Controller data recorded at given time.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct RecordedFrame {
+    /// Time since recording start.
+    pub time: Duration,
+    pub slot: u8,
+    pub controller_data: ControllerData,
+}
+
+/// Timed sequence of controller data changes.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Recording {
+    pub frames: Vec<RecordedFrame>,
+}
+
+impl Recording {
+    /// Creates new, empty recording.
+    pub fn new() -> Recording {
+        Default::default()
+    }
+
+    /// Time of the last frame.
+    pub fn duration(&self) -> Duration {
+        self.frames
+            .last()
+            .map(|frame| frame.time)
+            .unwrap_or_default()
+    }
+
+    /// Returns part of recording between given times, with frame times relative to `start`.
+    pub fn trim(&self, start: Duration, end: Duration) -> Recording {
+        let frames = self
+            .frames
+            .iter()
+            .filter(|frame| frame.time >= start && frame.time <= end)
+            .map(|frame| RecordedFrame {
+                time: frame.time - start,
+                ..*frame
+            })
+            .collect();
+
+        Recording { frames }
+    }
+
+    /// Appends frames of other recording after the last frame of this one.
+    ///
+    /// # Arguments
+    ///
+    /// * `other` - recording to append.
+    /// * `gap` - time between the last frame of this recording and the first frame of `other`.
+    pub fn append(&mut self, other: &Recording, gap: Duration) {
+        let offset = if self.frames.is_empty() {
+            Duration::from_secs(0)
+        } else {
+            self.duration() + gap
+        };
+
+        self.frames
+            .extend(other.frames.iter().map(|frame| RecordedFrame {
+                time: offset + frame.time,
+                ..*frame
+            }));
+    }
+
+    /// Concatenates recordings, separating them with given gap.
+    pub fn concat(recordings: &[Recording], gap: Duration) -> Recording {
+        let mut result = Recording::new();
+        for recording in recordings.iter() {
+            result.append(recording, gap);
+        }
+
+        result
+    }
+
+    /// Moves frames between slots, frame recorded in slot `n` is moved to slot `mapping[n]`.
+    ///
+    /// Frames of slots mapping doesn't cover stay in their slots.
+    pub fn remap_slots(&mut self, mapping: &[u8]) {
+        assert!(mapping.iter().all(|&slot| slot < MAX_SLOT_COUNT));
+
+        for frame in self.frames.iter_mut() {
+            if let Some(&slot) = mapping.get(frame.slot as usize) {
+                frame.slot = slot;
+            }
+        }
+    }
+
+    /// Summarizes recording, useful for validating capture quality.
+    ///
+    /// # Arguments
+    ///
+    /// * `gap_threshold` - intervals between frames of the same slot longer than this are reported as gaps.
+    pub fn summary(&self, gap_threshold: Duration) -> RecordingSummary {
+        let mut slots: [Option<SlotSummary>; MAX_SLOT_COUNT as usize] = Default::default();
+        let mut trackers = [ButtonTracker::new(); MAX_SLOT_COUNT as usize];
+        let mut previous_times: [Option<Duration>; MAX_SLOT_COUNT as usize] =
+            [None; MAX_SLOT_COUNT as usize];
+        let mut gaps = vec![];
+
+        for frame in self.frames.iter() {
+            let index = frame.slot as usize;
+            let data = &frame.controller_data;
+            let acceleration = [
+                data.accelerometer_x,
+                data.accelerometer_y,
+                data.accelerometer_z,
+            ];
+            let rotation = [
+                data.gyroscope_pitch,
+                data.gyroscope_yaw,
+                data.gyroscope_roll,
+            ];
+
+            let slot = slots[index].get_or_insert_with(|| SlotSummary {
+                frame_count: 0,
+                first_time: frame.time,
+                last_time: frame.time,
+                button_presses: HashMap::new(),
+                accelerometer_range: [acceleration, acceleration],
+                gyroscope_range: [rotation, rotation],
+            });
+
+            slot.frame_count += 1;
+            slot.last_time = frame.time;
+            for axis in 0..3 {
+                slot.accelerometer_range[0][axis] =
+                    slot.accelerometer_range[0][axis].min(acceleration[axis]);
+                slot.accelerometer_range[1][axis] =
+                    slot.accelerometer_range[1][axis].max(acceleration[axis]);
+                slot.gyroscope_range[0][axis] = slot.gyroscope_range[0][axis].min(rotation[axis]);
+                slot.gyroscope_range[1][axis] = slot.gyroscope_range[1][axis].max(rotation[axis]);
+            }
+
+            for event in trackers[index].update(data) {
+                if event.edge == ButtonEdge::Pressed {
+                    *slot.button_presses.entry(event.button).or_insert(0) += 1;
+                }
+            }
+
+            if let Some(previous_time) = previous_times[index] {
+                let interval = frame.time.saturating_sub(previous_time);
+                if interval > gap_threshold {
+                    gaps.push(Gap {
+                        slot: frame.slot,
+                        start: previous_time,
+                        duration: interval,
+                    });
+                }
+            }
+            previous_times[index] = Some(frame.time);
+        }
+
+        RecordingSummary {
+            duration: self.duration(),
+            frame_count: self.frames.len(),
+            slots,
+            gaps,
+        }
+    }
+
+    /// Writes recording in binary format.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let mut buffer = vec![];
+        buffer.extend_from_slice(MAGIC);
+        buffer.write_u16::<LittleEndian>(RECORDING_VERSION)?;
+        buffer.write_u32::<LittleEndian>(self.frames.len() as u32)?;
+
+        let mut encoded_data = vec![];
+        for frame in self.frames.iter() {
+            encoded_data.clear();
+            encode_controller_data(&mut encoded_data, 0, frame.controller_data)?;
+
+            buffer.write_u64::<LittleEndian>(frame.time.as_micros() as u64)?;
+            buffer.write_u8(frame.slot)?;
+            buffer.write_u16::<LittleEndian>(encoded_data.len() as u16)?;
+            buffer.extend_from_slice(&encoded_data);
+        }
+
+        writer.write_all(&buffer)
+    }
+
+    /// Reads recording written with `write_to` by this or any older version.
+    pub fn read_from<R: Read>(reader: &mut R) -> Result<Recording> {
+        Recording::read_versioned_from(reader).map(|(recording, _version)| recording)
+    }
+
+    /// Reads recording written with `write_to`, returns it along with format version it was written in.
+    pub fn read_versioned_from<R: Read>(reader: &mut R) -> Result<(Recording, u16)> {
+        let mut magic = [0_u8; 4];
+        reader.read_exact(&mut magic)?;
+        let version = if &magic == LEGACY_MAGIC {
+            1
+        } else if &magic == MAGIC {
+            reader.read_u16::<LittleEndian>()?
+        } else {
+            return Err(Error::new(ErrorKind::InvalidData, "Not a recording"));
+        };
+
+        if version == 0 || version > RECORDING_VERSION {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("Unsupported recording version: {}", version),
+            ));
+        }
+
+        let frame_count = reader.read_u32::<LittleEndian>()?;
+        let mut frames = vec![];
+        let mut encoded_data = vec![];
+        for _ in 0..frame_count {
+            let time = Duration::from_micros(reader.read_u64::<LittleEndian>()?);
+            let slot = reader.read_u8()?;
+            if slot >= MAX_SLOT_COUNT {
+                return Err(Error::new(ErrorKind::InvalidData, "Invalid slot number"));
+            }
+            let length = reader.read_u16::<LittleEndian>()?;
+
+            encoded_data.resize(length as usize, 0);
+            reader.read_exact(&mut encoded_data)?;
+            let controller_data = parse_frame_data(version, &encoded_data)?;
+
+            frames.push(RecordedFrame {
+                time,
+                slot,
+                controller_data,
+            });
+        }
+
+        Ok((Recording { frames }, version))
+    }
+
+    /// Rewrites recording of any supported version in current version, returns version it was read in.
+    pub fn migrate<R: Read, W: Write>(reader: &mut R, writer: &mut W) -> Result<u16> {
+        let (recording, version) = Recording::read_versioned_from(reader)?;
+        recording.write_to(writer)?;
+
+        Ok(version)
+    }
+}
+
+/// Parses controller data of a frame written in given format version.
+fn parse_frame_data(version: u16, encoded_data: &[u8]) -> Result<ControllerData> {
+    match version {
+        // Add migrations here when controller data encoding changes,
+        // parsing data of older versions into current `ControllerData`.
+        1 | 2 => {
+            // Fields appended to the encoding after frame was written are taken from default data.
+            let mut padded = vec![];
+            encode_controller_data(&mut padded, 0, ControllerData::default())?;
+            let encoded_data = if encoded_data.len() < padded.len() {
+                padded[..encoded_data.len()].copy_from_slice(encoded_data);
+                &padded
+            } else {
+                encoded_data
+            };
+
+            parse_controller_data(&mut Cursor::new(encoded_data))
+                .map(|(_packet_number, controller_data)| controller_data)
+        }
+        _ => Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("Unsupported recording version: {}", version),
+        )),
+    }
+}
+
+/// Summary of frames recorded for a single slot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SlotSummary {
+    pub frame_count: usize,
+    pub first_time: Duration,
+    pub last_time: Duration,
+    /// Number of presses of every button pressed at least once.
+    pub button_presses: HashMap<Button, u32>,
+    /// Minimum and maximum accelerometer reading per axis.
+    pub accelerometer_range: [[f32; 3]; 2],
+    /// Minimum and maximum gyroscope reading (pitch, yaw, roll).
+    pub gyroscope_range: [[f32; 3]; 2],
+}
+
+impl SlotSummary {
+    /// Average frames per second, `None` if there are less than two frames.
+    pub fn sample_rate(&self) -> Option<f64> {
+        let duration = self.last_time.saturating_sub(self.first_time).as_secs_f64();
+        if self.frame_count < 2 || duration <= 0.0 {
+            None
+        } else {
+            Some((self.frame_count - 1) as f64 / duration)
+        }
+    }
+}
+
+/// Interval without frames for a slot.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Gap {
+    pub slot: u8,
+    /// Time of the last frame before the gap.
+    pub start: Duration,
+    pub duration: Duration,
+}
+
+/// Summary of a recording.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RecordingSummary {
+    pub duration: Duration,
+    pub frame_count: usize,
+    /// Summary of every slot, `None` for slots with no frames.
+    pub slots: [Option<SlotSummary>; MAX_SLOT_COUNT as usize],
+    pub gaps: Vec<Gap>,
+}
+
+/// Records controller data changes with their timing.
+pub struct Recorder {
+    start: Option<Instant>,
+    latest: [Option<ControllerData>; MAX_SLOT_COUNT as usize],
+    recording: Recording,
+}
+
+impl Default for Recorder {
+    fn default() -> Recorder {
+        Recorder::new()
+    }
+}
+
+impl Recorder {
+    /// Creates new recorder, time starts with the first recorded frame.
+    pub fn new() -> Recorder {
+        Recorder {
+            start: None,
+            latest: [None; MAX_SLOT_COUNT as usize],
+            recording: Recording::new(),
+        }
+    }
+
+    /// Records controller data for given slot if it differs from previously recorded data.
+    pub fn record(&mut self, slot_number: u8, controller_data: ControllerData) {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        if self.latest[slot_number as usize] == Some(controller_data) {
+            return;
+        }
+        self.latest[slot_number as usize] = Some(controller_data);
+
+        let now = Instant::now();
+        let start = *self.start.get_or_insert(now);
+
+        // Recordings store time with microsecond precision.
+        let time = Duration::from_micros(now.duration_since(start).as_micros() as u64);
+        self.recording.frames.push(RecordedFrame {
+            time,
+            slot: slot_number,
+            controller_data,
+        });
+    }
+
+    /// Finishes recording.
+    pub fn finish(self) -> Recording {
+        self.recording
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Playback {
+    Once,
+    Looped,
+}
+
+/// Starts background thread replaying recording into server.
+///
+/// Motion timestamps are rewritten to time elapsed since playback start, so they keep increasing when looping.
+///
+/// Fails with `ErrorKind::InvalidInput` error if given slot number or (when it's `None`) slot of any frame
+/// is invalid for server, e.g. when recording of extended server is replayed into a standard one.
+///
+/// # Arguments
+///
+/// * `recording` - recording to replay.
+/// * `server` - server to update.
+/// * `slot_number` - slot to replay all frames into, pass `None` to use slots frames were recorded in.
+/// * `playback` - whether to stop after replaying once or repeat until stopped.
+/// * `countinue_running` - playback stops when this is set to `false`.
+pub fn play(
+    recording: Recording,
+    server: Arc<Server>,
+    slot_number: Option<u8>,
+    playback: Playback,
+    countinue_running: Arc<AtomicBool>,
+) -> Result<JoinHandle<()>> {
+    match slot_number {
+        Some(slot_number) => {
+            check_slot_number_in(slot_number, server.slot_count())?;
+        }
+        None => {
+            for frame in recording.frames.iter() {
+                check_slot_number_in(frame.slot, server.slot_count())?;
+            }
+        }
+    }
+
+    Ok(std::thread::spawn(move || {
+        if recording.frames.is_empty() {
+            return;
+        }
+
+        let start = Instant::now();
+        let mut loop_start = Duration::from_secs(0);
+        loop {
+            for frame in recording.frames.iter() {
+                let frame_time = loop_start + frame.time;
+                loop {
+                    if !countinue_running.load(Ordering::SeqCst) {
+                        return;
+                    }
+
+                    let elapsed = start.elapsed();
+                    if elapsed >= frame_time {
+                        break;
+                    }
+                    std::thread::sleep((frame_time - elapsed).min(Duration::from_millis(100)));
+                }
+
+                let mut controller_data = frame.controller_data;
+                controller_data.motion_data_timestamp = start.elapsed().as_micros() as u64;
+                server.update_controller_data(slot_number.unwrap_or(frame.slot), controller_data);
+            }
+
+            match playback {
+                Playback::Once => return,
+                Playback::Looped => {
+                    // Avoid spinning on zero-length recordings.
+                    loop_start += recording.duration().max(Duration::from_millis(1));
+                }
+            }
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(time_ms: u64, slot: u8, timestamp: u64) -> RecordedFrame {
+        RecordedFrame {
+            time: Duration::from_millis(time_ms),
+            slot,
+            controller_data: ControllerData {
+                connected: true,
+                cross: true,
+                left_stick_x: 200,
+                motion_data_timestamp: timestamp,
+                accelerometer_y: -1.0,
+                gyroscope_yaw: 90.0,
+                ..Default::default()
+            },
+        }
+    }
+
+    /// Encodes frames in unversioned format, cutting controller data of each frame to given length.
+    fn encode_v1(frames: &[RecordedFrame], data_length: Option<usize>) -> Vec<u8> {
+        let mut buffer = vec![];
+        buffer.extend_from_slice(LEGACY_MAGIC);
+        buffer
+            .write_u32::<LittleEndian>(frames.len() as u32)
+            .unwrap();
+        for frame in frames {
+            let mut encoded_data = vec![];
+            encode_controller_data(&mut encoded_data, 0, frame.controller_data).unwrap();
+            if let Some(data_length) = data_length {
+                encoded_data.truncate(data_length);
+            }
+
+            buffer
+                .write_u64::<LittleEndian>(frame.time.as_micros() as u64)
+                .unwrap();
+            buffer.write_u8(frame.slot).unwrap();
+            buffer
+                .write_u16::<LittleEndian>(encoded_data.len() as u16)
+                .unwrap();
+            buffer.extend_from_slice(&encoded_data);
+        }
+
+        buffer
+    }
+
+    #[test]
+    fn reads_v1_recording() {
+        let recording = Recording {
+            frames: vec![frame(0, 0, 1000), frame(4, 1, 5000)],
+        };
+
+        let (read, version) =
+            Recording::read_versioned_from(&mut &encode_v1(&recording.frames, None)[..]).unwrap();
+        assert_eq!(version, 1);
+        assert_eq!(read, recording);
+    }
+
+    #[test]
+    fn migrates_v1_recording() {
+        let recording = Recording {
+            frames: vec![frame(0, 0, 1000), frame(4, 1, 5000)],
+        };
+
+        let mut migrated = vec![];
+        let version =
+            Recording::migrate(&mut &encode_v1(&recording.frames, None)[..], &mut migrated)
+                .unwrap();
+        assert_eq!(version, 1);
+
+        let (read, version) = Recording::read_versioned_from(&mut &migrated[..]).unwrap();
+        assert_eq!(version, RECORDING_VERSION);
+        assert_eq!(read, recording);
+    }
+
+    #[test]
+    fn fields_missing_from_older_frames_are_default() {
+        let recorded = frame(0, 0, 1000);
+        let mut encoded_data = vec![];
+        encode_controller_data(&mut encoded_data, 0, recorded.controller_data).unwrap();
+        // Frame written before accelerometer and gyroscope were encoded.
+        let data_length = encoded_data.len() - 6 * 4;
+
+        let read =
+            Recording::read_from(&mut &encode_v1(&[recorded], Some(data_length))[..]).unwrap();
+        assert_eq!(
+            read.frames[0].controller_data,
+            ControllerData {
+                accelerometer_y: 0.0,
+                gyroscope_yaw: 0.0,
+                ..recorded.controller_data
+            }
+        );
+    }
+
+    #[test]
+    fn frames_beyond_server_slots_are_rejected() {
+        let network = crate::transport::LoopbackNetwork::new();
+        let server = Arc::new(
+            Server::builder()
+                .build_with_transport(Box::new(
+                    network
+                        .bind(std::net::SocketAddr::from(([10, 0, 0, 1], 26760)))
+                        .unwrap(),
+                ))
+                .unwrap(),
+        );
+        let recording = Recording {
+            frames: vec![frame(0, 0, 1000), frame(4, MAX_SLOT_COUNT - 1, 5000)],
+        };
+        let running = Arc::new(AtomicBool::new(true));
+
+        let error = play(
+            recording.clone(),
+            server.clone(),
+            None,
+            Playback::Once,
+            running.clone(),
+        )
+        .err()
+        .unwrap();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        play(recording, server, Some(0), Playback::Once, running)
+            .unwrap()
+            .join()
+            .unwrap();
+    }
+}
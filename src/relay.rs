@@ -0,0 +1,418 @@
+//! Relaying protocol traffic between home server and remote client through public endpoint.
+//!
+//! Both ends connect out to [`Relay`] using [`RelayTransport`] and register with the same token,
+//! so neither needs to accept incoming connections from the internet. Registration is repeated
+//! every [`KEEPALIVE_INTERVAL`], keeping NAT mappings of both ends open.
+//!
+//! Typical setup is a [`crate::bridge::Bridge`] at each end: at home, its client talks to local server
+//! and its server uses relay transport in [`RelayRole::Server`]; at remote end, its client uses relay transport
+//! in [`RelayRole::Client`] and its server serves local emulator.
+//!
+//! Relay frames start with [`RELAY_MAGIC`] followed by frame kind:
+//! * `0` (hello) - role (`0` server, `1` client) and token bytes,
+//! * `1` (data) - between relay and server: address of remote client (family `4` or `6`, IP address bytes,
+//!   `u16` little-endian port) and datagram; between relay and client: datagram only.
+
+use crate::transport::*;
+use std::collections::HashMap;
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, UdpSocket};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Magic string starting relay frames.
+pub const RELAY_MAGIC: [u8; 4] = *b"DSUR";
+
+/// Time between registrations sent by relay transports.
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Registration not renewed for this long expires.
+pub const REGISTRATION_TIMEOUT: Duration = Duration::from_secs(30);
+
+const HELLO: u8 = 0;
+const DATA: u8 = 1;
+
+const MAX_DATAGRAM_LENGTH: usize = 2048;
+
+/// Side of relayed connection.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum RelayRole {
+    /// Home end, talking to server.
+    Server,
+    /// Remote end, talking to client.
+    Client,
+}
+
+fn encode_hello(role: RelayRole, token: &[u8]) -> Vec<u8> {
+    let mut frame = RELAY_MAGIC.to_vec();
+    frame.push(HELLO);
+    frame.push(match role {
+        RelayRole::Server => 0,
+        RelayRole::Client => 1,
+    });
+    frame.extend_from_slice(token);
+
+    frame
+}
+
+fn encode_data(peer: Option<SocketAddr>, datagram: &[u8]) -> Vec<u8> {
+    let mut frame = RELAY_MAGIC.to_vec();
+    frame.push(DATA);
+    if let Some(peer) = peer {
+        match peer.ip() {
+            IpAddr::V4(ip) => {
+                frame.push(4);
+                frame.extend_from_slice(&ip.octets());
+            }
+            IpAddr::V6(ip) => {
+                frame.push(6);
+                frame.extend_from_slice(&ip.octets());
+            }
+        }
+        frame.extend_from_slice(&peer.port().to_le_bytes());
+    }
+    frame.extend_from_slice(datagram);
+
+    frame
+}
+
+fn invalid_frame_error(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+/// Splits data frame body into peer address and datagram.
+fn parse_peer(body: &[u8]) -> Result<(SocketAddr, &[u8])> {
+    let (ip, rest): (IpAddr, &[u8]) = match body.first() {
+        Some(4) if body.len() >= 7 => {
+            let mut octets = [0; 4];
+            octets.copy_from_slice(&body[1..5]);
+            (Ipv4Addr::from(octets).into(), &body[5..])
+        }
+        Some(6) if body.len() >= 19 => {
+            let mut octets = [0; 16];
+            octets.copy_from_slice(&body[1..17]);
+            (Ipv6Addr::from(octets).into(), &body[17..])
+        }
+        _ => return Err(invalid_frame_error("Invalid peer address")),
+    };
+    let port = u16::from_le_bytes([rest[0], rest[1]]);
+
+    Ok((SocketAddr::new(ip, port), &rest[2..]))
+}
+
+/// Returns frame kind and body.
+fn parse_frame(frame: &[u8]) -> Result<(u8, &[u8])> {
+    if frame.len() < 5 || frame[..4] != RELAY_MAGIC {
+        return Err(invalid_frame_error("Not a relay frame"));
+    }
+
+    Ok((frame[4], &frame[5..]))
+}
+
+#[derive(Clone, Debug)]
+struct Registration {
+    role: RelayRole,
+    token: Vec<u8>,
+    last_seen: Instant,
+}
+
+/// Relay metrics snapshot.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct RelayStats {
+    /// Currently registered servers.
+    pub servers: usize,
+    /// Currently registered clients.
+    pub clients: usize,
+    pub forwarded_packets: u64,
+    /// Packets that were invalid, came from unregistered endpoints or had nobody to be forwarded to.
+    pub dropped_packets: u64,
+}
+
+/// Public endpoint forwarding traffic between servers and clients registered with the same token.
+///
+/// If multiple servers register with the same token, the one heard from most recently gets the traffic.
+pub struct Relay {
+    socket: UdpSocket,
+    registrations: Mutex<HashMap<SocketAddr, Registration>>,
+    forwarded_packets: AtomicU64,
+    dropped_packets: AtomicU64,
+}
+
+impl Relay {
+    /// Creates new relay.
+    ///
+    /// # Arguments
+    ///
+    /// * `address` - relay's public UDP socket address.
+    pub fn new(address: SocketAddr) -> Result<Relay> {
+        Ok(Relay {
+            socket: bind_udp(address)?,
+            registrations: Mutex::new(HashMap::new()),
+            forwarded_packets: AtomicU64::new(0),
+            dropped_packets: AtomicU64::new(0),
+        })
+    }
+
+    /// Returns relay's local socket address.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    pub fn stats(&self) -> RelayStats {
+        let registrations = self.registrations.lock().unwrap();
+        let count = |role: RelayRole| {
+            registrations
+                .values()
+                .filter(|registration| registration.role == role)
+                .count()
+        };
+
+        RelayStats {
+            servers: count(RelayRole::Server),
+            clients: count(RelayRole::Client),
+            forwarded_packets: self.forwarded_packets.load(Ordering::Relaxed),
+            dropped_packets: self.dropped_packets.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Starts forwarding on background thread.
+    pub fn start(self: Arc<Self>, countinue_running: Arc<AtomicBool>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut buf = vec![0; MAX_DATAGRAM_LENGTH];
+            while countinue_running.load(Ordering::SeqCst) {
+                if let Ok((amount, source)) = self.socket.recv_from(&mut buf) {
+                    if self.handle_frame(source, &buf[..amount]).is_err() {
+                        self.dropped_packets.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+
+                self.expire_registrations();
+            }
+        })
+    }
+
+    fn expire_registrations(&self) {
+        let now = Instant::now();
+        self.registrations
+            .lock()
+            .unwrap()
+            .retain(|_, registration| {
+                now.duration_since(registration.last_seen) < REGISTRATION_TIMEOUT
+            });
+    }
+
+    fn handle_frame(&self, source: SocketAddr, frame: &[u8]) -> Result<()> {
+        let (kind, body) = parse_frame(frame)?;
+        match kind {
+            HELLO => {
+                let role = match body.first() {
+                    Some(0) => RelayRole::Server,
+                    Some(1) => RelayRole::Client,
+                    _ => return Err(invalid_frame_error("Invalid role")),
+                };
+                let token = &body[1..];
+                if token.is_empty() {
+                    return Err(invalid_frame_error("Empty token"));
+                }
+
+                self.registrations.lock().unwrap().insert(
+                    source,
+                    Registration {
+                        role,
+                        token: token.to_vec(),
+                        last_seen: Instant::now(),
+                    },
+                );
+
+                Ok(())
+            }
+            DATA => {
+                let (target, frame) = {
+                    let mut registrations = self.registrations.lock().unwrap();
+                    let registration = registrations
+                        .get_mut(&source)
+                        .ok_or_else(|| invalid_frame_error("Source is not registered"))?;
+                    registration.last_seen = Instant::now();
+                    let registration = registration.clone();
+
+                    match registration.role {
+                        RelayRole::Client => {
+                            let server = registrations
+                                .iter()
+                                .filter(|(_, candidate)| {
+                                    candidate.role == RelayRole::Server
+                                        && candidate.token == registration.token
+                                })
+                                .max_by_key(|(_, candidate)| candidate.last_seen)
+                                .map(|(&address, _)| address)
+                                .ok_or_else(|| invalid_frame_error("No server registered"))?;
+
+                            (server, encode_data(Some(source), body))
+                        }
+                        RelayRole::Server => {
+                            let (client, datagram) = parse_peer(body)?;
+                            let is_paired = registrations.get(&client).is_some_and(|candidate| {
+                                candidate.role == RelayRole::Client
+                                    && candidate.token == registration.token
+                            });
+                            if !is_paired {
+                                return Err(invalid_frame_error("Client is not registered"));
+                            }
+
+                            (client, encode_data(None, datagram))
+                        }
+                    }
+                };
+
+                self.socket.send_to(&frame, target)?;
+                self.forwarded_packets.fetch_add(1, Ordering::Relaxed);
+
+                Ok(())
+            }
+            _ => Err(invalid_frame_error("Unknown frame kind")),
+        }
+    }
+}
+
+/// Transport talking to peers through relay.
+///
+/// In [`RelayRole::Server`] role, datagrams are exchanged with remote clients addressed by their public addresses.
+/// In [`RelayRole::Client`] role, datagrams are always sent to server, regardless of target address,
+/// and received datagrams come from relay's address, so client should use relay's address as server address.
+pub struct RelayTransport {
+    socket: UdpSocket,
+    relay_address: SocketAddr,
+    role: RelayRole,
+    token: Vec<u8>,
+    last_hello: Mutex<Instant>,
+}
+
+impl RelayTransport {
+    /// Registers with relay.
+    ///
+    /// # Arguments
+    ///
+    /// * `relay_address` - relay's public UDP socket address.
+    /// * `role` - which end of relayed connection this is.
+    /// * `token` - secret shared by both ends, pairs them at relay.
+    pub fn connect(
+        relay_address: SocketAddr,
+        role: RelayRole,
+        token: &str,
+    ) -> Result<RelayTransport> {
+        if token.is_empty() {
+            return Err(Error::new(
+                ErrorKind::InvalidInput,
+                "Token must not be empty",
+            ));
+        }
+
+        let local_address = match relay_address {
+            SocketAddr::V4(_) => SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0)),
+            SocketAddr::V6(_) => SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0)),
+        };
+        let socket = bind_udp(local_address)?;
+        let token = token.as_bytes().to_vec();
+        socket.send_to(&encode_hello(role, &token), relay_address)?;
+
+        Ok(RelayTransport {
+            socket,
+            relay_address,
+            role,
+            token,
+            last_hello: Mutex::new(Instant::now()),
+        })
+    }
+
+    fn keep_alive(&self) -> Result<()> {
+        let mut last_hello = self.last_hello.lock().unwrap();
+        if last_hello.elapsed() >= KEEPALIVE_INTERVAL {
+            self.socket
+                .send_to(&encode_hello(self.role, &self.token), self.relay_address)?;
+            *last_hello = Instant::now();
+        }
+
+        Ok(())
+    }
+}
+
+impl Transport for RelayTransport {
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize> {
+        self.keep_alive()?;
+
+        let frame = match self.role {
+            RelayRole::Server => encode_data(Some(target), buf),
+            RelayRole::Client => encode_data(None, buf),
+        };
+        self.socket.send_to(&frame, self.relay_address)?;
+
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.keep_alive()?;
+
+        let mut frame = vec![0; MAX_DATAGRAM_LENGTH];
+        let (amount, source) = self.socket.recv_from(&mut frame)?;
+        if source != self.relay_address {
+            return Err(invalid_frame_error("Datagram did not come from relay"));
+        }
+
+        let (kind, body) = parse_frame(&frame[..amount])?;
+        if kind != DATA {
+            return Err(invalid_frame_error("Unexpected frame kind"));
+        }
+        let (source, datagram) = match self.role {
+            RelayRole::Server => parse_peer(body)?,
+            RelayRole::Client => (self.relay_address, body),
+        };
+
+        let amount = datagram.len().min(buf.len());
+        buf[..amount].copy_from_slice(&datagram[..amount]);
+
+        Ok((amount, source))
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn data_frames_roundtrip_with_peer_addresses() {
+        let peers = [
+            SocketAddr::from(([203, 0, 113, 7], 26760)),
+            SocketAddr::from(([0x2001, 0xdb8, 0, 0, 0, 0, 0, 1], 3333)),
+        ];
+        for peer in peers {
+            let frame = encode_data(Some(peer), b"DSUS");
+            let (kind, body) = parse_frame(&frame).unwrap();
+            assert_eq!(kind, DATA);
+            assert_eq!(parse_peer(body).unwrap(), (peer, &b"DSUS"[..]));
+        }
+
+        let frame = encode_data(None, b"DSUC");
+        assert_eq!(parse_frame(&frame).unwrap(), (DATA, &b"DSUC"[..]));
+    }
+
+    #[test]
+    fn hello_frame_carries_role_and_token() {
+        let frame = encode_hello(RelayRole::Client, b"secret");
+        assert_eq!(parse_frame(&frame).unwrap(), (HELLO, &b"\x01secret"[..]));
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        assert!(parse_frame(b"DSUS\x01").is_err());
+        assert!(parse_frame(b"DSUR").is_err());
+        assert!(parse_peer(&[4, 127, 0, 0, 1, 0]).is_err());
+        assert!(parse_peer(&[6, 0, 0]).is_err());
+        assert!(parse_peer(&[5, 0, 0, 0, 0, 0, 0]).is_err());
+    }
+}
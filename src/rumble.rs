@@ -0,0 +1,449 @@
+//! Routing of rumble requests to output devices.
+//!
+//! [`RumbleRouter`] maps slots to any number of [`RumbleOutput`]s (physical controllers, virtual gamepads, ...),
+//! scaling intensity per slot. Closures implement [`RumbleOutput`], so outputs without built-in support
+//! (e.g. ViGEm virtual gamepads) can be registered directly.
+//!
+//! Router converted with [`RumbleRouter::into_callback`] handles rumble requests received by server
+//! (see `Server::set_rumble_callback`). Alternatively each slot can have its own [`RumbleSink`]
+//! (see `Server::set_rumble_sink`), e.g. output of backend providing slot's controller wrapped in [`OutputSink`].
+
+use crate::protocol::MAX_SLOT_COUNT;
+use crate::server::RumbleCallback;
+use std::io::Result;
+use std::sync::Mutex;
+
+/// Index of large motor in rumble requests, see `RumbleRouter::into_callback` and `OutputSink`.
+pub const LARGE_MOTOR: u8 = 0;
+
+/// Index of small motor in rumble requests, see `RumbleRouter::into_callback` and `OutputSink`.
+pub const SMALL_MOTOR: u8 = 1;
+
+/// Intensities of controller's motors, from 0 (off) to 255 (full).
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct Rumble {
+    /// Heavy (low frequency) motor, usually in the left grip.
+    pub large: u8,
+    /// Light (high frequency) motor, usually in the right grip.
+    pub small: u8,
+}
+
+impl Rumble {
+    /// Motors off.
+    pub const OFF: Rumble = Rumble { large: 0, small: 0 };
+
+    /// Scales both intensities, result is clamped to valid range.
+    pub fn scaled(self, scale: f32) -> Rumble {
+        let scale_intensity = |intensity: u8| (intensity as f32 * scale).clamp(0.0, 255.0) as u8;
+
+        Rumble {
+            large: scale_intensity(self.large),
+            small: scale_intensity(self.small),
+        }
+    }
+}
+
+/// Device able to rumble.
+pub trait RumbleOutput: Send {
+    fn set_rumble(&mut self, rumble: Rumble) -> Result<()>;
+}
+
+impl<F> RumbleOutput for F
+where
+    F: FnMut(Rumble) -> Result<()> + Send,
+{
+    fn set_rumble(&mut self, rumble: Rumble) -> Result<()> {
+        self(rumble)
+    }
+}
+
+/// Receiver of rumble requests for motors of single slot, see `Server::set_rumble_sink`.
+pub trait RumbleSink: Send {
+    /// Number of motors reported to clients.
+    fn motor_count(&self) -> u8;
+
+    /// Sets intensity (from 0 to 255) of motor, called only for motors lower than `motor_count`.
+    fn set_motor(&mut self, motor: u8, intensity: u8) -> Result<()>;
+}
+
+/// Sink driving output's motors with requests of motors `LARGE_MOTOR` and `SMALL_MOTOR`.
+pub struct OutputSink<O: RumbleOutput> {
+    output: O,
+    rumble: Rumble,
+}
+
+impl<O: RumbleOutput> OutputSink<O> {
+    pub fn new(output: O) -> OutputSink<O> {
+        OutputSink {
+            output,
+            rumble: Rumble::OFF,
+        }
+    }
+
+    /// Returns intensities last sent to output.
+    pub fn rumble(&self) -> Rumble {
+        self.rumble
+    }
+
+    pub fn into_inner(self) -> O {
+        self.output
+    }
+}
+
+impl<O: RumbleOutput> RumbleSink for OutputSink<O> {
+    fn motor_count(&self) -> u8 {
+        2
+    }
+
+    fn set_motor(&mut self, motor: u8, intensity: u8) -> Result<()> {
+        match motor {
+            LARGE_MOTOR => self.rumble.large = intensity,
+            SMALL_MOTOR => self.rumble.small = intensity,
+            _ => return Ok(()),
+        }
+
+        self.output.set_rumble(self.rumble)
+    }
+}
+
+#[derive(Clone)]
+struct SlotRoute {
+    outputs: Vec<usize>,
+    scale: f32,
+    enabled: bool,
+}
+
+impl Default for SlotRoute {
+    fn default() -> SlotRoute {
+        SlotRoute {
+            outputs: vec![],
+            scale: 1.0,
+            enabled: true,
+        }
+    }
+}
+
+/// Routes rumble of slots to registered outputs.
+#[derive(Default)]
+pub struct RumbleRouter {
+    outputs: Vec<Box<dyn RumbleOutput>>,
+    routes: [SlotRoute; MAX_SLOT_COUNT as usize],
+}
+
+impl RumbleRouter {
+    pub fn new() -> RumbleRouter {
+        Default::default()
+    }
+
+    /// Registers output, returns its index.
+    pub fn add_output<O: RumbleOutput + 'static>(&mut self, output: O) -> usize {
+        self.outputs.push(Box::new(output));
+
+        self.outputs.len() - 1
+    }
+
+    /// Routes rumble of slot to output (slot may be routed to multiple outputs and vice versa).
+    pub fn connect(&mut self, slot_number: u8, output: usize) {
+        assert!(slot_number < MAX_SLOT_COUNT);
+        assert!(output < self.outputs.len());
+
+        let outputs = &mut self.routes[slot_number as usize].outputs;
+        if !outputs.contains(&output) {
+            outputs.push(output);
+        }
+    }
+
+    /// Stops routing rumble of slot to output, stopping output's motors.
+    pub fn disconnect(&mut self, slot_number: u8, output: usize) -> Result<()> {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        let outputs = &mut self.routes[slot_number as usize].outputs;
+        if let Some(index) = outputs.iter().position(|&routed| routed == output) {
+            outputs.remove(index);
+            self.outputs[output].set_rumble(Rumble::OFF)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns outputs slot's rumble is routed to.
+    pub fn outputs_of(&self, slot_number: u8) -> &[usize] {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        &self.routes[slot_number as usize].outputs
+    }
+
+    /// Sets intensity scale of slot (1.0 by default).
+    pub fn set_scale(&mut self, slot_number: u8, scale: f32) {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        self.routes[slot_number as usize].scale = scale.max(0.0);
+    }
+
+    pub fn scale(&self, slot_number: u8) -> f32 {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        self.routes[slot_number as usize].scale
+    }
+
+    /// Enables or disables rumble of slot (enabled by default), disabling stops its outputs' motors.
+    pub fn set_enabled(&mut self, slot_number: u8, enabled: bool) -> Result<()> {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        self.routes[slot_number as usize].enabled = enabled;
+        if !enabled {
+            self.send(slot_number, Rumble::OFF)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn is_enabled(&self, slot_number: u8) -> bool {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        self.routes[slot_number as usize].enabled
+    }
+
+    /// Sends rumble of slot (scaled) to all its outputs, ignored if slot's rumble is disabled.
+    ///
+    /// All outputs are updated even if some of them fail, first error is returned.
+    pub fn route(&mut self, slot_number: u8, rumble: Rumble) -> Result<()> {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        let route = &self.routes[slot_number as usize];
+        if !route.enabled {
+            return Ok(());
+        }
+
+        let rumble = rumble.scaled(route.scale);
+        self.send(slot_number, rumble)
+    }
+
+    /// Converts router to server's rumble callback, routing requests of motors `LARGE_MOTOR` and `SMALL_MOTOR`
+    /// (set slot's motor count to 2 with `Server::set_motor_count`). Errors of outputs are ignored.
+    pub fn into_callback(self) -> RumbleCallback {
+        let state = Mutex::new((self, [Rumble::OFF; MAX_SLOT_COUNT as usize]));
+
+        Box::new(move |slot_number, motor, intensity| {
+            let mut state = state.lock().unwrap();
+            let (router, rumbles) = &mut *state;
+            let rumble = &mut rumbles[slot_number as usize];
+            match motor {
+                LARGE_MOTOR => rumble.large = intensity,
+                SMALL_MOTOR => rumble.small = intensity,
+                _ => return,
+            }
+
+            let rumble = *rumble;
+            let _ = router.route(slot_number, rumble);
+        })
+    }
+
+    fn send(&mut self, slot_number: u8, rumble: Rumble) -> Result<()> {
+        let mut result = Ok(());
+        for &output in self.routes[slot_number as usize].outputs.iter() {
+            let output_result = self.outputs[output].set_rumble(rumble);
+            if result.is_ok() {
+                result = output_result;
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(feature = "hid")]
+pub use self::hid::*;
+
+#[cfg(feature = "hid")]
+mod hid {
+    use super::*;
+    use hidapi::HidDevice;
+    use std::io::Error;
+
+    fn write_report(device: &HidDevice, report: &[u8]) -> Result<()> {
+        device
+            .write(report)
+            .map(|_| ())
+            .map_err(|error| Error::other(error.to_string()))
+    }
+
+    /// DualShock 4 connected over USB.
+    pub struct DualShock4Rumble(pub HidDevice);
+
+    impl RumbleOutput for DualShock4Rumble {
+        fn set_rumble(&mut self, rumble: Rumble) -> Result<()> {
+            let mut report = [0u8; 32];
+            report[0] = 0x05;
+            // Only motors are updated.
+            report[1] = 0x01;
+            report[4] = rumble.small;
+            report[5] = rumble.large;
+
+            write_report(&self.0, &report)
+        }
+    }
+
+    /// DualSense connected over USB.
+    pub struct DualSenseRumble(pub HidDevice);
+
+    impl RumbleOutput for DualSenseRumble {
+        fn set_rumble(&mut self, rumble: Rumble) -> Result<()> {
+            let mut report = [0u8; 48];
+            report[0] = 0x02;
+            // Compatible vibration and haptics select.
+            report[1] = 0x03;
+            report[3] = rumble.small;
+            report[4] = rumble.large;
+
+            write_report(&self.0, &report)
+        }
+    }
+}
+
+#[cfg(all(feature = "evdev-rumble", target_os = "linux"))]
+pub use self::evdev_rumble::*;
+
+#[cfg(all(feature = "evdev-rumble", target_os = "linux"))]
+mod evdev_rumble {
+    use super::*;
+    use evdev::{Device, FFEffect, FFEffectData, FFEffectKind, FFReplay, FFTrigger};
+    use std::path::Path;
+
+    /// Evdev device supporting force feedback rumble effect.
+    pub struct EvdevRumble {
+        device: Device,
+        effect: Option<FFEffect>,
+    }
+
+    impl EvdevRumble {
+        /// Opens evdev device (e.g. `/dev/input/event5`), writing to it usually requires user to be in `input` group.
+        pub fn open<P: AsRef<Path>>(path: P) -> Result<EvdevRumble> {
+            Ok(EvdevRumble {
+                device: Device::open(path)?,
+                effect: None,
+            })
+        }
+    }
+
+    impl RumbleOutput for EvdevRumble {
+        fn set_rumble(&mut self, rumble: Rumble) -> Result<()> {
+            let data = FFEffectData {
+                direction: 0,
+                trigger: FFTrigger::default(),
+                replay: FFReplay {
+                    // Played until updated.
+                    length: u16::MAX,
+                    delay: 0,
+                },
+                kind: FFEffectKind::Rumble {
+                    strong_magnitude: rumble.large as u16 * 257,
+                    weak_magnitude: rumble.small as u16 * 257,
+                },
+            };
+
+            match &mut self.effect {
+                Some(effect) => effect.update(data)?,
+                None => {
+                    let mut effect = self.device.upload_ff_effect(data)?;
+                    effect.play(1)?;
+                    self.effect = Some(effect);
+                }
+            }
+
+            Ok(())
+        }
+    }
+}
+
+#[cfg(feature = "gilrs-rumble")]
+pub use self::gilrs_rumble::*;
+
+#[cfg(feature = "gilrs-rumble")]
+mod gilrs_rumble {
+    use super::*;
+    use gilrs::ff::{BaseEffect, BaseEffectType, Effect, EffectBuilder, Repeat};
+    use gilrs::{GamepadId, Gilrs};
+    use std::io::{Error, ErrorKind};
+
+    fn ff_error(error: gilrs::ff::Error) -> Error {
+        Error::other(error.to_string())
+    }
+
+    /// Plays effect driving single motor with full magnitude until stopped, intensity is controlled with gain.
+    fn motor_effect(gilrs: &mut Gilrs, gamepad: GamepadId, kind: BaseEffectType) -> Result<Effect> {
+        EffectBuilder::new()
+            .add_effect(BaseEffect {
+                kind,
+                ..Default::default()
+            })
+            .gamepads(&[gamepad])
+            .repeat(Repeat::Infinitely)
+            .gain(0.0)
+            .finish(gilrs)
+            .map_err(ff_error)
+    }
+
+    /// Gamepad managed by gilrs, using its force feedback.
+    pub struct GilrsRumble {
+        large: Effect,
+        small: Effect,
+        playing: bool,
+    }
+
+    impl GilrsRumble {
+        /// Creates force feedback effects on gamepad.
+        ///
+        /// Fails with `ErrorKind::NotFound` error if gamepad isn't connected
+        /// and with `ErrorKind::Unsupported` error if it doesn't support force feedback.
+        pub fn new(gilrs: &mut Gilrs, gamepad: GamepadId) -> Result<GilrsRumble> {
+            let ff_supported = gilrs
+                .connected_gamepad(gamepad)
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "Gamepad is not connected"))?
+                .is_ff_supported();
+            if !ff_supported {
+                return Err(Error::new(
+                    ErrorKind::Unsupported,
+                    "Gamepad doesn't support force feedback",
+                ));
+            }
+
+            Ok(GilrsRumble {
+                large: motor_effect(
+                    gilrs,
+                    gamepad,
+                    BaseEffectType::Strong {
+                        magnitude: u16::MAX,
+                    },
+                )?,
+                small: motor_effect(
+                    gilrs,
+                    gamepad,
+                    BaseEffectType::Weak {
+                        magnitude: u16::MAX,
+                    },
+                )?,
+                playing: false,
+            })
+        }
+    }
+
+    impl RumbleOutput for GilrsRumble {
+        fn set_rumble(&mut self, rumble: Rumble) -> Result<()> {
+            self.large
+                .set_gain(rumble.large as f32 / 255.0)
+                .map_err(ff_error)?;
+            self.small
+                .set_gain(rumble.small as f32 / 255.0)
+                .map_err(ff_error)?;
+
+            if !self.playing {
+                self.large.play().map_err(ff_error)?;
+                self.small.play().map_err(ff_error)?;
+                self.playing = true;
+            }
+
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Frame-synchronized sampling of client's controller data for game loops.
+
+use crate::client::*;
+use crate::protocol::*;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Controller data of all slots for single frame.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Frame {
+    /// Frames since sampler was created, skipped frames are counted too.
+    pub number: u64,
+    pub time: Instant,
+    /// Indexed by slot number, covers all slots of sampled client.
+    pub slots: Vec<ControllerData>,
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Sample {
+    controller_data: ControllerData,
+    seen: Instant,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct SlotHistory {
+    previous: Option<Sample>,
+    latest: Option<Sample>,
+}
+
+fn lerp(from: f32, to: f32, alpha: f32) -> f32 {
+    from + (to - from) * alpha
+}
+
+/// Samples client once per frame, so game loop never sees data change mid-frame.
+///
+/// By default latest data wins. With interpolation enabled, motion (accelerometer and gyroscope) is
+/// interpolated between two latest distinct samples, trailing them by one sample interval;
+/// buttons, sticks and touches always come from the latest sample.
+pub struct FrameSampler {
+    client: Arc<Client>,
+    frame_interval: Duration,
+    start: Instant,
+    next_frame: u64,
+    interpolation: bool,
+    history: Vec<SlotHistory>,
+}
+
+impl FrameSampler {
+    /// Creates new sampler.
+    ///
+    /// # Arguments
+    ///
+    /// * `client` - client to sample (it should be already started).
+    /// * `frame_rate` - target frames per second.
+    pub fn new(client: Arc<Client>, frame_rate: f64) -> FrameSampler {
+        assert!(frame_rate > 0.0);
+
+        FrameSampler {
+            history: vec![Default::default(); client.slot_count() as usize],
+            client,
+            frame_interval: Duration::from_secs_f64(1.0 / frame_rate),
+            start: Instant::now(),
+            next_frame: 0,
+            interpolation: false,
+        }
+    }
+
+    /// Enables or disables motion interpolation (disabled by default).
+    pub fn set_interpolation(&mut self, interpolation: bool) {
+        self.interpolation = interpolation;
+    }
+
+    /// Waits for start of the next frame and samples it.
+    ///
+    /// If caller fell behind by whole frames, missed frames are skipped instead of being returned in a burst.
+    pub fn next_frame(&mut self) -> Frame {
+        let now = Instant::now();
+        let elapsed_frames = (now.duration_since(self.start).as_secs_f64()
+            / self.frame_interval.as_secs_f64()) as u64;
+        if elapsed_frames > self.next_frame {
+            self.next_frame = elapsed_frames;
+        }
+
+        let frame_time = self.start + self.frame_interval.mul_f64(self.next_frame as f64);
+        if frame_time > now {
+            std::thread::sleep(frame_time - now);
+        }
+
+        let frame = self.sample_at(self.next_frame, frame_time);
+        self.next_frame += 1;
+
+        frame
+    }
+
+    fn sample_at(&mut self, number: u64, time: Instant) -> Frame {
+        let mut slots = self.client.snapshot();
+        for (slot_number, controller_data) in slots.iter_mut().enumerate() {
+            let history = &mut self.history[slot_number];
+            let is_new = history.latest.is_none_or(|latest| {
+                latest.controller_data.motion_data_timestamp
+                    != controller_data.motion_data_timestamp
+            });
+            if is_new {
+                history.previous = history.latest;
+                history.latest = Some(Sample {
+                    controller_data: *controller_data,
+                    seen: time,
+                });
+            }
+
+            if self.interpolation {
+                if let (Some(previous), Some(latest)) = (history.previous, history.latest) {
+                    let interval = latest.seen.duration_since(previous.seen).as_secs_f32();
+                    if interval > 0.0 {
+                        let alpha = (time.duration_since(latest.seen).as_secs_f32() / interval)
+                            .clamp(0.0, 1.0);
+                        interpolate_motion(
+                            controller_data,
+                            &previous.controller_data,
+                            &latest.controller_data,
+                            alpha,
+                        );
+                    }
+                }
+            }
+        }
+
+        Frame {
+            number,
+            time,
+            slots,
+        }
+    }
+}
+
+fn interpolate_motion(
+    controller_data: &mut ControllerData,
+    previous: &ControllerData,
+    latest: &ControllerData,
+    alpha: f32,
+) {
+    controller_data.accelerometer_x = lerp(previous.accelerometer_x, latest.accelerometer_x, alpha);
+    controller_data.accelerometer_y = lerp(previous.accelerometer_y, latest.accelerometer_y, alpha);
+    controller_data.accelerometer_z = lerp(previous.accelerometer_z, latest.accelerometer_z, alpha);
+    controller_data.gyroscope_pitch = lerp(previous.gyroscope_pitch, latest.gyroscope_pitch, alpha);
+    controller_data.gyroscope_yaw = lerp(previous.gyroscope_yaw, latest.gyroscope_yaw, alpha);
+    controller_data.gyroscope_roll = lerp(previous.gyroscope_roll, latest.gyroscope_roll, alpha);
+}
@@ -1,56 +1,708 @@
+use crossbeam_queue::ArrayQueue;
 use rand::Rng;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
-use std::io::Result;
-use std::net::SocketAddr;
-use std::net::UdpSocket;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 use std::sync::Arc;
-use std::sync::Mutex;
+use std::sync::{Condvar, Mutex};
 use std::thread::JoinHandle;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
+#[cfg(feature = "compression")]
+use crate::compression::*;
+use crate::history::*;
+#[cfg(feature = "profiles")]
+use crate::profiles::ProfileStore;
 use crate::protocol::*;
+use crate::recording::Recording;
+use crate::rumble::RumbleSink;
+use crate::state::ServerState;
+use crate::transform::Transform;
+use crate::transport::*;
 
 #[derive(Copy, Clone, Debug, Default)]
 struct Slot {
     controller_info: ControllerInfo,
     controller_data: ControllerData,
+    disabled: bool,
+    idle: bool,
+    last_input_time: Option<Instant>,
+    min_send_interval: Option<Duration>,
+    last_send_time: Option<Instant>,
+    send_pending: bool,
+    driver_status: DriverStatus,
+    battery_percent: Option<u8>,
+    /// Controller data updates since health was last published.
+    update_count: u32,
+    /// Amount of motors reported to clients using rumble extension.
+    motor_count: u8,
+}
+
+impl Slot {
+    /// Checks whether slot is hidden from clients.
+    fn is_hidden(&self) -> bool {
+        self.disabled || self.idle
+    }
+
+    /// Checks whether slot's rate limit (see `Server::set_slot_rate_limit`) allows sending it at given time.
+    fn is_send_allowed(&self, now: Instant) -> bool {
+        self.min_send_interval.zip(self.last_send_time).is_none_or(
+            |(min_send_interval, last_send_time)| {
+                now.duration_since(last_send_time) >= min_send_interval
+            },
+        )
+    }
+
+    /// Slot as it should be reported to clients.
+    fn reported(&self) -> Slot {
+        if self.is_hidden() {
+            let mut slot: Slot = Default::default();
+            slot.controller_info.slot = self.controller_info.slot;
+            slot
+        } else {
+            *self
+        }
+    }
 }
 
 struct RequestedControllerData {
     packet_number: u32,
+    /// Controller data updates skipped or sent to client, used for decimation.
+    update_count: u32,
+    last_request_time: Instant,
     slot_numbers: HashSet<u8>,
     mac_addresses: HashSet<u64>,
 }
 
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct ClientIdentity {
+    source_id: u32,
+    protocol_version: u16,
+}
+
+struct ClientState {
+    identity: ClientIdentity,
+    last_datagram: Vec<u8>,
+    last_datagram_time: Instant,
+    /// Time of last valid message, used to forget inactive clients.
+    last_message_time: Instant,
+    last_info_response_time: Option<Instant>,
+    last_data_request_time: Option<Instant>,
+    request_intervals: RequestIntervalHistogram,
+}
+
+/// Upper bounds of `RequestIntervalHistogram` buckets, the last bucket counts all longer intervals.
+pub const REQUEST_INTERVAL_BUCKETS: [Duration; 8] = [
+    Duration::from_millis(10),
+    Duration::from_millis(50),
+    Duration::from_millis(100),
+    Duration::from_millis(250),
+    Duration::from_millis(500),
+    Duration::from_millis(1000),
+    Duration::from_millis(2000),
+    Duration::from_millis(5000),
+];
+
+/// Histogram of intervals between controller data requests of a client.
+///
+/// `counts[i]` is the amount of intervals shorter than `REQUEST_INTERVAL_BUCKETS[i]`
+/// (and not shorter than previous bound), `counts[8]` counts intervals of 5 seconds and longer.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RequestIntervalHistogram {
+    pub counts: [u64; 9],
+    pub min: Option<Duration>,
+    pub max: Option<Duration>,
+    pub total: Duration,
+}
+
+impl RequestIntervalHistogram {
+    fn add(&mut self, interval: Duration) {
+        let bucket = REQUEST_INTERVAL_BUCKETS
+            .iter()
+            .position(|&bound| interval < bound)
+            .unwrap_or(REQUEST_INTERVAL_BUCKETS.len());
+        self.counts[bucket] += 1;
+
+        self.min = Some(self.min.map_or(interval, |min| min.min(interval)));
+        self.max = Some(self.max.map_or(interval, |max| max.max(interval)));
+        self.total += interval;
+    }
+
+    /// Amount of recorded intervals.
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// Mean interval or `None` if no interval was recorded yet.
+    pub fn mean(&self) -> Option<Duration> {
+        match self.count() {
+            0 => None,
+            count => Some(self.total / count as u32),
+        }
+    }
+}
+
+/// Callback actuating motors on rumble requests of clients, receives slot number, motor and intensity
+/// (0 is off, 255 is full).
+pub type RumbleCallback = Box<dyn Fn(u8, u8, u8) + Send + Sync>;
+
+struct Mirror {
+    source: u8,
+    transform: Option<Box<dyn Transform>>,
+}
+
+/// Derives MAC address of mirrored controller, so clients don't confuse it with the original.
+fn mirrored_mac_address(mac_address: u64, destination: u8) -> u64 {
+    mac_address ^ ((destination as u64 + 1) << 40)
+}
+
+/// Priority of client when server is under send-rate pressure.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub enum ClientPriority {
+    /// Client always gets every controller data update (e.g. local emulator).
+    #[default]
+    High,
+    /// Client gets only every Nth controller data update under pressure (e.g. remote logger).
+    Low,
+}
+
+/// Controls decimation of data sent to low priority clients.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SendPressure {
+    /// Server is under pressure when it sent more packets than this during previous second.
+    pub packets_per_second: u64,
+    /// Low priority clients get every Nth controller data update under pressure.
+    pub low_priority_every: u32,
+}
+
+/// Packets sent during current and previous second.
+#[derive(Copy, Clone, Debug)]
+struct SendRate {
+    window_start: Instant,
+    window_packets: u64,
+    previous_packets: u64,
+}
+
+impl SendRate {
+    /// Moves to window containing current time.
+    fn advance(&mut self) {
+        let elapsed = self.window_start.elapsed();
+        if elapsed >= Duration::from_secs(2) {
+            self.window_start = Instant::now();
+            self.previous_packets = 0;
+            self.window_packets = 0;
+        } else if elapsed >= Duration::from_secs(1) {
+            self.window_start += Duration::from_secs(1);
+            self.previous_packets = self.window_packets;
+            self.window_packets = 0;
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+struct Settings {
+    identity_policy: IdentityPolicy,
+    replay_window: Option<Duration>,
+    min_info_interval: Option<Duration>,
+    idle_timeout: Option<Duration>,
+    reject_stale_data: bool,
+    motion_validation: MotionValidation,
+    send_pressure: Option<SendPressure>,
+    shutdown_notification: bool,
+    health_interval: Option<Duration>,
+    client_timeout: Option<Duration>,
+    verify_checksums: bool,
+    tick_interval: Option<Duration>,
+}
+
+impl Default for Settings {
+    fn default() -> Settings {
+        Settings {
+            identity_policy: Default::default(),
+            replay_window: None,
+            min_info_interval: None,
+            idle_timeout: None,
+            reject_stale_data: false,
+            motion_validation: MotionValidation::Disabled,
+            send_pressure: None,
+            shutdown_notification: true,
+            health_interval: None,
+            client_timeout: Some(DEFAULT_CLIENT_TIMEOUT),
+            verify_checksums: true,
+            tick_interval: None,
+        }
+    }
+}
+
+/// How server reacts when client at given address changes its source ID or protocol version between requests.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum IdentityPolicy {
+    /// Accept changed identity silently.
+    Ignore,
+    /// Accept changed identity, but count it in `ServerStats::identity_mismatches`.
+    #[default]
+    Flag,
+    /// Reject requests not matching identity client used first (and count them).
+    Reject,
+}
+
+/// Information about client currently receiving controller data.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ClientStats {
+    pub address: SocketAddr,
+    pub packet_number: u32,
+    pub slot_numbers: Vec<u8>,
+    pub mac_addresses: Vec<u64>,
+    pub request_intervals: RequestIntervalHistogram,
+    /// Time since client's last controller data request.
+    pub since_last_request: Duration,
+}
+
+/// Server metrics snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ServerStats {
+    pub received_packets: u64,
+    pub invalid_packets: u64,
+    /// Packets claiming length larger than maximum message length, counted as invalid as well.
+    pub oversize_packets: u64,
+    pub sent_packets: u64,
+    pub identity_mismatches: u64,
+    pub rejected_replays: u64,
+    pub throttled_info_requests: u64,
+    /// Controller data updates dropped for being older than slot's current data.
+    pub stale_data_updates: u64,
+    /// Controller data updates with implausible motion values (rejected or clamped, see `Server::set_motion_validation`).
+    pub invalid_motion_updates: u64,
+    /// Panics caught in server's threads, see `ServerEvent::ThreadPanicked`.
+    pub thread_panics: u64,
+    pub clients: Vec<ClientStats>,
+}
+
 const DEFAULT_PORT: u16 = 26760;
 
+/// Datagrams with their targets.
+pub type Datagrams = Vec<(SocketAddr, Vec<u8>)>;
+
+thread_local! {
+    /// Datagrams sent during `Server::handle_datagram` call on this thread.
+    static CAPTURED_DATAGRAMS: RefCell<Option<Datagrams>> = const { RefCell::new(None) };
+}
+
+/// Default time since client's last controller data request after which it's evicted, see `Server::set_client_timeout`.
+pub const DEFAULT_CLIENT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Maximum number of addresses server tracks per-client state (identity, request history) of,
+/// state of the least recently active address is dropped to make room for a new one.
+pub const MAX_CLIENT_STATES: usize = 1024;
+
 pub trait DsServer {
     /// Starts background server thread.
+    ///
+    /// Thread stops when `countinue_running` is set to `false`, notifying clients unless disabled with `set_shutdown_notification`.
     fn start(self, countinue_running: Arc<AtomicBool>) -> JoinHandle<()>;
 
+    /// Returns number of controller slots (`SLOT_COUNT` unless server uses extended mode).
+    fn slot_count(&self) -> u8 {
+        SLOT_COUNT
+    }
+
     /// Update controller info (it will automatically send this data to connected clients).
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `try_update_controller_info`.
     fn update_controller_info(&self, controller_info: ControllerInfo);
 
+    /// Same as `update_controller_info`, but fails with `ErrorKind::InvalidInput` error on invalid slot number.
+    fn try_update_controller_info(&self, controller_info: ControllerInfo) -> Result<()> {
+        check_slot_number_in(controller_info.slot, self.slot_count())?;
+        self.update_controller_info(controller_info);
+
+        Ok(())
+    }
+
     /// Update controller data (it will automatically send this data to connected clients).
+    ///
+    /// Updates of a slot are sent to every client with increasing packet numbers,
+    /// and a client never receives data older than data it already received for the same slot,
+    /// even when updates come from multiple threads (though intermediate updates may be skipped).
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `try_update_controller_data`.
     fn update_controller_data(&self, slot_number: u8, controller_data: ControllerData);
+
+    /// Updates slot once per motion sample, so clients receive every sample with its timestamp
+    /// (unless slot rate limit or fixed send rate skips some of them).
+    ///
+    /// Each update is `controller_data` with motion fields replaced by sample's.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot_number` - updated slot.
+    /// * `controller_data` - buttons, sticks and touches shared by all samples.
+    /// * `samples` - motion samples from the oldest to the latest.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `try_update_motion_samples`.
+    fn update_motion_samples(
+        &self,
+        slot_number: u8,
+        controller_data: ControllerData,
+        samples: &[MotionSample],
+    );
+
+    /// Same as `update_controller_data`, but takes validated slot index.
+    fn update_slot_data(&self, slot_index: SlotIndex, controller_data: ControllerData) {
+        self.update_controller_data(slot_index.get(), controller_data);
+    }
+
+    /// Same as `update_motion_samples`, but takes validated slot index.
+    fn update_slot_motion_samples(
+        &self,
+        slot_index: SlotIndex,
+        controller_data: ControllerData,
+        samples: &[MotionSample],
+    ) {
+        self.update_motion_samples(slot_index.get(), controller_data, samples);
+    }
+
+    /// Same as `update_controller_data`, but fails with `ErrorKind::InvalidInput` error on invalid slot number.
+    fn try_update_controller_data(
+        &self,
+        slot_number: u8,
+        controller_data: ControllerData,
+    ) -> Result<()> {
+        check_slot_number_in(slot_number, self.slot_count())?;
+        self.update_controller_data(slot_number, controller_data);
+
+        Ok(())
+    }
+
+    /// Same as `update_motion_samples`, but fails with `ErrorKind::InvalidInput` error on invalid slot number.
+    fn try_update_motion_samples(
+        &self,
+        slot_number: u8,
+        controller_data: ControllerData,
+        samples: &[MotionSample],
+    ) -> Result<()> {
+        check_slot_number_in(slot_number, self.slot_count())?;
+        self.update_motion_samples(slot_number, controller_data, samples);
+
+        Ok(())
+    }
+}
+
+/// Event emitted by server.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ServerEvent {
+    /// Connected slot received no input change for idle timeout and is now reported as not connected.
+    SlotIdle(ControllerInfo),
+    /// Idle slot received input change and is reported with its previous info again.
+    SlotResumed(ControllerInfo),
+    /// Client sent its first controller data request (or first one since it was evicted) and now receives controller data.
+    ClientConnected(SocketAddr),
+    /// Client sent no controller data request for client timeout and no longer receives controller data.
+    ClientEvicted(SocketAddr),
+    /// Sending controller data to client failed. After transient error client keeps receiving later updates,
+    /// after fatal one (see `error_severity`) it no longer receives controller data until it requests it again.
+    ClientSendFailed(SocketAddr),
+    /// Server's thread panicked (e.g. in user callback) and was resumed with slot state intact,
+    /// see `Server::last_panic`.
+    ThreadPanicked,
+    /// Server's transport failed with fatal error and server's thread stopped, see `Server::take_terminal_error`.
+    TransportFailed(ErrorKind),
+    /// Server was built bound to given address reachable from local network (see `ServerBuilder::with_lan_exposure`),
+    /// anyone on it can read controller data and request rumble.
+    ExposedToLan(SocketAddr),
+}
+
+/// Checks whether controller data differs in anything but motion timestamp.
+fn input_changed(previous: &ControllerData, current: &ControllerData) -> bool {
+    let mut current = *current;
+    current.motion_data_timestamp = previous.motion_data_timestamp;
+
+    *previous != current
 }
 
 pub struct Server {
-    message_header: MessageHeader,
-    slots: Mutex<[Slot; 4]>,
+    source_id: AtomicU32,
+    slot_count: u8,
+    slots: Mutex<Vec<Slot>>,
     connected_clients: Mutex<HashMap<SocketAddr, RequestedControllerData>>,
-    socket: UdpSocket,
+    client_states: Mutex<HashMap<SocketAddr, ClientState>>,
+    settings: Mutex<Settings>,
+    /// Notified when fixed send rate changes or server's thread stops, wakes parked tick thread.
+    tick_interval_changed: Condvar,
+    /// Serializes sends of controller data, so clients receive updates of a slot in order
+    /// even though slots and clients aren't kept locked while sending.
+    send_lock: Mutex<()>,
+    transforms: Mutex<Vec<Option<Box<dyn Transform>>>>,
+    mirrors: Mutex<Vec<Option<Mirror>>>,
+    registered_slots: Mutex<Vec<bool>>,
+    events: ArrayQueue<ServerEvent>,
+    client_priorities: Mutex<HashMap<SocketAddr, ClientPriority>>,
+    send_rate: Mutex<SendRate>,
+    last_health_publish: Mutex<Instant>,
+    state_file: Mutex<Option<PathBuf>>,
+    remembered_mac_addresses: Mutex<Vec<u64>>,
+    history: Mutex<Option<StateHistory>>,
+    #[cfg(feature = "profiles")]
+    profiles: Mutex<Option<ProfileStore>>,
+    #[cfg(feature = "compression")]
+    compression: AtomicBool,
+    #[cfg(feature = "compression")]
+    compressed_clients: Mutex<HashSet<SocketAddr>>,
+    socket: Box<dyn Transport>,
+    tap: Mutex<Option<DatagramTap>>,
+    rumble_callback: Mutex<Option<RumbleCallback>>,
+    rumble_sinks: Mutex<Vec<Option<Box<dyn RumbleSink>>>>,
+    last_panic: Mutex<Option<String>>,
+    thread_panics: AtomicU64,
+    terminal_error: Mutex<Option<Error>>,
+    received_packets: AtomicU64,
+    invalid_packets: AtomicU64,
+    oversize_packets: AtomicU64,
+    receive_buffer_size: AtomicUsize,
+    max_message_length: AtomicUsize,
+    sent_packets: AtomicU64,
+    identity_mismatches: AtomicU64,
+    rejected_replays: AtomicU64,
+    throttled_info_requests: AtomicU64,
+    stale_data_updates: AtomicU64,
+    invalid_motion_updates: AtomicU64,
+}
+
+/// Networks server is reachable from, see `ServerBuilder::with_visibility`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Visibility {
+    /// Only programs running on the same machine (loopback interface).
+    Localhost,
+    /// Any device of local network (all interfaces), requires `ServerBuilder::with_lan_exposure`.
+    Lan,
+}
+
+impl Visibility {
+    /// Visibility of server bound to given address.
+    pub fn of(address: SocketAddr) -> Visibility {
+        if address.ip().is_loopback() {
+            Visibility::Localhost
+        } else {
+            Visibility::Lan
+        }
+    }
+
+    /// Address server with this visibility binds to on given port.
+    ///
+    /// IPv4 addresses are used on every platform: emulators connect over IPv4 and IPv6 wildcard sockets
+    /// don't accept IPv4 clients on Windows (and on Linux with `bindv6only` set).
+    pub fn bind_address(self, port: u16) -> SocketAddr {
+        match self {
+            Visibility::Localhost => SocketAddr::from((Ipv4Addr::LOCALHOST, port)),
+            Visibility::Lan => SocketAddr::from((Ipv4Addr::UNSPECIFIED, port)),
+        }
+    }
+}
+
+/// Builder of `Server` with socket and behavior options, see `Server::builder`.
+#[derive(Clone, Debug)]
+pub struct ServerBuilder {
+    id: Option<u32>,
+    address: SocketAddr,
+    lan_exposure: bool,
+    udp_options: UdpOptions,
+    client_timeout: Option<Duration>,
+    verify_checksums: bool,
+    send_rate: Option<f64>,
+    slot_count: u8,
+}
+
+impl Default for ServerBuilder {
+    fn default() -> ServerBuilder {
+        ServerBuilder {
+            id: None,
+            address: Visibility::Localhost.bind_address(DEFAULT_PORT),
+            lan_exposure: false,
+            udp_options: Default::default(),
+            client_timeout: Some(DEFAULT_CLIENT_TIMEOUT),
+            verify_checksums: true,
+            send_rate: None,
+            slot_count: SLOT_COUNT,
+        }
+    }
+}
+
+impl ServerBuilder {
+    /// Server ID (random by default).
+    pub fn with_id(mut self, id: u32) -> ServerBuilder {
+        self.id = Some(id);
+        self
+    }
+
+    /// Server's UDP socket address (`127.0.0.1:26760` by default).
+    ///
+    /// Addresses other than loopback ones expose server to local network, see `with_lan_exposure`.
+    pub fn with_address(mut self, address: SocketAddr) -> ServerBuilder {
+        self.address = address;
+        self
+    }
+
+    /// Binds server to address appropriate for given visibility, keeping port of current address.
+    pub fn with_visibility(mut self, visibility: Visibility) -> ServerBuilder {
+        self.address = visibility.bind_address(self.address.port());
+        self
+    }
+
+    /// Allows server to be reachable beyond localhost (disabled by default).
+    ///
+    /// Protocol has no authentication, so anyone on the network can read controller data
+    /// and request rumble. Building server with `Visibility::Lan` fails unless this is enabled.
+    pub fn with_lan_exposure(mut self, lan_exposure: bool) -> ServerBuilder {
+        self.lan_exposure = lan_exposure;
+        self
+    }
+
+    /// Timeout of blocking receive (default: `READ_TIMEOUT`), server thread stops at most this long
+    /// after it's asked to. Longer timeouts delay idle checks and health publishing when no requests arrive.
+    pub fn with_read_timeout(mut self, read_timeout: Duration) -> ServerBuilder {
+        self.udp_options.read_timeout = read_timeout;
+        self
+    }
+
+    /// Timeout of blocking send (default: `READ_TIMEOUT`), pass `None` to block until datagram is sent.
+    pub fn with_write_timeout(mut self, write_timeout: Option<Duration>) -> ServerBuilder {
+        self.udp_options.write_timeout = write_timeout;
+        self
+    }
+
+    /// Sets `SO_REUSEADDR` on server's socket (disabled by default).
+    pub fn with_reuse_address(mut self, reuse_address: bool) -> ServerBuilder {
+        self.udp_options.reuse_address = reuse_address;
+        self
+    }
+
+    /// Sets `SO_BROADCAST` on server's socket (disabled by default).
+    pub fn with_broadcast(mut self, broadcast: bool) -> ServerBuilder {
+        self.udp_options.broadcast = broadcast;
+        self
+    }
+
+    /// See `Server::set_client_timeout`.
+    pub fn with_client_timeout(mut self, client_timeout: Option<Duration>) -> ServerBuilder {
+        self.client_timeout = client_timeout;
+        self
+    }
+
+    /// See `Server::set_verify_checksums`.
+    pub fn with_verify_checksums(mut self, verify_checksums: bool) -> ServerBuilder {
+        self.verify_checksums = verify_checksums;
+        self
+    }
+
+    /// See `Server::set_send_rate`.
+    pub fn with_send_rate(mut self, send_rate: Option<f64>) -> ServerBuilder {
+        self.send_rate = send_rate;
+        self
+    }
+
+    /// Number of controller slots (`SLOT_COUNT` by default), up to `MAX_SLOT_COUNT`.
+    ///
+    /// Standard DSU clients only know 4 slots, use more only with clients supporting extended mode.
+    pub fn with_slot_count(mut self, slot_count: u8) -> ServerBuilder {
+        self.slot_count = slot_count;
+        self
+    }
+
+    /// Binds server's socket and creates server.
+    ///
+    /// Fails with error wrapping [`PortInUse`] if address is already bound,
+    /// with `ErrorKind::PermissionDenied` error if address isn't loopback one and LAN exposure isn't enabled
+    /// (see `with_lan_exposure`), or with `ErrorKind::InvalidInput` error if slot count is invalid.
+    pub fn build(self) -> Result<Server> {
+        check_slot_count(self.slot_count)?;
+        let visibility = Visibility::of(self.address);
+        if visibility == Visibility::Lan && !self.lan_exposure {
+            return Err(Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "Server address {} is reachable beyond localhost, enable LAN exposure to allow it",
+                    self.address
+                ),
+            ));
+        }
+
+        let address = self.address;
+        let socket = bind_udp_with(address, &self.udp_options)?;
+        let server = self.build_with_transport(Box::new(socket))?;
+        if visibility == Visibility::Lan {
+            let _ = server.events.push(ServerEvent::ExposedToLan(address));
+        }
+
+        Ok(server)
+    }
+
+    /// Creates server using given transport, address and UDP options are ignored.
+    ///
+    /// Fails with `ErrorKind::InvalidInput` error if slot count is invalid.
+    pub fn build_with_transport(self, socket: Box<dyn Transport>) -> Result<Server> {
+        check_slot_count(self.slot_count)?;
+        let server = Server::with_transport_and_slot_count(self.id, socket, self.slot_count);
+        server.set_client_timeout(self.client_timeout);
+        server.set_verify_checksums(self.verify_checksums);
+        server.set_send_rate(self.send_rate);
+
+        Ok(server)
+    }
 }
 
 impl Server {
-    /// Creates new server.
+    /// Returns builder of server with socket and behavior options.
+    pub fn builder() -> ServerBuilder {
+        Default::default()
+    }
+
+    /// Creates new server, same as building it with `builder`.
+    ///
+    /// Fails with `ErrorKind::PermissionDenied` error if address isn't loopback one,
+    /// use `ServerBuilder::with_lan_exposure` to serve local network.
     ///
     /// # Arguments
     ///
     /// * `id` - server ID, pass `None` to use a random number.
     /// * `address` - server's UDP socket address, if `None` is passed `127.0.0.1:26760` is used.
     pub fn new(id: Option<u32>, address: Option<SocketAddr>) -> Result<Server> {
+        let mut builder = Server::builder();
+        if let Some(id) = id {
+            builder = builder.with_id(id);
+        }
+        if let Some(address) = address {
+            builder = builder.with_address(address);
+        }
+
+        builder.build()
+    }
+
+    /// Creates new server using given transport instead of UDP socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `id` - server ID, pass `None` to use a random number.
+    /// * `socket` - transport to serve clients on.
+    pub fn with_transport(id: Option<u32>, socket: Box<dyn Transport>) -> Server {
+        Server::with_transport_and_slot_count(id, socket, SLOT_COUNT)
+    }
+
+    fn with_transport_and_slot_count(
+        id: Option<u32>,
+        socket: Box<dyn Transport>,
+        slot_count: u8,
+    ) -> Server {
         let mut rng = rand::thread_rng();
 
         let server_id = match id {
@@ -58,18 +710,8 @@ impl Server {
             None => rng.gen(),
         };
 
-        let message_header = {
-            MessageHeader {
-                source: MessageSource::Server,
-                protocol_version: PROTOCOL_VERSION,
-                message_length: 0,
-                checksum: 0,
-                source_id: server_id,
-            }
-        };
-
         let slots = {
-            let mut slots: [Slot; 4] = [Default::default(); 4];
+            let mut slots: Vec<Slot> = vec![Default::default(); slot_count as usize];
             for (i, slot) in slots.iter_mut().enumerate() {
                 slot.controller_info.slot = i as u8;
             }
@@ -79,129 +721,1708 @@ impl Server {
 
         let connected_clients = Mutex::new(HashMap::new());
 
-        let socket_address = match address {
-            Some(address) => address,
-            None => SocketAddr::from(([127, 0, 0, 1], DEFAULT_PORT)),
-        };
-        let socket = UdpSocket::bind(socket_address)?;
-        socket.set_read_timeout(Some(Duration::from_secs_f64(0.2)))?;
-        socket.set_write_timeout(Some(Duration::from_secs_f64(0.2)))?;
-
-        Ok(Server {
-            message_header,
+        Server {
+            source_id: AtomicU32::new(server_id),
+            slot_count,
             slots,
             connected_clients,
+            client_states: Mutex::new(HashMap::new()),
+            settings: Mutex::new(Settings::default()),
+            tick_interval_changed: Condvar::new(),
+            send_lock: Mutex::new(()),
+            transforms: Mutex::new((0..slot_count).map(|_| None).collect()),
+            mirrors: Mutex::new((0..slot_count).map(|_| None).collect()),
+            registered_slots: Mutex::new(vec![false; slot_count as usize]),
+            events: ArrayQueue::new(50),
+            client_priorities: Mutex::new(HashMap::new()),
+            send_rate: Mutex::new(SendRate {
+                window_start: Instant::now(),
+                window_packets: 0,
+                previous_packets: 0,
+            }),
+            last_health_publish: Mutex::new(Instant::now()),
+            state_file: Mutex::new(None),
+            remembered_mac_addresses: Mutex::new(vec![0; slot_count as usize]),
+            history: Mutex::new(None),
+            #[cfg(feature = "profiles")]
+            profiles: Mutex::new(None),
+            #[cfg(feature = "compression")]
+            compression: AtomicBool::new(false),
+            #[cfg(feature = "compression")]
+            compressed_clients: Mutex::new(HashSet::new()),
             socket,
-        })
+            tap: Mutex::new(None),
+            rumble_callback: Mutex::new(None),
+            rumble_sinks: Mutex::new((0..slot_count).map(|_| None).collect()),
+            last_panic: Mutex::new(None),
+            thread_panics: AtomicU64::new(0),
+            terminal_error: Mutex::new(None),
+            received_packets: AtomicU64::new(0),
+            invalid_packets: AtomicU64::new(0),
+            oversize_packets: AtomicU64::new(0),
+            receive_buffer_size: AtomicUsize::new(DEFAULT_RECEIVE_BUFFER_SIZE),
+            max_message_length: AtomicUsize::new(DEFAULT_RECEIVE_BUFFER_SIZE),
+            sent_packets: AtomicU64::new(0),
+            identity_mismatches: AtomicU64::new(0),
+            rejected_replays: AtomicU64::new(0),
+            throttled_info_requests: AtomicU64::new(0),
+            stale_data_updates: AtomicU64::new(0),
+            invalid_motion_updates: AtomicU64::new(0),
+        }
     }
 
-    fn encode_and_send(&self, target: SocketAddr, message: Message) -> Result<()> {
-        let mut encoded_message = vec![];
-        encode_message(&mut encoded_message, message).unwrap();
+    /// Sets how server reacts to clients changing their source ID or protocol version (default: `IdentityPolicy::Flag`).
+    pub fn set_identity_policy(&self, identity_policy: IdentityPolicy) {
+        self.settings.lock().unwrap().identity_policy = identity_policy;
+    }
+
+    /// Enables or disables given slot (all slots are enabled by default).
+    ///
+    /// Disabled slot is reported to clients as not connected and controller data updates for it are dropped.
+    /// Controller info updates are still stored, so slot reports current info once enabled again.
+    pub fn set_slot_enabled(&self, slot_number: u8, enabled: bool) {
+        assert!(slot_number < self.slot_count);
+
+        {
+            let mut slots = self.slots.lock().unwrap();
+            if slots[slot_number as usize].disabled != enabled {
+                return;
+            }
+            slots[slot_number as usize].disabled = !enabled;
+        }
 
-        self.socket
-            .send_to(&encoded_message, target)
-            .map(|_amount| ())
+        for address in self.clients() {
+            let _ = self.send_connected_controller_info(address, slot_number);
+        }
     }
 
-    fn send_protocol_version(&self, target: SocketAddr) -> Result<()> {
-        let message = Message {
-            header: self.message_header,
-            message_type: MessageType::ConnectedControllers,
-            payload: MessagePayload::ProtocolVersion(PROTOCOL_VERSION),
-        };
+    /// Checks whether given slot is enabled.
+    pub fn is_slot_enabled(&self, slot_number: u8) -> bool {
+        assert!(slot_number < self.slot_count);
 
-        self.encode_and_send(target, message)
+        !self.slots.lock().unwrap()[slot_number as usize].disabled
     }
 
-    fn send_connected_controller_info(&self, target: SocketAddr, slot_number: u8) -> Result<()> {
-        let controller_info = self.slots.lock().unwrap()[slot_number as usize].controller_info;
+    /// Sets amount of motors reported for given slot to clients using rumble extension (0 by default).
+    ///
+    /// Rumble requests for motors slot doesn't have are ignored.
+    pub fn set_motor_count(&self, slot_number: u8, motor_count: u8) {
+        assert!(slot_number < self.slot_count);
 
-        let payload = MessagePayload::ConnectedControllerResponse { controller_info };
+        self.slots.lock().unwrap()[slot_number as usize].motor_count = motor_count;
+    }
 
-        let message = Message {
-            header: self.message_header,
-            message_type: MessageType::ConnectedControllers,
-            payload,
-        };
+    pub fn motor_count(&self, slot_number: u8) -> u8 {
+        assert!(slot_number < self.slot_count);
 
-        self.encode_and_send(target, message)
+        self.slots.lock().unwrap()[slot_number as usize].motor_count
     }
 
-    fn send_slot_data(
-        &self,
-        target: SocketAddr,
-        slot: Slot,
-        packet_number: &mut u32,
-    ) -> Result<()> {
-        let payload = MessagePayload::ControllerData {
-            packet_number: *packet_number,
-            controller_info: slot.controller_info,
-            controller_data: slot.controller_data,
-        };
+    /// Sets callback called when client requests rumble of a motor of connected slot, pass `None` to remove it.
+    ///
+    /// Callback is called from server's thread, keep it short.
+    pub fn set_rumble_callback(&self, callback: Option<RumbleCallback>) {
+        *self.rumble_callback.lock().unwrap() = callback;
+    }
 
-        let message = Message {
-            header: self.message_header,
-            message_type: MessageType::ControllerData,
-            payload,
+    /// Sets sink receiving rumble requests for motors of given slot instead of rumble callback,
+    /// pass `None` to remove it.
+    ///
+    /// Slot's motor count is set to sink's (0 once sink is removed), see `set_motor_count`.
+    /// Sink is called from server's thread and its errors are ignored.
+    pub fn set_rumble_sink(&self, slot_number: u8, sink: Option<Box<dyn RumbleSink>>) {
+        assert!(slot_number < self.slot_count);
+
+        let motor_count = sink.as_ref().map_or(0, |sink| sink.motor_count());
+        self.rumble_sinks.lock().unwrap()[slot_number as usize] = sink;
+        self.set_motor_count(slot_number, motor_count);
+    }
+
+    /// Sets transform applied to controller data of given slot on every update, pass `None` to remove it.
+    pub fn set_transform(&self, slot_number: u8, transform: Option<Box<dyn Transform>>) {
+        assert!(slot_number < self.slot_count);
+
+        self.transforms.lock().unwrap()[slot_number as usize] = transform;
+    }
+
+    /// Same as `set_transform`, but takes validated slot index.
+    pub fn set_slot_transform(&self, slot_index: SlotIndex, transform: Option<Box<dyn Transform>>) {
+        self.set_transform(slot_index.get(), transform);
+    }
+
+    /// Duplicates controller of one slot into another slot, so one controller can drive two players.
+    ///
+    /// Destination slot reports source's controller info with a derived MAC address
+    /// and receives every controller data update of source slot.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - slot to mirror.
+    /// * `destination` - slot receiving mirrored controller, its own updates are overwritten by mirrored ones.
+    /// * `transform` - optional transform applied to mirrored controller data only.
+    pub fn mirror_slot(&self, source: u8, destination: u8, transform: Option<Box<dyn Transform>>) {
+        assert!(source < self.slot_count);
+        assert!(destination < self.slot_count);
+        assert!(source != destination);
+
+        self.mirrors.lock().unwrap()[destination as usize] = Some(Mirror { source, transform });
+
+        let controller_info = self.controller_info(source);
+        self.update_mirrors_info(controller_info);
+    }
+
+    /// Stops mirroring into given slot, slot becomes not connected.
+    pub fn stop_mirroring(&self, destination: u8) {
+        assert!(destination < self.slot_count);
+
+        if self.mirrors.lock().unwrap()[destination as usize]
+            .take()
+            .is_none()
+        {
+            return;
+        }
+
+        {
+            let mut slots = self.slots.lock().unwrap();
+            slots[destination as usize].controller_info = ControllerInfo {
+                slot: destination,
+                ..Default::default()
+            };
+            slots[destination as usize].controller_data = Default::default();
+        }
+        self.notify_controller_info(destination);
+    }
+
+    /// Assigns device to a free slot and reports it to clients as connected, returns `None` if no slot is free.
+    ///
+    /// Slot device was last connected to is preferred (see `remembered_slot`), the lowest free slot otherwise.
+    /// Slot is free when it isn't registered or mirrored into and doesn't report a controller.
+    /// Slot becomes not connected again once returned handle is unregistered or dropped.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller_info` - device's controller info, its slot number and state are replaced.
+    pub fn register_device(
+        self: &Arc<Server>,
+        controller_info: ControllerInfo,
+    ) -> Option<SlotHandle> {
+        let slot_number = {
+            let mut registered_slots = self.registered_slots.lock().unwrap();
+            let is_free = |slot_number: u8| {
+                !registered_slots[slot_number as usize]
+                    && self.mirrors.lock().unwrap()[slot_number as usize].is_none()
+                    && self.controller_info(slot_number).slot_state == SlotState::NotConnected
+            };
+
+            let slot_number = self
+                .remembered_slot(controller_info.mac_address)
+                .filter(|&slot_number| is_free(slot_number))
+                .or_else(|| (0..self.slot_count).find(|&slot_number| is_free(slot_number)))?;
+            registered_slots[slot_number as usize] = true;
+
+            slot_number
         };
 
-        let result = self.encode_and_send(target, message);
-        if result.is_ok() {
-            *packet_number += 1;
+        self.update_controller_info(ControllerInfo {
+            slot: slot_number,
+            slot_state: SlotState::Connected,
+            ..controller_info
+        });
+
+        Some(SlotHandle {
+            server: self.clone(),
+            slot_number,
+        })
+    }
+
+    fn unregister_slot(self: &Arc<Server>, slot_number: u8) {
+        self.slots.lock().unwrap()[slot_number as usize].controller_data = Default::default();
+        self.update_controller_info(ControllerInfo {
+            slot: slot_number,
+            ..Default::default()
+        });
+
+        self.registered_slots.lock().unwrap()[slot_number as usize] = false;
+    }
+
+    fn update_mirrors_info(&self, controller_info: ControllerInfo) {
+        let destinations: Vec<u8> = self
+            .mirrors
+            .lock()
+            .unwrap()
+            .iter()
+            .enumerate()
+            .filter(|(_, mirror)| {
+                mirror
+                    .as_ref()
+                    .is_some_and(|mirror| mirror.source == controller_info.slot)
+            })
+            .map(|(destination, _)| destination as u8)
+            .collect();
+
+        for destination in destinations {
+            {
+                let mut slots = self.slots.lock().unwrap();
+                let slot = &mut slots[destination as usize];
+                slot.controller_info = ControllerInfo {
+                    slot: destination,
+                    mac_address: mirrored_mac_address(controller_info.mac_address, destination),
+                    ..controller_info
+                };
+                slot.idle = false;
+                slot.last_input_time = Some(Instant::now());
+            }
+            self.notify_controller_info(destination);
+        }
+    }
+
+    fn update_mirrors_data(&self, source: u8, controller_data: ControllerData) {
+        let mut resumed = vec![];
+        let mut mirrors = self.mirrors.lock().unwrap();
+        for (destination, mirror) in mirrors.iter_mut().enumerate() {
+            let mirror = match mirror {
+                Some(mirror) if mirror.source == source => mirror,
+                _ => continue,
+            };
+
+            let destination = destination as u8;
+            if !self.is_slot_enabled(destination) {
+                continue;
+            }
+
+            let mut controller_data = controller_data;
+            if let Some(transform) = &mut mirror.transform {
+                transform.apply(destination, &mut controller_data);
+            }
+            if let Some(controller_info) = self.store_controller_data(destination, controller_data)
+            {
+                resumed.push(controller_info);
+            }
+        }
+        drop(mirrors);
+
+        for controller_info in resumed {
+            self.resume_slot(controller_info);
+        }
+    }
+
+    /// Validates, transforms and stores controller data update.
+    ///
+    /// Returns stored data and slot's controller info if slot was resumed from idle, `None` if update was rejected.
+    fn transform_and_store(
+        &self,
+        transforms: &mut [Option<Box<dyn Transform>>],
+        slot_number: u8,
+        mut controller_data: ControllerData,
+    ) -> Option<(ControllerData, Option<ControllerInfo>)> {
+        let settings = *self.settings.lock().unwrap();
+        let current_timestamp = self.controller_data(slot_number).motion_data_timestamp;
+
+        if settings.motion_validation != MotionValidation::Disabled
+            && !controller_data.has_valid_motion()
+        {
+            self.invalid_motion_updates.fetch_add(1, Ordering::Relaxed);
+            match settings.motion_validation {
+                MotionValidation::Clamp => controller_data.clamp_motion(current_timestamp),
+                _ => return None,
+            }
+        }
+
+        if settings.reject_stale_data && controller_data.motion_data_timestamp < current_timestamp {
+            self.stale_data_updates.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+
+        if let Some(transform) = &mut transforms[slot_number as usize] {
+            transform.apply(slot_number, &mut controller_data);
+        }
+
+        let resumed = self.store_controller_data(slot_number, controller_data);
+        Some((controller_data, resumed))
+    }
+
+    /// Stores controller data in slot, returns slot's controller info if slot was resumed from idle.
+    fn store_controller_data(
+        &self,
+        slot_number: u8,
+        controller_data: ControllerData,
+    ) -> Option<ControllerInfo> {
+        let mut slots = self.slots.lock().unwrap();
+        let slot = &mut slots[slot_number as usize];
+        let mut resumed = false;
+        if input_changed(&slot.controller_data, &controller_data) {
+            slot.last_input_time = Some(Instant::now());
+            resumed = slot.idle;
+            slot.idle = false;
+        }
+        slot.controller_data = controller_data;
+        slot.update_count = slot.update_count.saturating_add(1);
+
+        if let Some(history) = self.history.lock().unwrap().as_mut() {
+            history.push(HistoryEntry {
+                time: Instant::now(),
+                controller_info: slot.controller_info,
+                controller_data,
+            });
+        }
+
+        resumed.then_some(slot.controller_info)
+    }
+
+    /// Keeps state of slot after every controller data update in memory for given duration
+    /// (disabled by default, pass `None` to disable it and drop kept states).
+    pub fn set_history_duration(&self, duration: Option<Duration>) {
+        let mut history = self.history.lock().unwrap();
+        match (history.as_mut(), duration) {
+            (Some(history), Some(duration)) => history.set_duration(duration),
+            (_, duration) => *history = duration.map(StateHistory::new),
+        }
+    }
+
+    /// Returns kept slot states from the oldest to the latest, see `set_history_duration`.
+    pub fn history(&self) -> Vec<HistoryEntry> {
+        match self.history.lock().unwrap().as_ref() {
+            Some(history) => history.entries().copied().collect(),
+            None => vec![],
+        }
+    }
+
+    /// Returns kept slot states as recording, e.g. to save them with `Recording::write_to` after a crash.
+    pub fn history_recording(&self) -> Recording {
+        match self.history.lock().unwrap().as_ref() {
+            Some(history) => history.to_recording(),
+            None => Recording::new(),
+        }
+    }
+
+    fn resume_slot(&self, controller_info: ControllerInfo) {
+        let _ = self.events.push(ServerEvent::SlotResumed(controller_info));
+        self.notify_controller_info(controller_info.slot);
+    }
+
+    fn notify_controller_info(&self, slot_number: u8) {
+        if !self.is_slot_enabled(slot_number) {
+            return;
+        }
+
+        for address in self.clients() {
+            let _ = self.send_connected_controller_info(address, slot_number);
+        }
+    }
+
+    /// Installs controller profiles, pass `None` to remove them.
+    ///
+    /// Whenever controller info update brings a MAC address with a profile into a slot,
+    /// slot's transform is replaced with profile's pipeline.
+    #[cfg(feature = "profiles")]
+    pub fn set_profiles(&self, profiles: Option<ProfileStore>) {
+        *self.profiles.lock().unwrap() = profiles;
+    }
+
+    #[cfg(feature = "profiles")]
+    fn apply_profile(&self, controller_info: ControllerInfo) {
+        let profiles = self.profiles.lock().unwrap();
+        if let Some(profile) = profiles
+            .as_ref()
+            .and_then(|profiles| profiles.get(controller_info.mac_address))
+        {
+            self.set_transform(controller_info.slot, Some(Box::new(profile.pipeline())));
+        }
+    }
+
+    /// Drops datagrams identical to previous datagram from the same client received within given window (disabled by default).
+    ///
+    /// Legitimate clients repeat requests at most a few times per second, so a window of several milliseconds
+    /// filters replayed or looping packets without affecting them.
+    pub fn set_replay_window(&self, replay_window: Option<Duration>) {
+        self.settings.lock().unwrap().replay_window = replay_window;
+    }
+
+    /// Sets minimum interval between controller info responses sent to a single client (disabled by default).
+    /// Info requests arriving sooner are ignored.
+    pub fn set_min_info_interval(&self, min_info_interval: Option<Duration>) {
+        self.settings.lock().unwrap().min_info_interval = min_info_interval;
+    }
+
+    /// Sets how long client may go without requesting controller data before it's evicted
+    /// and stops receiving it (default: `DEFAULT_CLIENT_TIMEOUT`, pass `None` to keep clients until sending to them fails).
+    ///
+    /// DSU clients repeat their requests every few seconds at most, so evicted clients are gone
+    /// (e.g. closed emulator). Evicted client is registered again with its next request.
+    pub fn set_client_timeout(&self, client_timeout: Option<Duration>) {
+        self.settings.lock().unwrap().client_timeout = client_timeout;
+    }
+
+    /// Enables or disables verification of checksums of received messages (enabled by default).
+    ///
+    /// Messages with incorrect checksum are counted as invalid and dropped.
+    pub fn set_verify_checksums(&self, verify_checksums: bool) {
+        self.settings.lock().unwrap().verify_checksums = verify_checksums;
+    }
+
+    /// Returns addresses of clients currently registered for controller data.
+    ///
+    /// Server sends to the returned copy, so it never holds clients' lock while taking slots' lock.
+    pub fn clients(&self) -> Vec<SocketAddr> {
+        let mut clients: Vec<SocketAddr> = self
+            .connected_clients
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect();
+        clients.sort();
+
+        clients
+    }
+
+    /// Sets how long connected slot may go without input change
+    /// before it's reported to clients as not connected (disabled by default).
+    ///
+    /// Idle slot is resumed as soon as its controller data changes or its controller info is updated.
+    pub fn set_idle_timeout(&self, idle_timeout: Option<Duration>) {
+        self.settings.lock().unwrap().idle_timeout = idle_timeout;
+    }
+
+    /// Drops controller data updates with motion timestamp older than slot's current data (disabled by default).
+    ///
+    /// Protects clients from data going back in time when producers deliver updates out of order.
+    pub fn set_reject_stale_data(&self, reject_stale_data: bool) {
+        self.settings.lock().unwrap().reject_stale_data = reject_stale_data;
+    }
+
+    /// Sets how controller data updates with NaN, infinite or otherwise implausible motion values are handled
+    /// (disabled by default), see `ControllerData::has_valid_motion`.
+    ///
+    /// Clamped implausible timestamp is replaced with slot's current timestamp.
+    pub fn set_motion_validation(&self, motion_validation: MotionValidation) {
+        self.settings.lock().unwrap().motion_validation = motion_validation;
+    }
+
+    /// Sets size of buffer datagrams are received into (default: `DEFAULT_RECEIVE_BUFFER_SIZE`).
+    ///
+    /// Datagrams claiming to be longer than buffer are rejected instead of being parsed truncated.
+    pub fn set_receive_buffer_size(&self, receive_buffer_size: usize) {
+        assert!(receive_buffer_size >= HEADER_LENGTH);
+
+        self.receive_buffer_size
+            .store(receive_buffer_size, Ordering::SeqCst);
+    }
+
+    /// Sets maximum accepted message length, including header (default: `DEFAULT_RECEIVE_BUFFER_SIZE`).
+    pub fn set_max_message_length(&self, max_message_length: usize) {
+        self.max_message_length
+            .store(max_message_length, Ordering::SeqCst);
+    }
+
+    /// Sets priority of client with given address (clients have `ClientPriority::High` priority by default).
+    pub fn set_client_priority(&self, address: SocketAddr, priority: ClientPriority) {
+        self.client_priorities
+            .lock()
+            .unwrap()
+            .insert(address, priority);
+    }
+
+    /// Enables decimation of data sent to low priority clients under send-rate pressure, pass `None` to disable it (default).
+    pub fn set_send_pressure(&self, send_pressure: Option<SendPressure>) {
+        self.settings.lock().unwrap().send_pressure = send_pressure;
+    }
+
+    /// Limits how often controller data updates of given slot are sent to clients, pass `None` to remove limit (default).
+    ///
+    /// Updates arriving sooner than allowed aren't queued, the latest one is sent once allowed
+    /// (with the next update of any slot, or by server thread at the latest).
+    /// Sends of other slots don't count towards the limit.
+    ///
+    /// # Arguments
+    ///
+    /// * `slot_number` - slot to limit.
+    /// * `max_rate` - maximum number of sends per second.
+    pub fn set_slot_rate_limit(&self, slot_number: u8, max_rate: Option<f64>) {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize].min_send_interval =
+            max_rate.map(|max_rate| {
+                assert!(max_rate > 0.0);
+                Duration::from_secs_f64(1.0 / max_rate)
+            });
+    }
+
+    /// Sends controller data to clients at fixed rate from a dedicated thread instead of on every update,
+    /// pass `None` to send on every update (default).
+    ///
+    /// Each tick sends the latest data of all slots if any of them was updated since the previous tick,
+    /// so rate of updates doesn't affect timing of sends. Slot rate limits are ignored while enabled.
+    ///
+    /// # Arguments
+    ///
+    /// * `send_rate` - sends per second, e.g. 250.
+    pub fn set_send_rate(&self, send_rate: Option<f64>) {
+        self.settings.lock().unwrap().tick_interval = send_rate.map(|send_rate| {
+            assert!(send_rate > 0.0);
+            Duration::from_secs_f64(1.0 / send_rate)
+        });
+        self.tick_interval_changed.notify_all();
+    }
+
+    /// Checks whether update of given slot should be sent now, marks it pending otherwise.
+    fn is_send_due(&self, slot_number: u8) -> bool {
+        let tick_interval = self.settings.lock().unwrap().tick_interval;
+        let mut slots = self.slots.lock().unwrap();
+        let slot = &mut slots[slot_number as usize];
+        if tick_interval.is_some() {
+            slot.send_pending = true;
+            return false;
+        }
+
+        if slot.is_send_allowed(Instant::now()) {
+            true
+        } else {
+            slot.send_pending = true;
+            false
+        }
+    }
+
+    /// Sends pending updates at rate set with `set_send_rate` until `countinue_running` is set to `false`.
+    ///
+    /// Thread is parked while fixed send rate isn't set.
+    fn run_ticks(&self, countinue_running: &AtomicBool) {
+        let mut next_tick = Instant::now();
+        while countinue_running.load(Ordering::SeqCst) {
+            // Settings aren't kept locked while sleeping.
+            let tick_interval = {
+                let mut settings = self.settings.lock().unwrap();
+                if settings.tick_interval.is_none() {
+                    while settings.tick_interval.is_none()
+                        && countinue_running.load(Ordering::SeqCst)
+                    {
+                        settings = self.tick_interval_changed.wait(settings).unwrap();
+                    }
+                    next_tick = Instant::now();
+                }
+
+                settings.tick_interval
+            };
+            let tick_interval = match tick_interval {
+                Some(tick_interval) => tick_interval,
+                None => break,
+            };
+
+            next_tick += tick_interval;
+            let now = Instant::now();
+            if next_tick > now {
+                std::thread::sleep(next_tick - now);
+            } else {
+                // Skip missed ticks instead of sending them in a burst.
+                next_tick = now;
+            }
+
+            self.guarded(|| self.send_pending());
+        }
+    }
+
+    fn send_pending(&self) {
+        let pending = self
+            .slots
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|slot| slot.send_pending);
+        if pending {
+            // Slot rate limits don't apply to ticks.
+            let _ = self.send_slots_data(|_| true);
+        }
+    }
+
+    /// Sends updates accumulated since previous tick when fixed send rate is set (see `set_send_rate`),
+    /// returning datagrams server would send instead of sending them.
+    ///
+    /// Server's thread calls it every tick interval (see `tick_interval`), event loops driving server
+    /// without starting its thread should do the same.
+    pub fn tick(&self) -> Datagrams {
+        self.capture_datagrams(|| self.send_pending()).1
+    }
+
+    /// Returns interval between sends when fixed send rate is set, `None` otherwise.
+    pub fn tick_interval(&self) -> Option<Duration> {
+        self.settings.lock().unwrap().tick_interval
+    }
+
+    /// Runs part of server's thread, catching panic so thread keeps running.
+    ///
+    /// Panic message is printed by panic hook as usual, stored (see `last_panic`) and reported with `ServerEvent::ThreadPanicked`.
+    fn guarded<F: FnOnce()>(&self, operation: F) {
+        let payload = match panic::catch_unwind(AssertUnwindSafe(operation)) {
+            Ok(()) => return,
+            Err(payload) => payload,
+        };
+
+        let message = payload
+            .downcast_ref::<&str>()
+            .map(|message| message.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "Unknown panic".to_string());
+
+        self.clear_poison();
+        *self.last_panic.lock().unwrap() = Some(message);
+        self.thread_panics.fetch_add(1, Ordering::Relaxed);
+        let _ = self.events.push(ServerEvent::ThreadPanicked);
+    }
+
+    /// Makes locks held during caught panic usable again, state they protect is kept as it was.
+    fn clear_poison(&self) {
+        self.slots.clear_poison();
+        self.connected_clients.clear_poison();
+        self.client_states.clear_poison();
+        self.settings.clear_poison();
+        self.transforms.clear_poison();
+        self.mirrors.clear_poison();
+        self.registered_slots.clear_poison();
+        self.client_priorities.clear_poison();
+        self.send_rate.clear_poison();
+        self.last_health_publish.clear_poison();
+        self.state_file.clear_poison();
+        self.remembered_mac_addresses.clear_poison();
+        self.history.clear_poison();
+        #[cfg(feature = "profiles")]
+        self.profiles.clear_poison();
+        #[cfg(feature = "compression")]
+        self.compressed_clients.clear_poison();
+        self.tap.clear_poison();
+        self.rumble_callback.clear_poison();
+        self.rumble_sinks.clear_poison();
+        self.last_panic.clear_poison();
+        self.terminal_error.clear_poison();
+    }
+
+    /// Takes fatal transport error which stopped server's thread, `None` if thread didn't stop because of one.
+    ///
+    /// Transient errors (see `error_severity`) are retried by server's thread and never returned.
+    pub fn take_terminal_error(&self) -> Option<Error> {
+        self.terminal_error.lock().unwrap().take()
+    }
+
+    /// Returns message of the latest panic caught in server's threads, `None` if there was none.
+    pub fn last_panic(&self) -> Option<String> {
+        self.last_panic.lock().unwrap().clone()
+    }
+
+    fn handle_timers(&self) {
+        self.check_idle();
+        self.evict_stale_clients();
+        self.flush_pending_sends();
+        self.publish_health();
+    }
+
+    /// Runs timer driven work (idle checks, client eviction, rate limited sends and health publishing),
+    /// returning datagrams server would send instead of sending them.
+    ///
+    /// Server's thread calls it after every received datagram and whenever receive times out,
+    /// event loops driving server without starting its thread should call it by `next_timer` at the latest.
+    pub fn poll_timers(&self) -> Datagrams {
+        self.capture_datagrams(|| self.handle_timers()).1
+    }
+
+    /// Returns when `poll_timers` should be called next.
+    ///
+    /// Rate limited sends and health publishing are scheduled precisely,
+    /// idle checks and client eviction are checked every `READ_TIMEOUT`.
+    pub fn next_timer(&self) -> Instant {
+        let now = Instant::now();
+        let mut next_timer = now + READ_TIMEOUT;
+
+        let settings = *self.settings.lock().unwrap();
+        if settings.tick_interval.is_none() {
+            for slot in self.slots.lock().unwrap().iter() {
+                if let (true, Some(min_send_interval), Some(last_send_time)) = (
+                    slot.send_pending,
+                    slot.min_send_interval,
+                    slot.last_send_time,
+                ) {
+                    next_timer = next_timer.min(last_send_time + min_send_interval);
+                }
+            }
+        }
+        if let Some(health_interval) = settings.health_interval {
+            next_timer =
+                next_timer.min(*self.last_health_publish.lock().unwrap() + health_interval);
+        }
+
+        next_timer.max(now)
+    }
+
+    /// Sends pending rate limited updates that are due.
+    fn flush_pending_sends(&self) {
+        if self.settings.lock().unwrap().tick_interval.is_some() {
+            return;
+        }
+
+        let now = Instant::now();
+        let is_due = |slot: &Slot| slot.send_pending && slot.is_send_allowed(now);
+        if self.slots.lock().unwrap().iter().any(is_due) {
+            let _ = self.send_slots_data(is_due);
+        }
+    }
+
+    /// Sets whether server tells clients all slots are not connected when its thread stops (enabled by default),
+    /// so emulators show controllers as disconnected immediately instead of waiting for timeout.
+    pub fn set_shutdown_notification(&self, shutdown_notification: bool) {
+        self.settings.lock().unwrap().shutdown_notification = shutdown_notification;
+    }
+
+    /// Tells connected clients all slots are not connected.
+    pub fn notify_shutdown(&self) {
+        for address in self.clients() {
+            for slot_number in 0..self.slot_count {
+                let controller_info = ControllerInfo {
+                    slot: slot_number,
+                    ..Default::default()
+                };
+                let _ = self.send_controller_info(address, controller_info);
+            }
+        }
+    }
+
+    /// Sends empty datagram to server's own socket, so blocked receive returns immediately.
+    fn wake(&self) -> Result<()> {
+        let mut address = self.socket.local_addr()?;
+        if address.ip().is_unspecified() {
+            let loopback = match address {
+                SocketAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                SocketAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+            };
+            address.set_ip(loopback);
+        }
+
+        self.socket.send_to(&[], address)?;
+        Ok(())
+    }
+
+    /// Allows clients to negotiate compression extension (disabled by default),
+    /// disabling it stops compressing datagrams sent to clients that negotiated it before.
+    ///
+    /// See [`crate::compression`].
+    #[cfg(feature = "compression")]
+    pub fn set_compression(&self, enabled: bool) {
+        self.compression.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.compressed_clients.lock().unwrap().clear();
+        }
+    }
+
+    #[cfg(feature = "compression")]
+    fn negotiate_compression(&self, source: SocketAddr, algorithms: u32) -> Result<()> {
+        let algorithm = if self.compression.load(Ordering::SeqCst) {
+            algorithms & COMPRESSION_LZ4
+        } else {
+            0
+        };
+
+        // Confirmation itself is never compressed, so client can read it before learning the outcome.
+        self.compressed_clients.lock().unwrap().remove(&source);
+        let message = Message {
+            header: self.message_header(),
+            message_type: MessageType::Compression,
+            payload: MessagePayload::Compression(algorithm),
+        };
+        self.encode_and_send(source, message)?;
+
+        if algorithm != 0 {
+            self.compressed_clients.lock().unwrap().insert(source);
+        }
+
+        Ok(())
+    }
+
+    #[cfg(feature = "compression")]
+    fn compress<'a>(&self, target: SocketAddr, datagram: &'a [u8]) -> Cow<'a, [u8]> {
+        if self.compressed_clients.lock().unwrap().contains(&target) {
+            compress_datagram(datagram)
+        } else {
+            Cow::Borrowed(datagram)
+        }
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn compress<'a>(&self, _target: SocketAddr, datagram: &'a [u8]) -> Cow<'a, [u8]> {
+        Cow::Borrowed(datagram)
+    }
+
+    #[cfg(feature = "compression")]
+    fn decompress<'a>(&self, datagram: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        decompress_datagram(datagram, self.receive_buffer_size.load(Ordering::SeqCst))
+    }
+
+    #[cfg(not(feature = "compression"))]
+    fn decompress<'a>(&self, datagram: &'a [u8]) -> Result<Cow<'a, [u8]>> {
+        Ok(Cow::Borrowed(datagram))
+    }
+
+    /// Enables health extension: every `health_interval` connected clients are sent `SlotHealth` message
+    /// for each connected slot (disabled by default, pass `None` to disable it).
+    ///
+    /// Extension messages are not part of Cemuhook protocol, clients not supporting them should ignore them.
+    pub fn set_health_interval(&self, health_interval: Option<Duration>) {
+        self.settings.lock().unwrap().health_interval = health_interval;
+    }
+
+    /// Sets status of driver providing slot's controller data, published with slot health.
+    pub fn set_driver_status(&self, slot_number: u8, driver_status: DriverStatus) {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize].driver_status = driver_status;
+    }
+
+    /// Sets battery charge (from 0 to 100) of slot's controller, published with slot health.
+    pub fn set_battery_percent(&self, slot_number: u8, battery_percent: Option<u8>) {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize].battery_percent =
+            battery_percent.map(|percent| percent.min(100));
+    }
+
+    fn publish_health(&self) {
+        let health_interval = match self.settings.lock().unwrap().health_interval {
+            Some(health_interval) => health_interval,
+            None => return,
+        };
+
+        let elapsed = {
+            let mut last_health_publish = self.last_health_publish.lock().unwrap();
+            let elapsed = last_health_publish.elapsed();
+            if elapsed < health_interval {
+                return;
+            }
+            *last_health_publish = Instant::now();
+
+            elapsed
+        };
+
+        let mut healths = vec![];
+        {
+            let mut slots = self.slots.lock().unwrap();
+            for slot in slots.iter_mut() {
+                let update_count = std::mem::take(&mut slot.update_count);
+                let reported = slot.reported();
+                if reported.controller_info.slot_state != SlotState::Connected {
+                    continue;
+                }
+
+                healths.push(SlotHealth {
+                    slot: reported.controller_info.slot,
+                    sample_rate: update_count as f32 / elapsed.as_secs_f32(),
+                    driver_status: slot.driver_status,
+                    battery_percent: slot.battery_percent,
+                });
+            }
+        }
+
+        for address in self.clients() {
+            for &slot_health in healths.iter() {
+                let message = Message {
+                    header: self.message_header(),
+                    message_type: MessageType::SlotHealth,
+                    payload: MessagePayload::SlotHealth(slot_health),
+                };
+                let _ = self.encode_and_send(address, message);
+            }
+        }
+    }
+
+    /// Returns next event in event queue or `None` if empty.
+    pub fn next_event(&self) -> Option<ServerEvent> {
+        self.events.pop()
+    }
+
+    fn check_idle(&self) {
+        let idle_timeout = match self.settings.lock().unwrap().idle_timeout {
+            Some(idle_timeout) => idle_timeout,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let mut idle_slots = vec![];
+        {
+            let mut slots = self.slots.lock().unwrap();
+            for slot in slots.iter_mut() {
+                if slot.idle || slot.controller_info.slot_state != SlotState::Connected {
+                    continue;
+                }
+
+                let last_input_time = *slot.last_input_time.get_or_insert(now);
+                if now.duration_since(last_input_time) >= idle_timeout {
+                    slot.idle = true;
+                    idle_slots.push(slot.controller_info);
+                }
+            }
+        }
+
+        for controller_info in idle_slots {
+            let _ = self.events.push(ServerEvent::SlotIdle(controller_info));
+
+            for address in self.clients() {
+                let _ = self.send_connected_controller_info(address, controller_info.slot);
+            }
+        }
+    }
+
+    fn evict_stale_clients(&self) {
+        let client_timeout = match self.settings.lock().unwrap().client_timeout {
+            Some(client_timeout) => client_timeout,
+            None => return,
+        };
+
+        let now = Instant::now();
+        let mut evicted_clients = vec![];
+        self.connected_clients
+            .lock()
+            .unwrap()
+            .retain(|&address, requested_controller_data| {
+                let is_stale = now.duration_since(requested_controller_data.last_request_time)
+                    >= client_timeout;
+                if is_stale {
+                    evicted_clients.push(address);
+                }
+
+                !is_stale
+            });
+
+        self.client_states
+            .lock()
+            .unwrap()
+            .retain(|_, state| now.duration_since(state.last_message_time) < client_timeout);
+
+        #[cfg(feature = "compression")]
+        self.compressed_clients
+            .lock()
+            .unwrap()
+            .retain(|address| !evicted_clients.contains(address));
+
+        for address in evicted_clients {
+            let _ = self.events.push(ServerEvent::ClientEvicted(address));
+        }
+    }
+
+    /// Persists server ID and MAC addresses of controllers connected to slots in given file, pass `None` to stop.
+    ///
+    /// If file exists, server takes ID and remembered slot assignments from it (see `remembered_slot`),
+    /// otherwise it's created with current ID. File is updated whenever controller with new MAC address
+    /// connects to a slot.
+    pub fn set_state_file<P: AsRef<Path>>(&self, path: Option<P>) -> Result<()> {
+        let path = match path {
+            Some(path) => path.as_ref().to_path_buf(),
+            None => {
+                *self.state_file.lock().unwrap() = None;
+                return Ok(());
+            }
+        };
+
+        if path.exists() {
+            let state = ServerState::load(&path)?;
+            self.set_id(state.server_id);
+            let mut slot_mac_addresses = state.slot_mac_addresses;
+            slot_mac_addresses.resize(self.slot_count as usize, 0);
+            *self.remembered_mac_addresses.lock().unwrap() = slot_mac_addresses;
+        }
+
+        *self.state_file.lock().unwrap() = Some(path);
+        self.save_state()
+    }
+
+    fn save_state(&self) -> Result<()> {
+        let state_file = self.state_file.lock().unwrap();
+        match state_file.as_ref() {
+            Some(path) => ServerState {
+                server_id: self.id(),
+                slot_mac_addresses: self.remembered_mac_addresses.lock().unwrap().clone(),
+            }
+            .save(path),
+            None => Ok(()),
+        }
+    }
+
+    fn remember_mac_address(&self, controller_info: ControllerInfo) {
+        if controller_info.slot_state != SlotState::Connected || controller_info.mac_address == 0 {
+            return;
+        }
+
+        {
+            let mut remembered = self.remembered_mac_addresses.lock().unwrap();
+            if remembered[controller_info.slot as usize] == controller_info.mac_address {
+                return;
+            }
+            // Controller is remembered in one slot only.
+            for mac_address in remembered.iter_mut() {
+                if *mac_address == controller_info.mac_address {
+                    *mac_address = 0;
+                }
+            }
+            remembered[controller_info.slot as usize] = controller_info.mac_address;
+        }
+
+        let _ = self.save_state();
+    }
+
+    /// Returns slot controller with given MAC address was last connected to.
+    pub fn remembered_slot(&self, mac_address: u64) -> Option<u8> {
+        if mac_address == 0 {
+            return None;
+        }
+
+        self.remembered_mac_addresses
+            .lock()
+            .unwrap()
+            .iter()
+            .position(|&remembered| remembered == mac_address)
+            .map(|slot_number| slot_number as u8)
+    }
+
+    /// Returns number of controller slots, see `ServerBuilder::with_slot_count`.
+    pub fn slot_count(&self) -> u8 {
+        self.slot_count
+    }
+
+    /// Returns indices of all server's slots in order.
+    pub fn slots(&self) -> impl Iterator<Item = SlotIndex> {
+        SlotIndex::all_in(self.slot_count)
+    }
+
+    /// Returns server's local socket address.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Gets current controller info for given slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot index was created for more slots than server has (see `slots`).
+    pub fn slot_info(&self, slot_index: SlotIndex) -> ControllerInfo {
+        self.controller_info(slot_index.get())
+    }
+
+    /// Gets current controller data for given slot.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot index was created for more slots than server has (see `slots`).
+    pub fn slot_data(&self, slot_index: SlotIndex) -> ControllerData {
+        self.controller_data(slot_index.get())
+    }
+
+    /// Gets current controller info for given slot number or `None` if slot number is invalid.
+    pub fn get_controller_info(&self, slot_number: u8) -> Option<ControllerInfo> {
+        check_slot_number_in(slot_number, self.slot_count).ok()?;
+
+        Some(self.controller_info(slot_number))
+    }
+
+    /// Gets current controller data for given slot number or `None` if slot number is invalid.
+    pub fn get_controller_data(&self, slot_number: u8) -> Option<ControllerData> {
+        check_slot_number_in(slot_number, self.slot_count).ok()?;
+
+        Some(self.controller_data(slot_number))
+    }
+
+    /// Same as `set_slot_enabled`, but fails with `ErrorKind::InvalidInput` error on invalid slot number.
+    pub fn try_set_slot_enabled(&self, slot_number: u8, enabled: bool) -> Result<()> {
+        check_slot_number_in(slot_number, self.slot_count)?;
+        self.set_slot_enabled(slot_number, enabled);
+
+        Ok(())
+    }
+
+    /// Same as `set_transform`, but fails with `ErrorKind::InvalidInput` error on invalid slot number.
+    pub fn try_set_transform(
+        &self,
+        slot_number: u8,
+        transform: Option<Box<dyn Transform>>,
+    ) -> Result<()> {
+        check_slot_number_in(slot_number, self.slot_count)?;
+        self.set_transform(slot_number, transform);
+
+        Ok(())
+    }
+
+    /// Gets current controller info for given slot number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `get_controller_info`.
+    pub fn controller_info(&self, slot_number: u8) -> ControllerInfo {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize].controller_info
+    }
+
+    /// Gets current controller data for given slot number.
+    ///
+    /// # Panics
+    ///
+    /// Panics if slot number is invalid, see `get_controller_data`.
+    pub fn controller_data(&self, slot_number: u8) -> ControllerData {
+        assert!(slot_number < self.slot_count);
+
+        self.slots.lock().unwrap()[slot_number as usize].controller_data
+    }
+
+    /// Gets controller info and data of slot as reported to clients (hidden slots are reported as empty).
+    pub(crate) fn reported_slot(&self, slot_number: u8) -> (ControllerInfo, ControllerData) {
+        assert!(slot_number < self.slot_count);
+
+        let slot = self.slots.lock().unwrap()[slot_number as usize].reported();
+        (slot.controller_info, slot.controller_data)
+    }
+
+    /// Returns server ID used in message headers.
+    pub fn id(&self) -> u32 {
+        self.source_id.load(Ordering::SeqCst)
+    }
+
+    /// Changes server ID used in message headers, takes effect with the next sent message.
+    pub fn set_id(&self, id: u32) {
+        self.source_id.store(id, Ordering::SeqCst);
+    }
+
+    fn message_header(&self) -> MessageHeader {
+        MessageHeader {
+            source: MessageSource::Server,
+            protocol_version: PROTOCOL_VERSION,
+            message_length: 0,
+            checksum: 0,
+            source_id: self.id(),
+        }
+    }
+
+    /// Returns snapshot of server metrics and connected clients.
+    pub fn stats(&self) -> ServerStats {
+        let clients = {
+            let connected_clients = self.connected_clients.lock().unwrap();
+            let mut clients: Vec<ClientStats> = connected_clients
+                .iter()
+                .map(|(&address, requested)| {
+                    let mut slot_numbers: Vec<u8> =
+                        requested.slot_numbers.iter().copied().collect();
+                    slot_numbers.sort_unstable();
+                    let mut mac_addresses: Vec<u64> =
+                        requested.mac_addresses.iter().copied().collect();
+                    mac_addresses.sort_unstable();
+
+                    ClientStats {
+                        address,
+                        packet_number: requested.packet_number,
+                        slot_numbers,
+                        mac_addresses,
+                        request_intervals: Default::default(),
+                        since_last_request: requested.last_request_time.elapsed(),
+                    }
+                })
+                .collect();
+            clients.sort_by_key(|client| client.address);
+
+            clients
+        };
+
+        let clients = {
+            let client_states = self.client_states.lock().unwrap();
+            clients
+                .into_iter()
+                .map(|mut client| {
+                    if let Some(state) = client_states.get(&client.address) {
+                        client.request_intervals = state.request_intervals;
+                    }
+                    client
+                })
+                .collect()
+        };
+
+        ServerStats {
+            received_packets: self.received_packets.load(Ordering::Relaxed),
+            invalid_packets: self.invalid_packets.load(Ordering::Relaxed),
+            oversize_packets: self.oversize_packets.load(Ordering::Relaxed),
+            sent_packets: self.sent_packets.load(Ordering::Relaxed),
+            identity_mismatches: self.identity_mismatches.load(Ordering::Relaxed),
+            rejected_replays: self.rejected_replays.load(Ordering::Relaxed),
+            throttled_info_requests: self.throttled_info_requests.load(Ordering::Relaxed),
+            stale_data_updates: self.stale_data_updates.load(Ordering::Relaxed),
+            invalid_motion_updates: self.invalid_motion_updates.load(Ordering::Relaxed),
+            thread_panics: self.thread_panics.load(Ordering::Relaxed),
+            clients,
+        }
+    }
+
+    fn encode_and_send(&self, target: SocketAddr, message: Message) -> Result<()> {
+        let mut encoded_message = vec![];
+        encode_message(&mut encoded_message, message).unwrap();
+
+        let datagram = self.compress(target, &encoded_message);
+        let captured = CAPTURED_DATAGRAMS.with(|captured| match captured.borrow_mut().as_mut() {
+            Some(captured) => {
+                captured.push((target, datagram.to_vec()));
+                true
+            }
+            None => false,
+        });
+        let sent = if captured {
+            Ok(datagram.len())
+        } else {
+            self.socket.send_to(&datagram, target)
+        };
+
+        sent.map(|_amount| {
+            self.sent_packets.fetch_add(1, Ordering::Relaxed);
+            self.count_sent_packet();
+            self.tap_datagram(Direction::Sent, target, &encoded_message);
+        })
+    }
+
+    /// Sets callback receiving every raw datagram sent or received by server, pass `None` to remove it.
+    ///
+    /// Callback is called from server's threads, keep it short.
+    pub fn set_tap(&self, tap: Option<DatagramTap>) {
+        *self.tap.lock().unwrap() = tap;
+    }
+
+    fn tap_datagram(&self, direction: Direction, peer: SocketAddr, datagram: &[u8]) {
+        if let Some(tap) = self.tap.lock().unwrap().as_ref() {
+            tap(direction, peer, datagram);
+        }
+    }
+
+    fn count_sent_packet(&self) {
+        let mut send_rate = self.send_rate.lock().unwrap();
+        send_rate.advance();
+        send_rate.window_packets += 1;
+    }
+
+    /// Returns number of updates low priority clients should get one of, `None` if server isn't under pressure.
+    fn low_priority_decimation(&self) -> Option<u32> {
+        let send_pressure = self.settings.lock().unwrap().send_pressure?;
+
+        let mut send_rate = self.send_rate.lock().unwrap();
+        send_rate.advance();
+
+        (send_rate.previous_packets > send_pressure.packets_per_second)
+            .then_some(send_pressure.low_priority_every.max(1))
+    }
+
+    fn send_protocol_version(&self, target: SocketAddr) -> Result<()> {
+        let message = Message {
+            header: self.message_header(),
+            message_type: MessageType::ProtocolVersion,
+            payload: MessagePayload::ProtocolVersion(PROTOCOL_VERSION),
+        };
+
+        self.encode_and_send(target, message)
+    }
+
+    fn send_connected_controller_info(&self, target: SocketAddr, slot_number: u8) -> Result<()> {
+        let controller_info = self.slots.lock().unwrap()[slot_number as usize]
+            .reported()
+            .controller_info;
+
+        self.send_controller_info(target, controller_info)
+    }
+
+    fn send_controller_info(
+        &self,
+        target: SocketAddr,
+        controller_info: ControllerInfo,
+    ) -> Result<()> {
+        let payload = MessagePayload::ConnectedControllerResponse { controller_info };
+
+        let message = Message {
+            header: self.message_header(),
+            message_type: MessageType::ConnectedControllers,
+            payload,
+        };
+
+        self.encode_and_send(target, message)
+    }
+
+    fn send_motor_info(&self, target: SocketAddr, slot: Slot) -> Result<()> {
+        let message = Message {
+            header: self.message_header(),
+            message_type: MessageType::MotorInfo,
+            payload: MessagePayload::MotorInfoResponse {
+                controller_info: slot.controller_info,
+                motor_count: slot.motor_count,
+            },
+        };
+
+        self.encode_and_send(target, message)
+    }
+
+    /// Returns slots matching request, as they should be reported to clients.
+    fn requested_slots(&self, request: ControllerDataRequest) -> Vec<Slot> {
+        let slots = self.slots.lock().unwrap();
+        slots
+            .iter()
+            .map(Slot::reported)
+            .filter(|slot| match request {
+                ControllerDataRequest::ReportAll => true,
+                ControllerDataRequest::SlotNumber(slot_number) => {
+                    slot.controller_info.slot == slot_number
+                }
+                ControllerDataRequest::MAC(mac) => {
+                    slot.controller_info.slot_state == SlotState::Connected
+                        && slot.controller_info.mac_address == mac
+                }
+            })
+            .collect()
+    }
+
+    fn handle_rumble_request(&self, request: ControllerDataRequest, motor: u8, intensity: u8) {
+        let slot_numbers: Vec<u8> = self
+            .requested_slots(request)
+            .iter()
+            .filter(|slot| {
+                slot.controller_info.slot_state == SlotState::Connected && motor < slot.motor_count
+            })
+            .map(|slot| slot.controller_info.slot)
+            .collect();
+
+        let rumble_callback = self.rumble_callback.lock().unwrap();
+        let mut rumble_sinks = self.rumble_sinks.lock().unwrap();
+        for slot_number in slot_numbers {
+            match (
+                &mut rumble_sinks[slot_number as usize],
+                rumble_callback.as_ref(),
+            ) {
+                (Some(sink), _) => {
+                    let _ = sink.set_motor(motor, intensity);
+                }
+                (None, Some(rumble_callback)) => rumble_callback(slot_number, motor, intensity),
+                (None, None) => {}
+            }
+        }
+    }
+
+    fn send_slot_data(
+        &self,
+        target: SocketAddr,
+        slot: Slot,
+        packet_number: &mut u32,
+    ) -> Result<()> {
+        let payload = MessagePayload::ControllerData {
+            packet_number: *packet_number,
+            controller_info: slot.controller_info,
+            controller_data: slot.controller_data,
+        };
+
+        let message = Message {
+            header: self.message_header(),
+            message_type: MessageType::ControllerData,
+            payload,
+        };
+
+        let result = self.encode_and_send(target, message);
+        if result.is_ok() {
+            *packet_number += 1;
+        }
+
+        result
+    }
+
+    /// Sends data of slots their rate limits allow sending now.
+    fn send_controller_data(&self) -> Result<()> {
+        let now = Instant::now();
+        self.send_slots_data(|slot| slot.is_send_allowed(now))
+    }
+
+    /// Sends data of slots selected by given predicate to clients requesting them.
+    ///
+    /// Only selected slots count as sent for their rate limits and have their pending updates flushed.
+    fn send_slots_data<F: Fn(&Slot) -> bool>(&self, is_selected: F) -> Result<()> {
+        let decimation = self.low_priority_decimation();
+        let client_priorities = self.client_priorities.lock().unwrap().clone();
+
+        let _sending = self.send_lock.lock().unwrap();
+
+        // Slots and clients are only locked to take snapshots, so producers aren't blocked by sends.
+        let (slots, selected) = {
+            let mut slots = self.slots.lock().unwrap();
+            let now = Instant::now();
+            let selected: Vec<bool> = slots.iter().map(&is_selected).collect();
+            for (slot, &selected) in slots.iter_mut().zip(&selected) {
+                if selected {
+                    slot.last_send_time = Some(now);
+                    slot.send_pending = false;
+                }
+            }
+
+            (slots.clone(), selected)
+        };
+
+        let mut sends = vec![];
+        for (&client_address, requested_controller_data) in
+            self.connected_clients.lock().unwrap().iter_mut()
+        {
+            let update_count = requested_controller_data.update_count;
+            requested_controller_data.update_count = update_count.wrapping_add(1);
+            if let Some(every) = decimation {
+                let priority = client_priorities
+                    .get(&client_address)
+                    .copied()
+                    .unwrap_or_default();
+                if priority == ClientPriority::Low && !update_count.is_multiple_of(every) {
+                    continue;
+                }
+            }
+
+            let mut already_sent = HashSet::new();
+            let mut client_slots = vec![];
+
+            for &slot_number in requested_controller_data.slot_numbers.iter() {
+                if selected[slot_number as usize] {
+                    client_slots.push(slots[slot_number as usize].reported());
+                    already_sent.insert(slot_number);
+                }
+            }
+
+            for &mac_address in requested_controller_data.mac_addresses.iter() {
+                let slot_number = slots.iter().position(|slot| {
+                    !slot.is_hidden() && slot.controller_info.mac_address == mac_address
+                });
+                if let Some(slot_number) = slot_number {
+                    if selected[slot_number] && already_sent.insert(slot_number as u8) {
+                        client_slots.push(slots[slot_number]);
+                    }
+                }
+            }
+
+            if !client_slots.is_empty() {
+                sends.push((
+                    client_address,
+                    requested_controller_data.packet_number,
+                    client_slots,
+                ));
+            }
+        }
+
+        let mut packet_numbers = vec![];
+        let mut failed_clients = vec![];
+        for (client_address, mut packet_number, client_slots) in sends {
+            for slot in client_slots {
+                if let Err(error) = self.send_slot_data(client_address, slot, &mut packet_number) {
+                    failed_clients.push((client_address, error_severity(&error)));
+                    break;
+                }
+            }
+
+            packet_numbers.push((client_address, packet_number));
+        }
+
+        {
+            let mut connected_clients = self.connected_clients.lock().unwrap();
+            for (address, packet_number) in packet_numbers {
+                if let Some(requested_controller_data) = connected_clients.get_mut(&address) {
+                    requested_controller_data.packet_number = packet_number;
+                }
+            }
+            for &(address, severity) in &failed_clients {
+                if severity == ErrorSeverity::Fatal {
+                    connected_clients.remove(&address);
+                }
+            }
+        }
+
+        for (address, _) in failed_clients {
+            let _ = self.events.push(ServerEvent::ClientSendFailed(address));
+        }
+
+        Ok(())
+    }
+
+    fn is_replay(&self, source: SocketAddr, packet: &[u8]) -> bool {
+        let replay_window = match self.settings.lock().unwrap().replay_window {
+            Some(replay_window) => replay_window,
+            None => return false,
+        };
+
+        let now = Instant::now();
+        let mut client_states = self.client_states.lock().unwrap();
+        if let Some(state) = client_states.get_mut(&source) {
+            let is_replay = state.last_datagram == packet
+                && now.duration_since(state.last_datagram_time) < replay_window;
+
+            state.last_datagram.clear();
+            state.last_datagram.extend_from_slice(packet);
+            state.last_datagram_time = now;
+
+            if is_replay {
+                self.rejected_replays.fetch_add(1, Ordering::Relaxed);
+            }
+
+            is_replay
+        } else {
+            false
+        }
+    }
+
+    fn verify_identity(&self, source: SocketAddr, packet: &[u8], header: MessageHeader) -> bool {
+        let identity = ClientIdentity {
+            source_id: header.source_id,
+            protocol_version: header.protocol_version,
+        };
+
+        let mut client_states = self.client_states.lock().unwrap();
+        if client_states.len() >= MAX_CLIENT_STATES && !client_states.contains_key(&source) {
+            // Without client timeout states are never evicted otherwise.
+            let least_recent = client_states
+                .iter()
+                .min_by_key(|(_, state)| state.last_message_time)
+                .map(|(&address, _)| address);
+            if let Some(address) = least_recent {
+                client_states.remove(&address);
+            }
+        }
+        let state = client_states.entry(source).or_insert_with(|| ClientState {
+            identity,
+            last_datagram: packet.to_vec(),
+            last_datagram_time: Instant::now(),
+            last_message_time: Instant::now(),
+            last_info_response_time: None,
+            last_data_request_time: None,
+            request_intervals: Default::default(),
+        });
+        state.last_message_time = Instant::now();
+        if state.identity == identity {
+            return true;
+        }
+
+        match self.settings.lock().unwrap().identity_policy {
+            IdentityPolicy::Ignore => {
+                state.identity = identity;
+                true
+            }
+            IdentityPolicy::Flag => {
+                self.identity_mismatches.fetch_add(1, Ordering::Relaxed);
+                state.identity = identity;
+                true
+            }
+            IdentityPolicy::Reject => {
+                self.identity_mismatches.fetch_add(1, Ordering::Relaxed);
+                false
+            }
+        }
+    }
+
+    fn should_throttle_info(&self, source: SocketAddr) -> bool {
+        let min_info_interval = match self.settings.lock().unwrap().min_info_interval {
+            Some(min_info_interval) => min_info_interval,
+            None => return false,
+        };
+
+        let now = Instant::now();
+        let mut client_states = self.client_states.lock().unwrap();
+        if let Some(state) = client_states.get_mut(&source) {
+            if let Some(last_info_response_time) = state.last_info_response_time {
+                if now.duration_since(last_info_response_time) < min_info_interval {
+                    self.throttled_info_requests.fetch_add(1, Ordering::Relaxed);
+                    return true;
+                }
+            }
+
+            state.last_info_response_time = Some(now);
+        }
+
+        false
+    }
+
+    fn record_data_request(&self, source: SocketAddr) {
+        let now = Instant::now();
+        let mut client_states = self.client_states.lock().unwrap();
+        if let Some(state) = client_states.get_mut(&source) {
+            if let Some(last_data_request_time) = state.last_data_request_time {
+                state
+                    .request_intervals
+                    .add(now.duration_since(last_data_request_time));
+            }
+            state.last_data_request_time = Some(now);
+        }
+    }
+
+    fn receive_datagram(&self, source: SocketAddr, datagram: &[u8]) -> Result<()> {
+        self.received_packets.fetch_add(1, Ordering::Relaxed);
+
+        match self.decompress(datagram) {
+            Ok(datagram) => {
+                self.tap_datagram(Direction::Received, source, &datagram);
+                self.handle_packet(source, &datagram)
+            }
+            Err(error) => {
+                self.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                Err(error)
+            }
         }
+    }
 
-        result
+    /// Handles datagram synchronously on calling thread, as if server's thread received it,
+    /// returning datagrams (and their targets) server would send in response instead of sending them.
+    ///
+    /// Allows testing protocol behavior without threads (e.g. with transport bound to [`LoopbackNetwork`])
+    /// or driving server from custom event loop. Datagrams sent by other threads meanwhile are sent as usual.
+    /// Fails with `ProtocolError` describing why datagram isn't valid message,
+    /// or with `ProtocolError::Io` if response couldn't be sent.
+    ///
+    /// # Arguments
+    ///
+    /// * `source` - address datagram came from.
+    /// * `datagram` - received datagram.
+    pub fn handle_datagram(
+        &self,
+        source: SocketAddr,
+        datagram: &[u8],
+    ) -> std::result::Result<Datagrams, ProtocolError> {
+        let (result, datagrams) =
+            self.capture_datagrams(|| self.receive_datagram(source, datagram));
+
+        result.map(|()| datagrams).map_err(ProtocolError::from)
     }
 
-    fn send_controller_data(&self) -> Result<()> {
-        let slots = self.slots.lock().unwrap();
-        let mut connected_clients = self.connected_clients.lock().unwrap();
+    /// Calls `operation` (e.g. `DsServer::update_controller_data` or `notify_shutdown`) and returns its result
+    /// with datagrams (and their targets) server would send meanwhile instead of sending them.
+    ///
+    /// Only datagrams sent on calling thread are captured, server's state is updated as if they were sent.
+    pub fn capture_datagrams<R, F: FnOnce() -> R>(&self, operation: F) -> (R, Datagrams) {
+        let previous = CAPTURED_DATAGRAMS.with(|captured| captured.replace(Some(vec![])));
+        let result = operation();
+        let captured = CAPTURED_DATAGRAMS.with(|captured| captured.replace(previous));
 
-        connected_clients.retain(|&client_address, requested_controller_data| {
-            let mut already_sent = HashSet::new();
+        (result, captured.unwrap_or_default())
+    }
 
-            for &slot_number in requested_controller_data.slot_numbers.iter() {
-                let slot = slots[slot_number as usize];
-                let result = self.send_slot_data(
-                    client_address,
-                    slot,
-                    &mut requested_controller_data.packet_number,
-                );
+    fn handle_packet(&self, source: SocketAddr, packet: &[u8]) -> Result<()> {
+        if self.is_replay(source, packet) {
+            return Ok(());
+        }
 
-                if result.is_ok() {
-                    already_sent.insert(slot_number);
-                } else {
-                    return false;
-                }
-            }
+        if let Err(error) = check_message_length(
+            packet,
+            self.max_message_length
+                .load(Ordering::SeqCst)
+                .min(self.receive_buffer_size.load(Ordering::SeqCst)),
+        ) {
+            self.invalid_packets.fetch_add(1, Ordering::Relaxed);
+            self.oversize_packets.fetch_add(1, Ordering::Relaxed);
+            return Err(error);
+        }
 
-            for &mac_address in requested_controller_data.mac_addresses.iter() {
-                let slot_number = slots
-                    .iter()
-                    .position(|slot| slot.controller_info.mac_address == mac_address);
-                if let Some(slot_number) = slot_number {
-                    if !already_sent.contains(&(slot_number as u8)) {
-                        let slot = slots[slot_number];
-                        let result = self.send_slot_data(
-                            client_address,
-                            slot,
-                            &mut requested_controller_data.packet_number,
-                        );
-
-                        if result.is_ok() {
-                            already_sent.insert(slot_number as u8);
-                        } else {
-                            return false;
-                        }
-                    }
-                }
+        let verify_checksums = self.settings.lock().unwrap().verify_checksums;
+        let message = match parse_message(MessageSource::Client, packet, verify_checksums) {
+            Ok(message) => message,
+            Err(error) => {
+                self.invalid_packets.fetch_add(1, Ordering::Relaxed);
+                return Err(error.into());
             }
+        };
 
-            true
-        });
+        if !self.verify_identity(source, packet, message.header) {
+            return Ok(());
+        }
 
-        Ok(())
+        self.handle_request(source, message)
     }
 
     fn handle_request(&self, source: SocketAddr, request: Message) -> Result<()> {
@@ -213,33 +2434,67 @@ impl Server {
                         amount,
                         slot_numbers,
                     } => {
+                        if self.should_throttle_info(source) {
+                            return Ok(());
+                        }
+
                         for i in 0..amount {
                             let slot_number = slot_numbers[i as usize];
-                            self.send_connected_controller_info(source, slot_number)?;
+                            if slot_number < self.slot_count {
+                                self.send_connected_controller_info(source, slot_number)?;
+                            }
+                        }
+
+                        Ok(())
+                    }
+                    MessagePayload::MotorInfoRequest(request) => {
+                        for slot in self.requested_slots(request) {
+                            self.send_motor_info(source, slot)?;
                         }
 
                         Ok(())
                     }
+                    MessagePayload::RumbleRequest {
+                        request,
+                        motor,
+                        intensity,
+                    } => {
+                        self.handle_rumble_request(request, motor, intensity);
+
+                        Ok(())
+                    }
+                    #[cfg(feature = "compression")]
+                    MessagePayload::Compression(algorithms) => {
+                        self.negotiate_compression(source, algorithms)
+                    }
                     MessagePayload::ControllerDataRequest(request) => {
+                        self.record_data_request(source);
+
                         {
                             let mut connected_clients = self.connected_clients.lock().unwrap();
+                            if !connected_clients.contains_key(&source) {
+                                let _ = self.events.push(ServerEvent::ClientConnected(source));
+                            }
                             let requested = connected_clients.entry(source).or_insert(
                                 RequestedControllerData {
                                     packet_number: 0,
+                                    update_count: 0,
+                                    last_request_time: Instant::now(),
                                     slot_numbers: HashSet::new(),
                                     mac_addresses: HashSet::new(),
                                 },
                             );
+                            requested.last_request_time = Instant::now();
 
                             match request {
                                 ControllerDataRequest::ReportAll => {
-                                    requested.slot_numbers.insert(0);
-                                    requested.slot_numbers.insert(1);
-                                    requested.slot_numbers.insert(2);
-                                    requested.slot_numbers.insert(3);
+                                    requested.slot_numbers.extend(0..self.slot_count);
                                 }
                                 ControllerDataRequest::SlotNumber(slot_number) => {
-                                    requested.slot_numbers.insert(slot_number);
+                                    // Slots server doesn't have are ignored.
+                                    if slot_number < self.slot_count {
+                                        requested.slot_numbers.insert(slot_number);
+                                    }
                                 }
                                 ControllerDataRequest::MAC(mac) => {
                                     requested.mac_addresses.insert(mac);
@@ -259,41 +2514,810 @@ impl Server {
 impl DsServer for Arc<Server> {
     fn start(self, countinue_running: Arc<AtomicBool>) -> JoinHandle<()> {
         std::thread::spawn(move || {
-            let mut buf = [0_u8; 100];
+            let ticks_running = Arc::new(AtomicBool::new(true));
+            let ticks = {
+                let server = self.clone();
+                let ticks_running = ticks_running.clone();
+                std::thread::spawn(move || server.run_ticks(&ticks_running))
+            };
+
+            let mut buf = vec![];
+            let mut backoff = ReceiveBackoff::default();
             while countinue_running.load(Ordering::SeqCst) {
-                if let Ok((amount, source)) = self.socket.recv_from(&mut buf) {
-                    let message = parse_message(MessageSource::Client, &buf[..amount], true);
-                    if let Ok(message) = message {
-                        let _ = self.handle_request(source, message);
-                    }
+                buf.resize(self.receive_buffer_size.load(Ordering::SeqCst), 0);
+                let received = self.socket.recv_from(&mut buf);
+                // Stopping server wakes it with an empty datagram, don't handle it.
+                if !countinue_running.load(Ordering::SeqCst) {
+                    break;
                 }
+
+                let received = match backoff.handle(received) {
+                    Ok(received) => received,
+                    Err(error) => {
+                        let _ = self.events.push(ServerEvent::TransportFailed(error.kind()));
+                        *self.terminal_error.lock().unwrap() = Some(error);
+                        break;
+                    }
+                };
+
+                // Panic (e.g. in user callback) is caught, so thread keeps serving producers' updates.
+                self.guarded(|| {
+                    if let Some((amount, source)) = received {
+                        let _ = self.receive_datagram(source, &buf[..amount]);
+                    }
+
+                    self.handle_timers();
+                });
+            }
+
+            {
+                // Settings are locked, so tick thread can't miss notification between its check and wait.
+                let _settings = self.settings.lock().unwrap();
+                ticks_running.store(false, Ordering::SeqCst);
+                self.tick_interval_changed.notify_all();
             }
+            let _ = ticks.join();
+            self.guarded(|| {
+                if self.settings.lock().unwrap().shutdown_notification {
+                    self.notify_shutdown();
+                }
+            });
         })
     }
 
+    fn slot_count(&self) -> u8 {
+        self.slot_count
+    }
+
     fn update_controller_info(&self, controller_info: ControllerInfo) {
-        assert!(controller_info.slot < 4);
+        assert!(controller_info.slot < self.slot_count);
 
         let slot_number = controller_info.slot;
-        {
+        let previous_mac_address = {
             let mut slots = self.slots.lock().unwrap();
-            slots[slot_number as usize].controller_info = controller_info;
+            let slot = &mut slots[slot_number as usize];
+            let previous_mac_address = slot.controller_info.mac_address;
+            slot.controller_info = controller_info;
+            slot.idle = false;
+            slot.last_input_time = Some(Instant::now());
+            previous_mac_address
+        };
+
+        if controller_info.mac_address != previous_mac_address {
+            #[cfg(feature = "profiles")]
+            self.apply_profile(controller_info);
         }
+        self.remember_mac_address(controller_info);
 
-        let connected_clients = self.connected_clients.lock().unwrap();
-        for &address in connected_clients.keys() {
-            let _ = self.send_connected_controller_info(address, slot_number);
+        self.notify_controller_info(slot_number);
+        self.update_mirrors_info(controller_info);
+    }
+
+    fn update_controller_data(&self, slot_number: u8, controller_data: ControllerData) {
+        assert!(slot_number < self.slot_count);
+
+        if !self.is_slot_enabled(slot_number) {
+            return;
+        }
+
+        let (controller_data, resumed) = {
+            // Transforms stay locked until data is stored,
+            // so concurrent updates are stored in the order they were transformed.
+            let mut transforms = self.transforms.lock().unwrap();
+            match self.transform_and_store(&mut transforms, slot_number, controller_data) {
+                Some(stored) => stored,
+                None => return,
+            }
+        };
+
+        if let Some(controller_info) = resumed {
+            self.resume_slot(controller_info);
+        }
+        self.update_mirrors_data(slot_number, controller_data);
+
+        if self.is_send_due(slot_number) {
+            let _ = self.send_controller_data();
+        }
+    }
+
+    fn update_motion_samples(
+        &self,
+        slot_number: u8,
+        controller_data: ControllerData,
+        samples: &[MotionSample],
+    ) {
+        assert!(slot_number < self.slot_count);
+
+        if !self.is_slot_enabled(slot_number) {
+            return;
+        }
+
+        // Transforms stay locked for the whole batch, so updates from other threads don't interleave with it.
+        let mut transforms = self.transforms.lock().unwrap();
+        for sample in samples {
+            let mut sampled = controller_data;
+            sample.apply_to(&mut sampled);
+
+            let (sampled, resumed) =
+                match self.transform_and_store(&mut transforms, slot_number, sampled) {
+                    Some(stored) => stored,
+                    None => continue,
+                };
+
+            if let Some(controller_info) = resumed {
+                self.resume_slot(controller_info);
+            }
+            self.update_mirrors_data(slot_number, sampled);
+
+            if self.is_send_due(slot_number) {
+                let _ = self.send_controller_data();
+            }
+        }
+    }
+}
+
+/// Cheap to clone handle of shared `Server`, usable from any thread.
+///
+/// Dereferences to `Server`, so besides `DsServer` methods it exposes all of server's settings and queries.
+#[derive(Clone)]
+pub struct ServerHandle(Arc<Server>);
+
+impl ServerHandle {
+    pub fn new(server: Server) -> ServerHandle {
+        ServerHandle(Arc::new(server))
+    }
+
+    /// Returns shared server, e.g. for APIs taking `Arc<Server>`.
+    pub fn server(&self) -> &Arc<Server> {
+        &self.0
+    }
+
+    /// See `Server::register_device`.
+    pub fn register_device(&self, controller_info: ControllerInfo) -> Option<SlotHandle> {
+        self.0.register_device(controller_info)
+    }
+
+    /// Starts background server thread, returning handle stopping it.
+    ///
+    /// Unlike `DsServer::start`, stopping doesn't wait for receive timeout.
+    pub fn spawn(&self) -> RunningServer {
+        let countinue_running = Arc::new(AtomicBool::new(true));
+        let thread = self.clone().start(countinue_running.clone());
+
+        RunningServer {
+            server: self.clone(),
+            countinue_running,
+            thread,
         }
     }
+}
+
+/// Slot assigned to device with `Server::register_device`.
+///
+/// Slot is reported to clients as not connected and becomes free once handle is unregistered or dropped.
+pub struct SlotHandle {
+    server: Arc<Server>,
+    slot_number: u8,
+}
+
+impl SlotHandle {
+    pub fn slot_number(&self) -> u8 {
+        self.slot_number
+    }
+
+    pub fn slot_index(&self) -> SlotIndex {
+        SlotIndex::new_in(self.slot_number, self.server.slot_count).unwrap()
+    }
+
+    pub fn server(&self) -> &Arc<Server> {
+        &self.server
+    }
+
+    /// Updates device's controller info, slot number and state are kept.
+    pub fn update_controller_info(&self, controller_info: ControllerInfo) {
+        self.server.update_controller_info(ControllerInfo {
+            slot: self.slot_number,
+            slot_state: SlotState::Connected,
+            ..controller_info
+        });
+    }
+
+    /// Updates device's controller data, see `DsServer::update_controller_data`.
+    pub fn update_controller_data(&self, controller_data: ControllerData) {
+        self.server
+            .update_controller_data(self.slot_number, controller_data);
+    }
+
+    /// Updates device's slot once per motion sample, see `DsServer::update_motion_samples`.
+    pub fn update_motion_samples(&self, controller_data: ControllerData, samples: &[MotionSample]) {
+        self.server
+            .update_motion_samples(self.slot_number, controller_data, samples);
+    }
+
+    /// Frees slot, reporting it to clients as not connected (same as dropping handle).
+    pub fn unregister(self) {}
+}
+
+impl Drop for SlotHandle {
+    fn drop(&mut self) {
+        self.server.unregister_slot(self.slot_number);
+    }
+}
+
+/// Background thread of server started with `ServerHandle::spawn`.
+///
+/// Dropping it without calling `stop` leaves thread running.
+pub struct RunningServer {
+    server: ServerHandle,
+    countinue_running: Arc<AtomicBool>,
+    thread: JoinHandle<()>,
+}
+
+impl RunningServer {
+    pub fn server(&self) -> &ServerHandle {
+        &self.server
+    }
+
+    /// Checks whether server thread is still running.
+    pub fn is_running(&self) -> bool {
+        !self.thread.is_finished()
+    }
+
+    /// Asks server thread to stop and wakes it, so it stops (notifying clients unless disabled
+    /// with `set_shutdown_notification`) without waiting for receive timeout.
+    pub fn stop(&self) {
+        self.countinue_running.store(false, Ordering::SeqCst);
+        // If wake fails thread still stops after receive timeout.
+        let _ = self.server.wake();
+    }
+
+    /// Waits for server thread to finish, call `stop` first.
+    pub fn join(self) -> std::thread::Result<()> {
+        self.thread.join()
+    }
+
+    /// Stops server thread and waits for it to finish.
+    pub fn shutdown(self) -> std::thread::Result<()> {
+        self.stop();
+        self.join()
+    }
+}
+
+impl From<Server> for ServerHandle {
+    fn from(server: Server) -> ServerHandle {
+        ServerHandle::new(server)
+    }
+}
+
+impl From<Arc<Server>> for ServerHandle {
+    fn from(server: Arc<Server>) -> ServerHandle {
+        ServerHandle(server)
+    }
+}
+
+impl std::ops::Deref for ServerHandle {
+    type Target = Server;
+
+    fn deref(&self) -> &Server {
+        &self.0
+    }
+}
+
+impl DsServer for ServerHandle {
+    fn start(self, countinue_running: Arc<AtomicBool>) -> JoinHandle<()> {
+        self.0.start(countinue_running)
+    }
+
+    fn slot_count(&self) -> u8 {
+        self.0.slot_count
+    }
+
+    fn update_controller_info(&self, controller_info: ControllerInfo) {
+        self.0.update_controller_info(controller_info)
+    }
 
     fn update_controller_data(&self, slot_number: u8, controller_data: ControllerData) {
-        assert!(slot_number < 4);
+        self.0.update_controller_data(slot_number, controller_data)
+    }
+
+    fn update_motion_samples(
+        &self,
+        slot_number: u8,
+        controller_data: ControllerData,
+        samples: &[MotionSample],
+    ) {
+        self.0
+            .update_motion_samples(slot_number, controller_data, samples)
+    }
+}
+
+// Server is shared between its thread and input sources, fail to compile if it stops being thread-safe.
+const _: fn() = || {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Server>();
+    assert_send_sync::<ServerHandle>();
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], port))
+    }
+
+    fn loopback_server(network: &LoopbackNetwork) -> ServerHandle {
+        let transport = network.bind(address(26760)).unwrap();
+        let server = ServerHandle::new(
+            Server::builder()
+                .build_with_transport(Box::new(transport))
+                .unwrap(),
+        );
+        server.update_controller_info(ControllerInfo {
+            slot: 0,
+            slot_state: SlotState::Connected,
+            ..Default::default()
+        });
+
+        server
+    }
+
+    fn data_request(slot_number: u8) -> Vec<u8> {
+        request(
+            MessageType::ControllerData,
+            MessagePayload::ControllerDataRequest(ControllerDataRequest::SlotNumber(slot_number)),
+        )
+    }
+
+    fn request(message_type: MessageType, payload: MessagePayload) -> Vec<u8> {
+        let message = Message {
+            header: MessageHeader {
+                source: MessageSource::Client,
+                protocol_version: PROTOCOL_VERSION,
+                message_length: 0,
+                checksum: 0,
+                source_id: 1,
+            },
+            message_type,
+            payload,
+        };
+        let mut datagram = vec![];
+        encode_message(&mut datagram, message).unwrap();
+
+        datagram
+    }
+
+    fn request_slot_data(server: &Server, client: SocketAddr, slot_number: u8) {
+        server
+            .handle_datagram(client, &data_request(slot_number))
+            .unwrap();
+    }
+
+    /// Returns packet numbers and data of received controller data messages, in order of arrival.
+    fn received_data(client: &dyn Transport) -> Vec<(u32, ControllerData)> {
+        let mut received = vec![];
+        let mut buf = vec![0; DEFAULT_RECEIVE_BUFFER_SIZE];
+        while let Ok((amount, _)) = client.recv_from(&mut buf) {
+            let message = parse_message(MessageSource::Server, &buf[..amount], true).unwrap();
+            if let MessagePayload::ControllerData {
+                packet_number,
+                controller_data,
+                ..
+            } = message.payload
+            {
+                received.push((packet_number, controller_data));
+            }
+        }
+
+        received
+    }
+
+    #[test]
+    fn concurrent_updates_are_received_in_order() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        server.set_reject_stale_data(true);
+        let client = network.bind(address(1)).unwrap();
+        request_slot_data(&server, address(1), 0);
+
+        let producers: Vec<_> = (0..4)
+            .map(|producer| {
+                let server = server.clone();
+                std::thread::spawn(move || {
+                    for i in 0..100 {
+                        let controller_data = ControllerData {
+                            motion_data_timestamp: i * 4 + producer,
+                            ..Default::default()
+                        };
+                        server.update_controller_data(0, controller_data);
+                    }
+                })
+            })
+            .collect();
+        for producer in producers {
+            producer.join().unwrap();
+        }
+
+        let received = received_data(&client);
+        assert!(!received.is_empty());
+        for pair in received.windows(2) {
+            assert!(pair[0].0 < pair[1].0);
+            assert!(pair[0].1.motion_data_timestamp <= pair[1].1.motion_data_timestamp);
+        }
+        assert_eq!(received.last().unwrap().1, server.controller_data(0));
+    }
+
+    #[test]
+    fn stale_updates_are_dropped_when_rejected() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        server.set_reject_stale_data(true);
+        let client = network.bind(address(1)).unwrap();
+        request_slot_data(&server, address(1), 0);
+
+        for motion_data_timestamp in [10, 5, 20] {
+            server.update_controller_data(
+                0,
+                ControllerData {
+                    motion_data_timestamp,
+                    ..Default::default()
+                },
+            );
+        }
 
+        let timestamps: Vec<_> = received_data(&client)
+            .iter()
+            .map(|(_, controller_data)| controller_data.motion_data_timestamp)
+            .collect();
+        assert_eq!(timestamps, [10, 20]);
+        assert_eq!(server.stats().stale_data_updates, 1);
+    }
+
+    #[test]
+    fn invalid_datagrams_are_reported_as_protocol_errors() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        let datagram = data_request(0);
+
+        let mut corrupted = datagram.clone();
+        *corrupted.last_mut().unwrap() ^= 1;
+        assert!(matches!(
+            server.handle_datagram(address(1), &corrupted),
+            Err(ProtocolError::BadChecksum { .. })
+        ));
+        assert!(matches!(
+            server.handle_datagram(address(1), &datagram[..datagram.len() - 1]),
+            Err(ProtocolError::Truncated)
+        ));
+        assert!(matches!(
+            server.handle_datagram(address(1), b"XXXX"),
+            Err(ProtocolError::BadMagic)
+        ));
+        assert_eq!(server.stats().invalid_packets, 3);
+    }
+
+    #[test]
+    fn client_states_are_bounded_without_client_timeout() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        server.set_client_timeout(None);
+
+        let version_request = request(MessageType::ProtocolVersion, MessagePayload::None);
+        server
+            .handle_datagram(address(1), &version_request)
+            .unwrap();
+        std::thread::sleep(Duration::from_millis(10));
+        for port in 2..=MAX_CLIENT_STATES as u16 + 10 {
+            server
+                .handle_datagram(address(port), &version_request)
+                .unwrap();
+        }
+
+        let client_states = server.client_states.lock().unwrap();
+        assert_eq!(client_states.len(), MAX_CLIENT_STATES);
+        assert!(!client_states.contains_key(&address(1)));
+        assert!(client_states.contains_key(&address(MAX_CLIENT_STATES as u16 + 10)));
+    }
+
+    #[test]
+    fn handle_clones_share_server_across_threads() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+
+        let updater = server.clone();
+        std::thread::spawn(move || {
+            updater.update_controller_data(
+                0,
+                ControllerData {
+                    motion_data_timestamp: 42,
+                    ..Default::default()
+                },
+            );
+        })
+        .join()
+        .unwrap();
+
+        assert_eq!(server.controller_data(0).motion_data_timestamp, 42);
+    }
+
+    #[test]
+    fn handle_wraps_shared_server() {
+        let network = LoopbackNetwork::new();
+        let shared = loopback_server(&network).server().clone();
+
+        let server = ServerHandle::from(shared.clone());
+        assert!(Arc::ptr_eq(server.server(), &shared));
+
+        server.update_controller_info(ControllerInfo {
+            slot: 1,
+            slot_state: SlotState::Connected,
+            ..Default::default()
+        });
+        assert_eq!(shared.controller_info(1).slot_state, SlotState::Connected);
+    }
+
+    #[test]
+    fn send_rate_set_after_spawn_wakes_tick_thread() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        let client = network.bind(address(1)).unwrap();
+        request_slot_data(&server, address(1), 0);
+        let running = server.spawn();
+
+        server.set_send_rate(Some(100.0));
+        let controller_data = ControllerData {
+            motion_data_timestamp: 1,
+            ..Default::default()
+        };
+        server.update_controller_data(0, controller_data);
+
+        let received: Vec<_> = received_data(&client)
+            .into_iter()
+            .map(|(_, controller_data)| controller_data)
+            .collect();
+        assert_eq!(received, vec![controller_data]);
+        running.shutdown().unwrap();
+    }
+
+    #[test]
+    fn slot_indices_cover_extended_slots() {
+        let network = LoopbackNetwork::new();
+        let server = Server::builder()
+            .with_slot_count(MAX_SLOT_COUNT)
+            .build_with_transport(Box::new(network.bind(address(26760)).unwrap()))
+            .unwrap();
+        assert_eq!(server.slots().count(), MAX_SLOT_COUNT as usize);
+        assert_eq!(SlotIndex::new(MAX_SLOT_COUNT - 1), None);
+
+        let last = SlotIndex::new_in(MAX_SLOT_COUNT - 1, server.slot_count()).unwrap();
+        assert_eq!(server.slots().last(), Some(last));
+        assert_eq!(server.slot_info(last).slot, MAX_SLOT_COUNT - 1);
+    }
+
+    #[test]
+    fn checked_updates_reject_invalid_slots() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        let invalid_slot = server.slot_count();
+
+        let error = server
+            .try_update_controller_info(ControllerInfo {
+                slot: invalid_slot,
+                ..Default::default()
+            })
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = server
+            .try_update_controller_data(invalid_slot, Default::default())
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+        let error = server
+            .try_update_motion_samples(invalid_slot, Default::default(), &[])
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+
+        server
+            .try_update_motion_samples(0, Default::default(), &[])
+            .unwrap();
+    }
+
+    #[test]
+    fn lan_addresses_require_exposure() {
+        let address = SocketAddr::from(([0, 0, 0, 0], 0));
+        let error = Server::new(None, Some(address)).err().unwrap();
+        assert_eq!(error.kind(), ErrorKind::PermissionDenied);
+
+        let server = Server::builder()
+            .with_address(address)
+            .with_lan_exposure(true)
+            .build()
+            .unwrap();
+        assert_eq!(
+            server.next_event(),
+            Some(ServerEvent::ExposedToLan(address))
+        );
+    }
+
+    #[test]
+    fn replayed_datagrams_are_rejected_within_window() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        server.set_replay_window(Some(Duration::from_secs(1)));
+
+        request_slot_data(&server, address(1), 0);
+        request_slot_data(&server, address(1), 0);
+        request_slot_data(&server, address(1), 1);
+        // Each client is checked against its own previous datagram.
+        request_slot_data(&server, address(2), 1);
+        assert_eq!(server.stats().rejected_replays, 1);
+
+        server.set_replay_window(None);
+        request_slot_data(&server, address(1), 1);
+        assert_eq!(server.stats().rejected_replays, 1);
+    }
+
+    #[test]
+    fn info_requests_are_throttled_per_client() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        server.set_min_info_interval(Some(Duration::from_secs(1)));
+        let info_request = request(
+            MessageType::ConnectedControllers,
+            MessagePayload::ConnectedControllersRequest {
+                amount: 1,
+                slot_numbers: [0, 0, 0, 0],
+            },
+        );
+
+        assert_eq!(
+            server
+                .handle_datagram(address(1), &info_request)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(server
+            .handle_datagram(address(1), &info_request)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            server
+                .handle_datagram(address(2), &info_request)
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(server.stats().throttled_info_requests, 1);
+    }
+
+    #[test]
+    fn low_priority_clients_are_decimated_under_pressure() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        let low_priority = network.bind(address(1)).unwrap();
+        let high_priority = network.bind(address(2)).unwrap();
+        request_slot_data(&server, address(1), 0);
+        request_slot_data(&server, address(2), 0);
+        server.set_client_priority(address(1), ClientPriority::Low);
+
+        server.set_send_pressure(Some(SendPressure {
+            packets_per_second: 10,
+            low_priority_every: 3,
+        }));
         {
-            let mut slots = self.slots.lock().unwrap();
-            slots[slot_number as usize].controller_data = controller_data;
+            // Pretend previous second was over the limit.
+            let mut send_rate = server.send_rate.lock().unwrap();
+            send_rate.window_start = Instant::now();
+            send_rate.previous_packets = 11;
+        }
+
+        for timestamp in 1..=6 {
+            server.update_controller_data(
+                0,
+                ControllerData {
+                    motion_data_timestamp: timestamp,
+                    ..Default::default()
+                },
+            );
+        }
+
+        let timestamps = |client: &dyn Transport| -> Vec<u64> {
+            received_data(client)
+                .into_iter()
+                .map(|(_, controller_data)| controller_data.motion_data_timestamp)
+                .collect()
+        };
+        let low_priority_timestamps = timestamps(&low_priority);
+        assert_eq!(low_priority_timestamps.len(), 2);
+        assert_eq!(low_priority_timestamps[1] - low_priority_timestamps[0], 3);
+        assert_eq!(timestamps(&high_priority), [1, 2, 3, 4, 5, 6]);
+    }
+
+    #[test]
+    fn rate_limited_slot_sends_latest_update_once_allowed() {
+        let network = LoopbackNetwork::new();
+        let server = loopback_server(&network);
+        let client = network.bind(address(1)).unwrap();
+        request_slot_data(&server, address(1), 0);
+        server.set_slot_rate_limit(0, Some(20.0));
+
+        for timestamp in 1..=3 {
+            server.update_controller_data(
+                0,
+                ControllerData {
+                    motion_data_timestamp: timestamp,
+                    ..Default::default()
+                },
+            );
         }
+        // Response to data request counts as send, so all updates wait for the limit.
+        assert!(received_data(&client).is_empty());
+
+        std::thread::sleep(Duration::from_millis(50));
+        let flushed: Vec<u64> = server
+            .poll_timers()
+            .into_iter()
+            .filter_map(|(_, datagram)| {
+                match parse_message(MessageSource::Server, &datagram, true)
+                    .unwrap()
+                    .payload
+                {
+                    MessagePayload::ControllerData {
+                        controller_data, ..
+                    } => Some(controller_data.motion_data_timestamp),
+                    _ => None,
+                }
+            })
+            .collect();
+        assert_eq!(flushed, [3]);
+    }
+
+    #[test]
+    fn registered_devices_get_free_and_remembered_slots() {
+        let network = LoopbackNetwork::new();
+        let server = ServerHandle::new(
+            Server::builder()
+                .build_with_transport(Box::new(network.bind(address(26760)).unwrap()))
+                .unwrap(),
+        );
+        let device = |mac_address| ControllerInfo {
+            mac_address,
+            device_type: DeviceType::FullGyro,
+            ..Default::default()
+        };
+
+        let first = server.register_device(device(1)).unwrap();
+        let second = server.register_device(device(2)).unwrap();
+        assert_eq!((first.slot_number(), second.slot_number()), (0, 1));
+        assert_eq!(server.controller_info(1).slot_state, SlotState::Connected);
+
+        drop(first);
+        second.unregister();
+        assert_eq!(
+            server.controller_info(1).slot_state,
+            SlotState::NotConnected
+        );
+
+        // Device gets slot it was last connected to, others get the lowest free one.
+        let second = server.register_device(device(2)).unwrap();
+        let third = server.register_device(device(3)).unwrap();
+        assert_eq!((second.slot_number(), third.slot_number()), (1, 0));
+
+        let rest: Vec<SlotHandle> = (4..6)
+            .map(|mac_address| server.register_device(device(mac_address)).unwrap())
+            .collect();
+        assert_eq!(rest.len(), 2);
+        assert!(server.register_device(device(6)).is_none());
+    }
+
+    #[test]
+    fn spawned_server_stops_without_waiting_for_receive_timeout() {
+        let network = LoopbackNetwork::new();
+        let running = loopback_server(&network).spawn();
+        assert!(running.is_running());
 
-        let _ = self.send_controller_data();
+        let start = Instant::now();
+        running.shutdown().unwrap();
+        assert!(start.elapsed() < READ_TIMEOUT);
     }
 }
@@ -0,0 +1,313 @@
+//! Bluetooth LE IMU peripheral source (feature `ble`).
+//!
+//! Connects to peripherals advertising [`IMU_SERVICE_UUID`] and subscribes to notifications of
+//! [`IMU_CHARACTERISTIC_UUID`], each carrying single IMU sample (see [`ImuSample::parse`]),
+//! so homemade motion controllers can be served without custom firmware protocols.
+
+use crate::protocol::*;
+use crate::source::*;
+use btleplug::api::{Central, Manager as _, Peripheral as _, ScanFilter};
+use btleplug::platform::{Adapter, Manager, Peripheral};
+use futures::StreamExt;
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Mutex;
+use tokio::runtime::Runtime;
+use uuid::Uuid;
+
+/// Service advertised by IMU peripherals.
+pub const IMU_SERVICE_UUID: Uuid = Uuid::from_u128(0x6d6f7469_6f6e_4000_8000_000000000001);
+
+/// Characteristic notifying IMU samples.
+pub const IMU_CHARACTERISTIC_UUID: Uuid = Uuid::from_u128(0x6d6f7469_6f6e_4000_8000_000000000002);
+
+/// Time between checks for new peripherals.
+const SCAN_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Single accelerometer and gyroscope reading.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct ImuSample {
+    /// Acceleration (X, Y, Z) in g.
+    pub accelerometer: [f32; 3],
+    /// Angular velocity (pitch, yaw, roll) in degrees per second.
+    pub gyroscope: [f32; 3],
+    /// Peripheral's sample time in microseconds (wraps around).
+    pub timestamp: Option<u32>,
+}
+
+impl ImuSample {
+    /// Parses notification value: six little-endian `f32` values (accelerometer X, Y, Z, then gyroscope pitch, yaw, roll)
+    /// optionally followed by little-endian `u32` timestamp in microseconds.
+    ///
+    /// Returns `None` if value is too short.
+    pub fn parse(bytes: &[u8]) -> Option<ImuSample> {
+        if bytes.len() < 24 {
+            return None;
+        }
+
+        let float = |index: usize| {
+            let start = index * 4;
+            f32::from_le_bytes([
+                bytes[start],
+                bytes[start + 1],
+                bytes[start + 2],
+                bytes[start + 3],
+            ])
+        };
+
+        Some(ImuSample {
+            accelerometer: [float(0), float(1), float(2)],
+            gyroscope: [float(3), float(4), float(5)],
+            timestamp: bytes
+                .get(24..28)
+                .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])),
+        })
+    }
+}
+
+struct BleDevice {
+    /// `None` while connecting.
+    descriptor: Option<DeviceDescriptor>,
+    latest: Option<ControllerData>,
+}
+
+type Devices = Arc<Mutex<HashMap<String, BleDevice>>>;
+
+fn to_io_error(error: btleplug::Error) -> Error {
+    Error::other(error.to_string())
+}
+
+/// Input source providing connected BLE IMU peripherals.
+///
+/// Scanning, connecting and receiving notifications happens on background thread, stopped when source is dropped.
+pub struct BleSource {
+    devices: Devices,
+    countinue_running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl BleSource {
+    /// Starts scanning for peripherals using default service and characteristic UUIDs.
+    pub fn new() -> Result<BleSource> {
+        BleSource::with_uuids(IMU_SERVICE_UUID, IMU_CHARACTERISTIC_UUID)
+    }
+
+    /// Starts scanning for peripherals using first Bluetooth adapter.
+    ///
+    /// # Arguments
+    ///
+    /// * `service` - UUID of service advertised by peripherals.
+    /// * `characteristic` - UUID of characteristic notifying IMU samples.
+    pub fn with_uuids(service: Uuid, characteristic: Uuid) -> Result<BleSource> {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+
+        let adapter = runtime.block_on(async {
+            let manager = Manager::new().await.map_err(to_io_error)?;
+            let adapter = manager
+                .adapters()
+                .await
+                .map_err(to_io_error)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, "No Bluetooth adapter"))?;
+            adapter
+                .start_scan(ScanFilter {
+                    services: vec![service],
+                })
+                .await
+                .map_err(to_io_error)?;
+
+            Ok::<Adapter, Error>(adapter)
+        })?;
+
+        let devices: Devices = Arc::new(Mutex::new(HashMap::new()));
+        let countinue_running = Arc::new(AtomicBool::new(true));
+        let thread = {
+            let devices = devices.clone();
+            let countinue_running = countinue_running.clone();
+            std::thread::spawn(move || {
+                run(
+                    runtime,
+                    adapter,
+                    service,
+                    characteristic,
+                    devices,
+                    countinue_running,
+                )
+            })
+        };
+
+        Ok(BleSource {
+            devices,
+            countinue_running,
+            thread: Some(thread),
+        })
+    }
+}
+
+impl Drop for BleSource {
+    fn drop(&mut self) {
+        self.countinue_running.store(false, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run(
+    runtime: Runtime,
+    adapter: Adapter,
+    service: Uuid,
+    characteristic: Uuid,
+    devices: Devices,
+    countinue_running: Arc<AtomicBool>,
+) {
+    runtime.block_on(async {
+        while countinue_running.load(Ordering::SeqCst) {
+            if let Ok(peripherals) = adapter.peripherals().await {
+                for peripheral in peripherals {
+                    let id = peripheral.id().to_string();
+                    if devices.lock().unwrap().contains_key(&id) {
+                        continue;
+                    }
+
+                    let advertises_service = matches!(
+                        peripheral.properties().await,
+                        Ok(Some(properties)) if properties.services.contains(&service)
+                    );
+                    if advertises_service {
+                        devices.lock().unwrap().insert(
+                            id.clone(),
+                            BleDevice {
+                                descriptor: None,
+                                latest: None,
+                            },
+                        );
+                        tokio::spawn(serve_peripheral(
+                            id,
+                            peripheral,
+                            characteristic,
+                            devices.clone(),
+                        ));
+                    }
+                }
+            }
+
+            tokio::time::sleep(SCAN_INTERVAL).await;
+        }
+
+        let _ = adapter.stop_scan().await;
+    });
+}
+
+/// Connects to peripheral and receives its samples until it disconnects.
+async fn serve_peripheral(
+    id: String,
+    peripheral: Peripheral,
+    characteristic: Uuid,
+    devices: Devices,
+) {
+    let _ = receive_samples(&id, &peripheral, characteristic, &devices).await;
+
+    devices.lock().unwrap().remove(&id);
+    let _ = peripheral.disconnect().await;
+}
+
+async fn receive_samples(
+    id: &str,
+    peripheral: &Peripheral,
+    characteristic: Uuid,
+    devices: &Devices,
+) -> btleplug::Result<()> {
+    peripheral.connect().await?;
+    peripheral.discover_services().await?;
+
+    let characteristic = peripheral
+        .characteristics()
+        .into_iter()
+        .find(|candidate| candidate.uuid == characteristic)
+        .ok_or(btleplug::Error::NotSupported(
+            "IMU characteristic".to_string(),
+        ))?;
+    peripheral.subscribe(&characteristic).await?;
+    let mut notifications = peripheral.notifications().await?;
+
+    let properties = peripheral.properties().await?.unwrap_or_default();
+    if let Some(device) = devices.lock().unwrap().get_mut(id) {
+        device.descriptor = Some(DeviceDescriptor {
+            id: id.to_string(),
+            name: properties
+                .local_name
+                .unwrap_or_else(|| "BLE IMU".to_string()),
+            mac_address: u64::from(properties.address),
+            device_type: DeviceType::FullGyro,
+            connection_type: ConnectionType::Bluetooth,
+            battery_status: BatteryStatus::NotApplicable,
+        });
+    }
+
+    let start = Instant::now();
+    // Peripheral's wrapping 32-bit timestamp extended to 64 bits.
+    let mut timestamp: Option<(u32, u64)> = None;
+    while let Some(notification) = notifications.next().await {
+        if notification.uuid != characteristic.uuid {
+            continue;
+        }
+        let sample = match ImuSample::parse(&notification.value) {
+            Some(sample) => sample,
+            None => continue,
+        };
+
+        let motion_data_timestamp = match sample.timestamp {
+            Some(raw) => {
+                let extended = match timestamp {
+                    Some((previous_raw, previous)) => {
+                        previous + u64::from(raw.wrapping_sub(previous_raw))
+                    }
+                    None => u64::from(raw),
+                };
+                timestamp = Some((raw, extended));
+                extended
+            }
+            None => start.elapsed().as_micros() as u64,
+        };
+
+        let controller_data = ControllerData {
+            connected: true,
+            motion_data_timestamp,
+            accelerometer_x: sample.accelerometer[0],
+            accelerometer_y: sample.accelerometer[1],
+            accelerometer_z: sample.accelerometer[2],
+            gyroscope_pitch: sample.gyroscope[0],
+            gyroscope_yaw: sample.gyroscope[1],
+            gyroscope_roll: sample.gyroscope[2],
+            ..Default::default()
+        };
+        if let Some(device) = devices.lock().unwrap().get_mut(id) {
+            device.latest = Some(controller_data);
+        }
+    }
+
+    Ok(())
+}
+
+impl InputSource for BleSource {
+    fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+        self.devices
+            .lock()
+            .unwrap()
+            .values()
+            .filter_map(|device| device.descriptor.clone())
+            .collect()
+    }
+
+    fn poll(&mut self, device_id: &str) -> Option<ControllerData> {
+        self.devices
+            .lock()
+            .unwrap()
+            .get_mut(device_id)?
+            .latest
+            .take()
+    }
+}
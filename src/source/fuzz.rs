@@ -0,0 +1,210 @@
+//! Source of pathological, but protocol-valid inputs for hardening protocol consumers (e.g. emulators).
+
+use crate::buttons::Button;
+use crate::protocol::*;
+use crate::source::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Classes of pathological input, each can be toggled separately.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct FuzzSettings {
+    /// All buttons flip with every sample.
+    pub button_flips: bool,
+    /// Motion values are NaN, infinite, subnormal or extremely large.
+    pub non_finite_motion: bool,
+    /// Timestamps jump backwards, stall, hit zero and `u64::MAX`.
+    pub extreme_timestamps: bool,
+    /// Sticks and analog buttons jump between extremes.
+    pub extreme_analogs: bool,
+    /// Touches appear and disappear at random with changing IDs and out-of-range coordinates.
+    pub touch_churn: bool,
+}
+
+impl FuzzSettings {
+    /// All pathology classes enabled.
+    pub const ALL: FuzzSettings = FuzzSettings {
+        button_flips: true,
+        non_finite_motion: true,
+        extreme_timestamps: true,
+        extreme_analogs: true,
+        touch_churn: true,
+    };
+
+    /// All pathology classes disabled, producing well-behaved samples.
+    pub const NONE: FuzzSettings = FuzzSettings {
+        button_flips: false,
+        non_finite_motion: false,
+        extreme_timestamps: false,
+        extreme_analogs: false,
+        touch_churn: false,
+    };
+}
+
+impl Default for FuzzSettings {
+    fn default() -> FuzzSettings {
+        FuzzSettings::ALL
+    }
+}
+
+const PATHOLOGICAL_FLOATS: [f32; 9] = [
+    f32::NAN,
+    f32::INFINITY,
+    f32::NEG_INFINITY,
+    f32::MAX,
+    f32::MIN,
+    f32::MIN_POSITIVE,
+    -0.0,
+    1.0e-45,
+    1.0e30,
+];
+
+const FUZZ_DEVICE_ID: &str = "fuzz";
+
+/// Input source providing single device producing new pathological sample with every poll.
+///
+/// Samples are generated from seeded random generator, so the same seed and settings produce the same sequence.
+pub struct FuzzSource {
+    settings: FuzzSettings,
+    rng: StdRng,
+    timestamp: u64,
+    buttons_pressed: bool,
+}
+
+impl FuzzSource {
+    /// Creates new fuzz source.
+    ///
+    /// # Arguments
+    ///
+    /// * `settings` - enabled pathology classes.
+    /// * `seed` - random generator seed.
+    pub fn new(settings: FuzzSettings, seed: u64) -> FuzzSource {
+        FuzzSource {
+            settings,
+            rng: StdRng::seed_from_u64(seed),
+            timestamp: 0,
+            buttons_pressed: false,
+        }
+    }
+
+    pub fn settings(&self) -> FuzzSettings {
+        self.settings
+    }
+
+    /// Changes enabled pathology classes, takes effect with the next sample.
+    pub fn set_settings(&mut self, settings: FuzzSettings) {
+        self.settings = settings;
+    }
+
+    fn motion_value(&mut self, normal: f32) -> f32 {
+        if self.settings.non_finite_motion && self.rng.gen_bool(0.5) {
+            PATHOLOGICAL_FLOATS[self.rng.gen_range(0..PATHOLOGICAL_FLOATS.len())]
+        } else {
+            normal
+        }
+    }
+
+    fn next_timestamp(&mut self) -> u64 {
+        // Regular 4 ms steps.
+        let regular = self.timestamp.wrapping_add(4000);
+        self.timestamp = if self.settings.extreme_timestamps {
+            match self.rng.gen_range(0..6) {
+                0 => 0,
+                1 => u64::MAX,
+                2 => self.timestamp,
+                3 => self
+                    .timestamp
+                    .saturating_sub(self.rng.gen_range(1..1_000_000)),
+                4 => self
+                    .timestamp
+                    .wrapping_add(self.rng.gen_range(1..u64::MAX / 2)),
+                _ => regular,
+            }
+        } else {
+            regular
+        };
+
+        self.timestamp
+    }
+
+    fn analog_value(&mut self) -> u8 {
+        if self.rng.gen_bool(0.5) {
+            0
+        } else {
+            255
+        }
+    }
+
+    fn touch(&mut self) -> TouchData {
+        TouchData {
+            active: self.rng.gen_bool(0.5),
+            id: self.rng.gen(),
+            position_x: self.rng.gen(),
+            position_y: self.rng.gen(),
+        }
+    }
+
+    /// Generates next sample.
+    pub fn sample(&mut self) -> ControllerData {
+        let mut controller_data = ControllerData {
+            connected: true,
+            left_stick_x: 128,
+            left_stick_y: 128,
+            right_stick_x: 128,
+            right_stick_y: 128,
+            ..Default::default()
+        };
+
+        controller_data.motion_data_timestamp = self.next_timestamp();
+        controller_data.accelerometer_x = self.motion_value(0.0);
+        controller_data.accelerometer_y = self.motion_value(0.0);
+        controller_data.accelerometer_z = self.motion_value(1.0);
+        controller_data.gyroscope_pitch = self.motion_value(0.0);
+        controller_data.gyroscope_yaw = self.motion_value(0.0);
+        controller_data.gyroscope_roll = self.motion_value(0.0);
+
+        if self.settings.button_flips {
+            self.buttons_pressed = !self.buttons_pressed;
+            for button in Button::DIGITAL.iter() {
+                button.set(&mut controller_data, self.buttons_pressed);
+            }
+        }
+
+        if self.settings.extreme_analogs {
+            controller_data.left_stick_x = self.analog_value();
+            controller_data.left_stick_y = self.analog_value();
+            controller_data.right_stick_x = self.analog_value();
+            controller_data.right_stick_y = self.analog_value();
+            controller_data.analog_r2 = self.analog_value();
+            controller_data.analog_l2 = self.analog_value();
+        }
+
+        if self.settings.touch_churn {
+            controller_data.first_touch = self.touch();
+            controller_data.second_touch = self.touch();
+        }
+
+        controller_data
+    }
+}
+
+impl InputSource for FuzzSource {
+    fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+        vec![DeviceDescriptor {
+            id: FUZZ_DEVICE_ID.to_string(),
+            name: "Fuzz".to_string(),
+            mac_address: 0x00_00_46_55_5A_5A,
+            device_type: DeviceType::FullGyro,
+            connection_type: ConnectionType::NotApplicable,
+            battery_status: BatteryStatus::NotApplicable,
+        }]
+    }
+
+    fn poll(&mut self, device_id: &str) -> Option<ControllerData> {
+        if device_id != FUZZ_DEVICE_ID {
+            return None;
+        }
+
+        Some(self.sample())
+    }
+}
@@ -0,0 +1,130 @@
+//! Raw HID passthrough source (feature `hid`).
+//!
+//! Reads input reports of HID devices and converts them into controller data with user supplied decoder,
+//! so niche controllers can be served without dealing with sockets or slots.
+
+use crate::protocol::*;
+use crate::source::*;
+use hidapi::{BusType, HidApi, HidDevice};
+use std::io::{Error, Result};
+
+/// Largest input report read from devices.
+const MAX_REPORT_SIZE: usize = 1024;
+
+type Decoder = Box<dyn Fn(&[u8]) -> Option<ControllerData> + Send>;
+
+fn to_io_error(error: hidapi::HidError) -> Error {
+    Error::other(error.to_string())
+}
+
+/// Input source reading devices with given vendor and product ID.
+pub struct HidSource {
+    api: HidApi,
+    vendor_id: u16,
+    product_id: u16,
+    device_type: DeviceType,
+    decoder: Decoder,
+    devices: HashMap<String, HidDevice>,
+}
+
+impl HidSource {
+    /// Creates new HID source.
+    ///
+    /// # Arguments
+    ///
+    /// * `vendor_id` - USB vendor ID of devices.
+    /// * `product_id` - USB product ID of devices.
+    /// * `decoder` - converts input report into controller data, returns `None` for reports that should be ignored.
+    pub fn new<F>(vendor_id: u16, product_id: u16, decoder: F) -> Result<HidSource>
+    where
+        F: Fn(&[u8]) -> Option<ControllerData> + Send + 'static,
+    {
+        Ok(HidSource {
+            api: HidApi::new().map_err(to_io_error)?,
+            vendor_id,
+            product_id,
+            device_type: DeviceType::FullGyro,
+            decoder: Box::new(decoder),
+            devices: HashMap::new(),
+        })
+    }
+
+    /// Sets device type reported for devices (`DeviceType::FullGyro` by default).
+    pub fn set_device_type(&mut self, device_type: DeviceType) {
+        self.device_type = device_type;
+    }
+}
+
+impl InputSource for HidSource {
+    fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+        self.api.reset_devices().ok();
+        if self
+            .api
+            .add_devices(self.vendor_id, self.product_id)
+            .is_err()
+        {
+            return vec![];
+        }
+
+        let mut descriptors = vec![];
+        for device_info in self.api.device_list() {
+            let id = device_info.path().to_string_lossy().into_owned();
+            if !self.devices.contains_key(&id) {
+                let device = match self.api.open_path(device_info.path()) {
+                    Ok(device) => device,
+                    Err(_) => continue,
+                };
+                if device.set_blocking_mode(false).is_err() {
+                    continue;
+                }
+                self.devices.insert(id.clone(), device);
+            }
+
+            descriptors.push(DeviceDescriptor {
+                id,
+                name: device_info
+                    .product_string()
+                    .unwrap_or("HID device")
+                    .to_string(),
+                mac_address: 0,
+                device_type: self.device_type,
+                connection_type: match device_info.bus_type() {
+                    BusType::Usb => ConnectionType::USB,
+                    BusType::Bluetooth => ConnectionType::Bluetooth,
+                    _ => ConnectionType::NotApplicable,
+                },
+                battery_status: BatteryStatus::NotApplicable,
+            });
+        }
+
+        self.devices
+            .retain(|id, _| descriptors.iter().any(|descriptor| &descriptor.id == id));
+
+        descriptors
+    }
+
+    fn poll(&mut self, device_id: &str) -> Option<ControllerData> {
+        let device = self.devices.get(device_id)?;
+
+        // Drain all pending reports, latest decoded one wins.
+        let mut buffer = [0u8; MAX_REPORT_SIZE];
+        let mut controller_data = None;
+        loop {
+            match device.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(amount) => {
+                    if let Some(decoded) = (self.decoder)(&buffer[..amount]) {
+                        controller_data = Some(decoded);
+                    }
+                }
+                Err(_) => {
+                    // Device is most likely gone, next scan will release its slot.
+                    self.devices.remove(device_id);
+                    break;
+                }
+            }
+        }
+
+        controller_data
+    }
+}
@@ -0,0 +1,270 @@
+//! Input sources providing controller data from physical (or virtual) devices.
+
+#[cfg(feature = "ble")]
+pub mod ble;
+pub mod fuzz;
+#[cfg(feature = "hid")]
+pub mod hid;
+pub mod mouse;
+#[cfg(feature = "serial")]
+pub mod serial;
+pub mod test_pattern;
+
+use crate::protocol::*;
+use crate::server::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Device available from an input source.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DeviceDescriptor {
+    /// Identifier unique within source (e.g. HID path or evdev node).
+    pub id: String,
+    pub name: String,
+    pub mac_address: u64,
+    pub device_type: DeviceType,
+    pub connection_type: ConnectionType,
+    pub battery_status: BatteryStatus,
+}
+
+/// Provider of devices and their controller data (backed by hidapi, evdev, a socket, ...).
+pub trait InputSource: Send {
+    /// Lists devices currently available.
+    fn enumerate(&mut self) -> Vec<DeviceDescriptor>;
+
+    /// Reads latest controller data of given device, returns `None` if there is no new data.
+    fn poll(&mut self, device_id: &str) -> Option<ControllerData>;
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum HotplugEvent {
+    /// Device appeared and was assigned a slot.
+    Connected {
+        source: usize,
+        device: DeviceDescriptor,
+        slot_number: u8,
+    },
+    /// Device disappeared and its slot was released.
+    Disconnected {
+        source: usize,
+        device_id: String,
+        slot_number: u8,
+    },
+    /// Device appeared, but all slots are taken.
+    NoFreeSlot {
+        source: usize,
+        device: DeviceDescriptor,
+    },
+}
+
+type HotplugCallback = Box<dyn FnMut(&HotplugEvent) + Send>;
+
+/// Ties devices of input sources to server slots.
+///
+/// Each scan enumerates all sources, registers new devices with `Server::register_device`
+/// and releases slots of devices that disappeared (marking them as not connected for clients).
+pub struct SourceManager {
+    server: Arc<Server>,
+    sources: Vec<Box<dyn InputSource>>,
+    assignments: HashMap<(usize, String), SlotHandle>,
+    hotplug_callback: Option<HotplugCallback>,
+}
+
+impl SourceManager {
+    /// Creates new manager updating given server.
+    pub fn new(server: Arc<Server>) -> SourceManager {
+        SourceManager {
+            server,
+            sources: vec![],
+            assignments: HashMap::new(),
+            hotplug_callback: None,
+        }
+    }
+
+    /// Adds input source, returns its index.
+    pub fn add_source<S: InputSource + 'static>(&mut self, source: S) -> usize {
+        self.sources.push(Box::new(source));
+
+        self.sources.len() - 1
+    }
+
+    /// Sets function called with every hotplug event.
+    pub fn set_hotplug_callback<F: FnMut(&HotplugEvent) + Send + 'static>(&mut self, callback: F) {
+        self.hotplug_callback = Some(Box::new(callback));
+    }
+
+    /// Returns slot assigned to given device.
+    pub fn slot_of(&self, source: usize, device_id: &str) -> Option<u8> {
+        self.assignments
+            .get(&(source, device_id.to_string()))
+            .map(SlotHandle::slot_number)
+    }
+
+    /// Enumerates devices of all sources, assigning and releasing slots.
+    pub fn scan(&mut self) -> Vec<HotplugEvent> {
+        let mut events = vec![];
+
+        for source in 0..self.sources.len() {
+            let devices = self.sources[source].enumerate();
+
+            let removed: Vec<(String, u8)> = self
+                .assignments
+                .iter()
+                .filter(|((assigned_source, device_id), _)| {
+                    *assigned_source == source
+                        && !devices.iter().any(|device| &device.id == device_id)
+                })
+                .map(|((_, device_id), slot)| (device_id.clone(), slot.slot_number()))
+                .collect();
+            for (device_id, slot_number) in removed {
+                // Dropping handle frees slot and reports it as not connected.
+                self.assignments.remove(&(source, device_id.clone()));
+
+                events.push(HotplugEvent::Disconnected {
+                    source,
+                    device_id,
+                    slot_number,
+                });
+            }
+
+            for device in devices {
+                if self.slot_of(source, &device.id).is_some() {
+                    continue;
+                }
+
+                let slot = self.server.register_device(ControllerInfo {
+                    device_type: device.device_type,
+                    connection_type: device.connection_type,
+                    mac_address: device.mac_address,
+                    battery_status: device.battery_status,
+                    ..Default::default()
+                });
+                match slot {
+                    Some(slot) => {
+                        let slot_number = slot.slot_number();
+                        self.assignments.insert((source, device.id.clone()), slot);
+
+                        events.push(HotplugEvent::Connected {
+                            source,
+                            device,
+                            slot_number,
+                        });
+                    }
+                    None => events.push(HotplugEvent::NoFreeSlot { source, device }),
+                }
+            }
+        }
+
+        if let Some(callback) = &mut self.hotplug_callback {
+            for event in events.iter() {
+                callback(event);
+            }
+        }
+
+        events
+    }
+
+    /// Polls all assigned devices and sends their new data to server.
+    pub fn poll(&mut self) {
+        for ((source, device_id), slot) in self.assignments.iter() {
+            if let Some(controller_data) = self.sources[*source].poll(device_id) {
+                slot.update_controller_data(controller_data);
+            }
+        }
+    }
+
+    /// Starts background thread polling devices and periodically scanning for hotplugged ones.
+    ///
+    /// # Arguments
+    ///
+    /// * `poll_interval` - time between polls.
+    /// * `scan_interval` - time between scans.
+    /// * `countinue_running` - thread stops when this is set to `false`.
+    pub fn start(
+        mut self,
+        poll_interval: Duration,
+        scan_interval: Duration,
+        countinue_running: Arc<AtomicBool>,
+    ) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let mut last_scan: Option<Instant> = None;
+            while countinue_running.load(Ordering::SeqCst) {
+                if last_scan.is_none_or(|time| time.elapsed() >= scan_interval) {
+                    self.scan();
+                    last_scan = Some(Instant::now());
+                }
+
+                self.poll();
+                std::thread::sleep(poll_interval);
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::LoopbackNetwork;
+    use std::net::SocketAddr;
+
+    struct FixedDevices(Vec<DeviceDescriptor>);
+
+    impl InputSource for FixedDevices {
+        fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+            self.0.clone()
+        }
+
+        fn poll(&mut self, _device_id: &str) -> Option<ControllerData> {
+            None
+        }
+    }
+
+    fn device(id: &str) -> DeviceDescriptor {
+        DeviceDescriptor {
+            id: id.to_string(),
+            name: id.to_string(),
+            mac_address: 0,
+            device_type: DeviceType::FullGyro,
+            connection_type: ConnectionType::USB,
+            battery_status: BatteryStatus::Full,
+        }
+    }
+
+    #[test]
+    fn devices_share_slots_with_registered_devices() {
+        let network = LoopbackNetwork::new();
+        let server = Arc::new(
+            Server::builder()
+                .build_with_transport(Box::new(
+                    network
+                        .bind(SocketAddr::from(([10, 0, 0, 1], 26760)))
+                        .unwrap(),
+                ))
+                .unwrap(),
+        );
+        let registered = server.register_device(Default::default()).unwrap();
+        assert_eq!(registered.slot_number(), 0);
+
+        let mut manager = SourceManager::new(server.clone());
+        manager.add_source(FixedDevices(vec![device("a")]));
+        manager.scan();
+        assert_eq!(manager.slot_of(0, "a"), Some(1));
+
+        // Slot released by source manager is free for other devices again.
+        manager.sources[0] = Box::new(FixedDevices(vec![]));
+        manager.scan();
+        assert_eq!(
+            server.controller_info(1).slot_state,
+            SlotState::NotConnected
+        );
+        assert_eq!(
+            server
+                .register_device(Default::default())
+                .map(|slot| slot.slot_number()),
+            Some(1)
+        );
+    }
+}
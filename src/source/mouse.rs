@@ -0,0 +1,345 @@
+//! Mouse emulating controller gyroscope.
+//!
+//! [`MouseToGyro`] converts mouse movement into gyroscope rates on any platform,
+//! [`MouseSource`] (feature `mouse`) reads mice with Raw Input on Windows and evdev on Linux.
+
+use crate::protocol::*;
+use std::time::Instant;
+
+/// Gyroscope axis mouse wheel rotates around.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum WheelAxis {
+    #[default]
+    Yaw,
+    Pitch,
+    Roll,
+    /// Wheel is ignored.
+    Disabled,
+}
+
+/// Settings of mouse to gyroscope conversion.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MouseGyroSettings {
+    /// Degrees of rotation per mouse count.
+    pub sensitivity: f32,
+    /// Degrees of rotation per wheel notch.
+    pub wheel_sensitivity: f32,
+    /// Horizontal movement rolls the other way.
+    pub invert_x: bool,
+    /// Vertical movement pitches the other way.
+    pub invert_y: bool,
+    pub wheel_axis: WheelAxis,
+}
+
+impl Default for MouseGyroSettings {
+    fn default() -> MouseGyroSettings {
+        MouseGyroSettings {
+            sensitivity: 0.1,
+            wheel_sensitivity: 3.0,
+            invert_x: false,
+            invert_y: false,
+            wheel_axis: WheelAxis::Yaw,
+        }
+    }
+}
+
+/// Shortest interval rates are computed over, prevents spikes when polled in rapid succession.
+const MIN_INTERVAL: f32 = 0.001;
+
+fn inverter(invert: bool) -> f32 {
+    if invert {
+        -1.0
+    } else {
+        1.0
+    }
+}
+
+/// Accumulates mouse movement and converts it into gyroscope rates.
+///
+/// Rates are computed from movement accumulated since the previous sample divided by time elapsed,
+/// so rotation angle doesn't depend on how often samples are taken.
+#[derive(Clone, Debug)]
+pub struct MouseToGyro {
+    pub settings: MouseGyroSettings,
+    epoch: Instant,
+    previous_sample: Instant,
+    delta_x: f32,
+    delta_y: f32,
+    delta_wheel: f32,
+    moving: bool,
+}
+
+impl Default for MouseToGyro {
+    fn default() -> MouseToGyro {
+        MouseToGyro::new(Default::default())
+    }
+}
+
+impl MouseToGyro {
+    pub fn new(settings: MouseGyroSettings) -> MouseToGyro {
+        let now = Instant::now();
+
+        MouseToGyro {
+            settings,
+            epoch: now,
+            previous_sample: now,
+            delta_x: 0.0,
+            delta_y: 0.0,
+            delta_wheel: 0.0,
+            moving: false,
+        }
+    }
+
+    /// Adds mouse movement in counts.
+    pub fn add_movement(&mut self, delta_x: f32, delta_y: f32) {
+        self.delta_x += delta_x;
+        self.delta_y += delta_y;
+    }
+
+    /// Adds wheel rotation in notches.
+    pub fn add_wheel(&mut self, delta: f32) {
+        self.delta_wheel += delta;
+    }
+
+    /// Writes rates of movement accumulated since previous sample into gyroscope of controller data and resets it.
+    ///
+    /// Motion timestamp is set to sample time (in microseconds since converter was created).
+    /// Returns `false` if mouse didn't move since either this or previous sample (rates are zero and unchanged).
+    ///
+    /// # Arguments
+    ///
+    /// * `controller_data` - controller data to update.
+    /// * `now` - sample time.
+    pub fn sample(&mut self, controller_data: &mut ControllerData, now: Instant) -> bool {
+        let elapsed = now
+            .saturating_duration_since(self.previous_sample)
+            .as_secs_f32()
+            .max(MIN_INTERVAL);
+        self.previous_sample = now;
+
+        let settings = &self.settings;
+        let roll = self.delta_x * settings.sensitivity * inverter(settings.invert_x) / elapsed;
+        let pitch = -self.delta_y * settings.sensitivity * inverter(settings.invert_y) / elapsed;
+        let wheel = self.delta_wheel * settings.wheel_sensitivity / elapsed;
+
+        let moving = self.delta_x != 0.0 || self.delta_y != 0.0 || self.delta_wheel != 0.0;
+        let changed = moving || self.moving;
+        self.moving = moving;
+        self.delta_x = 0.0;
+        self.delta_y = 0.0;
+        self.delta_wheel = 0.0;
+
+        controller_data.motion_data_timestamp =
+            now.saturating_duration_since(self.epoch).as_micros() as u64;
+        controller_data.gyroscope_pitch = pitch;
+        controller_data.gyroscope_yaw = 0.0;
+        controller_data.gyroscope_roll = roll;
+        match settings.wheel_axis {
+            WheelAxis::Yaw => controller_data.gyroscope_yaw += wheel,
+            WheelAxis::Pitch => controller_data.gyroscope_pitch += wheel,
+            WheelAxis::Roll => controller_data.gyroscope_roll += wheel,
+            WheelAxis::Disabled => {}
+        }
+
+        changed
+    }
+}
+
+#[cfg(feature = "mouse")]
+pub use self::platform::MouseSource;
+
+#[cfg(all(feature = "mouse", target_os = "linux"))]
+mod platform {
+    use super::*;
+    use crate::source::*;
+    use evdev::{Device, InputEventKind, RelativeAxisType};
+    use std::collections::HashMap;
+    use std::os::unix::io::AsRawFd;
+
+    struct Mouse {
+        device: Device,
+        converter: MouseToGyro,
+    }
+
+    /// Input source providing mice (evdev devices with relative X and Y axes) as gyroscope-only controllers.
+    ///
+    /// Reading `/dev/input/event*` nodes usually requires user to be in `input` group.
+    pub struct MouseSource {
+        settings: MouseGyroSettings,
+        mice: HashMap<String, Mouse>,
+    }
+
+    impl MouseSource {
+        pub fn new(settings: MouseGyroSettings) -> MouseSource {
+            MouseSource {
+                settings,
+                mice: HashMap::new(),
+            }
+        }
+    }
+
+    fn is_mouse(device: &Device) -> bool {
+        device.supported_relative_axes().is_some_and(|axes| {
+            axes.contains(RelativeAxisType::REL_X) && axes.contains(RelativeAxisType::REL_Y)
+        })
+    }
+
+    fn set_nonblocking(device: &Device) -> bool {
+        let fd = device.as_raw_fd();
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            flags >= 0 && libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) >= 0
+        }
+    }
+
+    impl InputSource for MouseSource {
+        fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+            let mut devices = vec![];
+            let mut present = vec![];
+            for (path, device) in evdev::enumerate() {
+                if !is_mouse(&device) {
+                    continue;
+                }
+
+                let id = path.to_string_lossy().into_owned();
+                let name = device.name().unwrap_or("Mouse").to_string();
+                present.push(id.clone());
+                if !self.mice.contains_key(&id) {
+                    if !set_nonblocking(&device) {
+                        continue;
+                    }
+                    self.mice.insert(
+                        id.clone(),
+                        Mouse {
+                            device,
+                            converter: MouseToGyro::new(self.settings),
+                        },
+                    );
+                }
+
+                devices.push(DeviceDescriptor {
+                    id,
+                    name,
+                    mac_address: 0,
+                    device_type: DeviceType::FullGyro,
+                    connection_type: ConnectionType::USB,
+                    battery_status: BatteryStatus::NotApplicable,
+                });
+            }
+            self.mice.retain(|id, _| present.contains(id));
+
+            devices
+        }
+
+        fn poll(&mut self, device_id: &str) -> Option<ControllerData> {
+            let mouse = self.mice.get_mut(device_id)?;
+            if let Ok(events) = mouse.device.fetch_events() {
+                for event in events {
+                    match event.kind() {
+                        InputEventKind::RelAxis(RelativeAxisType::REL_X) => {
+                            mouse.converter.add_movement(event.value() as f32, 0.0)
+                        }
+                        InputEventKind::RelAxis(RelativeAxisType::REL_Y) => {
+                            mouse.converter.add_movement(0.0, event.value() as f32)
+                        }
+                        InputEventKind::RelAxis(RelativeAxisType::REL_WHEEL) => {
+                            mouse.converter.add_wheel(event.value() as f32)
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            let mut controller_data = ControllerData {
+                connected: true,
+                ..Default::default()
+            };
+            if mouse.converter.sample(&mut controller_data, Instant::now()) {
+                Some(controller_data)
+            } else {
+                None
+            }
+        }
+    }
+}
+
+#[cfg(all(feature = "mouse", windows))]
+mod platform {
+    use super::*;
+    use crate::source::*;
+    use multiinput::{RawEvent, RawInputManager};
+
+    /// Input source providing mice (read with Raw Input) as gyroscope-only controllers.
+    pub struct MouseSource {
+        settings: MouseGyroSettings,
+        manager: RawInputManager,
+        converters: Vec<MouseToGyro>,
+    }
+
+    impl MouseSource {
+        pub fn new(settings: MouseGyroSettings) -> MouseSource {
+            let mut manager = RawInputManager::new().unwrap();
+            manager.register_devices(multiinput::DeviceType::Mice);
+
+            MouseSource {
+                settings,
+                manager,
+                converters: vec![],
+            }
+        }
+
+        fn pump_events(&mut self) {
+            while let Some(event) = self.manager.get_event() {
+                match event {
+                    RawEvent::MouseMoveEvent(mouse_id, delta_x, delta_y) => {
+                        if let Some(converter) = self.converters.get_mut(mouse_id) {
+                            converter.add_movement(delta_x as f32, delta_y as f32);
+                        }
+                    }
+                    RawEvent::MouseWheelEvent(mouse_id, delta) => {
+                        if let Some(converter) = self.converters.get_mut(mouse_id) {
+                            converter.add_wheel(delta);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    impl InputSource for MouseSource {
+        fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+            let mice = self.manager.get_device_list().mice;
+            let settings = self.settings;
+            self.converters
+                .resize_with(mice.len(), || MouseToGyro::new(settings));
+
+            mice.into_iter()
+                .enumerate()
+                .map(|(index, mouse)| DeviceDescriptor {
+                    id: index.to_string(),
+                    name: mouse.name,
+                    mac_address: 0,
+                    device_type: DeviceType::FullGyro,
+                    connection_type: ConnectionType::USB,
+                    battery_status: BatteryStatus::NotApplicable,
+                })
+                .collect()
+        }
+
+        fn poll(&mut self, device_id: &str) -> Option<ControllerData> {
+            self.pump_events();
+
+            let converter = self.converters.get_mut(device_id.parse::<usize>().ok()?)?;
+            let mut controller_data = ControllerData {
+                connected: true,
+                ..Default::default()
+            };
+            if converter.sample(&mut controller_data, Instant::now()) {
+                Some(controller_data)
+            } else {
+                None
+            }
+        }
+    }
+}
@@ -0,0 +1,233 @@
+//! Serial port (USB-CDC) motion source for microcontroller peripherals (feature `serial`).
+//!
+//! Devices (e.g. Arduino or RP2040 boards) send [`SerialFrame`]s: sync bytes `0xAA 0x55`,
+//! 32 byte little-endian payload and checksum (wrapping sum of payload bytes).
+//! Payload consists of `u32` timestamp in microseconds, accelerometer X, Y, Z in g and gyroscope pitch, yaw, roll
+//! in degrees per second (all `f32`) and `u32` bitmask of pressed buttons (bit `i` is `Button::DIGITAL[i]`).
+
+use crate::buttons::Button;
+use crate::protocol::*;
+use crate::source::*;
+use serialport::SerialPort;
+
+const SYNC: [u8; 2] = [0xAA, 0x55];
+const PAYLOAD_LENGTH: usize = 32;
+
+/// Length of encoded frame: sync bytes, payload and checksum.
+pub const FRAME_LENGTH: usize = SYNC.len() + PAYLOAD_LENGTH + 1;
+
+/// Time single read waits for data.
+const READ_TIMEOUT: Duration = Duration::from_millis(1);
+
+/// Single sample sent by serial device.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct SerialFrame {
+    /// Device's sample time in microseconds (wraps around).
+    pub timestamp: u32,
+    /// Acceleration (X, Y, Z) in g.
+    pub accelerometer: [f32; 3],
+    /// Angular velocity (pitch, yaw, roll) in degrees per second.
+    pub gyroscope: [f32; 3],
+    /// Pressed buttons, bit `i` is `Button::DIGITAL[i]`.
+    pub buttons: u32,
+}
+
+fn checksum(payload: &[u8]) -> u8 {
+    payload
+        .iter()
+        .fold(0u8, |checksum, byte| checksum.wrapping_add(*byte))
+}
+
+impl SerialFrame {
+    pub fn encode(&self) -> [u8; FRAME_LENGTH] {
+        let mut frame = [0u8; FRAME_LENGTH];
+        frame[..2].copy_from_slice(&SYNC);
+        frame[2..6].copy_from_slice(&self.timestamp.to_le_bytes());
+        let values = self.accelerometer.iter().chain(self.gyroscope.iter());
+        for (index, value) in values.enumerate() {
+            let start = 6 + index * 4;
+            frame[start..start + 4].copy_from_slice(&value.to_le_bytes());
+        }
+        frame[30..34].copy_from_slice(&self.buttons.to_le_bytes());
+        frame[34] = checksum(&frame[2..34]);
+
+        frame
+    }
+
+    fn decode(payload: &[u8]) -> SerialFrame {
+        let word = |start: usize| {
+            [
+                payload[start],
+                payload[start + 1],
+                payload[start + 2],
+                payload[start + 3],
+            ]
+        };
+        let float = |index: usize| f32::from_le_bytes(word(4 + index * 4));
+
+        SerialFrame {
+            timestamp: u32::from_le_bytes(word(0)),
+            accelerometer: [float(0), float(1), float(2)],
+            gyroscope: [float(3), float(4), float(5)],
+            buttons: u32::from_le_bytes(word(28)),
+        }
+    }
+
+    /// Writes frame into controller data (timestamp is left unchanged).
+    pub fn apply(&self, controller_data: &mut ControllerData) {
+        controller_data.accelerometer_x = self.accelerometer[0];
+        controller_data.accelerometer_y = self.accelerometer[1];
+        controller_data.accelerometer_z = self.accelerometer[2];
+        controller_data.gyroscope_pitch = self.gyroscope[0];
+        controller_data.gyroscope_yaw = self.gyroscope[1];
+        controller_data.gyroscope_roll = self.gyroscope[2];
+        for (index, button) in Button::DIGITAL.iter().enumerate() {
+            button.set(controller_data, self.buttons & (1 << index) != 0);
+        }
+    }
+}
+
+/// Extracts frames from stream of bytes, skipping garbage and frames with invalid checksum.
+#[derive(Clone, Debug, Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    pub fn new() -> FrameDecoder {
+        Default::default()
+    }
+
+    /// Adds received bytes, returns frames completed by them.
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<SerialFrame> {
+        self.buffer.extend_from_slice(bytes);
+
+        let mut frames = vec![];
+        let mut start = 0;
+        while self.buffer.len() - start >= FRAME_LENGTH {
+            let candidate = &self.buffer[start..start + FRAME_LENGTH];
+            if candidate[..2] == SYNC && checksum(&candidate[2..34]) == candidate[34] {
+                frames.push(SerialFrame::decode(&candidate[2..34]));
+                start += FRAME_LENGTH;
+            } else {
+                // Resynchronize on next byte.
+                start += 1;
+            }
+        }
+        self.buffer.drain(..start);
+
+        frames
+    }
+}
+
+struct OpenPort {
+    port: Box<dyn SerialPort>,
+    decoder: FrameDecoder,
+    /// Device's wrapping timestamp extended to 64 bits.
+    timestamp: Option<(u32, u64)>,
+}
+
+/// Input source reading motion devices connected to given serial ports.
+///
+/// Port failing to read (e.g. device was unplugged) is closed, following scans try to reopen it.
+pub struct SerialSource {
+    ports: Vec<String>,
+    baud_rate: u32,
+    open: HashMap<String, OpenPort>,
+}
+
+impl SerialSource {
+    /// Creates new serial source.
+    ///
+    /// # Arguments
+    ///
+    /// * `ports` - port names (e.g. `COM3` or `/dev/ttyACM0`).
+    /// * `baud_rate` - baud rate devices use (ignored by USB-CDC devices).
+    pub fn new(ports: &[&str], baud_rate: u32) -> SerialSource {
+        SerialSource {
+            ports: ports.iter().map(|port| port.to_string()).collect(),
+            baud_rate,
+            open: HashMap::new(),
+        }
+    }
+}
+
+impl InputSource for SerialSource {
+    fn enumerate(&mut self) -> Vec<DeviceDescriptor> {
+        let mut devices = vec![];
+        for name in self.ports.iter() {
+            if !self.open.contains_key(name) {
+                let port = match serialport::new(name.as_str(), self.baud_rate)
+                    .timeout(READ_TIMEOUT)
+                    .open()
+                {
+                    Ok(port) => port,
+                    Err(_) => continue,
+                };
+                self.open.insert(
+                    name.clone(),
+                    OpenPort {
+                        port,
+                        decoder: FrameDecoder::new(),
+                        timestamp: None,
+                    },
+                );
+            }
+
+            devices.push(DeviceDescriptor {
+                id: name.clone(),
+                name: name.clone(),
+                mac_address: 0,
+                device_type: DeviceType::FullGyro,
+                connection_type: ConnectionType::USB,
+                battery_status: BatteryStatus::NotApplicable,
+            });
+        }
+
+        devices
+    }
+
+    fn poll(&mut self, device_id: &str) -> Option<ControllerData> {
+        let open = self.open.get_mut(device_id)?;
+
+        let available = match open.port.bytes_to_read() {
+            Ok(available) => available as usize,
+            Err(_) => {
+                self.open.remove(device_id);
+                return None;
+            }
+        };
+        if available == 0 {
+            return None;
+        }
+
+        let mut bytes = vec![0u8; available];
+        let amount = match open.port.read(&mut bytes) {
+            Ok(amount) => amount,
+            Err(error) if error.kind() == std::io::ErrorKind::TimedOut => 0,
+            Err(_) => {
+                self.open.remove(device_id);
+                return None;
+            }
+        };
+
+        // Latest frame wins.
+        let frame = *open.decoder.push(&bytes[..amount]).last()?;
+        let timestamp = match open.timestamp {
+            Some((previous_raw, previous)) => {
+                previous + u64::from(frame.timestamp.wrapping_sub(previous_raw))
+            }
+            None => u64::from(frame.timestamp),
+        };
+        open.timestamp = Some((frame.timestamp, timestamp));
+
+        let mut controller_data = ControllerData {
+            connected: true,
+            motion_data_timestamp: timestamp,
+            ..Default::default()
+        };
+        frame.apply(&mut controller_data);
+
+        Some(controller_data)
+    }
+}
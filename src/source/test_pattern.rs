@@ -0,0 +1,222 @@
+//! Known sequence of inputs for verifying controller setup in emulators.
+//!
+//! [`TestPattern`] walks slot through its steps (e.g. each button in order, stick circles, gyro figure-eight),
+//! so frontends can guide user ("press the highlighted button" style) and check emulator sees the same inputs.
+
+use crate::buttons::Button;
+use crate::conventions::ACCELEROMETER_AT_REST;
+use crate::protocol::*;
+use crate::server::DsServer;
+use std::f32::consts::TAU;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+/// Default duration of single step.
+pub const DEFAULT_STEP_DURATION: Duration = Duration::from_secs(1);
+
+/// Interval between samples sent by `TestPattern::play` (250 Hz).
+pub const SAMPLE_INTERVAL: Duration = Duration::from_millis(4);
+
+/// Largest yaw angle (in degrees) of gyroscope figure-eight, pitch reaches half of it.
+pub const FIGURE_EIGHT_AMPLITUDE: f32 = 30.0;
+
+/// Part of button step the button is held for, so consecutive presses are separate.
+const BUTTON_HOLD_PART: f32 = 0.75;
+
+/// Single step of test pattern.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TestStep {
+    /// Button is held for most of the step.
+    Button(Button),
+    /// Left stick makes full counterclockwise circle at maximum deflection, starting right.
+    LeftStickCircle,
+    /// Right stick makes full counterclockwise circle at maximum deflection, starting right.
+    RightStickCircle,
+    /// Controller traces figure-eight: yaw swings left and right once while pitch swings twice.
+    GyroFigureEight,
+}
+
+impl TestStep {
+    /// Applies step to controller data.
+    ///
+    /// # Arguments
+    ///
+    /// * `controller_data` - neutral controller data to modify.
+    /// * `progress` - part of step already done, from 0 to 1.
+    /// * `step_duration` - duration of the whole step (gyroscope rates depend on it).
+    pub fn apply(
+        self,
+        controller_data: &mut ControllerData,
+        progress: f32,
+        step_duration: Duration,
+    ) {
+        let angle = TAU * progress;
+        let stick = |value: f32| (128.0 + value * 127.0).round().clamp(0.0, 255.0) as u8;
+
+        match self {
+            TestStep::Button(button) => button.set(controller_data, progress < BUTTON_HOLD_PART),
+            TestStep::LeftStickCircle => {
+                controller_data.left_stick_x = stick(angle.cos());
+                controller_data.left_stick_y = stick(angle.sin());
+            }
+            TestStep::RightStickCircle => {
+                controller_data.right_stick_x = stick(angle.cos());
+                controller_data.right_stick_y = stick(angle.sin());
+            }
+            TestStep::GyroFigureEight => {
+                // Yaw angle follows A sin(wt) and pitch angle A/2 sin(2wt), rates are their derivatives.
+                let angular_frequency = TAU / step_duration.as_secs_f32().max(f32::EPSILON);
+                let amplitude = FIGURE_EIGHT_AMPLITUDE * angular_frequency;
+                controller_data.gyroscope_yaw = amplitude * angle.cos();
+                controller_data.gyroscope_pitch = amplitude * (2.0 * angle).cos();
+            }
+        }
+    }
+}
+
+/// Position within test pattern.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TestProgress {
+    pub step_index: usize,
+    pub step_count: usize,
+    pub step: TestStep,
+    /// Part of current step already done, from 0 to 1.
+    pub step_progress: f32,
+}
+
+/// Sequence of test steps of equal duration.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TestPattern {
+    pub steps: Vec<TestStep>,
+    pub step_duration: Duration,
+}
+
+impl Default for TestPattern {
+    /// Every digital button in order, both stick circles and gyroscope figure-eight.
+    fn default() -> TestPattern {
+        let mut steps: Vec<TestStep> = Button::DIGITAL
+            .iter()
+            .copied()
+            .map(TestStep::Button)
+            .collect();
+        steps.push(TestStep::LeftStickCircle);
+        steps.push(TestStep::RightStickCircle);
+        steps.push(TestStep::GyroFigureEight);
+
+        TestPattern::new(steps, DEFAULT_STEP_DURATION)
+    }
+}
+
+impl TestPattern {
+    pub fn new(steps: Vec<TestStep>, step_duration: Duration) -> TestPattern {
+        TestPattern {
+            steps,
+            step_duration,
+        }
+    }
+
+    /// Duration of the whole pattern.
+    pub fn duration(&self) -> Duration {
+        self.step_duration * self.steps.len() as u32
+    }
+
+    /// Returns position within pattern at given time since its start, `None` once pattern is over.
+    pub fn progress_at(&self, elapsed: Duration) -> Option<TestProgress> {
+        if self.step_duration.is_zero() || elapsed >= self.duration() {
+            return None;
+        }
+
+        let steps_done = elapsed.as_secs_f64() / self.step_duration.as_secs_f64();
+        let step_index = (steps_done as usize).min(self.steps.len() - 1);
+
+        Some(TestProgress {
+            step_index,
+            step_count: self.steps.len(),
+            step: self.steps[step_index],
+            step_progress: (steps_done - step_index as f64).clamp(0.0, 1.0) as f32,
+        })
+    }
+
+    /// Returns controller data at given time since pattern's start, `None` once pattern is over.
+    ///
+    /// Motion timestamp is set to elapsed time (in microseconds).
+    pub fn sample_at(&self, elapsed: Duration) -> Option<ControllerData> {
+        let progress = self.progress_at(elapsed)?;
+
+        let mut controller_data = neutral_controller_data();
+        controller_data.motion_data_timestamp = elapsed.as_micros() as u64;
+        progress.step.apply(
+            &mut controller_data,
+            progress.step_progress,
+            self.step_duration,
+        );
+
+        Some(controller_data)
+    }
+
+    /// Plays pattern on server's slot, blocking until it's over or `countinue_running` is set to `false`.
+    ///
+    /// Slot is left with neutral controller data afterwards. Returns `true` if pattern was played to the end.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - server to update.
+    /// * `slot_number` - slot to play pattern on.
+    /// * `countinue_running` - flag cancelling playback.
+    /// * `on_step` - called with progress whenever next step starts.
+    pub fn play<S, F>(
+        &self,
+        server: &S,
+        slot_number: u8,
+        countinue_running: &AtomicBool,
+        mut on_step: F,
+    ) -> bool
+    where
+        S: DsServer,
+        F: FnMut(&TestProgress),
+    {
+        let start = Instant::now();
+        let mut previous_step = None;
+        let completed = loop {
+            if !countinue_running.load(Ordering::SeqCst) {
+                break false;
+            }
+
+            let elapsed = start.elapsed();
+            let (progress, controller_data) =
+                match self.progress_at(elapsed).zip(self.sample_at(elapsed)) {
+                    Some(sample) => sample,
+                    None => break true,
+                };
+
+            server.update_controller_data(slot_number, controller_data);
+            if previous_step != Some(progress.step_index) {
+                previous_step = Some(progress.step_index);
+                on_step(&progress);
+            }
+
+            std::thread::sleep(SAMPLE_INTERVAL);
+        };
+
+        let mut controller_data = neutral_controller_data();
+        controller_data.motion_data_timestamp = start.elapsed().as_micros() as u64;
+        server.update_controller_data(slot_number, controller_data);
+
+        completed
+    }
+}
+
+/// Connected controller at rest with centered sticks and nothing pressed.
+fn neutral_controller_data() -> ControllerData {
+    ControllerData {
+        connected: true,
+        left_stick_x: 128,
+        left_stick_y: 128,
+        right_stick_x: 128,
+        right_stick_y: 128,
+        accelerometer_x: ACCELEROMETER_AT_REST[0],
+        accelerometer_y: ACCELEROMETER_AT_REST[1],
+        accelerometer_z: ACCELEROMETER_AT_REST[2],
+        ..Default::default()
+    }
+}
@@ -0,0 +1,219 @@
+//! Secondary, read-only view of server's slots for overlays and stream widgets.
+//!
+//! [`Spectator`] republishes data of all slots on its own socket at reduced rate. Spectators are tracked
+//! separately from server's clients, so they never affect emulator-facing traffic, rate limiting or statistics.
+
+use crate::protocol::*;
+use crate::server::Server;
+use crate::transport::*;
+use std::collections::HashMap;
+use std::io::Result;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+/// Spectator not renewing its data request for this long stops receiving data.
+pub const SUBSCRIPTION_TIMEOUT: Duration = Duration::from_secs(5);
+
+struct Subscriber {
+    packet_number: u32,
+    last_request_time: Instant,
+}
+
+/// Republishes server's slots to spectators at reduced rate.
+///
+/// Spectators talk the regular protocol: any controller data request subscribes them to all slots
+/// for [`SUBSCRIPTION_TIMEOUT`].
+pub struct Spectator {
+    server: Arc<Server>,
+    socket: Box<dyn Transport>,
+    send_interval: Duration,
+    subscribers: Mutex<HashMap<SocketAddr, Subscriber>>,
+}
+
+impl Spectator {
+    /// Creates new spectator port.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - server whose slots are republished.
+    /// * `address` - spectator port's UDP socket address, must differ from server's address.
+    /// * `rate` - updates per second sent to spectators.
+    pub fn new(server: Arc<Server>, address: SocketAddr, rate: f64) -> Result<Spectator> {
+        let socket = bind_udp(address)?;
+
+        Ok(Spectator::with_transport(server, Box::new(socket), rate))
+    }
+
+    /// Creates new spectator port using given transport instead of UDP socket.
+    ///
+    /// # Arguments
+    ///
+    /// * `server` - server whose slots are republished.
+    /// * `socket` - transport to serve spectators on.
+    /// * `rate` - updates per second sent to spectators.
+    pub fn with_transport(server: Arc<Server>, socket: Box<dyn Transport>, rate: f64) -> Spectator {
+        assert!(rate > 0.0);
+
+        Spectator {
+            server,
+            socket,
+            send_interval: Duration::from_secs_f64(1.0 / rate),
+            subscribers: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns spectator port's local socket address.
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Returns number of subscribed spectators.
+    pub fn subscriber_count(&self) -> usize {
+        self.subscribers.lock().unwrap().len()
+    }
+
+    /// Starts receiving spectator requests and sending them data on background threads.
+    pub fn start(self: Arc<Self>, countinue_running: Arc<AtomicBool>) -> JoinHandle<()> {
+        std::thread::spawn(move || {
+            let receiver = {
+                let spectator = self.clone();
+                let countinue_running = countinue_running.clone();
+                std::thread::spawn(move || {
+                    let mut buf = vec![0; DEFAULT_RECEIVE_BUFFER_SIZE];
+                    while countinue_running.load(Ordering::SeqCst) {
+                        if let Ok((amount, source)) = spectator.socket.recv_from(&mut buf) {
+                            let _ = spectator.handle_datagram(source, &buf[..amount]);
+                        }
+                    }
+                })
+            };
+
+            while countinue_running.load(Ordering::SeqCst) {
+                let next_send = Instant::now() + self.send_interval;
+                self.send_controller_data();
+
+                let now = Instant::now();
+                if next_send > now {
+                    std::thread::sleep(next_send - now);
+                }
+            }
+
+            let _ = receiver.join();
+        })
+    }
+
+    fn message_header(&self) -> MessageHeader {
+        MessageHeader {
+            source: MessageSource::Server,
+            protocol_version: PROTOCOL_VERSION,
+            message_length: 0,
+            checksum: 0,
+            source_id: self.server.id(),
+        }
+    }
+
+    fn send(
+        &self,
+        target: SocketAddr,
+        message_type: MessageType,
+        payload: MessagePayload,
+    ) -> Result<()> {
+        let message = Message {
+            header: self.message_header(),
+            message_type,
+            payload,
+        };
+
+        let mut encoded_message = vec![];
+        encode_message(&mut encoded_message, message)?;
+        self.socket.send_to(&encoded_message, target).map(|_| ())
+    }
+
+    fn handle_datagram(&self, source: SocketAddr, packet: &[u8]) -> Result<()> {
+        check_message_length(packet, DEFAULT_RECEIVE_BUFFER_SIZE)?;
+        let request = parse_message(MessageSource::Client, packet, true)?;
+
+        if request.message_type == MessageType::ProtocolVersion {
+            return self.send(
+                source,
+                MessageType::ProtocolVersion,
+                MessagePayload::ProtocolVersion(PROTOCOL_VERSION),
+            );
+        }
+
+        match request.payload {
+            MessagePayload::ConnectedControllersRequest {
+                amount,
+                slot_numbers,
+            } => {
+                for &slot_number in slot_numbers.iter().take(amount as usize) {
+                    // Slots server doesn't have are ignored.
+                    if slot_number >= self.server.slot_count() {
+                        continue;
+                    }
+
+                    let (controller_info, _) = self.server.reported_slot(slot_number);
+                    self.send(
+                        source,
+                        MessageType::ConnectedControllers,
+                        MessagePayload::ConnectedControllerResponse { controller_info },
+                    )?;
+                }
+
+                Ok(())
+            }
+            MessagePayload::ControllerDataRequest(_) => {
+                let now = Instant::now();
+                self.subscribers
+                    .lock()
+                    .unwrap()
+                    .entry(source)
+                    .or_insert(Subscriber {
+                        packet_number: 0,
+                        last_request_time: now,
+                    })
+                    .last_request_time = now;
+
+                Ok(())
+            }
+            _ => Ok(()), // ignore request
+        }
+    }
+
+    fn send_controller_data(&self) {
+        let now = Instant::now();
+        let mut subscribers = self.subscribers.lock().unwrap();
+        subscribers.retain(|_, subscriber| {
+            now.duration_since(subscriber.last_request_time) < SUBSCRIPTION_TIMEOUT
+        });
+        if subscribers.is_empty() {
+            return;
+        }
+
+        let slots: Vec<(ControllerInfo, ControllerData)> = (0..self.server.slot_count())
+            .map(|slot_number| self.server.reported_slot(slot_number))
+            .collect();
+
+        subscribers.retain(|&address, subscriber| {
+            for &(controller_info, controller_data) in slots.iter() {
+                let payload = MessagePayload::ControllerData {
+                    packet_number: subscriber.packet_number,
+                    controller_info,
+                    controller_data,
+                };
+                if self
+                    .send(address, MessageType::ControllerData, payload)
+                    .is_err()
+                {
+                    return false;
+                }
+                subscriber.packet_number = subscriber.packet_number.wrapping_add(1);
+            }
+
+            true
+        });
+    }
+}
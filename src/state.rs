@@ -0,0 +1,133 @@
+//! Server and client identities persisted across restarts.
+//!
+//! Keeping server ID and slot assignments stable lets clients tracking packet numbers and server identity
+//! treat restarted server as the same one, and controllers reappear in the slots they used before.
+//! Likewise, servers tracking client identity by source ID keep recognizing restarted client.
+
+use crate::protocol::{MAX_SLOT_COUNT, SLOT_COUNT};
+use std::fs;
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+
+const HEADER: &str = "pad-motion-state 1";
+const CLIENT_HEADER: &str = "pad-motion-client-state 1";
+
+/// Persisted server state.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerState {
+    pub server_id: u32,
+    /// MAC address of controller last connected to each slot (0 if none),
+    /// at least `SLOT_COUNT` entries, more for servers with extended slot count.
+    pub slot_mac_addresses: Vec<u64>,
+}
+
+impl Default for ServerState {
+    fn default() -> ServerState {
+        ServerState {
+            server_id: 0,
+            slot_mac_addresses: vec![0; SLOT_COUNT as usize],
+        }
+    }
+}
+
+fn invalid_state_error(message: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, message)
+}
+
+impl ServerState {
+    /// Loads state from file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ServerState> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+        if lines.next() != Some(HEADER) {
+            return Err(invalid_state_error("Not a server state file"));
+        }
+
+        let mut state = ServerState::default();
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["server_id", server_id] => {
+                    state.server_id = server_id
+                        .parse()
+                        .map_err(|_| invalid_state_error("Invalid server ID"))?;
+                }
+                ["slot", slot_number, mac_address] => {
+                    let slot_number: usize = slot_number
+                        .parse()
+                        .ok()
+                        .filter(|&slot_number| slot_number < MAX_SLOT_COUNT as usize)
+                        .ok_or_else(|| invalid_state_error("Invalid slot number"))?;
+                    if state.slot_mac_addresses.len() <= slot_number {
+                        state.slot_mac_addresses.resize(slot_number + 1, 0);
+                    }
+                    state.slot_mac_addresses[slot_number] = u64::from_str_radix(mac_address, 16)
+                        .map_err(|_| invalid_state_error("Invalid MAC address"))?;
+                }
+                _ => return Err(invalid_state_error("Unrecognized line")),
+            }
+        }
+
+        Ok(state)
+    }
+
+    /// Saves state to file, replacing it atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let path = path.as_ref();
+
+        let mut content = format!("{}\nserver_id {}\n", HEADER, self.server_id);
+        for (slot_number, mac_address) in self.slot_mac_addresses.iter().enumerate() {
+            content += &format!("slot {} {:012x}\n", slot_number, mac_address);
+        }
+
+        write_atomically(path, &content)
+    }
+}
+
+/// Persisted client state.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub client_id: u32,
+}
+
+impl ClientIdentity {
+    /// Loads client identity from file.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<ClientIdentity> {
+        let content = fs::read_to_string(path)?;
+        let mut lines = content.lines();
+        if lines.next() != Some(CLIENT_HEADER) {
+            return Err(invalid_state_error("Not a client state file"));
+        }
+
+        let mut identity = ClientIdentity::default();
+        for line in lines {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            match fields.as_slice() {
+                [] => {}
+                ["client_id", client_id] => {
+                    identity.client_id = client_id
+                        .parse()
+                        .map_err(|_| invalid_state_error("Invalid client ID"))?;
+                }
+                _ => return Err(invalid_state_error("Unrecognized line")),
+            }
+        }
+
+        Ok(identity)
+    }
+
+    /// Saves client identity to file, replacing it atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let content = format!("{}\nclient_id {}\n", CLIENT_HEADER, self.client_id);
+
+        write_atomically(path.as_ref(), &content)
+    }
+}
+
+fn write_atomically(path: &Path, content: &str) -> Result<()> {
+    let mut temporary_path = path.as_os_str().to_owned();
+    temporary_path.push(".tmp");
+    fs::write(&temporary_path, content)?;
+    fs::rename(&temporary_path, path)
+}
@@ -0,0 +1,240 @@
+//! Helpers for end-to-end behavioral tests of servers, clients and drivers built on them.
+//!
+//! ```no_run
+//! # use pad_motion::buttons::Button;
+//! # use pad_motion::testing::*;
+//! # use std::time::Duration;
+//! # fn main() -> std::io::Result<()> {
+//! let (server, client) = loopback_pair()?;
+//! // ... start both, feed server from driver under test ...
+//! Expectation::controller_data(0)
+//!     .within(Duration::from_millis(10))
+//!     .pressed(Button::Cross)
+//!     .verify_client(&client)?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::buttons::Button;
+use crate::client::*;
+use crate::protocol::*;
+use crate::server::*;
+use crate::transport::*;
+use std::io::{Error, ErrorKind, Result};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+const DEFAULT_WITHIN: Duration = Duration::from_millis(100);
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+type Predicate = Box<dyn Fn(&ControllerData) -> bool + Send + Sync>;
+
+/// Condition controller data of a slot has to meet within given time.
+pub struct Expectation {
+    slot_number: u8,
+    within: Duration,
+    conditions: Vec<(String, Predicate)>,
+}
+
+impl Expectation {
+    /// Expects controller data for given slot, by default within 100 ms.
+    pub fn controller_data(slot_number: u8) -> Expectation {
+        assert!(slot_number < MAX_SLOT_COUNT);
+
+        Expectation {
+            slot_number,
+            within: DEFAULT_WITHIN,
+            conditions: vec![],
+        }
+    }
+
+    /// Sets how long to wait for controller data meeting all conditions.
+    pub fn within(mut self, within: Duration) -> Expectation {
+        self.within = within;
+        self
+    }
+
+    /// Expects button to be pressed.
+    pub fn pressed(self, button: Button) -> Expectation {
+        self.matching(&format!("{:?} pressed", button), move |controller_data| {
+            button.is_pressed(controller_data, 128)
+        })
+    }
+
+    /// Expects button to be released.
+    pub fn released(self, button: Button) -> Expectation {
+        self.matching(&format!("{:?} released", button), move |controller_data| {
+            !button.is_pressed(controller_data, 128)
+        })
+    }
+
+    /// Expects controller to be connected.
+    pub fn connected(self) -> Expectation {
+        self.matching("connected", |controller_data| controller_data.connected)
+    }
+
+    /// Expects custom condition.
+    ///
+    /// # Arguments
+    ///
+    /// * `description` - condition description used in failure message.
+    /// * `predicate` - returns `true` if controller data meets the condition.
+    pub fn matching<F>(mut self, description: &str, predicate: F) -> Expectation
+    where
+        F: Fn(&ControllerData) -> bool + Send + Sync + 'static,
+    {
+        self.conditions
+            .push((description.to_string(), Box::new(predicate)));
+        self
+    }
+
+    /// Checks whether controller data meets all conditions.
+    pub fn is_met_by(&self, controller_data: &ControllerData) -> bool {
+        self.conditions
+            .iter()
+            .all(|(_, predicate)| predicate(controller_data))
+    }
+
+    /// Waits until server's controller data meets all conditions, returns matching data.
+    ///
+    /// Fails with `ErrorKind::TimedOut` describing unmet conditions,
+    /// or with `ErrorKind::InvalidInput` if server doesn't have expected slot.
+    pub fn verify_server(&self, server: &Server) -> Result<ControllerData> {
+        check_slot_number_in(self.slot_number, server.slot_count())?;
+        self.verify(|| server.controller_data(self.slot_number))
+    }
+
+    /// Waits until client's controller data meets all conditions, returns matching data.
+    ///
+    /// Fails with `ErrorKind::TimedOut` describing unmet conditions,
+    /// or with `ErrorKind::InvalidInput` if client doesn't have expected slot.
+    pub fn verify_client(&self, client: &Arc<Client>) -> Result<ControllerData> {
+        check_slot_number_in(self.slot_number, client.slot_count())?;
+        self.verify(|| client.controller_data(self.slot_number))
+    }
+
+    fn verify<F>(&self, controller_data: F) -> Result<ControllerData>
+    where
+        F: Fn() -> ControllerData,
+    {
+        let start = Instant::now();
+        loop {
+            let controller_data = controller_data();
+            if self.is_met_by(&controller_data) {
+                return Ok(controller_data);
+            }
+
+            if start.elapsed() >= self.within {
+                let unmet: Vec<&str> = self
+                    .conditions
+                    .iter()
+                    .filter(|(_, predicate)| !predicate(&controller_data))
+                    .map(|(description, _)| description.as_str())
+                    .collect();
+
+                return Err(Error::new(
+                    ErrorKind::TimedOut,
+                    format!(
+                        "Slot {} controller data not met within {:?}: {}",
+                        self.slot_number,
+                        self.within,
+                        unmet.join(", ")
+                    ),
+                ));
+            }
+
+            std::thread::sleep(POLL_INTERVAL);
+        }
+    }
+}
+
+/// Creates server and client connected over new in-memory loopback network.
+pub fn loopback_pair() -> Result<(Arc<Server>, Arc<Client>)> {
+    let network = LoopbackNetwork::new();
+    let server_address = SocketAddr::from(([127, 0, 0, 1], 26760));
+    let client_address = SocketAddr::from(([127, 0, 0, 1], 3333));
+
+    let server = Server::with_transport(None, Box::new(network.bind(server_address)?));
+    let client = Client::with_transport(
+        None,
+        Box::new(network.bind(client_address)?),
+        Some(server_address),
+    );
+
+    Ok((Arc::new(server), Arc::new(client)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicBool, Ordering};
+
+    #[test]
+    fn expectation_is_verified_over_loopback() {
+        let (server, client) = loopback_pair().unwrap();
+        let running = Arc::new(AtomicBool::new(true));
+        let server_thread = server.clone().start(running.clone());
+        let client_thread = client.clone().start(running.clone());
+
+        server.update_controller_info(ControllerInfo {
+            slot: 0,
+            slot_state: SlotState::Connected,
+            ..Default::default()
+        });
+        client
+            .request_controller_data(ControllerDataRequest::SlotNumber(0))
+            .unwrap();
+        server.update_controller_data(
+            0,
+            ControllerData {
+                connected: true,
+                cross: true,
+                ..Default::default()
+            },
+        );
+
+        let verified = Expectation::controller_data(0)
+            .within(Duration::from_secs(1))
+            .connected()
+            .pressed(Button::Cross)
+            .verify_client(&client);
+
+        running.store(false, Ordering::SeqCst);
+        let _ = server_thread.join();
+        let _ = client_thread.join();
+        assert!(verified.unwrap().cross);
+    }
+
+    #[test]
+    fn unmet_expectation_reports_unmet_conditions() {
+        let (server, _client) = loopback_pair().unwrap();
+        server.update_controller_data(
+            0,
+            ControllerData {
+                cross: true,
+                ..Default::default()
+            },
+        );
+
+        let error = Expectation::controller_data(0)
+            .within(Duration::from_millis(5))
+            .pressed(Button::Cross)
+            .released(Button::Circle)
+            .connected()
+            .verify_server(&server)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::TimedOut);
+        assert!(error.to_string().ends_with(": connected"));
+    }
+
+    #[test]
+    fn expectation_for_missing_slot_is_invalid() {
+        let (server, _client) = loopback_pair().unwrap();
+
+        let error = Expectation::controller_data(SLOT_COUNT)
+            .verify_server(&server)
+            .unwrap_err();
+        assert_eq!(error.kind(), ErrorKind::InvalidInput);
+    }
+}
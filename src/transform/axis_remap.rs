@@ -0,0 +1,113 @@
+use super::*;
+
+/// Common IMU mounting orientations relative to DS4 frame.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MountingPreset {
+    /// IMU axes match DS4 axes.
+    Identity,
+    /// Rotated 180° around Z axis (device held upside down).
+    UpsideDown,
+    /// Rotated 90° counter-clockwise around Z axis (device held sideways, e.g. single Joy-Con).
+    SidewaysLeft,
+    /// Rotated 90° clockwise around Z axis.
+    SidewaysRight,
+    /// Rotated 180° around Y axis (IMU mounted facing down).
+    FaceDown,
+}
+
+impl MountingPreset {
+    /// Rotation matrix mapping device axes to DS4 axes.
+    pub fn matrix(self) -> [[f32; 3]; 3] {
+        match self {
+            MountingPreset::Identity => [[1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, 1.0]],
+            MountingPreset::UpsideDown => [[-1.0, 0.0, 0.0], [0.0, -1.0, 0.0], [0.0, 0.0, 1.0]],
+            MountingPreset::SidewaysLeft => [[0.0, -1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+            MountingPreset::SidewaysRight => [[0.0, 1.0, 0.0], [-1.0, 0.0, 0.0], [0.0, 0.0, 1.0]],
+            MountingPreset::FaceDown => [[-1.0, 0.0, 0.0], [0.0, 1.0, 0.0], [0.0, 0.0, -1.0]],
+        }
+    }
+}
+
+fn determinant(matrix: &[[f32; 3]; 3]) -> f32 {
+    matrix[0][0] * (matrix[1][1] * matrix[2][2] - matrix[1][2] * matrix[2][1])
+        - matrix[0][1] * (matrix[1][0] * matrix[2][2] - matrix[1][2] * matrix[2][0])
+        + matrix[0][2] * (matrix[1][0] * matrix[2][1] - matrix[1][1] * matrix[2][0])
+}
+
+fn multiply(matrix: &[[f32; 3]; 3], vector: [f32; 3]) -> [f32; 3] {
+    let mut result = [0.0; 3];
+    for (i, row) in matrix.iter().enumerate() {
+        result[i] = row[0] * vector[0] + row[1] * vector[1] + row[2] * vector[2];
+    }
+
+    result
+}
+
+/// Applies 3×3 rotation/axis-swap matrix to accelerometer and gyroscope vectors.
+///
+/// Accelerometer vector is `(x, y, z)`, gyroscope vector is `(pitch, yaw, roll)` - rotation rates around the same axes.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AxisRemap {
+    pub accelerometer_matrix: [[f32; 3]; 3],
+    pub gyroscope_matrix: [[f32; 3]; 3],
+}
+
+impl Default for AxisRemap {
+    fn default() -> AxisRemap {
+        AxisRemap::from_preset(MountingPreset::Identity)
+    }
+}
+
+impl AxisRemap {
+    /// Creates remap from matrix mapping device axes to DS4 axes.
+    ///
+    /// Rotation rates are pseudovectors, so for matrices mirroring space (negative determinant)
+    /// gyroscope matrix is negated to keep rotations consistent with accelerometer.
+    pub fn new(matrix: [[f32; 3]; 3]) -> AxisRemap {
+        let mut gyroscope_matrix = matrix;
+        if determinant(&matrix) < 0.0 {
+            for row in gyroscope_matrix.iter_mut() {
+                for value in row.iter_mut() {
+                    *value = -*value;
+                }
+            }
+        }
+
+        AxisRemap {
+            accelerometer_matrix: matrix,
+            gyroscope_matrix,
+        }
+    }
+
+    pub fn from_preset(preset: MountingPreset) -> AxisRemap {
+        AxisRemap::new(preset.matrix())
+    }
+}
+
+impl Transform for AxisRemap {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        let [x, y, z] = multiply(
+            &self.accelerometer_matrix,
+            [
+                controller_data.accelerometer_x,
+                controller_data.accelerometer_y,
+                controller_data.accelerometer_z,
+            ],
+        );
+        controller_data.accelerometer_x = x;
+        controller_data.accelerometer_y = y;
+        controller_data.accelerometer_z = z;
+
+        let [pitch, yaw, roll] = multiply(
+            &self.gyroscope_matrix,
+            [
+                controller_data.gyroscope_pitch,
+                controller_data.gyroscope_yaw,
+                controller_data.gyroscope_roll,
+            ],
+        );
+        controller_data.gyroscope_pitch = pitch;
+        controller_data.gyroscope_yaw = yaw;
+        controller_data.gyroscope_roll = roll;
+    }
+}
@@ -0,0 +1,68 @@
+use super::*;
+use crate::analysis::MotionReport;
+
+/// Removes sensor bias and corrects sensor scale.
+///
+/// Offsets are subtracted first, then values are multiplied by scales.
+#[derive(Copy, Clone, Debug, PartialEq)]
+#[cfg_attr(
+    feature = "profiles",
+    derive(serde::Serialize, serde::Deserialize),
+    serde(default)
+)]
+pub struct Calibration {
+    /// Gyroscope bias (pitch, yaw, roll) in deg/s.
+    pub gyroscope_offset: [f32; 3],
+    pub gyroscope_scale: [f32; 3],
+    /// Accelerometer bias (x, y, z) in g.
+    pub accelerometer_offset: [f32; 3],
+    pub accelerometer_scale: [f32; 3],
+}
+
+impl Default for Calibration {
+    fn default() -> Calibration {
+        Calibration {
+            gyroscope_offset: [0.0; 3],
+            gyroscope_scale: [1.0; 3],
+            accelerometer_offset: [0.0; 3],
+            accelerometer_scale: [1.0; 3],
+        }
+    }
+}
+
+impl Calibration {
+    /// Creates calibration removing gyroscope drift measured while controller was lying still.
+    pub fn from_stationary_report(report: &MotionReport) -> Calibration {
+        let drift = report.gyroscope_drift_per_minute;
+
+        Calibration {
+            gyroscope_offset: [
+                (drift[0] / 60.0) as f32,
+                (drift[1] / 60.0) as f32,
+                (drift[2] / 60.0) as f32,
+            ],
+            ..Default::default()
+        }
+    }
+}
+
+impl Transform for Calibration {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        controller_data.gyroscope_pitch =
+            (controller_data.gyroscope_pitch - self.gyroscope_offset[0]) * self.gyroscope_scale[0];
+        controller_data.gyroscope_yaw =
+            (controller_data.gyroscope_yaw - self.gyroscope_offset[1]) * self.gyroscope_scale[1];
+        controller_data.gyroscope_roll =
+            (controller_data.gyroscope_roll - self.gyroscope_offset[2]) * self.gyroscope_scale[2];
+
+        controller_data.accelerometer_x = (controller_data.accelerometer_x
+            - self.accelerometer_offset[0])
+            * self.accelerometer_scale[0];
+        controller_data.accelerometer_y = (controller_data.accelerometer_y
+            - self.accelerometer_offset[1])
+            * self.accelerometer_scale[1];
+        controller_data.accelerometer_z = (controller_data.accelerometer_z
+            - self.accelerometer_offset[2])
+            * self.accelerometer_scale[2];
+    }
+}
@@ -0,0 +1,156 @@
+use super::*;
+
+/// Converts right stick into camera turns via synthesized gyroscope yaw.
+///
+/// Pushing stick to its edge turns camera by stick's angle from forward (up) direction within `flick_time`,
+/// rotating stick while holding it at the edge keeps turning by the rotation angle.
+/// Turns are added to gyroscope yaw rate, positive stick angles (to the right) produce negative yaw.
+#[derive(Copy, Clone, Debug)]
+pub struct FlickStick {
+    /// Deflection (as fraction of full deflection) stick has to reach to start a flick.
+    pub flick_threshold: f32,
+    /// Deflection below which stick counts as released.
+    pub release_threshold: f32,
+    /// Time flick turn is spread over, in seconds.
+    pub flick_time: f32,
+    /// Turn direction is flipped.
+    pub invert: bool,
+    /// Clear right stick after synthesizing turns, so it doesn't move camera at the same time.
+    pub consume_stick: bool,
+    /// Angle of stick at previous sample while held at the edge, in degrees.
+    stick_angle: Option<f32>,
+    /// Remaining flick angle and its rate in degrees per second.
+    flick: Option<(f32, f32)>,
+    previous_timestamp: Option<u64>,
+}
+
+impl Default for FlickStick {
+    fn default() -> FlickStick {
+        FlickStick::new()
+    }
+}
+
+impl FlickStick {
+    pub fn new() -> FlickStick {
+        FlickStick {
+            flick_threshold: 0.9,
+            release_threshold: 0.7,
+            flick_time: 0.1,
+            invert: false,
+            consume_stick: true,
+            stick_angle: None,
+            flick: None,
+            previous_timestamp: None,
+        }
+    }
+}
+
+/// Wraps angle in degrees into `-180..180` range.
+fn wrap_angle(angle: f32) -> f32 {
+    (angle + 180.0).rem_euclid(360.0) - 180.0
+}
+
+impl Transform for FlickStick {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        let elapsed = elapsed_seconds(
+            self.previous_timestamp,
+            controller_data.motion_data_timestamp,
+        );
+        self.previous_timestamp = Some(controller_data.motion_data_timestamp);
+
+        let x = stick_axis(controller_data.right_stick_x);
+        let y = stick_axis(controller_data.right_stick_y);
+        let magnitude = (x * x + y * y).sqrt();
+        let angle = x.atan2(y).to_degrees();
+
+        let mut turn = 0.0;
+        match self.stick_angle {
+            None if magnitude >= self.flick_threshold => {
+                let rate = angle / self.flick_time.max(f32::EPSILON);
+                self.flick = Some((angle, rate));
+                self.stick_angle = Some(angle);
+            }
+            Some(_) if magnitude < self.release_threshold => {
+                self.stick_angle = None;
+            }
+            Some(previous_angle) => {
+                turn += wrap_angle(angle - previous_angle);
+                self.stick_angle = Some(angle);
+            }
+            None => {}
+        }
+
+        let mut rate = 0.0;
+        if elapsed > 0.0 {
+            rate += turn / elapsed;
+        }
+        if let Some((remaining, flick_rate)) = self.flick {
+            // Flick starting on this sample is delivered from the next one, when sample interval is known.
+            if elapsed > 0.0 {
+                let step = flick_rate * elapsed;
+                if step.abs() >= remaining.abs() {
+                    rate += remaining / elapsed;
+                    self.flick = None;
+                } else {
+                    rate += flick_rate;
+                    self.flick = Some((remaining - step, flick_rate));
+                }
+            }
+        }
+
+        let sign = if self.invert { 1.0 } else { -1.0 };
+        controller_data.gyroscope_yaw += sign * rate;
+
+        if self.consume_stick {
+            controller_data.right_stick_x = 128;
+            controller_data.right_stick_y = 128;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds right stick positions sampled every 10 ms, returns total turn (integrated yaw) in degrees.
+    fn turn(flick_stick: &mut FlickStick, positions: &[(u8, u8)]) -> f32 {
+        positions
+            .iter()
+            .enumerate()
+            .map(|(index, &(x, y))| {
+                let mut controller_data = ControllerData {
+                    right_stick_x: x,
+                    right_stick_y: y,
+                    motion_data_timestamp: 1_000_000 + index as u64 * 10_000,
+                    ..Default::default()
+                };
+                flick_stick.apply(0, &mut controller_data);
+                assert_eq!(
+                    (controller_data.right_stick_x, controller_data.right_stick_y),
+                    (128, 128)
+                );
+
+                controller_data.gyroscope_yaw * 0.01
+            })
+            .sum()
+    }
+
+    #[test]
+    fn flick_turns_by_stick_angle_within_flick_time() {
+        let mut flick_stick = FlickStick::new();
+        let mut positions = vec![(255, 128); 12];
+        positions.push((128, 128));
+
+        assert!((turn(&mut flick_stick, &positions) + 90.0).abs() < 0.5);
+    }
+
+    #[test]
+    fn rotating_held_stick_keeps_turning() {
+        let mut flick_stick = FlickStick::new();
+        flick_stick.flick_time = 0.0;
+        // Flick straight up doesn't turn, rotating to the right then turns by 90 degrees.
+        let positions = [(128, 255), (128, 255), (218, 218), (255, 128), (255, 128)];
+
+        assert!((turn(&mut flick_stick, &positions) + 90.0).abs() < 0.5);
+    }
+}
@@ -0,0 +1,63 @@
+use super::*;
+use crate::fusion::GravityEstimator;
+
+/// Space gyroscope rates are expressed in.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub enum GyroSpace {
+    /// Rates relative to controller, as reported by its sensor.
+    #[default]
+    Local,
+    /// Yaw is rotation around gravity axis, no matter how controller is held.
+    World,
+    /// Like world space, but yaw also follows controller's own yaw and roll axes
+    /// so turning works naturally whether controller is held flat or upright.
+    Player,
+}
+
+/// Ratio allowing player space yaw to exceed its world space projection, following common gyro aiming guides.
+const PLAYER_SPACE_YAW_RELAX_FACTOR: f32 = 1.41;
+
+/// Converts local gyroscope rates into world or player space yaw and pitch.
+///
+/// Gravity is tracked with [`GravityEstimator`]. Converted yaw replaces gyroscope yaw,
+/// local pitch is kept and roll is cleared, so games using either yaw or roll for turning don't turn twice.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct GyroSpaceConversion {
+    pub space: GyroSpace,
+    estimator: GravityEstimator,
+}
+
+impl GyroSpaceConversion {
+    pub fn new(space: GyroSpace) -> GyroSpaceConversion {
+        GyroSpaceConversion {
+            space,
+            estimator: Default::default(),
+        }
+    }
+}
+
+impl Transform for GyroSpaceConversion {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        let gravity = self.estimator.update(controller_data).gravity;
+
+        let pitch = controller_data.gyroscope_pitch;
+        let yaw = controller_data.gyroscope_yaw;
+        let roll = controller_data.gyroscope_roll;
+        // Gravity estimate points along accelerometer reading at rest (-Y when lying flat),
+        // so flat controller keeps its local yaw.
+        let world_yaw = -(gravity[0] * pitch + gravity[1] * yaw + gravity[2] * roll);
+
+        let converted_yaw = match self.space {
+            GyroSpace::Local => return,
+            GyroSpace::World => world_yaw,
+            GyroSpace::Player => {
+                let player_yaw = -(gravity[1] * yaw + gravity[2] * roll);
+                let limit = (yaw * yaw + roll * roll).sqrt();
+                player_yaw.signum() * (player_yaw.abs() * PLAYER_SPACE_YAW_RELAX_FACTOR).min(limit)
+            }
+        };
+
+        controller_data.gyroscope_yaw = converted_yaw;
+        controller_data.gyroscope_roll = 0.0;
+    }
+}
@@ -0,0 +1,74 @@
+use super::*;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+/// Maximum number of samples kept, bounds memory used by long delays at high sample rates.
+const MAX_BUFFERED_SAMPLES: usize = 4096;
+
+/// Delays controller data by fixed amount, for testing how game handles additional controller latency.
+///
+/// Samples are buffered and each update outputs the newest sample at least `delay` older
+/// (by `motion_data_timestamp`) than the incoming one, keeping sample's own timestamp.
+/// Until enough history is buffered, the oldest sample is repeated.
+/// As output is driven by incoming samples, delay is only accurate for devices reporting at steady rate.
+#[derive(Clone, Debug)]
+pub struct Latency {
+    pub delay: Duration,
+    buffer: VecDeque<ControllerData>,
+}
+
+impl Latency {
+    /// Creates latency transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `delay` - added latency.
+    pub fn new(delay: Duration) -> Latency {
+        Latency {
+            delay,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// Drops buffered samples, e.g. after changing delay.
+    pub fn clear(&mut self) {
+        self.buffer.clear();
+    }
+}
+
+impl Transform for Latency {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        if self.delay == Duration::from_secs(0) {
+            self.buffer.clear();
+            return;
+        }
+
+        let timestamp = controller_data.motion_data_timestamp;
+        // Timestamps went back (e.g. device reconnected), history no longer applies.
+        if self
+            .buffer
+            .back()
+            .is_some_and(|latest| latest.motion_data_timestamp > timestamp)
+        {
+            self.buffer.clear();
+        }
+        if self.buffer.len() == MAX_BUFFERED_SAMPLES {
+            self.buffer.pop_front();
+        }
+        self.buffer.push_back(*controller_data);
+
+        let due = timestamp.saturating_sub(self.delay.as_micros() as u64);
+        // Samples older than the newest due one are no longer needed.
+        while self
+            .buffer
+            .get(1)
+            .is_some_and(|next| next.motion_data_timestamp <= due)
+        {
+            self.buffer.pop_front();
+        }
+
+        if let Some(delayed) = self.buffer.front() {
+            *controller_data = *delayed;
+        }
+    }
+}
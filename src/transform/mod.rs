@@ -0,0 +1,172 @@
+//! Transforms modifying controller data before it is sent to clients.
+
+mod axis_remap;
+mod calibration;
+mod flick_stick;
+mod gyro_space;
+mod latency;
+mod motion_clamp;
+mod one_handed;
+#[cfg(feature = "scripting")]
+mod script;
+mod sensitivity;
+mod southpaw;
+mod stick_touch;
+mod touch_scale;
+mod trigger_buttons;
+mod turbo;
+
+pub use axis_remap::*;
+pub use calibration::*;
+pub use flick_stick::*;
+pub use gyro_space::*;
+pub use latency::*;
+pub use motion_clamp::*;
+pub use one_handed::*;
+#[cfg(feature = "scripting")]
+pub use script::*;
+pub use sensitivity::*;
+pub use southpaw::*;
+pub use stick_touch::*;
+pub use touch_scale::*;
+pub use trigger_buttons::*;
+pub use turbo::*;
+
+use crate::protocol::*;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Modifies controller data of a slot.
+///
+/// Transforms may keep state between calls, time should be derived from `motion_data_timestamp`
+/// so that results stay deterministic.
+pub trait Transform: Send {
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData);
+}
+
+impl<F> Transform for F
+where
+    F: FnMut(u8, &mut ControllerData) + Send,
+{
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData) {
+        self(slot_number, controller_data)
+    }
+}
+
+impl Transform for Box<dyn Transform> {
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData) {
+        (**self).apply(slot_number, controller_data)
+    }
+}
+
+/// Sequence of transforms applied in order.
+#[derive(Default)]
+pub struct Pipeline {
+    stages: Vec<Box<dyn Transform>>,
+}
+
+impl Pipeline {
+    /// Creates new, empty pipeline.
+    pub fn new() -> Pipeline {
+        Default::default()
+    }
+
+    /// Appends transform to the end of pipeline.
+    pub fn push<T: Transform + 'static>(&mut self, transform: T) {
+        self.stages.push(Box::new(transform));
+    }
+
+    /// Returns pipeline with transform appended.
+    pub fn with<T: Transform + 'static>(mut self, transform: T) -> Pipeline {
+        self.push(transform);
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.stages.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+}
+
+impl Transform for Pipeline {
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData) {
+        for stage in self.stages.iter_mut() {
+            stage.apply(slot_number, controller_data);
+        }
+    }
+}
+
+/// Enables or disables associated `Toggle` transform from any thread.
+#[derive(Clone, Debug)]
+pub struct ToggleHandle {
+    enabled: Arc<AtomicBool>,
+}
+
+impl ToggleHandle {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::SeqCst);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// Flips enabled state, returns new state.
+    pub fn toggle(&self) -> bool {
+        !self.enabled.fetch_xor(true, Ordering::SeqCst)
+    }
+}
+
+/// Wraps transform so it can be switched on and off at runtime without replacing slot's pipeline.
+pub struct Toggle<T: Transform> {
+    transform: T,
+    handle: ToggleHandle,
+}
+
+impl<T: Transform> Toggle<T> {
+    /// Wraps transform, returns wrapper and handle switching it.
+    pub fn new(transform: T, enabled: bool) -> (Toggle<T>, ToggleHandle) {
+        let handle = ToggleHandle {
+            enabled: Arc::new(AtomicBool::new(enabled)),
+        };
+
+        (
+            Toggle {
+                transform,
+                handle: handle.clone(),
+            },
+            handle,
+        )
+    }
+
+    pub fn handle(&self) -> ToggleHandle {
+        self.handle.clone()
+    }
+}
+
+impl<T: Transform> Transform for Toggle<T> {
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData) {
+        if self.handle.is_enabled() {
+            self.transform.apply(slot_number, controller_data);
+        }
+    }
+}
+
+/// Stick axis value scaled to `-1.0..=1.0` range.
+pub(crate) fn stick_axis(value: u8) -> f32 {
+    ((value as f32 - 128.0) / 127.0).clamp(-1.0, 1.0)
+}
+
+/// Time elapsed between two motion timestamps in seconds, clamped to sane range
+/// so pauses and timestamp resets don't cause jumps.
+pub(crate) fn elapsed_seconds(previous_timestamp: Option<u64>, timestamp: u64) -> f32 {
+    match previous_timestamp {
+        Some(previous_timestamp) if timestamp > previous_timestamp => {
+            ((timestamp - previous_timestamp) as f32 / 1_000_000.0).min(0.1)
+        }
+        _ => 0.0,
+    }
+}
@@ -0,0 +1,120 @@
+use super::*;
+use std::sync::atomic::AtomicU64;
+
+/// Default accelerometer range (in g).
+pub const DEFAULT_ACCELEROMETER_RANGE: f32 = 8.0;
+
+/// Default gyroscope range (in degrees per second).
+pub const DEFAULT_GYROSCOPE_RANGE: f32 = 2000.0;
+
+/// Counts samples `MotionClamp` had to clamp, readable from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct SaturationCounters {
+    accelerometer: Arc<AtomicU64>,
+    gyroscope: Arc<AtomicU64>,
+}
+
+impl SaturationCounters {
+    /// Samples with at least one accelerometer axis clamped.
+    pub fn accelerometer(&self) -> u64 {
+        self.accelerometer.load(Ordering::Relaxed)
+    }
+
+    /// Samples with at least one gyroscope axis clamped.
+    pub fn gyroscope(&self) -> u64 {
+        self.gyroscope.load(Ordering::Relaxed)
+    }
+
+    pub fn reset(&self) {
+        self.accelerometer.store(0, Ordering::Relaxed);
+        self.gyroscope.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Clamps accelerometer and gyroscope readings to physical range of sensor,
+/// so spikes (e.g. caused by glitches of sensor's bus) don't reach sensor fusion.
+///
+/// NaN readings are replaced with zero and counted as clamped.
+pub struct MotionClamp {
+    /// Maximum absolute acceleration (in g).
+    pub accelerometer_range: f32,
+    /// Maximum absolute angular velocity (in degrees per second).
+    pub gyroscope_range: f32,
+    counters: SaturationCounters,
+}
+
+impl MotionClamp {
+    /// Creates new motion clamp.
+    ///
+    /// # Arguments
+    ///
+    /// * `accelerometer_range` - maximum absolute acceleration (in g), e.g. 8 for ±8 g sensor.
+    /// * `gyroscope_range` - maximum absolute angular velocity (in degrees per second), e.g. 2000 for ±2000 dps sensor.
+    pub fn new(accelerometer_range: f32, gyroscope_range: f32) -> MotionClamp {
+        MotionClamp {
+            accelerometer_range,
+            gyroscope_range,
+            counters: Default::default(),
+        }
+    }
+
+    /// Returns counters of clamped samples, shared with transform.
+    pub fn counters(&self) -> SaturationCounters {
+        self.counters.clone()
+    }
+}
+
+impl Default for MotionClamp {
+    fn default() -> MotionClamp {
+        MotionClamp::new(DEFAULT_ACCELEROMETER_RANGE, DEFAULT_GYROSCOPE_RANGE)
+    }
+}
+
+/// Clamps values to given range, returns whether any of them had to be changed.
+fn clamp_axes(values: [&mut f32; 3], range: f32) -> bool {
+    let mut saturated = false;
+    for value in values {
+        let clamped = if value.is_nan() {
+            0.0
+        } else {
+            value.clamp(-range, range)
+        };
+
+        // NaN never equals clamped value.
+        if clamped != *value {
+            saturated = true;
+            *value = clamped;
+        }
+    }
+
+    saturated
+}
+
+impl Transform for MotionClamp {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        let accelerometer_range = self.accelerometer_range.abs();
+        let gyroscope_range = self.gyroscope_range.abs();
+
+        if clamp_axes(
+            [
+                &mut controller_data.accelerometer_x,
+                &mut controller_data.accelerometer_y,
+                &mut controller_data.accelerometer_z,
+            ],
+            accelerometer_range,
+        ) {
+            self.counters.accelerometer.fetch_add(1, Ordering::Relaxed);
+        }
+
+        if clamp_axes(
+            [
+                &mut controller_data.gyroscope_pitch,
+                &mut controller_data.gyroscope_yaw,
+                &mut controller_data.gyroscope_roll,
+            ],
+            gyroscope_range,
+        ) {
+            self.counters.gyroscope.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
@@ -0,0 +1,142 @@
+use super::*;
+use crate::buttons::Button;
+
+/// While all `held` buttons are pressed, they are released and `output` buttons are pressed instead.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Combo {
+    pub held: Vec<Button>,
+    pub output: Vec<Button>,
+}
+
+impl Combo {
+    pub fn new(held: &[Button], output: &[Button]) -> Combo {
+        Combo {
+            held: held.to_vec(),
+            output: output.to_vec(),
+        }
+    }
+}
+
+/// Folds controller onto one hand.
+///
+/// Applies (in order) button combos, toggle-to-hold latching and stick ramping:
+/// * combos let buttons out of reach be pressed with a modifier (e.g. L1 + Cross acts as Triangle),
+/// * latched buttons toggle on each press instead of requiring to be held,
+/// * stick ramping limits how fast stick output can change, smoothing abrupt single-thumb movements.
+#[derive(Clone, Debug, Default)]
+pub struct OneHanded {
+    pub combos: Vec<Combo>,
+    pub latched: Vec<Button>,
+    /// Maximum stick output change per second, as fraction of full range (`None` disables ramping).
+    pub stick_ramp: Option<f32>,
+    latch_states: Vec<(Button, bool, bool)>,
+    sticks: Option<[f32; 4]>,
+    previous_timestamp: Option<u64>,
+}
+
+impl OneHanded {
+    pub fn new() -> OneHanded {
+        Default::default()
+    }
+
+    /// Returns transform with combo added.
+    pub fn with_combo(mut self, held: &[Button], output: &[Button]) -> OneHanded {
+        self.combos.push(Combo::new(held, output));
+        self
+    }
+
+    /// Returns transform with button latching enabled.
+    pub fn with_latched(mut self, button: Button) -> OneHanded {
+        self.latched.push(button);
+        self
+    }
+
+    /// Returns transform with stick ramping enabled.
+    pub fn with_stick_ramp(mut self, stick_ramp: f32) -> OneHanded {
+        self.stick_ramp = Some(stick_ramp);
+        self
+    }
+
+    fn apply_combos(&self, controller_data: &mut ControllerData) {
+        let original = *controller_data;
+        for combo in self.combos.iter() {
+            let active = !combo.held.is_empty()
+                && combo
+                    .held
+                    .iter()
+                    .all(|button| button.is_pressed(&original, 128));
+            if active {
+                for &button in combo.held.iter() {
+                    button.set(controller_data, false);
+                }
+                for &button in combo.output.iter() {
+                    button.set(controller_data, true);
+                }
+            }
+        }
+    }
+
+    fn apply_latching(&mut self, controller_data: &mut ControllerData) {
+        for &button in self.latched.iter() {
+            let index = match self
+                .latch_states
+                .iter()
+                .position(|&(latched_button, _, _)| latched_button == button)
+            {
+                Some(index) => index,
+                None => {
+                    self.latch_states.push((button, false, false));
+                    self.latch_states.len() - 1
+                }
+            };
+
+            let (_, was_pressed, latched) = &mut self.latch_states[index];
+            let is_pressed = button.is_pressed(controller_data, 128);
+            if is_pressed && !*was_pressed {
+                *latched = !*latched;
+            }
+            *was_pressed = is_pressed;
+
+            button.set(controller_data, *latched);
+        }
+    }
+
+    fn apply_stick_ramp(&mut self, controller_data: &mut ControllerData) {
+        let elapsed = elapsed_seconds(
+            self.previous_timestamp,
+            controller_data.motion_data_timestamp,
+        );
+        self.previous_timestamp = Some(controller_data.motion_data_timestamp);
+
+        let stick_ramp = match self.stick_ramp {
+            Some(stick_ramp) => stick_ramp,
+            None => return,
+        };
+
+        let targets = [
+            controller_data.left_stick_x as f32,
+            controller_data.left_stick_y as f32,
+            controller_data.right_stick_x as f32,
+            controller_data.right_stick_y as f32,
+        ];
+        let sticks = self.sticks.get_or_insert(targets);
+
+        let max_change = stick_ramp * 255.0 * elapsed;
+        for (stick, target) in sticks.iter_mut().zip(targets.iter()) {
+            *stick += (target - *stick).clamp(-max_change, max_change);
+        }
+
+        controller_data.left_stick_x = sticks[0].round() as u8;
+        controller_data.left_stick_y = sticks[1].round() as u8;
+        controller_data.right_stick_x = sticks[2].round() as u8;
+        controller_data.right_stick_y = sticks[3].round() as u8;
+    }
+}
+
+impl Transform for OneHanded {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        self.apply_combos(controller_data);
+        self.apply_latching(controller_data);
+        self.apply_stick_ramp(controller_data);
+    }
+}
@@ -0,0 +1,197 @@
+use super::*;
+use rhai::{Engine, Map, Scope, AST};
+use std::io::{Error, ErrorKind, Result};
+use std::path::Path;
+use std::sync::atomic::AtomicU64;
+use std::sync::Mutex;
+
+/// Maximum amount of operations script may run per sample, stops runaway loops.
+pub const MAX_SCRIPT_OPERATIONS: u64 = 100_000;
+
+/// Errors of associated `Script` transform, readable from any thread.
+#[derive(Clone, Debug, Default)]
+pub struct ScriptStatus {
+    errors: Arc<AtomicU64>,
+    last_error: Arc<Mutex<Option<String>>>,
+}
+
+impl ScriptStatus {
+    /// Samples script failed on (and left unchanged).
+    pub fn errors(&self) -> u64 {
+        self.errors.load(Ordering::Relaxed)
+    }
+
+    /// Message of the latest error, `None` if script never failed.
+    pub fn last_error(&self) -> Option<String> {
+        self.last_error.lock().unwrap().clone()
+    }
+
+    fn record_error(&self, error: String) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+}
+
+macro_rules! register_fields {
+    ($engine:expr, $type:ty, $from:expr, $to:expr, $($field:ident),* $(,)?) => {
+        $(
+            $engine.register_get_set(
+                stringify!($field),
+                |data: &mut ControllerData| $from(data.$field),
+                |data: &mut ControllerData, value: $type| data.$field = $to(value),
+            );
+        )*
+    };
+}
+
+fn new_engine() -> Engine {
+    let mut engine = Engine::new();
+    engine.set_max_operations(MAX_SCRIPT_OPERATIONS);
+    engine.set_max_call_levels(16);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(1024);
+    engine.set_max_array_size(1024);
+    engine.set_max_map_size(1024);
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+
+    engine.register_type_with_name::<ControllerData>("Pad");
+    register_fields!(
+        engine,
+        bool,
+        |value| value,
+        |value| value,
+        connected,
+        d_pad_left,
+        d_pad_down,
+        d_pad_right,
+        d_pad_up,
+        start,
+        right_stick_button,
+        left_stick_button,
+        select,
+        square,
+        cross,
+        circle,
+        triangle,
+        r1,
+        l1,
+        r2,
+        l2,
+    );
+    register_fields!(
+        engine,
+        i64,
+        |value: u8| value as i64,
+        |value: i64| value.clamp(0, 255) as u8,
+        ps,
+        touch,
+        left_stick_x,
+        left_stick_y,
+        right_stick_x,
+        right_stick_y,
+        analog_d_pad_left,
+        analog_d_pad_down,
+        analog_d_pad_right,
+        analog_d_pad_up,
+        analog_square,
+        analog_triangle,
+        analog_cross,
+        analog_circle,
+        analog_r1,
+        analog_l1,
+        analog_r2,
+        analog_l2,
+    );
+    register_fields!(
+        engine,
+        f64,
+        |value: f32| value as f64,
+        |value: f64| value as f32,
+        accelerometer_x,
+        accelerometer_y,
+        accelerometer_z,
+        gyroscope_pitch,
+        gyroscope_yaw,
+        gyroscope_roll,
+    );
+    engine.register_get("timestamp", |data: &mut ControllerData| {
+        data.motion_data_timestamp as i64
+    });
+
+    engine
+}
+
+/// Runs user's Rhai script on every sample (feature `scripting`).
+///
+/// Script sees variables:
+/// * `pad` - controller data, with buttons (e.g. `pad.cross`), sticks and analog buttons (0–255,
+///   e.g. `pad.left_stick_x`), motion (e.g. `pad.gyroscope_yaw`) and read-only `pad.timestamp` (in microseconds).
+/// * `slot` - slot number.
+/// * `state` - object map kept between samples.
+///
+/// Script can't access anything outside of these variables and is stopped after `MAX_SCRIPT_OPERATIONS`.
+/// Samples script fails on are left unchanged, see `ScriptStatus`.
+///
+/// ```rhai
+/// // Swap cross and circle, double yaw.
+/// let cross = pad.cross;
+/// pad.cross = pad.circle;
+/// pad.circle = cross;
+/// pad.gyroscope_yaw *= 2.0;
+/// ```
+pub struct Script {
+    engine: Engine,
+    ast: AST,
+    state: Map,
+    status: ScriptStatus,
+}
+
+impl Script {
+    /// Compiles script, fails with `ErrorKind::InvalidInput` error if it's not valid.
+    pub fn new(source: &str) -> Result<Script> {
+        let engine = new_engine();
+        let ast = engine
+            .compile(source)
+            .map_err(|error| Error::new(ErrorKind::InvalidInput, error.to_string()))?;
+
+        Ok(Script {
+            engine,
+            ast,
+            state: Map::new(),
+            status: Default::default(),
+        })
+    }
+
+    /// Reads and compiles script from file.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Script> {
+        Script::new(&std::fs::read_to_string(path)?)
+    }
+
+    /// Returns handle reporting script's errors.
+    pub fn status(&self) -> ScriptStatus {
+        self.status.clone()
+    }
+}
+
+impl Transform for Script {
+    fn apply(&mut self, slot_number: u8, controller_data: &mut ControllerData) {
+        let mut scope = Scope::new();
+        scope.push("pad", *controller_data);
+        scope.push("slot", slot_number as i64);
+        scope.push("state", std::mem::take(&mut self.state));
+
+        let result = self.engine.run_ast_with_scope(&mut scope, &self.ast);
+
+        self.state = scope.get_value::<Map>("state").unwrap_or_default();
+        match result {
+            Ok(()) => {
+                if let Some(scripted) = scope.get_value::<ControllerData>("pad") {
+                    *controller_data = scripted;
+                }
+            }
+            Err(error) => self.status.record_error(error.to_string()),
+        }
+    }
+}
@@ -0,0 +1,190 @@
+use super::*;
+use crate::buttons::Button;
+use std::sync::atomic::AtomicUsize;
+
+/// Named gyroscope sensitivity.
+///
+/// Every rate is scaled by `scale` after applying response curve:
+/// `reference_rate * (|rate| / reference_rate) ^ exponent`, so exponents above 1 slow down small,
+/// precise movements while keeping fast turns fast.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SensitivityProfile {
+    pub name: String,
+    /// Multipliers of pitch, yaw and roll rates.
+    pub scale: [f32; 3],
+    /// Response curve exponent, 1 is linear.
+    pub exponent: f32,
+    /// Rate (in degrees per second) curve leaves unchanged.
+    pub reference_rate: f32,
+}
+
+const DEFAULT_REFERENCE_RATE: f32 = 90.0;
+
+impl SensitivityProfile {
+    /// Creates linear profile with the same scale on all axes.
+    pub fn new(name: &str, scale: f32) -> SensitivityProfile {
+        SensitivityProfile {
+            name: name.to_string(),
+            scale: [scale; 3],
+            exponent: 1.0,
+            reference_rate: DEFAULT_REFERENCE_RATE,
+        }
+    }
+
+    /// Returns profile with given response curve exponent.
+    pub fn with_curve(mut self, exponent: f32) -> SensitivityProfile {
+        self.exponent = exponent;
+        self
+    }
+
+    /// Slow profile with curve favoring small movements.
+    pub fn precision() -> SensitivityProfile {
+        SensitivityProfile::new("precision", 0.5).with_curve(1.5)
+    }
+
+    /// Unchanged rates.
+    pub fn normal() -> SensitivityProfile {
+        SensitivityProfile::new("normal", 1.0)
+    }
+
+    /// Fast profile for quick turns.
+    pub fn fast() -> SensitivityProfile {
+        SensitivityProfile::new("fast", 2.0)
+    }
+
+    fn apply_to(&self, rate: f32, axis: usize) -> f32 {
+        let curved = if self.exponent == 1.0 || self.reference_rate <= 0.0 {
+            rate
+        } else {
+            rate.signum()
+                * self.reference_rate
+                * (rate.abs() / self.reference_rate).powf(self.exponent)
+        };
+
+        curved * self.scale[axis]
+    }
+}
+
+/// Switches active profile of associated `Sensitivity` transform from any thread.
+#[derive(Clone, Debug)]
+pub struct SensitivityHandle {
+    active: Arc<AtomicUsize>,
+    names: Arc<Vec<String>>,
+}
+
+impl SensitivityHandle {
+    /// Index of active profile.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::SeqCst)
+    }
+
+    /// Name of active profile.
+    pub fn active_name(&self) -> &str {
+        &self.names[self.active()]
+    }
+
+    /// Activates profile with given index.
+    pub fn set_active(&self, index: usize) {
+        assert!(index < self.names.len());
+
+        self.active.store(index, Ordering::SeqCst);
+    }
+
+    /// Activates profile with given name, returns `false` if there's no such profile.
+    pub fn select(&self, name: &str) -> bool {
+        match self
+            .names
+            .iter()
+            .position(|profile_name| profile_name == name)
+        {
+            Some(index) => {
+                self.set_active(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Activates next profile (wrapping around), returns its index.
+    pub fn next(&self) -> usize {
+        let count = self.names.len();
+        let previous = self
+            .active
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |active| {
+                Some((active + 1) % count)
+            })
+            .unwrap();
+
+        (previous + 1) % count
+    }
+}
+
+/// Scales gyroscope rates according to active sensitivity profile.
+pub struct Sensitivity {
+    profiles: Vec<SensitivityProfile>,
+    handle: SensitivityHandle,
+    switch_combo: Vec<Button>,
+    combo_held: bool,
+}
+
+impl Sensitivity {
+    /// Creates transform with given profiles, first one is active.
+    /// Returns transform and handle switching its active profile.
+    pub fn new(profiles: Vec<SensitivityProfile>) -> (Sensitivity, SensitivityHandle) {
+        assert!(!profiles.is_empty());
+
+        let handle = SensitivityHandle {
+            active: Arc::new(AtomicUsize::new(0)),
+            names: Arc::new(
+                profiles
+                    .iter()
+                    .map(|profile| profile.name.clone())
+                    .collect(),
+            ),
+        };
+
+        (
+            Sensitivity {
+                profiles,
+                handle: handle.clone(),
+                switch_combo: vec![],
+                combo_held: false,
+            },
+            handle,
+        )
+    }
+
+    /// Returns transform switching to next profile whenever all given buttons get pressed together.
+    pub fn with_switch_combo(mut self, buttons: &[Button]) -> Sensitivity {
+        self.switch_combo = buttons.to_vec();
+        self
+    }
+
+    pub fn handle(&self) -> SensitivityHandle {
+        self.handle.clone()
+    }
+
+    pub fn profiles(&self) -> &[SensitivityProfile] {
+        &self.profiles
+    }
+}
+
+impl Transform for Sensitivity {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        if !self.switch_combo.is_empty() {
+            let combo_held = self
+                .switch_combo
+                .iter()
+                .all(|button| button.is_pressed(controller_data, 128));
+            if combo_held && !self.combo_held {
+                self.handle.next();
+            }
+            self.combo_held = combo_held;
+        }
+
+        let profile = &self.profiles[self.handle.active()];
+        controller_data.gyroscope_pitch = profile.apply_to(controller_data.gyroscope_pitch, 0);
+        controller_data.gyroscope_yaw = profile.apply_to(controller_data.gyroscope_yaw, 1);
+        controller_data.gyroscope_roll = profile.apply_to(controller_data.gyroscope_roll, 2);
+    }
+}
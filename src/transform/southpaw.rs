@@ -0,0 +1,53 @@
+use super::*;
+use std::mem::swap;
+
+/// Left-handed mode: mirrors controller layout left to right.
+///
+/// Swaps sticks, swaps d-pad with face buttons (up with triangle, right with circle, down with cross,
+/// left with square) and optionally shoulder buttons. Motion is mirrored too: X acceleration, yaw and roll
+/// change sign, so tilting controller right behaves like tilting mirrored controller left.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Southpaw {
+    pub swap_shoulders: bool,
+    pub mirror_motion: bool,
+}
+
+impl Default for Southpaw {
+    fn default() -> Southpaw {
+        Southpaw {
+            swap_shoulders: true,
+            mirror_motion: true,
+        }
+    }
+}
+
+impl Transform for Southpaw {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        let data = controller_data;
+        swap(&mut data.left_stick_x, &mut data.right_stick_x);
+        swap(&mut data.left_stick_y, &mut data.right_stick_y);
+        swap(&mut data.left_stick_button, &mut data.right_stick_button);
+
+        swap(&mut data.d_pad_up, &mut data.triangle);
+        swap(&mut data.d_pad_right, &mut data.circle);
+        swap(&mut data.d_pad_down, &mut data.cross);
+        swap(&mut data.d_pad_left, &mut data.square);
+        swap(&mut data.analog_d_pad_up, &mut data.analog_triangle);
+        swap(&mut data.analog_d_pad_right, &mut data.analog_circle);
+        swap(&mut data.analog_d_pad_down, &mut data.analog_cross);
+        swap(&mut data.analog_d_pad_left, &mut data.analog_square);
+
+        if self.swap_shoulders {
+            swap(&mut data.l1, &mut data.r1);
+            swap(&mut data.l2, &mut data.r2);
+            swap(&mut data.analog_l1, &mut data.analog_r1);
+            swap(&mut data.analog_l2, &mut data.analog_r2);
+        }
+
+        if self.mirror_motion {
+            data.accelerometer_x = -data.accelerometer_x;
+            data.gyroscope_yaw = -data.gyroscope_yaw;
+            data.gyroscope_roll = -data.gyroscope_roll;
+        }
+    }
+}
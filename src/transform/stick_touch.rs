@@ -0,0 +1,91 @@
+use super::*;
+
+/// Synthesizes touchpad movement from right stick input.
+///
+/// While stick is deflected beyond dead zone, first touch is active and moves in stick direction,
+/// releasing stick lifts the finger. Position is kept between touches, so stick drives touchpad like a cursor.
+#[derive(Copy, Clone, Debug)]
+pub struct StickTouch {
+    /// Touch movement at full stick deflection, in touchpad units per second.
+    pub speed: f32,
+    /// Stick dead zone as fraction of full deflection (0.0 - 1.0).
+    pub dead_zone: f32,
+    /// Touch coordinates are kept in `0..width` range.
+    pub width: u16,
+    /// Touch coordinates are kept in `0..height` range.
+    pub height: u16,
+    /// Clear right stick after synthesizing touch, so it doesn't move camera at the same time.
+    pub consume_stick: bool,
+    position: (f32, f32),
+    touch_id: u8,
+    touching: bool,
+    previous_timestamp: Option<u64>,
+}
+
+impl Default for StickTouch {
+    fn default() -> StickTouch {
+        StickTouch::new(DS4_TOUCHPAD_WIDTH, DS4_TOUCHPAD_HEIGHT)
+    }
+}
+
+impl StickTouch {
+    /// Creates new transform for touchpad of given size, touch starts in its center.
+    pub fn new(width: u16, height: u16) -> StickTouch {
+        StickTouch {
+            speed: 1000.0,
+            dead_zone: 0.15,
+            width,
+            height,
+            consume_stick: false,
+            position: (width as f32 / 2.0, height as f32 / 2.0),
+            touch_id: 0,
+            touching: false,
+            previous_timestamp: None,
+        }
+    }
+}
+
+impl Transform for StickTouch {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        let elapsed = elapsed_seconds(
+            self.previous_timestamp,
+            controller_data.motion_data_timestamp,
+        );
+        self.previous_timestamp = Some(controller_data.motion_data_timestamp);
+
+        let x = stick_axis(controller_data.right_stick_x);
+        // Stick Y axis points up, touchpad Y axis points down.
+        let y = -stick_axis(controller_data.right_stick_y);
+        let magnitude = (x * x + y * y).sqrt();
+
+        if magnitude <= self.dead_zone {
+            self.touching = false;
+            controller_data.first_touch.active = false;
+            return;
+        }
+
+        if !self.touching {
+            self.touching = true;
+            self.touch_id = self.touch_id.wrapping_add(1);
+        }
+
+        // Rescale so movement starts from zero at dead zone edge.
+        let scale = (magnitude - self.dead_zone) / (1.0 - self.dead_zone) / magnitude;
+        let max_x = self.width.saturating_sub(1) as f32;
+        let max_y = self.height.saturating_sub(1) as f32;
+        self.position.0 = (self.position.0 + x * scale * self.speed * elapsed).clamp(0.0, max_x);
+        self.position.1 = (self.position.1 + y * scale * self.speed * elapsed).clamp(0.0, max_y);
+
+        controller_data.first_touch = TouchData {
+            active: true,
+            id: self.touch_id,
+            position_x: self.position.0.round() as u16,
+            position_y: self.position.1.round() as u16,
+        };
+
+        if self.consume_stick {
+            controller_data.right_stick_x = 128;
+            controller_data.right_stick_y = 128;
+        }
+    }
+}
@@ -0,0 +1,92 @@
+use super::*;
+
+/// Resolution of a touch surface.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TouchSurface {
+    pub width: u16,
+    pub height: u16,
+}
+
+impl TouchSurface {
+    /// DS4 touchpad, the coordinate space DSU clients expect.
+    pub const DS4: TouchSurface = TouchSurface {
+        width: DS4_TOUCHPAD_WIDTH,
+        height: DS4_TOUCHPAD_HEIGHT,
+    };
+
+    /// DualSense touchpad.
+    pub const DUALSENSE: TouchSurface = TouchSurface {
+        width: 1920,
+        height: 1080,
+    };
+
+    /// Steam Deck touchscreen.
+    pub const STEAM_DECK_SCREEN: TouchSurface = TouchSurface {
+        width: 1280,
+        height: 800,
+    };
+
+    pub fn new(width: u16, height: u16) -> TouchSurface {
+        TouchSurface { width, height }
+    }
+}
+
+/// How differing aspect ratios are handled when scaling touch coordinates.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum AspectMode {
+    /// Scale axes independently, so whole source surface covers whole target surface.
+    Stretch,
+    /// Preserve aspect ratio, source surface is centered within target surface.
+    Fit,
+    /// Preserve aspect ratio, source surface covers whole target surface and overflowing edges are clamped.
+    Fill,
+}
+
+/// Maps touch coordinates from source device resolution into another (by default DS4) coordinate space.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TouchScale {
+    pub source: TouchSurface,
+    pub target: TouchSurface,
+    pub aspect_mode: AspectMode,
+}
+
+impl TouchScale {
+    /// Creates transform mapping given surface into DS4 touchpad coordinate space.
+    pub fn new(source: TouchSurface, aspect_mode: AspectMode) -> TouchScale {
+        TouchScale {
+            source,
+            target: TouchSurface::DS4,
+            aspect_mode,
+        }
+    }
+
+    fn scale_touch(&self, touch: &mut TouchData) {
+        let source_width = self.source.width.max(1) as f32;
+        let source_height = self.source.height.max(1) as f32;
+        let target_width = self.target.width.max(1) as f32;
+        let target_height = self.target.height.max(1) as f32;
+
+        let scale_x = target_width / source_width;
+        let scale_y = target_height / source_height;
+        let (scale_x, scale_y) = match self.aspect_mode {
+            AspectMode::Stretch => (scale_x, scale_y),
+            AspectMode::Fit => (scale_x.min(scale_y), scale_x.min(scale_y)),
+            AspectMode::Fill => (scale_x.max(scale_y), scale_x.max(scale_y)),
+        };
+
+        let offset_x = (target_width - source_width * scale_x) / 2.0;
+        let offset_y = (target_height - source_height * scale_y) / 2.0;
+
+        let x = touch.position_x as f32 * scale_x + offset_x;
+        let y = touch.position_y as f32 * scale_y + offset_y;
+        touch.position_x = x.round().clamp(0.0, target_width - 1.0) as u16;
+        touch.position_y = y.round().clamp(0.0, target_height - 1.0) as u16;
+    }
+}
+
+impl Transform for TouchScale {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        self.scale_touch(&mut controller_data.first_touch);
+        self.scale_touch(&mut controller_data.second_touch);
+    }
+}
@@ -0,0 +1,39 @@
+use super::*;
+use crate::buttons::Hysteresis;
+
+/// Derives digital R2/L2 buttons from analog trigger values.
+///
+/// Useful for sources only reporting analog triggers. Digital buttons already pressed by source stay pressed.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TriggerButtons {
+    r2: Hysteresis,
+    l2: Hysteresis,
+}
+
+impl Default for TriggerButtons {
+    fn default() -> TriggerButtons {
+        TriggerButtons::new(160, 96)
+    }
+}
+
+impl TriggerButtons {
+    /// Creates new transform.
+    ///
+    /// # Arguments
+    ///
+    /// * `press_threshold` - analog value at or above which trigger becomes pressed.
+    /// * `release_threshold` - analog value at or below which trigger becomes released, must be lower than `press_threshold`.
+    pub fn new(press_threshold: u8, release_threshold: u8) -> TriggerButtons {
+        TriggerButtons {
+            r2: Hysteresis::new(press_threshold, release_threshold),
+            l2: Hysteresis::new(press_threshold, release_threshold),
+        }
+    }
+}
+
+impl Transform for TriggerButtons {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        controller_data.r2 |= self.r2.update(controller_data.analog_r2);
+        controller_data.l2 |= self.l2.update(controller_data.analog_l2);
+    }
+}
@@ -0,0 +1,164 @@
+use super::*;
+use crate::buttons::Button;
+use std::time::Instant;
+
+/// Source of current time in microseconds, see `Turbo::with_clock`.
+pub type Clock = Arc<dyn Fn() -> u64 + Send + Sync>;
+
+/// Turns held buttons into pulses.
+///
+/// Pulses are driven by motion data timestamps, so the same input always produces the same output:
+/// each pulse starts pressed at the moment button was pressed and is held for half of the period.
+/// Updates not advancing motion timestamp (e.g. from producers without motion, which send 0) are timed by clock instead.
+#[derive(Clone)]
+pub struct Turbo {
+    pub buttons: Vec<Button>,
+    /// Pulses per second.
+    pub rate: f32,
+    clock: Clock,
+    /// Motion timestamp and clock time of previous update.
+    previous_update: Option<(u64, u64)>,
+    /// Time elapsed over all updates, in microseconds.
+    time: u64,
+    press_timestamps: Vec<Option<u64>>,
+}
+
+impl std::fmt::Debug for Turbo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Turbo")
+            .field("buttons", &self.buttons)
+            .field("rate", &self.rate)
+            .field("time", &self.time)
+            .field("press_timestamps", &self.press_timestamps)
+            .finish()
+    }
+}
+
+const DEFAULT_RATE: f32 = 10.0;
+
+impl Turbo {
+    /// Creates turbo for given buttons with default rate of 10 pulses per second.
+    pub fn new(buttons: &[Button]) -> Turbo {
+        Turbo::with_rate(buttons, DEFAULT_RATE)
+    }
+
+    /// Creates turbo for given buttons.
+    ///
+    /// # Arguments
+    ///
+    /// * `buttons` - buttons to pulse while held.
+    /// * `rate` - pulses per second.
+    pub fn with_rate(buttons: &[Button], rate: f32) -> Turbo {
+        assert!(rate > 0.0);
+
+        let epoch = Instant::now();
+        Turbo {
+            buttons: buttons.to_vec(),
+            rate,
+            clock: Arc::new(move || epoch.elapsed().as_micros() as u64),
+            previous_update: None,
+            time: 0,
+            press_timestamps: vec![None; buttons.len()],
+        }
+    }
+
+    /// Times updates not advancing motion timestamp with given clock instead of wall clock.
+    pub fn with_clock(mut self, clock: Clock) -> Turbo {
+        self.clock = clock;
+        self
+    }
+
+    /// Advances time by motion timestamp difference if it increased since previous update, by clock difference otherwise.
+    fn advance(&mut self, timestamp: u64) -> u64 {
+        let now = (self.clock)();
+        let elapsed = match self.previous_update {
+            Some((previous_timestamp, _)) if timestamp > previous_timestamp => {
+                timestamp - previous_timestamp
+            }
+            Some((_, previous_now)) => now.saturating_sub(previous_now),
+            None => 0,
+        };
+        self.previous_update = Some((timestamp, now));
+        self.time += elapsed;
+
+        self.time
+    }
+
+    fn is_pulse_pressed(&self, press_timestamp: u64, timestamp: u64) -> bool {
+        let elapsed = timestamp.saturating_sub(press_timestamp) as f64 / 1_000_000.0;
+        let half_periods = (elapsed * self.rate as f64 * 2.0).floor() as u64;
+
+        half_periods.is_multiple_of(2)
+    }
+}
+
+impl Transform for Turbo {
+    fn apply(&mut self, _slot_number: u8, controller_data: &mut ControllerData) {
+        self.press_timestamps.resize(self.buttons.len(), None);
+
+        let timestamp = self.advance(controller_data.motion_data_timestamp);
+        for index in 0..self.buttons.len() {
+            let button = self.buttons[index];
+            if !button.is_pressed(controller_data, 128) {
+                self.press_timestamps[index] = None;
+                continue;
+            }
+
+            let press_timestamp = *self.press_timestamps[index].get_or_insert(timestamp);
+            let pressed = self.is_pulse_pressed(press_timestamp, timestamp);
+            button.set(controller_data, pressed);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicU64;
+
+    /// Applies turbo to held cross button with given motion timestamps, returns whether cross was reported pressed.
+    fn pulses(turbo: &mut Turbo, timestamps: &[u64], clock: Option<&AtomicU64>) -> Vec<bool> {
+        timestamps
+            .iter()
+            .map(|&timestamp| {
+                let mut controller_data = ControllerData {
+                    cross: true,
+                    motion_data_timestamp: timestamp,
+                    ..Default::default()
+                };
+                turbo.apply(0, &mut controller_data);
+                if let Some(clock) = clock {
+                    clock.fetch_add(25_000, Ordering::SeqCst);
+                }
+
+                controller_data.cross
+            })
+            .collect()
+    }
+
+    #[test]
+    fn pulses_follow_motion_timestamps() {
+        let mut turbo = Turbo::new(&[Button::Cross]);
+        let timestamps: Vec<u64> = (0..8).map(|i| 1_000_000 + i * 25_000).collect();
+
+        // 10 pulses per second are 50 ms pressed and 50 ms released.
+        assert_eq!(
+            pulses(&mut turbo, &timestamps, None),
+            [true, true, false, false, true, true, false, false]
+        );
+    }
+
+    #[test]
+    fn pulses_follow_clock_without_motion_timestamps() {
+        let clock = Arc::new(AtomicU64::new(0));
+        let mut turbo = Turbo::new(&[Button::Cross]).with_clock({
+            let clock = clock.clone();
+            Arc::new(move || clock.load(Ordering::SeqCst))
+        });
+
+        assert_eq!(
+            pulses(&mut turbo, &[0; 8], Some(&clock)),
+            [true, true, false, false, true, true, false, false]
+        );
+    }
+}
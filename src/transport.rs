@@ -0,0 +1,660 @@
+//! Datagram transports used by server and client.
+//!
+//! [`UdpSocket`] is the default transport, [`LoopbackNetwork`] provides in-memory one for tests
+//! and [`FaultyTransport`] injects faults into any other transport.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::collections::{BinaryHeap, HashMap};
+use std::io::{Error, ErrorKind, Result};
+use std::net::{SocketAddr, UdpSocket};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+/// Timeout of blocking receive, background threads check whether to keep running at least this often.
+pub const READ_TIMEOUT: Duration = Duration::from_millis(200);
+
+/// Shortest delay before retrying receive after transient error, doubled with every consecutive one.
+const MIN_RECEIVE_BACKOFF: Duration = Duration::from_millis(1);
+
+/// How endpoint's thread reacts to transport error.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ErrorSeverity {
+    /// Nothing arrived before timeout, call was interrupted or datagram was malformed, retry right away.
+    Retry,
+    /// Transient network condition (e.g. ICMP port unreachable reported as `ConnectionRefused`),
+    /// retry after backoff growing up to [`READ_TIMEOUT`].
+    Backoff,
+    /// Transport can't be used anymore (e.g. it was closed), endpoint's thread stops.
+    Fatal,
+}
+
+/// Classifies transport error, unrecognized errors are treated as transient.
+pub fn error_severity(error: &Error) -> ErrorSeverity {
+    match error.kind() {
+        ErrorKind::Interrupted
+        | ErrorKind::WouldBlock
+        | ErrorKind::TimedOut
+        | ErrorKind::InvalidData => ErrorSeverity::Retry,
+        ErrorKind::NotConnected | ErrorKind::BrokenPipe | ErrorKind::Unsupported => {
+            ErrorSeverity::Fatal
+        }
+        _ => ErrorSeverity::Backoff,
+    }
+}
+
+/// Backoff of endpoint's receive loop after transient errors.
+#[derive(Default)]
+pub(crate) struct ReceiveBackoff {
+    delay: Option<Duration>,
+}
+
+impl ReceiveBackoff {
+    /// Handles receive result, returns received value, `None` after transient error (sleeping before retry
+    /// when it needs backoff) or error back if it's fatal.
+    pub(crate) fn handle<T>(&mut self, result: Result<T>) -> Result<Option<T>> {
+        let error = match result {
+            Ok(value) => {
+                self.delay = None;
+                return Ok(Some(value));
+            }
+            Err(error) => error,
+        };
+
+        match error_severity(&error) {
+            ErrorSeverity::Retry => {
+                self.delay = None;
+                Ok(None)
+            }
+            ErrorSeverity::Backoff => {
+                let delay = self
+                    .delay
+                    .map_or(MIN_RECEIVE_BACKOFF, |delay| (delay * 2).min(READ_TIMEOUT));
+                std::thread::sleep(delay);
+                self.delay = Some(delay);
+                Ok(None)
+            }
+            ErrorSeverity::Fatal => Err(error),
+        }
+    }
+}
+
+/// Sends and receives datagrams.
+pub trait Transport: Send + Sync {
+    /// Sends datagram to given address, returns number of bytes sent.
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize>;
+
+    /// Receives single datagram, blocks at most for [`READ_TIMEOUT`] (unless configured otherwise, see [`UdpOptions`]).
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)>;
+
+    fn local_addr(&self) -> Result<SocketAddr>;
+}
+
+impl Transport for UdpSocket {
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize> {
+        UdpSocket::send_to(self, buf, target)
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        UdpSocket::recv_from(self, buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        UdpSocket::local_addr(self)
+    }
+}
+
+/// Direction of tapped datagram.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    Sent,
+    Received,
+}
+
+/// Callback receiving every raw datagram sent or received, along with its direction and peer address.
+pub type DatagramTap = Box<dyn Fn(Direction, SocketAddr, &[u8]) + Send + Sync>;
+
+/// Address is already bound, usually by another instance of the same program.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PortInUse {
+    pub address: SocketAddr,
+}
+
+impl std::fmt::Display for PortInUse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Address {} is already in use, is another instance running?",
+            self.address
+        )
+    }
+}
+
+impl std::error::Error for PortInUse {}
+
+/// Checks whether error was caused by address being already in use.
+pub fn is_port_in_use_error(error: &Error) -> bool {
+    error.get_ref().is_some_and(|inner| inner.is::<PortInUse>())
+}
+
+/// Options of UDP sockets bound with `bind_udp_with`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct UdpOptions {
+    /// Timeout of blocking receive, background threads check whether to keep running at least this often.
+    pub read_timeout: Duration,
+    /// Timeout of blocking send, `None` blocks until datagram is sent.
+    pub write_timeout: Option<Duration>,
+    /// Sets `SO_REUSEADDR`, so address can be bound again right after previous socket closed.
+    pub reuse_address: bool,
+    /// Sets `SO_BROADCAST`, allowing datagrams to be sent to broadcast addresses.
+    pub broadcast: bool,
+}
+
+impl Default for UdpOptions {
+    fn default() -> UdpOptions {
+        UdpOptions {
+            read_timeout: READ_TIMEOUT,
+            write_timeout: Some(READ_TIMEOUT),
+            reuse_address: false,
+            broadcast: false,
+        }
+    }
+}
+
+/// Binds UDP socket configured the way server and client expect.
+///
+/// Fails with `ErrorKind::AddrInUse` error wrapping [`PortInUse`] if address is already bound.
+pub fn bind_udp(address: SocketAddr) -> Result<UdpSocket> {
+    bind_udp_with(address, &Default::default())
+}
+
+/// Same as `bind_udp`, but with given socket options.
+pub fn bind_udp_with(address: SocketAddr, options: &UdpOptions) -> Result<UdpSocket> {
+    if options.read_timeout.is_zero() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Read timeout must not be zero",
+        ));
+    }
+
+    let socket = Socket::new(
+        Domain::for_address(address),
+        Type::DGRAM,
+        Some(Protocol::UDP),
+    )?;
+    socket.set_reuse_address(options.reuse_address)?;
+    socket.set_broadcast(options.broadcast)?;
+    socket.bind(&address.into()).map_err(|error| {
+        if error.kind() == ErrorKind::AddrInUse {
+            Error::new(ErrorKind::AddrInUse, PortInUse { address })
+        } else {
+            error
+        }
+    })?;
+
+    let socket: UdpSocket = socket.into();
+    socket.set_read_timeout(Some(options.read_timeout))?;
+    socket.set_write_timeout(options.write_timeout)?;
+
+    Ok(socket)
+}
+
+type Datagram = (Vec<u8>, SocketAddr);
+
+/// Datagram in flight, ordered by delivery time.
+struct Delivery {
+    due: Instant,
+    sequence: u64,
+    datagram: Datagram,
+}
+
+impl PartialEq for Delivery {
+    fn eq(&self, other: &Delivery) -> bool {
+        (self.due, self.sequence) == (other.due, other.sequence)
+    }
+}
+
+impl Eq for Delivery {}
+
+impl PartialOrd for Delivery {
+    fn partial_cmp(&self, other: &Delivery) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delivery {
+    fn cmp(&self, other: &Delivery) -> std::cmp::Ordering {
+        // Reversed, so binary heap pops the earliest delivery first.
+        (other.due, other.sequence).cmp(&(self.due, self.sequence))
+    }
+}
+
+/// Conditions emulated by loopback network.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct NetworkConditions {
+    /// Base delay of every datagram.
+    pub latency: Duration,
+    /// Maximum random delay added to latency, datagrams may arrive out of order when non-zero.
+    pub jitter: Duration,
+    /// Probability (from 0 to 1) of datagram being lost.
+    pub loss: f64,
+}
+
+struct NetworkState {
+    endpoints: HashMap<SocketAddr, Sender<Delivery>>,
+    conditions: NetworkConditions,
+    rng: StdRng,
+    sequence: u64,
+}
+
+/// In-memory network connecting loopback transports bound to it.
+///
+/// Datagrams sent to addresses with no bound transport are silently dropped, as with UDP.
+#[derive(Clone)]
+pub struct LoopbackNetwork {
+    state: Arc<Mutex<NetworkState>>,
+}
+
+impl Default for LoopbackNetwork {
+    fn default() -> LoopbackNetwork {
+        LoopbackNetwork::new()
+    }
+}
+
+impl LoopbackNetwork {
+    /// Creates perfect network, without delay or loss.
+    pub fn new() -> LoopbackNetwork {
+        LoopbackNetwork::with_conditions(Default::default(), 0)
+    }
+
+    /// Creates network emulating given conditions.
+    ///
+    /// # Arguments
+    ///
+    /// * `conditions` - delay, jitter and loss of datagrams.
+    /// * `seed` - random generator seed, pass the same seed to get the same jitter and loss in repeated runs.
+    pub fn with_conditions(conditions: NetworkConditions, seed: u64) -> LoopbackNetwork {
+        LoopbackNetwork {
+            state: Arc::new(Mutex::new(NetworkState {
+                endpoints: HashMap::new(),
+                conditions,
+                rng: StdRng::seed_from_u64(seed),
+                sequence: 0,
+            })),
+        }
+    }
+
+    pub fn conditions(&self) -> NetworkConditions {
+        self.state.lock().unwrap().conditions
+    }
+
+    /// Changes emulated conditions, takes effect with the next sent datagram.
+    pub fn set_conditions(&self, conditions: NetworkConditions) {
+        self.state.lock().unwrap().conditions = conditions;
+    }
+
+    /// Creates transport receiving datagrams sent to given address.
+    pub fn bind(&self, address: SocketAddr) -> Result<LoopbackTransport> {
+        let mut state = self.state.lock().unwrap();
+        if state.endpoints.contains_key(&address) {
+            return Err(Error::new(
+                ErrorKind::AddrInUse,
+                "Address already bound in loopback network",
+            ));
+        }
+
+        let (sender, receiver) = channel();
+        state.endpoints.insert(address, sender);
+
+        Ok(LoopbackTransport {
+            address,
+            network: self.clone(),
+            receiver: Mutex::new((receiver, BinaryHeap::new())),
+        })
+    }
+
+    fn deliver(&self, target: SocketAddr, datagram: Datagram) {
+        let mut state = self.state.lock().unwrap();
+        let conditions = state.conditions;
+        if state.rng.gen_bool(conditions.loss.clamp(0.0, 1.0)) {
+            return;
+        }
+
+        let mut delay = conditions.latency;
+        if !conditions.jitter.is_zero() {
+            delay += conditions.jitter.mul_f64(state.rng.gen::<f64>());
+        }
+
+        state.sequence += 1;
+        let delivery = Delivery {
+            due: Instant::now() + delay,
+            sequence: state.sequence,
+            datagram,
+        };
+        if let Some(sender) = state.endpoints.get(&target) {
+            let _ = sender.send(delivery);
+        }
+    }
+}
+
+/// Transport bound to [`LoopbackNetwork`].
+pub struct LoopbackTransport {
+    address: SocketAddr,
+    network: LoopbackNetwork,
+    receiver: Mutex<(Receiver<Delivery>, BinaryHeap<Delivery>)>,
+}
+
+impl Transport for LoopbackTransport {
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize> {
+        self.network.deliver(target, (buf.to_vec(), self.address));
+
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        let mut receiver = self.receiver.lock().unwrap();
+        let (receiver, in_flight) = &mut *receiver;
+
+        let deadline = Instant::now() + READ_TIMEOUT;
+        loop {
+            let now = Instant::now();
+            if in_flight.peek().is_some_and(|delivery| delivery.due <= now) {
+                let (datagram, source) = in_flight.pop().unwrap().datagram;
+
+                // Excess bytes are discarded, as with UDP.
+                let amount = datagram.len().min(buf.len());
+                buf[..amount].copy_from_slice(&datagram[..amount]);
+
+                return Ok((amount, source));
+            }
+
+            if now >= deadline {
+                return Err(Error::new(ErrorKind::WouldBlock, "Receive timed out"));
+            }
+
+            let wait_until = match in_flight.peek() {
+                Some(delivery) => delivery.due.min(deadline),
+                None => deadline,
+            };
+            match receiver.recv_timeout(wait_until - now) {
+                Ok(delivery) => in_flight.push(delivery),
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(Error::new(
+                        ErrorKind::NotConnected,
+                        "Loopback network dropped",
+                    ))
+                }
+            }
+        }
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.address)
+    }
+}
+
+impl Drop for LoopbackTransport {
+    fn drop(&mut self) {
+        self.network
+            .state
+            .lock()
+            .unwrap()
+            .endpoints
+            .remove(&self.address);
+    }
+}
+
+/// Probabilities (from 0 to 1) of faults injected into each sent datagram.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct FaultProbabilities {
+    /// Flips single random bit.
+    pub bit_flip: f64,
+    /// Cuts datagram at random length.
+    pub truncate: f64,
+    /// Sends datagram twice.
+    pub duplicate: f64,
+    /// Holds datagram back and sends it after the next one.
+    pub reorder: f64,
+}
+
+/// Transport wrapper corrupting, duplicating and reordering sent datagrams.
+///
+/// Received datagrams are passed through unchanged, wrap both ends to corrupt traffic in both directions.
+pub struct FaultyTransport<T: Transport> {
+    inner: T,
+    probabilities: Mutex<FaultProbabilities>,
+    rng: Mutex<StdRng>,
+    held_back: Mutex<Option<Datagram>>,
+}
+
+impl<T: Transport> FaultyTransport<T> {
+    /// Wraps transport.
+    ///
+    /// # Arguments
+    ///
+    /// * `inner` - transport to wrap.
+    /// * `probabilities` - fault probabilities.
+    /// * `seed` - random generator seed, pass the same seed to inject the same faults in repeated runs.
+    pub fn new(inner: T, probabilities: FaultProbabilities, seed: u64) -> FaultyTransport<T> {
+        FaultyTransport {
+            inner,
+            probabilities: Mutex::new(probabilities),
+            rng: Mutex::new(StdRng::seed_from_u64(seed)),
+            held_back: Mutex::new(None),
+        }
+    }
+
+    pub fn probabilities(&self) -> FaultProbabilities {
+        *self.probabilities.lock().unwrap()
+    }
+
+    /// Changes fault probabilities, takes effect with the next sent datagram.
+    pub fn set_probabilities(&self, probabilities: FaultProbabilities) {
+        *self.probabilities.lock().unwrap() = probabilities;
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+}
+
+impl<T: Transport> Transport for FaultyTransport<T> {
+    fn send_to(&self, buf: &[u8], target: SocketAddr) -> Result<usize> {
+        let probabilities = self.probabilities();
+        let mut rng = self.rng.lock().unwrap();
+
+        let mut datagram = buf.to_vec();
+        if !datagram.is_empty() && rng.gen_bool(probabilities.bit_flip.clamp(0.0, 1.0)) {
+            let bit = rng.gen_range(0..datagram.len() * 8);
+            datagram[bit / 8] ^= 1 << (bit % 8);
+        }
+        if !datagram.is_empty() && rng.gen_bool(probabilities.truncate.clamp(0.0, 1.0)) {
+            let length = rng.gen_range(0..datagram.len());
+            datagram.truncate(length);
+        }
+        let duplicate = rng.gen_bool(probabilities.duplicate.clamp(0.0, 1.0));
+        let reorder = rng.gen_bool(probabilities.reorder.clamp(0.0, 1.0));
+        drop(rng);
+
+        let mut held_back = self.held_back.lock().unwrap();
+        if reorder && held_back.is_none() {
+            *held_back = Some((datagram, target));
+            return Ok(buf.len());
+        }
+
+        self.inner.send_to(&datagram, target)?;
+        if duplicate {
+            self.inner.send_to(&datagram, target)?;
+        }
+        if let Some((datagram, target)) = held_back.take() {
+            self.inner.send_to(&datagram, target)?;
+        }
+
+        Ok(buf.len())
+    }
+
+    fn recv_from(&self, buf: &mut [u8]) -> Result<(usize, SocketAddr)> {
+        self.inner.recv_from(buf)
+    }
+
+    fn local_addr(&self) -> Result<SocketAddr> {
+        self.inner.local_addr()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn address(port: u16) -> SocketAddr {
+        SocketAddr::from(([10, 0, 0, 1], port))
+    }
+
+    /// Returns datagrams received by transport, in order of arrival.
+    fn received(transport: &dyn Transport) -> Vec<Vec<u8>> {
+        let mut received = vec![];
+        let mut buf = [0; 16];
+        while let Ok((amount, _)) = transport.recv_from(&mut buf) {
+            received.push(buf[..amount].to_vec());
+        }
+
+        received
+    }
+
+    fn faulty_pair(
+        network: &LoopbackNetwork,
+        probabilities: FaultProbabilities,
+    ) -> (FaultyTransport<LoopbackTransport>, LoopbackTransport) {
+        let sender = FaultyTransport::new(network.bind(address(1)).unwrap(), probabilities, 0);
+        let receiver = network.bind(address(2)).unwrap();
+
+        (sender, receiver)
+    }
+
+    #[test]
+    fn faulty_transport_duplicates_and_reorders() {
+        let network = LoopbackNetwork::new();
+        let (sender, receiver) = faulty_pair(
+            &network,
+            FaultProbabilities {
+                duplicate: 1.0,
+                ..Default::default()
+            },
+        );
+        sender.send_to(&[1], address(2)).unwrap();
+        assert_eq!(received(&receiver), [vec![1], vec![1]]);
+
+        sender.set_probabilities(FaultProbabilities {
+            reorder: 1.0,
+            ..Default::default()
+        });
+        for datagram in 1..=4 {
+            sender.send_to(&[datagram], address(2)).unwrap();
+        }
+        assert_eq!(received(&receiver), [vec![2], vec![1], vec![4], vec![3]]);
+    }
+
+    #[test]
+    fn faulty_transport_corrupts_datagrams() {
+        let network = LoopbackNetwork::new();
+        let datagram = [0u8; 8];
+        let (sender, receiver) = faulty_pair(
+            &network,
+            FaultProbabilities {
+                bit_flip: 1.0,
+                ..Default::default()
+            },
+        );
+        sender.send_to(&datagram, address(2)).unwrap();
+        let flipped = received(&receiver);
+        assert_eq!(flipped.len(), 1);
+        assert_eq!(flipped[0].len(), datagram.len());
+        let flipped_bits: u32 = flipped[0].iter().map(|byte| byte.count_ones()).sum();
+        assert_eq!(flipped_bits, 1);
+
+        sender.set_probabilities(FaultProbabilities {
+            truncate: 1.0,
+            ..Default::default()
+        });
+        sender.send_to(&datagram, address(2)).unwrap();
+        let truncated = received(&receiver);
+        assert_eq!(truncated.len(), 1);
+        assert!(truncated[0].len() < datagram.len());
+    }
+
+    #[test]
+    fn faulty_transport_without_faults_passes_datagrams_through() {
+        let network = LoopbackNetwork::new();
+        let (sender, receiver) = faulty_pair(&network, Default::default());
+        sender.send_to(&[1, 2, 3], address(2)).unwrap();
+        sender.send_to(&[4], address(2)).unwrap();
+
+        assert_eq!(received(&receiver), [vec![1, 2, 3], vec![4]]);
+    }
+
+    #[test]
+    fn loopback_network_delays_datagrams() {
+        let latency = Duration::from_millis(30);
+        let network = LoopbackNetwork::with_conditions(
+            NetworkConditions {
+                latency,
+                ..Default::default()
+            },
+            0,
+        );
+        let sender = network.bind(address(1)).unwrap();
+        let receiver = network.bind(address(2)).unwrap();
+
+        let start = Instant::now();
+        sender.send_to(&[1], address(2)).unwrap();
+        let mut buf = [0; 16];
+        receiver.recv_from(&mut buf).unwrap();
+        assert!(start.elapsed() >= latency);
+    }
+
+    #[test]
+    fn loopback_network_loses_datagrams() {
+        let network = LoopbackNetwork::with_conditions(
+            NetworkConditions {
+                loss: 1.0,
+                ..Default::default()
+            },
+            0,
+        );
+        let sender = network.bind(address(1)).unwrap();
+        let receiver = network.bind(address(2)).unwrap();
+        sender.send_to(&[1], address(2)).unwrap();
+        assert!(received(&receiver).is_empty());
+
+        network.set_conditions(Default::default());
+        sender.send_to(&[2], address(2)).unwrap();
+        assert_eq!(received(&receiver), [vec![2]]);
+    }
+
+    #[test]
+    fn loopback_network_jitter_is_reproducible_with_seed() {
+        let jittered_order = || {
+            let network = LoopbackNetwork::with_conditions(
+                NetworkConditions {
+                    jitter: Duration::from_millis(20),
+                    ..Default::default()
+                },
+                42,
+            );
+            let sender = network.bind(address(1)).unwrap();
+            let receiver = network.bind(address(2)).unwrap();
+            for datagram in 0..10 {
+                sender.send_to(&[datagram], address(2)).unwrap();
+            }
+
+            received(&receiver)
+        };
+
+        let order = jittered_order();
+        assert_eq!(order.len(), 10);
+        assert_eq!(order, jittered_order());
+    }
+}